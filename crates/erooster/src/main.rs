@@ -81,8 +81,9 @@ async fn main() -> Result<()> {
     erooster_imap::start(&config, &database, &storage, shutdown_flag.clone())?;
 
     let config_clone = config.clone();
+    let database_clone = database.clone();
     tokio::spawn(async move {
-        if let Err(e) = erooster_web::start(&config_clone).await {
+        if let Err(e) = erooster_web::start(&config_clone, &database_clone).await {
             error!("Unable to start webserver: {e:?}");
         }
     });
@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Access control lists of mailboxes (RFC 4314).
+-- The mailbox is stored as "{owner}/{name}", the identifier is a username,
+-- "anyone" or either of them prefixed with "-" for negative rights.
+CREATE TABLE IF NOT EXISTS mailbox_acl (
+    mailbox VARCHAR NOT NULL,
+    identifier VARCHAR NOT NULL,
+    rights VARCHAR NOT NULL,
+    PRIMARY KEY (mailbox, identifier)
+);
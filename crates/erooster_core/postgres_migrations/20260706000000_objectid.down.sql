@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+DROP TABLE IF EXISTS mailbox_ids;
+DROP INDEX IF EXISTS mails_message_id;
+ALTER TABLE mails DROP COLUMN save_date;
+ALTER TABLE mails DROP COLUMN message_id;
+ALTER TABLE mails DROP COLUMN thread_id;
+ALTER TABLE mails DROP COLUMN email_id;
@@ -0,0 +1,24 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Object identifiers of a message (RFC 8474) and the time it was saved in
+-- its mailbox as a unix timestamp (RFC 8514). The Message-ID header is kept
+-- to find the thread of replies. Messages stored before this migration get
+-- an own thread and no save date.
+ALTER TABLE mails ADD COLUMN email_id VARCHAR;
+ALTER TABLE mails ADD COLUMN thread_id VARCHAR;
+ALTER TABLE mails ADD COLUMN message_id VARCHAR;
+ALTER TABLE mails ADD COLUMN save_date BIGINT;
+
+UPDATE mails SET email_id = 'M' || md5(random()::text || maildir_id);
+UPDATE mails SET thread_id = 'T' || substr(email_id, 2);
+
+CREATE INDEX mails_message_id ON mails (message_id);
+
+-- The MAILBOXID of a mailbox, stored as "{owner}/{name}". It is created
+-- when first requested and follows the mailbox on RENAME.
+CREATE TABLE IF NOT EXISTS mailbox_ids (
+    mailbox VARCHAR PRIMARY KEY NOT NULL,
+    object_id VARCHAR NOT NULL
+);
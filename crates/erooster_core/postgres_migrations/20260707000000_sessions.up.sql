@@ -0,0 +1,15 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- The client connections of the running servers for `eroosterctl session list`.
+-- client_id holds the fields a client sent with the IMAP ID command (RFC 2971)
+-- as JSON object.
+CREATE TABLE IF NOT EXISTS sessions (
+    id VARCHAR PRIMARY KEY NOT NULL,
+    protocol VARCHAR NOT NULL,
+    peer VARCHAR NOT NULL,
+    username VARCHAR,
+    client_id VARCHAR,
+    started_at VARCHAR NOT NULL
+);
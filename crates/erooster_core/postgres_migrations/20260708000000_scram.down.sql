@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+ALTER TABLE users DROP COLUMN scram_sha256;
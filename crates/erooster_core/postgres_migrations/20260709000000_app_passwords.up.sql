@@ -0,0 +1,15 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Application-specific passwords. Each one is valid for the protocols in
+-- scope ("imap", "smtp" or "all") until it is revoked.
+CREATE TABLE IF NOT EXISTS app_passwords (
+    username VARCHAR NOT NULL,
+    name VARCHAR NOT NULL,
+    hash VARCHAR NOT NULL,
+    scope VARCHAR NOT NULL,
+    created_at VARCHAR NOT NULL,
+    last_used VARCHAR,
+    PRIMARY KEY (username, name)
+);
@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Addresses of hosted domains which deliver to other addresses, one row per
+-- target. The address "*@domain" is the catch-all of a domain. Targets are
+-- local users, further aliases or external addresses mail is forwarded to.
+CREATE TABLE IF NOT EXISTS aliases (
+    address VARCHAR NOT NULL,
+    target VARCHAR NOT NULL,
+    created_at VARCHAR NOT NULL,
+    PRIMARY KEY (address, target)
+);
@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+DROP TABLE IF EXISTS expunged_mails;
+DROP TABLE IF EXISTS mailbox_modseq_counter;
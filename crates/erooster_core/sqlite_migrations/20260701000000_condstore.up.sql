@@ -0,0 +1,24 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Per-mailbox modification sequence counter (RFC 7162 CONDSTORE).
+-- A mailbox without any row reports HIGHESTMODSEQ 1; every change allocates
+-- the next value from here.
+CREATE TABLE IF NOT EXISTS mailbox_modseq_counter (
+    mailbox TEXT PRIMARY KEY NOT NULL,
+    modseq BIGINT NOT NULL
+);
+
+INSERT INTO mailbox_modseq_counter (mailbox, modseq)
+SELECT mailbox, MAX(modseq) FROM mails GROUP BY mailbox;
+
+-- Tombstones for expunged messages so QRESYNC can report VANISHED UIDs.
+CREATE TABLE IF NOT EXISTS expunged_mails (
+    id INTEGER NOT NULL PRIMARY KEY,
+    mailbox TEXT NOT NULL,
+    uid INTEGER NOT NULL,
+    modseq BIGINT NOT NULL
+);
+
+CREATE INDEX expunged_mails_mailbox_modseq ON expunged_mails (mailbox, modseq);
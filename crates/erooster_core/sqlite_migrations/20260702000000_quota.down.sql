@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+DROP TABLE IF EXISTS domain_quotas;
+DROP TABLE IF EXISTS user_quotas;
+ALTER TABLE mails DROP COLUMN size;
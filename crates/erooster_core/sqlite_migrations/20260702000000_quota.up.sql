@@ -0,0 +1,22 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Size of the stored message in octets, used to calculate quota usage.
+-- Messages stored before this migration count with 0 octets.
+ALTER TABLE mails ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+
+-- Storage (octets) and message count limits per user (RFC 9208).
+-- NULL means the resource is not limited.
+CREATE TABLE IF NOT EXISTS user_quotas (
+    username TEXT PRIMARY KEY NOT NULL,
+    storage_limit BIGINT,
+    message_limit BIGINT
+);
+
+-- Default limits for all users of a domain without an own quota.
+CREATE TABLE IF NOT EXISTS domain_quotas (
+    domain TEXT PRIMARY KEY NOT NULL,
+    storage_limit BIGINT,
+    message_limit BIGINT
+);
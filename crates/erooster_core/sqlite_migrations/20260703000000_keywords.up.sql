@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- User-defined IMAP keywords (e.g. $Junk or $Label1) of a message,
+-- separated by spaces. Maildir only stores the system flags.
+ALTER TABLE mails ADD COLUMN keywords TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,15 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Server and mailbox annotations (RFC 5464).
+-- The mailbox is stored as "{owner}/{name}" or as an empty string for server
+-- annotations. /private entries belong to the user in "owner", /shared
+-- entries use an empty owner.
+CREATE TABLE IF NOT EXISTS metadata (
+    mailbox TEXT NOT NULL,
+    owner TEXT NOT NULL,
+    entry TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (mailbox, owner, entry)
+);
@@ -0,0 +1,15 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- The client connections of the running servers for `eroosterctl session list`.
+-- client_id holds the fields a client sent with the IMAP ID command (RFC 2971)
+-- as JSON object.
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    protocol TEXT NOT NULL,
+    peer TEXT NOT NULL,
+    username TEXT,
+    client_id TEXT,
+    started_at TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- The salted SCRAM-SHA-256 credentials of a user (RFC 7677) in the form
+-- "SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>". They are
+-- derived when the password is set, users who did not change their password
+-- since this migration can only log in with PLAIN.
+ALTER TABLE users ADD COLUMN scram_sha256 TEXT;
@@ -0,0 +1,15 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Application-specific passwords. Each one is valid for the protocols in
+-- scope ("imap", "smtp" or "all") until it is revoked.
+CREATE TABLE IF NOT EXISTS app_passwords (
+    username TEXT NOT NULL,
+    name TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    scope TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    last_used TEXT,
+    PRIMARY KEY (username, name)
+);
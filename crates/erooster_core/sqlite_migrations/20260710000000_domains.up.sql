@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- The mail domains hosted in addition to mail.hostname. Mail for them is
+-- delivered locally. Outgoing mail of a domain with its own DKIM key is
+-- signed with it, the others use the key of the config.
+CREATE TABLE IF NOT EXISTS domains (
+    domain TEXT PRIMARY KEY NOT NULL,
+    dkim_key_path TEXT,
+    dkim_key_selector TEXT,
+    created_at TEXT NOT NULL
+);
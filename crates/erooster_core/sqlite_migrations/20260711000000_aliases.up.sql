@@ -0,0 +1,13 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Addresses of hosted domains which deliver to other addresses, one row per
+-- target. The address "*@domain" is the catch-all of a domain. Targets are
+-- local users, further aliases or external addresses mail is forwarded to.
+CREATE TABLE IF NOT EXISTS aliases (
+    address TEXT NOT NULL,
+    target TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (address, target)
+);
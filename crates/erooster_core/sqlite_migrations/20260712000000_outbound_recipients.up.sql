@@ -0,0 +1,16 @@
+-- SPDX-FileCopyrightText: 2026 MTRNord
+--
+-- SPDX-License-Identifier: Apache-2.0
+
+-- Delivery state of each recipient of an outbound_queue entry, so a retry
+-- only goes to the recipients which have not got the message yet.
+-- status: 'pending' | 'delivered' | 'failed'
+CREATE TABLE IF NOT EXISTS outbound_recipients (
+    queue_id TEXT NOT NULL REFERENCES outbound_queue (id) ON DELETE CASCADE,
+    address TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    -- the last reply of the recipient's server
+    last_reply TEXT,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (queue_id, address)
+);
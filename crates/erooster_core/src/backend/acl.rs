@@ -0,0 +1,309 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Access control lists for mailboxes (RFC 4314).
+//!
+//! Every mailbox belongs to an owner. The owner always has all rights on it,
+//! everybody else only gets the rights granted in the `mailbox_acl` table.
+//! Mailboxes of other users are visible below [`OTHER_USERS_NAMESPACE`],
+//! mailboxes which belong to nobody in particular below [`SHARED_NAMESPACE`].
+
+use std::fmt;
+
+/// Prefix of the mailboxes of other users, e.g. `Users/bob@example.com/INBOX`
+pub const OTHER_USERS_NAMESPACE: &str = "Users/";
+/// Prefix of the shared mailboxes, e.g. `Shared/support`
+pub const SHARED_NAMESPACE: &str = "Shared/";
+/// The owner of all mailboxes in the shared namespace.
+///
+/// This can't clash with a user as it is not a valid address.
+pub const SHARED_OWNER: &str = "@shared";
+/// The identifier which matches every user
+pub const ANYONE: &str = "anyone";
+
+/// The letters of all rights in the order used in responses
+const RIGHT_LETTERS: &[u8; 11] = b"lrswipkxtea";
+
+/// A set of RFC 4314 rights
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rights(u16);
+
+impl Rights {
+    /// No rights at all
+    pub const NONE: Rights = Rights(0);
+    /// `l`: The mailbox is visible to LIST
+    pub const LOOKUP: Rights = Rights(1);
+    /// `r`: SELECT, EXAMINE, STATUS, FETCH, SEARCH and COPY from the mailbox
+    pub const READ: Rights = Rights(1 << 1);
+    /// `s`: Keep the `\Seen` flag
+    pub const SEEN: Rights = Rights(1 << 2);
+    /// `w`: Keep flags other than `\Seen` and `\Deleted`
+    pub const WRITE: Rights = Rights(1 << 3);
+    /// `i`: APPEND, COPY and MOVE into the mailbox
+    pub const INSERT: Rights = Rights(1 << 4);
+    /// `p`: Send mail to the submission address of the mailbox
+    pub const POST: Rights = Rights(1 << 5);
+    /// `k`: Create child mailboxes
+    pub const CREATE: Rights = Rights(1 << 6);
+    /// `x`: Delete or rename the mailbox
+    pub const DELETE_MAILBOX: Rights = Rights(1 << 7);
+    /// `t`: Set or clear the `\Deleted` flag
+    pub const DELETE_MESSAGES: Rights = Rights(1 << 8);
+    /// `e`: EXPUNGE
+    pub const EXPUNGE: Rights = Rights(1 << 9);
+    /// `a`: Administer the ACL of the mailbox
+    pub const ADMINISTER: Rights = Rights(1 << 10);
+    /// All rights
+    pub const ALL: Rights = Rights((1 << 11) - 1);
+    /// Any right that allows changing the messages in a mailbox
+    pub const MODIFY: Rights = Rights(
+        Rights::SEEN.0
+            | Rights::WRITE.0
+            | Rights::INSERT.0
+            | Rights::DELETE_MESSAGES.0
+            | Rights::EXPUNGE.0,
+    );
+
+    /// Parses a rights string like `lrs`.
+    ///
+    /// The obsolete `c` and `d` rights of RFC 2086 are mapped to `kx` and `te`.
+    /// Returns `None` if the string contains an unknown right.
+    #[must_use]
+    pub fn parse(rights: &str) -> Option<Rights> {
+        rights.bytes().try_fold(Rights::NONE, |acc, letter| {
+            let right = match letter {
+                b'c' => Rights::CREATE.union(Rights::DELETE_MAILBOX),
+                b'd' => Rights::DELETE_MESSAGES.union(Rights::EXPUNGE),
+                _ => {
+                    let index = RIGHT_LETTERS.iter().position(|l| *l == letter)?;
+                    Rights(1 << index)
+                }
+            };
+            Some(acc.union(right))
+        })
+    }
+
+    /// Whether all rights of `other` are in this set
+    #[must_use]
+    pub const fn contains(self, other: Rights) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any right of `other` is in this set
+    #[must_use]
+    pub const fn intersects(self, other: Rights) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Whether the set is empty
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The rights in either set
+    #[must_use]
+    pub const fn union(self, other: Rights) -> Rights {
+        Rights(self.0 | other.0)
+    }
+
+    /// The rights in this set but not in `other`
+    #[must_use]
+    pub const fn difference(self, other: Rights) -> Rights {
+        Rights(self.0 & !other.0)
+    }
+
+    /// The single rights in this set
+    pub fn iter(self) -> impl Iterator<Item = Rights> {
+        (0..RIGHT_LETTERS.len())
+            .map(|index| Rights(1 << index))
+            .filter(move |right| self.contains(*right))
+    }
+}
+
+impl fmt::Display for Rights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, letter) in RIGHT_LETTERS.iter().enumerate() {
+            if self.0 & (1 << index) != 0 {
+                write!(f, "{}", char::from(*letter))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single entry of an access control list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntry {
+    /// A username, `anyone`, or one of them prefixed with `-` for negative rights
+    pub identifier: String,
+    /// The granted (or for negative identifiers revoked) rights
+    pub rights: Rights,
+}
+
+/// The rights `username` has on a mailbox of `owner` with the given ACL.
+///
+/// Negative entries take precedence over positive ones.
+#[must_use]
+pub fn effective_rights(owner: &str, username: &str, entries: &[AclEntry]) -> Rights {
+    if owner == username {
+        return Rights::ALL;
+    }
+    let mut granted = Rights::NONE;
+    let mut revoked = Rights::NONE;
+    for entry in entries {
+        match entry.identifier.strip_prefix('-') {
+            Some(identifier) if identifier == username || identifier == ANYONE => {
+                revoked = revoked.union(entry.rights);
+            }
+            None if entry.identifier == username || entry.identifier == ANYONE => {
+                granted = granted.union(entry.rights);
+            }
+            _ => {}
+        }
+    }
+    granted.difference(revoked)
+}
+
+/// A mailbox name resolved to the user who owns it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailboxName {
+    /// The owner, or [`SHARED_OWNER`] for the shared namespace
+    pub owner: String,
+    /// The name of the mailbox relative to the owner, using `.` as the
+    /// hierarchy delimiter like the mailbox keys in the database
+    pub name: String,
+}
+
+impl MailboxName {
+    /// Resolves a mailbox name as seen by `username`
+    #[must_use]
+    pub fn resolve(mailbox: &str, username: &str) -> Self {
+        let mailbox = mailbox.trim_matches('"');
+        if let Some(name) = mailbox.strip_prefix(SHARED_NAMESPACE) {
+            if !name.is_empty() {
+                return MailboxName {
+                    owner: SHARED_OWNER.to_string(),
+                    name: name.replace('/', "."),
+                };
+            }
+        }
+        if let Some(rest) = mailbox.strip_prefix(OTHER_USERS_NAMESPACE) {
+            if let Some((owner, name)) = rest.split_once('/') {
+                if !owner.is_empty() && !name.is_empty() {
+                    return MailboxName {
+                        owner: owner.to_string(),
+                        name: name.replace('/', "."),
+                    };
+                }
+            }
+        }
+        MailboxName {
+            owner: username.to_string(),
+            name: mailbox.replace('/', "."),
+        }
+    }
+
+    /// Parses the key used for the mailbox in the database
+    #[must_use]
+    pub fn from_id(id: &str) -> Option<Self> {
+        let (owner, name) = id.split_once('/')?;
+        Some(MailboxName {
+            owner: owner.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    /// The key used for the mailbox in the database
+    #[must_use]
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    /// The name of the mailbox as seen by `username`
+    #[must_use]
+    pub fn display(&self, username: &str) -> String {
+        if self.owner == username {
+            self.name.clone()
+        } else if self.owner == SHARED_OWNER {
+            format!("{SHARED_NAMESPACE}{}", self.name.replace('.', "/"))
+        } else {
+            format!(
+                "{OTHER_USERS_NAMESPACE}{}/{}",
+                self.owner,
+                self.name.replace('.', "/")
+            )
+        }
+    }
+
+    /// Whether the mailbox belongs to `username`
+    #[must_use]
+    pub fn is_owned_by(&self, username: &str) -> bool {
+        self.owner == username
+    }
+}
+
+/// The database key of the mailbox `mailbox` as seen by `username`
+#[must_use]
+pub fn mailbox_id(mailbox: &str, username: &str) -> String {
+    MailboxName::resolve(mailbox, username).id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{effective_rights, AclEntry, MailboxName, Rights, SHARED_OWNER};
+
+    #[test]
+    fn rights_roundtrip_through_strings() {
+        let rights = Rights::parse("rl").unwrap_or_default();
+        assert_eq!(rights.to_string(), "lr");
+        assert_eq!(Rights::ALL.to_string(), "lrswipkxtea");
+        assert_eq!(Rights::parse("cd").unwrap_or_default().to_string(), "kxte");
+        assert_eq!(Rights::parse("lz"), None);
+        assert!(Rights::ALL.contains(Rights::ADMINISTER));
+        assert!(!Rights::LOOKUP.contains(Rights::READ));
+    }
+
+    #[test]
+    fn negative_entries_win() {
+        let entries = vec![
+            AclEntry {
+                identifier: String::from("anyone"),
+                rights: Rights::parse("lr").unwrap_or_default(),
+            },
+            AclEntry {
+                identifier: String::from("bob"),
+                rights: Rights::parse("lrsw").unwrap_or_default(),
+            },
+            AclEntry {
+                identifier: String::from("-bob"),
+                rights: Rights::parse("w").unwrap_or_default(),
+            },
+        ];
+        assert_eq!(effective_rights("alice", "alice", &entries), Rights::ALL);
+        assert_eq!(
+            effective_rights("alice", "bob", &entries).to_string(),
+            "lrs"
+        );
+        assert_eq!(effective_rights("alice", "eve", &entries).to_string(), "lr");
+    }
+
+    #[test]
+    fn mailbox_names_resolve_to_their_owner() {
+        let own = MailboxName::resolve("\"Archive/2026\"", "alice");
+        assert_eq!(own.id(), "alice/Archive.2026");
+        assert_eq!(own.display("alice"), "Archive.2026");
+
+        let other = MailboxName::resolve("Users/bob@example.com/INBOX", "alice");
+        assert_eq!(other.owner, "bob@example.com");
+        assert_eq!(other.name, "INBOX");
+        assert_eq!(other.display("alice"), "Users/bob@example.com/INBOX");
+        assert_eq!(other.display("bob@example.com"), "INBOX");
+
+        let shared = MailboxName::resolve("Shared/support/tickets", "alice");
+        assert_eq!(shared.owner, SHARED_OWNER);
+        assert_eq!(shared.display("alice"), "Shared/support/tickets");
+        assert_eq!(MailboxName::from_id(&shared.id()), Some(shared));
+    }
+}
@@ -0,0 +1,271 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Address aliases, catch-alls and forwarding.
+//!
+//! An alias delivers the mail for an address of a hosted domain to one or
+//! more targets: users, further aliases or external addresses the mail is
+//! forwarded to. The alias `*@domain` is the catch-all of a domain and gets
+//! the mail for addresses which are neither a user nor an alias.
+//! `user+tag@domain` reaches `user@domain` unless the tagged address is an
+//! alias of its own.
+
+use crate::{
+    backend::{
+        database::{Database, DB},
+        domains,
+        queue::now_utc_iso8601,
+    },
+    config::Config,
+    srs,
+};
+use color_eyre::eyre::{eyre, Result};
+use std::collections::BTreeSet;
+
+#[cfg(feature = "postgres")]
+use sqlx::PgPool as Pool;
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool as Pool;
+
+/// Separates the tag from the user in `user+tag@domain`
+const TAG_SEPARATOR: char = '+';
+
+/// One target of an alias from the `aliases` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    /// The lowercase address, `*@domain` for a catch-all.
+    pub address: String,
+    /// The lowercase address the mail is delivered to.
+    pub target: String,
+    /// ISO 8601 timestamp of when the target was added.
+    pub created_at: String,
+}
+
+/// Where mail for an address ends up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The INBOX of a local user
+    Mailbox(String),
+    /// An external address the mail is forwarded to
+    Forward(String),
+}
+
+/// Adds `target` to the alias `address`, creating the alias if needed.
+pub async fn add(pool: &Pool, address: &str, target: &str) -> Result<()> {
+    let address = address.to_lowercase();
+    let target = target.to_lowercase();
+    sqlx::query("INSERT INTO aliases (address, target, created_at) VALUES ($1, $2, $3)")
+        .bind(&address)
+        .bind(&target)
+        .bind(now_utc_iso8601())
+        .execute(pool)
+        .await
+        .map_err(|e| eyre!("Unable to add '{target}' to alias '{address}': {e}"))?;
+    Ok(())
+}
+
+/// Removes one target of an alias, or the whole alias if `target` is `None`.
+/// Returns the number of removed targets.
+pub async fn remove(pool: &Pool, address: &str, target: Option<&str>) -> Result<u64> {
+    let result = if let Some(target) = target {
+        sqlx::query("DELETE FROM aliases WHERE address = $1 AND target = $2")
+            .bind(address.to_lowercase())
+            .bind(target.to_lowercase())
+            .execute(pool)
+            .await?
+    } else {
+        sqlx::query("DELETE FROM aliases WHERE address = $1")
+            .bind(address.to_lowercase())
+            .execute(pool)
+            .await?
+    };
+    Ok(result.rows_affected())
+}
+
+/// Returns all alias targets ordered by address and target.
+pub async fn list(pool: &Pool) -> Result<Vec<Alias>> {
+    let rows: Vec<(String, String, String)> =
+        sqlx::query_as("SELECT address, target, created_at FROM aliases ORDER BY address, target")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(address, target, created_at)| Alias {
+            address,
+            target,
+            created_at,
+        })
+        .collect())
+}
+
+/// Returns the targets of the alias `address`, empty if it is none.
+async fn targets_of(pool: &Pool, address: &str) -> Result<Vec<String>> {
+    let rows: Vec<(String,)> =
+        sqlx::query_as("SELECT target FROM aliases WHERE address = $1 ORDER BY target")
+            .bind(address)
+            .fetch_all(pool)
+            .await?;
+    Ok(rows.into_iter().map(|(target,)| target).collect())
+}
+
+/// Resolves the recipient `address` to the mailboxes and external addresses
+/// its mail is delivered to. Addresses of hosted domains which reach nobody
+/// resolve to nothing. Bounces to senders rewritten by [`srs::forward`] are
+/// sent back to the original sender.
+pub async fn resolve(database: &DB, config: &Config, address: &str) -> Result<Vec<Target>> {
+    if let Some(secret) = &config.mail.srs_secret {
+        if let Some(sender) = srs::reverse(secret, address) {
+            return Ok(vec![Target::Forward(sender)]);
+        }
+    }
+
+    let pool = database.get_pool();
+    let mut targets = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut pending = vec![address.to_lowercase()];
+    while let Some(address) = pending.pop() {
+        let target = if !domains::is_local_address(pool, config, &address).await? {
+            Some(Target::Forward(address))
+        } else if !seen.insert(address.clone()) {
+            // An alias delivering to itself keeps a copy in the user's mailbox
+            database
+                .user_exists(&address)
+                .await
+                .then_some(Target::Mailbox(address))
+        } else {
+            match lookup(database, &address).await? {
+                Lookup::Mailbox(user) => Some(Target::Mailbox(user)),
+                Lookup::Alias(alias_targets) => {
+                    // Reversed so the targets are resolved in order
+                    pending.extend(alias_targets.into_iter().rev());
+                    None
+                }
+                Lookup::Unknown => None,
+            }
+        };
+        if let Some(target) = target {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+    }
+    Ok(targets)
+}
+
+enum Lookup {
+    Mailbox(String),
+    Alias(Vec<String>),
+    Unknown,
+}
+
+/// Looks up a local address: aliases come before users, the untagged address
+/// after the tagged one and the catch-all last.
+async fn lookup(database: &DB, address: &str) -> Result<Lookup> {
+    let pool = database.get_pool();
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return Ok(Lookup::Unknown);
+    };
+    let mut candidates = vec![address.to_string()];
+    if let Some((user, _tag)) = local.split_once(TAG_SEPARATOR) {
+        candidates.push(format!("{user}@{domain}"));
+    }
+    for candidate in candidates {
+        let alias_targets = targets_of(pool, &candidate).await?;
+        if !alias_targets.is_empty() {
+            return Ok(Lookup::Alias(alias_targets));
+        }
+        if database.user_exists(&candidate).await {
+            return Ok(Lookup::Mailbox(candidate));
+        }
+    }
+    let catch_all = targets_of(pool, &format!("*@{domain}")).await?;
+    if catch_all.is_empty() {
+        Ok(Lookup::Unknown)
+    } else {
+        Ok(Lookup::Alias(catch_all))
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::setup_test_database;
+
+    #[tokio::test]
+    async fn aliases_resolve_to_mailboxes_and_forwards() {
+        let (config, database, _storage) = setup_test_database().await.unwrap();
+        let pool = database.get_pool();
+        for user in ["alice@localhost", "bob@localhost"] {
+            database.add_user(user).await.unwrap();
+        }
+        add(pool, "Team@localhost", "alice@localhost")
+            .await
+            .unwrap();
+        add(pool, "team@localhost", "bob@localhost").await.unwrap();
+        add(pool, "team@localhost", "carol@example.com")
+            .await
+            .unwrap();
+        add(pool, "bob@localhost", "bob@localhost").await.unwrap();
+        add(pool, "bob@localhost", "bob@example.net").await.unwrap();
+        add(pool, "*@localhost", "alice@localhost").await.unwrap();
+        assert!(add(pool, "team@localhost", "bob@localhost").await.is_err());
+
+        let mailbox = |user: &str| Target::Mailbox(user.to_string());
+        let forward = |address: &str| Target::Forward(address.to_string());
+
+        assert_eq!(
+            resolve(&database, &config, "alice@localhost")
+                .await
+                .unwrap(),
+            [mailbox("alice@localhost")]
+        );
+        assert_eq!(
+            resolve(&database, &config, "alice+lists@localhost")
+                .await
+                .unwrap(),
+            [mailbox("alice@localhost")]
+        );
+        assert_eq!(
+            resolve(&database, &config, "TEAM@localhost").await.unwrap(),
+            [
+                mailbox("alice@localhost"),
+                forward("bob@example.net"),
+                mailbox("bob@localhost"),
+                forward("carol@example.com"),
+            ]
+        );
+        assert_eq!(
+            resolve(&database, &config, "nobody@localhost")
+                .await
+                .unwrap(),
+            [mailbox("alice@localhost")]
+        );
+        assert_eq!(
+            resolve(&database, &config, "dave@example.com")
+                .await
+                .unwrap(),
+            [forward("dave@example.com")]
+        );
+
+        let bounce = srs::forward("test secret", "carol@example.com", "localhost");
+        assert_eq!(
+            resolve(&database, &config, &bounce).await.unwrap(),
+            [forward("carol@example.com")]
+        );
+
+        assert_eq!(remove(pool, "*@localhost", None).await.unwrap(), 1);
+        assert!(resolve(&database, &config, "nobody@localhost")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            remove(pool, "team@localhost", Some("CAROL@example.com"))
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(list(pool).await.unwrap().len(), 4);
+    }
+}
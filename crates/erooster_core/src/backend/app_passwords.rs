@@ -0,0 +1,279 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Application-specific passwords.
+//!
+//! Users can give every mail client its own password instead of the account
+//! password. Each app password is limited to IMAP, SMTP submission or both,
+//! can be revoked on its own and records when it was last used. They are
+//! accepted by PLAIN and LOGIN, SCRAM only knows the account password.
+
+use crate::{backend::queue::now_utc_iso8601, sasl::Protocol};
+use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use color_eyre::eyre::{eyre, Report, Result};
+use rand_core::OsRng;
+use ring::rand::{SecureRandom, SystemRandom};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::Row;
+use std::{fmt, str::FromStr};
+use tracing::{debug, instrument};
+
+#[cfg(feature = "postgres")]
+use sqlx::PgPool as Pool;
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool as Pool;
+
+/// The letters of generated passwords, without the easily confused `l`, `o`,
+/// `0` and `1`. 32 letters so every random byte maps to one without bias.
+const ALPHABET: &[u8; 32] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+/// The protocols an app password is valid for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Only for reading mail over IMAP
+    Imap,
+    /// Only for sending mail over SMTP submission
+    Smtp,
+    /// For both protocols
+    All,
+}
+
+impl Scope {
+    /// Returns the scope as stored in the database.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Imap => "imap",
+            Self::Smtp => "smtp",
+            Self::All => "all",
+        }
+    }
+
+    /// Whether passwords of this scope may log in with `protocol`.
+    #[must_use]
+    pub const fn covers(self, protocol: Protocol) -> bool {
+        matches!(
+            (self, protocol),
+            (Self::All, _) | (Self::Imap, Protocol::Imap) | (Self::Smtp, Protocol::Smtp)
+        )
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "imap" => Ok(Self::Imap),
+            "smtp" => Ok(Self::Smtp),
+            "all" => Ok(Self::All),
+            _ => Err(eyre!("Unknown scope '{s}', expected imap, smtp or all")),
+        }
+    }
+}
+
+/// An app password as listed to its user, without the secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPassword {
+    /// The name the user gave it, unique per user.
+    pub name: String,
+    /// The protocols it is valid for.
+    pub scope: Scope,
+    /// ISO 8601 timestamp of the creation.
+    pub created_at: String,
+    /// ISO 8601 timestamp of the last successful login, if any.
+    pub last_used: Option<String>,
+}
+
+/// Generates a new app password for `username` and returns it. Only its hash
+/// is stored, so this is the only time it can be shown.
+#[instrument(skip(pool))]
+pub async fn create(pool: &Pool, username: &str, name: &str, scope: Scope) -> Result<SecretString> {
+    let password = generate()?;
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(normalize(password.expose_secret()).as_bytes(), &salt)?
+        .to_string();
+    sqlx::query(
+        "INSERT INTO app_passwords (username, name, hash, scope, created_at) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(username)
+    .bind(name)
+    .bind(hash)
+    .bind(scope.as_str())
+    .bind(now_utc_iso8601())
+    .execute(pool)
+    .await
+    .map_err(|e| eyre!("Unable to add app password '{name}' for '{username}': {e}"))?;
+    Ok(password)
+}
+
+/// Returns the app passwords of `username` ordered by name.
+pub async fn list(pool: &Pool, username: &str) -> Result<Vec<AppPassword>> {
+    let rows = sqlx::query(
+        "SELECT name, scope, created_at, last_used FROM app_passwords WHERE username = $1 ORDER BY name",
+    )
+    .bind(username)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(AppPassword {
+                name: r.get("name"),
+                scope: r.get::<String, _>("scope").parse()?,
+                created_at: r.get("created_at"),
+                last_used: r.get("last_used"),
+            })
+        })
+        .collect()
+}
+
+/// Revokes an app password. Returns `false` if the user has none of that
+/// name.
+pub async fn revoke(pool: &Pool, username: &str, name: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM app_passwords WHERE username = $1 AND name = $2")
+        .bind(username)
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Revokes all app passwords of a deleted user.
+pub async fn revoke_all(pool: &Pool, username: &str) -> Result<()> {
+    sqlx::query("DELETE FROM app_passwords WHERE username = $1")
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Checks `password` against the app passwords of `username` which are valid
+/// for `protocol` and records the use of the matching one.
+#[instrument(skip(pool, password))]
+pub async fn verify(
+    pool: &Pool,
+    username: &str,
+    password: &SecretString,
+    protocol: Protocol,
+) -> Result<bool> {
+    let rows = sqlx::query("SELECT name, hash, scope FROM app_passwords WHERE username = $1")
+        .bind(username)
+        .fetch_all(pool)
+        .await?;
+    let password = normalize(password.expose_secret());
+
+    for row in rows {
+        let scope: Scope = row.get::<String, _>("scope").parse()?;
+        if !scope.covers(protocol) {
+            continue;
+        }
+        let hash: String = row.get("hash");
+        let valid = PasswordHash::new(&hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        });
+        if valid {
+            let name: String = row.get("name");
+            debug!("[APP PASSWORD] {username} logged in with '{name}'");
+            sqlx::query(
+                "UPDATE app_passwords SET last_used = $1 WHERE username = $2 AND name = $3",
+            )
+            .bind(now_utc_iso8601())
+            .bind(username)
+            .bind(name)
+            .execute(pool)
+            .await?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// A random password like `abcd-efgh-ijkm-npqr`
+fn generate() -> Result<SecretString> {
+    let mut bytes = [0; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| eyre!("Unable to generate an app password"))?;
+    let letters: Vec<String> = bytes
+        .chunks(4)
+        .map(|group| {
+            group
+                .iter()
+                .map(|byte| char::from(ALPHABET[usize::from(byte % 32)]))
+                .collect()
+        })
+        .collect();
+    Ok(SecretString::from(letters.join("-")))
+}
+
+/// Drops the dashes and spaces, clients differ in keeping them when the
+/// password is pasted.
+fn normalize(password: &str) -> String {
+    password
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .collect()
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{backend::database::Database, test_helpers::setup_test_database};
+
+    #[tokio::test]
+    async fn app_passwords_are_scoped_and_revocable() {
+        let (_config, database, _storage) = setup_test_database().await.unwrap();
+        let pool = database.get_pool();
+        let phone = create(pool, "alice@localhost", "phone", Scope::Imap)
+            .await
+            .unwrap();
+        let laptop = create(pool, "alice@localhost", "laptop", Scope::All)
+            .await
+            .unwrap();
+        assert_eq!(phone.expose_secret().len(), 19);
+        assert!(create(pool, "alice@localhost", "phone", Scope::Smtp)
+            .await
+            .is_err());
+
+        assert!(verify(pool, "alice@localhost", &phone, Protocol::Imap)
+            .await
+            .unwrap());
+        assert!(!verify(pool, "alice@localhost", &phone, Protocol::Smtp)
+            .await
+            .unwrap());
+        assert!(!verify(pool, "bob@localhost", &phone, Protocol::Imap)
+            .await
+            .unwrap());
+        // Pasted without the dashes
+        let bare = SecretString::from(laptop.expose_secret().replace('-', ""));
+        assert!(verify(pool, "alice@localhost", &bare, Protocol::Smtp)
+            .await
+            .unwrap());
+
+        let passwords = list(pool, "alice@localhost").await.unwrap();
+        let names: Vec<&str> = passwords.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["laptop", "phone"]);
+        assert_eq!(passwords[0].scope, Scope::All);
+        assert!(passwords.iter().all(|p| p.last_used.is_some()));
+
+        assert!(revoke(pool, "alice@localhost", "phone").await.unwrap());
+        assert!(!revoke(pool, "alice@localhost", "phone").await.unwrap());
+        assert!(!verify(pool, "alice@localhost", &phone, Protocol::Imap)
+            .await
+            .unwrap());
+        revoke_all(pool, "alice@localhost").await.unwrap();
+        assert!(list(pool, "alice@localhost").await.unwrap().is_empty());
+    }
+}
@@ -2,7 +2,7 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::config::Config;
+use crate::{backend::quota::Quota, config::Config};
 use sqlx::Pool;
 use {
     color_eyre::{self, Result},
@@ -54,8 +54,23 @@ pub trait Database<S: sqlx::Database> {
     /// Returns all usernames ordered alphabetically
     async fn list_users(&self) -> color_eyre::eyre::Result<Vec<String>>;
 
-    /// Deletes a user and their password record
+    /// Deletes a user, their password record and their app passwords
     async fn delete_user(&self, username: &str) -> color_eyre::eyre::Result<()>;
+
+    /// Returns the quota that applies to the user
+    ///
+    /// This is the user's own quota or, if none was set, the default of the
+    /// user's domain.
+    async fn get_quota(&self, username: &str) -> color_eyre::eyre::Result<Quota>;
+
+    /// Sets the quota of a single user
+    ///
+    /// An unlimited quota removes the user's own limits so the domain default
+    /// applies again.
+    async fn set_user_quota(&self, username: &str, quota: Quota) -> color_eyre::eyre::Result<()>;
+
+    /// Sets the default quota for all users of a domain
+    async fn set_domain_quota(&self, domain: &str, quota: Quota) -> color_eyre::eyre::Result<()>;
 }
 
 /// Get a postgres database connection pool and the higher level wrapper
@@ -2,7 +2,15 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{backend::database::Database, config::Config};
+use crate::{
+    backend::{
+        app_passwords,
+        database::Database,
+        quota::{domain_of, limit_from_db, limit_to_db, Quota},
+    },
+    config::Config,
+    sasl::scram,
+};
 use sqlx::{pool::PoolOptions, PgPool};
 use std::sync::OnceLock;
 use {
@@ -91,8 +99,10 @@ impl Database<sqlx::Postgres> for Postgres {
         let password_hash = argon2
             .hash_password(password.expose_secret().as_bytes(), &salt)?
             .to_string();
-        sqlx::query("UPDATE users SET hash = $1 WHERE username = $2")
+        let scram_credentials = scram::Credentials::new(password.expose_secret())?.to_string();
+        sqlx::query("UPDATE users SET hash = $1, scram_sha256 = $2 WHERE username = $3")
             .bind(password_hash)
+            .bind(scram_credentials)
             .bind(username)
             .execute(self.get_pool())
             .await?;
@@ -123,6 +133,7 @@ impl Database<sqlx::Postgres> for Postgres {
             .bind(username)
             .execute(self.get_pool())
             .await?;
+        app_passwords::revoke_all(self.get_pool(), username).await?;
         Ok(())
     }
 
@@ -145,4 +156,72 @@ impl Database<sqlx::Postgres> for Postgres {
             }
         }
     }
+
+    #[instrument(skip(self, username))]
+    async fn get_quota(&self, username: &str) -> color_eyre::eyre::Result<Quota> {
+        let user: Option<(Option<i64>, Option<i64>)> = sqlx::query_as(
+            "SELECT storage_limit, message_limit FROM user_quotas WHERE username = $1",
+        )
+        .bind(username)
+        .fetch_optional(self.get_pool())
+        .await?;
+        let limits = match (user, domain_of(username)) {
+            (Some(limits), _) => Some(limits),
+            (None, Some(domain)) => {
+                sqlx::query_as(
+                    "SELECT storage_limit, message_limit FROM domain_quotas WHERE domain = $1",
+                )
+                .bind(domain)
+                .fetch_optional(self.get_pool())
+                .await?
+            }
+            (None, None) => None,
+        };
+        Ok(
+            limits.map_or_else(Quota::default, |(storage, messages)| Quota {
+                storage: limit_from_db(storage),
+                messages: limit_from_db(messages),
+            }),
+        )
+    }
+
+    #[instrument(skip(self, username))]
+    async fn set_user_quota(&self, username: &str, quota: Quota) -> color_eyre::eyre::Result<()> {
+        if quota.is_unlimited() {
+            sqlx::query("DELETE FROM user_quotas WHERE username = $1")
+                .bind(username)
+                .execute(self.get_pool())
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO user_quotas (username, storage_limit, message_limit) VALUES ($1, $2, $3) ON CONFLICT (username) DO UPDATE SET storage_limit = excluded.storage_limit, message_limit = excluded.message_limit",
+            )
+            .bind(username)
+            .bind(limit_to_db(quota.storage))
+            .bind(limit_to_db(quota.messages))
+            .execute(self.get_pool())
+            .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_domain_quota(&self, domain: &str, quota: Quota) -> color_eyre::eyre::Result<()> {
+        if quota.is_unlimited() {
+            sqlx::query("DELETE FROM domain_quotas WHERE domain = $1")
+                .bind(domain)
+                .execute(self.get_pool())
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO domain_quotas (domain, storage_limit, message_limit) VALUES ($1, $2, $3) ON CONFLICT (domain) DO UPDATE SET storage_limit = excluded.storage_limit, message_limit = excluded.message_limit",
+            )
+            .bind(domain)
+            .bind(limit_to_db(quota.storage))
+            .bind(limit_to_db(quota.messages))
+            .execute(self.get_pool())
+            .await?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,187 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The mail domains hosted by the server.
+//!
+//! `mail.hostname` is always hosted, further domains are added with
+//! `eroosterctl domain add`. Mail to a hosted domain is delivered locally,
+//! everything else is relayed. A domain may have its own DKIM key, otherwise
+//! its mail is signed with the key of the config.
+
+use crate::{backend::queue::now_utc_iso8601, config::Config};
+use color_eyre::eyre::{eyre, Result};
+
+#[cfg(feature = "postgres")]
+use sqlx::PgPool as Pool;
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool as Pool;
+
+/// A hosted domain from the `domains` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Domain {
+    /// The lowercase domain name.
+    pub name: String,
+    /// The DKIM key of the domain, if it has its own.
+    pub dkim: Option<DkimKey>,
+    /// ISO 8601 timestamp of when the domain was added.
+    pub created_at: String,
+}
+
+/// The key outgoing mail is signed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DkimKey {
+    /// The signing domain (`d=`).
+    pub domain: String,
+    /// Path of the PEM encoded private key.
+    pub key_path: String,
+    /// The selector of the public key in DNS (`s=`).
+    pub selector: String,
+}
+
+/// Adds a hosted domain, optionally with its own DKIM key and selector.
+pub async fn add(pool: &Pool, name: &str, dkim: Option<(&str, &str)>) -> Result<()> {
+    let name = name.to_lowercase();
+    let (key_path, selector) = dkim.unzip();
+    sqlx::query(
+        "INSERT INTO domains (domain, dkim_key_path, dkim_key_selector, created_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&name)
+    .bind(key_path)
+    .bind(selector)
+    .bind(now_utc_iso8601())
+    .execute(pool)
+    .await
+    .map_err(|e| eyre!("Unable to add domain '{name}': {e}"))?;
+    Ok(())
+}
+
+/// Removes a hosted domain. Returns `false` if it was not hosted.
+pub async fn remove(pool: &Pool, name: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM domains WHERE domain = $1")
+        .bind(name.to_lowercase())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The columns of a `domains` row.
+type DomainRow = (String, Option<String>, Option<String>, String);
+
+/// Returns the domains of the table ordered by name.
+pub async fn list(pool: &Pool) -> Result<Vec<Domain>> {
+    let rows: Vec<DomainRow> = sqlx::query_as(
+        "SELECT domain, dkim_key_path, dkim_key_selector, created_at FROM domains ORDER BY domain",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(Domain::from).collect())
+}
+
+/// Returns a domain of the table.
+pub async fn get(pool: &Pool, name: &str) -> Result<Option<Domain>> {
+    let row: Option<DomainRow> = sqlx::query_as(
+        "SELECT domain, dkim_key_path, dkim_key_selector, created_at FROM domains WHERE domain = $1",
+    )
+    .bind(name.to_lowercase())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(Domain::from))
+}
+
+impl From<DomainRow> for Domain {
+    fn from((name, key_path, selector, created_at): DomainRow) -> Self {
+        Domain {
+            dkim: key_path.zip(selector).map(|(key_path, selector)| DkimKey {
+                domain: name.clone(),
+                key_path,
+                selector,
+            }),
+            name,
+            created_at,
+        }
+    }
+}
+
+/// Whether mail for `domain` is delivered locally.
+pub async fn is_hosted(pool: &Pool, config: &Config, domain: &str) -> Result<bool> {
+    if domain.eq_ignore_ascii_case(&config.mail.hostname) {
+        return Ok(true);
+    }
+    Ok(get(pool, domain).await?.is_some())
+}
+
+/// Whether `address` belongs to a hosted domain.
+pub async fn is_local_address(pool: &Pool, config: &Config, address: &str) -> Result<bool> {
+    match address.rsplit_once('@') {
+        Some((_, domain)) if !domain.is_empty() => is_hosted(pool, config, domain).await,
+        _ => Ok(false),
+    }
+}
+
+/// The key to sign mail from `domain` with: its own one if it has one,
+/// otherwise the key of the config for `mail.hostname`.
+pub async fn dkim_key(pool: &Pool, config: &Config, domain: &str) -> Result<DkimKey> {
+    let own = get(pool, domain).await?.and_then(|domain| domain.dkim);
+    Ok(own.unwrap_or_else(|| DkimKey {
+        domain: config.mail.hostname.clone(),
+        key_path: config.mail.dkim_key_path.clone(),
+        selector: config.mail.dkim_key_selector.clone(),
+    }))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{backend::database::Database, test_helpers::setup_test_database};
+
+    #[tokio::test]
+    async fn hosted_domains_and_their_keys() {
+        let (config, database, _storage) = setup_test_database().await.unwrap();
+        let pool = database.get_pool();
+        add(pool, "Example.com", None).await.unwrap();
+        add(
+            pool,
+            "example.org",
+            Some(("/etc/erooster/org.private", "org")),
+        )
+        .await
+        .unwrap();
+        assert!(add(pool, "example.com", None).await.is_err());
+
+        assert!(is_hosted(pool, &config, "LOCALHOST").await.unwrap());
+        assert!(is_hosted(pool, &config, "example.com").await.unwrap());
+        assert!(is_local_address(pool, &config, "bob@EXAMPLE.org")
+            .await
+            .unwrap());
+        assert!(!is_local_address(pool, &config, "bob@example.net")
+            .await
+            .unwrap());
+        assert!(!is_local_address(pool, &config, "bob").await.unwrap());
+
+        let names: Vec<String> = list(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|d| d.name)
+            .collect();
+        assert_eq!(names, ["example.com", "example.org"]);
+
+        assert_eq!(
+            dkim_key(pool, &config, "example.org").await.unwrap(),
+            DkimKey {
+                domain: String::from("example.org"),
+                key_path: String::from("/etc/erooster/org.private"),
+                selector: String::from("org"),
+            }
+        );
+        let fallback = dkim_key(pool, &config, "example.com").await.unwrap();
+        assert_eq!(fallback.domain, "localhost");
+        assert_eq!(fallback.selector, "default");
+
+        assert!(remove(pool, "EXAMPLE.com").await.unwrap());
+        assert!(!remove(pool, "example.com").await.unwrap());
+        assert!(!is_hosted(pool, &config, "example.com").await.unwrap());
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! In-process notifications about mailbox changes.
+//!
+//! The storage backend publishes a [`MailboxEvent`] every time it stores,
+//! re-flags or expunges a message, and the protocol servers publish one when
+//! a mailbox gets created, deleted or renamed. Long running sessions (IMAP
+//! IDLE and NOTIFY for example) subscribe to the [`EventBus`] to learn about
+//! changes made by other sessions without having to poll the maildir.
+//!
+//! Mailboxes are identified by the same `{username}/{folder}` key that is
+//! used for the `mails` table.
+
+use tokio::sync::broadcast;
+
+/// Number of events a slow subscriber may fall behind before it starts
+/// missing events.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// A change to a single message in a mailbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailboxEvent {
+    /// A new message was stored in the mailbox
+    Exists {
+        /// The mailbox the message was stored in
+        mailbox: String,
+        /// The uid assigned to the message
+        uid: u32,
+    },
+    /// The flags of a message changed
+    FlagsChanged {
+        /// The mailbox the message is in
+        mailbox: String,
+        /// The uid of the message
+        uid: u32,
+        /// The modification sequence assigned by the change
+        modseq: u64,
+        /// The full set of IMAP flags after the change
+        flags: Vec<String>,
+    },
+    /// A message was permanently removed from the mailbox
+    Expunged {
+        /// The mailbox the message was removed from
+        mailbox: String,
+        /// The uid the message had
+        uid: u32,
+    },
+    /// The mailbox was created
+    Created {
+        /// The new mailbox
+        mailbox: String,
+    },
+    /// The mailbox was deleted
+    Deleted {
+        /// The deleted mailbox
+        mailbox: String,
+    },
+    /// The mailbox was renamed
+    Renamed {
+        /// The new name of the mailbox
+        mailbox: String,
+        /// The name the mailbox had before
+        old: String,
+    },
+}
+
+impl MailboxEvent {
+    /// The mailbox the event belongs to
+    ///
+    /// For renames this is the new name.
+    #[must_use]
+    pub fn mailbox(&self) -> &str {
+        match self {
+            MailboxEvent::Exists { mailbox, .. }
+            | MailboxEvent::FlagsChanged { mailbox, .. }
+            | MailboxEvent::Expunged { mailbox, .. }
+            | MailboxEvent::Created { mailbox }
+            | MailboxEvent::Deleted { mailbox }
+            | MailboxEvent::Renamed { mailbox, .. } => mailbox,
+        }
+    }
+}
+
+/// Broadcast bus for [`MailboxEvent`]s
+///
+/// Cloning the bus is cheap and all clones share the same subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<MailboxEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Create a new bus without subscribers
+    #[must_use]
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        EventBus { sender }
+    }
+
+    /// Publish an event to all current subscribers
+    ///
+    /// Events published while nobody is subscribed are dropped.
+    pub fn publish(&self, event: MailboxEvent) {
+        // An error only means there is currently no subscriber
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to all events published after this call
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<MailboxEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventBus, MailboxEvent};
+
+    #[tokio::test]
+    async fn subscribers_receive_events_published_after_subscribing() {
+        let bus = EventBus::new();
+        bus.publish(MailboxEvent::Exists {
+            mailbox: String::from("user/INBOX"),
+            uid: 1,
+        });
+
+        let mut receiver = bus.clone().subscribe();
+        let event = MailboxEvent::Expunged {
+            mailbox: String::from("user/INBOX"),
+            uid: 2,
+        };
+        bus.publish(event.clone());
+
+        assert_eq!(receiver.recv().await.ok(), Some(event));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,686 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Full-text index of the stored messages.
+//!
+//! Without an index SEARCH has to parse every message of a mailbox to find a
+//! string in it. The index splits the decoded text of a message into
+//! trigrams when it is stored, so a search only needs to look at the messages
+//! that contain all trigrams of the string. Those candidates are still
+//! checked against the message itself, which keeps the substring semantics
+//! of IMAP SEARCH.
+//!
+//! Messages are indexed by the [`Indexer`] in the background. A search checks
+//! the messages which are not in the index yet in full.
+
+use color_eyre::eyre::Result;
+use mailparse::ParsedMail;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+};
+use tantivy::{
+    collector::DocSetCollector,
+    directory::MmapDirectory,
+    query::{AllQuery, BooleanQuery, Query, TermQuery},
+    schema::{Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, FAST, STRING},
+    tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer},
+    DocId, Index, IndexReader, IndexWriter, ReloadPolicy, Searcher, TantivyDocument, Term,
+};
+use tokio::sync::oneshot;
+use tracing::error;
+
+/// The name the trigram tokenizer is registered under
+const TRIGRAM_TOKENIZER: &str = "trigram";
+/// The memory a writer may use before it flushes to disk, the minimum of tantivy
+const WRITER_MEMORY: usize = 15_000_000;
+/// How many queued changes the [`Indexer`] commits together at most
+const INDEXER_BATCH: usize = 1000;
+
+/// The parts of a message a search key looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    /// The header fields and the body
+    Text,
+    /// The text parts and the file names of attachments
+    Body,
+    /// The `Subject` header
+    Subject,
+    /// The `From` header
+    From,
+    /// The `To` header
+    To,
+    /// The `Cc` header
+    Cc,
+    /// The `Bcc` header
+    Bcc,
+}
+
+/// The searchable text of a message with the MIME encodings removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageText {
+    /// All header fields with their decoded values
+    pub headers: Vec<(String, String)>,
+    /// The decoded text parts and the file names of the attachments
+    pub body: String,
+}
+
+impl MessageText {
+    /// Extracts the text of a parsed message
+    #[must_use]
+    pub fn from_parsed(mail: &ParsedMail<'_>) -> Self {
+        let headers = mail
+            .headers
+            .iter()
+            .map(|header| (header.get_key(), header.get_value()))
+            .collect();
+        let mut body = String::new();
+        collect_body(mail, &mut body);
+        MessageText { headers, body }
+    }
+
+    /// The values of all header fields called `name`, one per line
+    #[must_use]
+    pub fn header(&self, name: &str) -> String {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// All header fields as `Name: value` lines
+    #[must_use]
+    pub fn header_lines(&self) -> String {
+        self.headers
+            .iter()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Appends the text parts and attachment names of `part` and its subparts
+fn collect_body(part: &ParsedMail<'_>, body: &mut String) {
+    let disposition = part.get_content_disposition();
+    if let Some(filename) = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+    {
+        body.push_str(filename);
+        body.push('\n');
+    }
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            collect_body(subpart, body);
+        }
+    } else if part.ctype.mimetype.starts_with("text/") {
+        if let Ok(text) = part.get_body() {
+            body.push_str(&text);
+            body.push('\n');
+        }
+    }
+}
+
+/// A full-text index of the messages of all users
+///
+/// Messages are identified by their storage id, which stays the same when a
+/// mailbox is renamed. A search therefore returns messages from all mailboxes
+/// of the owner. Changes are only visible to searches after [`commit`].
+///
+/// [`commit`]: FullTextIndex::commit
+pub trait FullTextIndex: Debug + Send + Sync {
+    /// Adds messages of `owner`, given with their storage id
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be written
+    fn add(&self, owner: &str, messages: &[(String, MessageText)]) -> Result<()>;
+    /// Removes the messages with the given storage ids of `owner`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be written
+    fn remove(&self, owner: &str, ids: &[&str]) -> Result<()>;
+    /// Drops all messages of `owner`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be written
+    fn clear(&self, owner: &str) -> Result<()>;
+    /// Makes the changes to the index of `owner` visible to searches
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be written
+    fn commit(&self, owner: &str) -> Result<()>;
+    /// The storage ids of the messages of `owner` which may contain `query` in `field`
+    ///
+    /// Returns `None` if the index can not narrow down the messages, for
+    /// example because the query is too short.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be read
+    fn candidates(
+        &self,
+        owner: &str,
+        field: TextField,
+        query: &str,
+    ) -> Result<Option<HashSet<String>>>;
+    /// The storage ids of all messages of `owner` in the index
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index can not be read
+    fn indexed(&self, owner: &str) -> Result<HashSet<String>>;
+}
+
+/// The index of one owner
+struct OwnerIndex {
+    index: Index,
+    reader: IndexReader,
+    /// Opened on the first change and kept, as tantivy allows only one
+    /// writer per index
+    writer: Mutex<Option<IndexWriter>>,
+}
+
+#[allow(clippy::missing_fields_in_debug)]
+impl Debug for OwnerIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OwnerIndex")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl OwnerIndex {
+    /// Runs `f` with the writer of the index
+    fn write(&self, f: impl FnOnce(&Schema, &IndexWriter) -> Result<()>) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let writer = match &mut *writer {
+            Some(writer) => writer,
+            None => writer.insert(self.index.writer_with_num_threads(1, WRITER_MEMORY)?),
+        };
+        f(&self.index.schema(), writer)
+    }
+}
+
+/// A tantivy index per mailbox owner, kept in a folder below `root`
+#[derive(Debug)]
+pub struct TantivyIndex {
+    root: PathBuf,
+    indexes: Mutex<HashMap<String, Arc<OwnerIndex>>>,
+}
+
+impl TantivyIndex {
+    /// Creates an index that keeps its files in `root`
+    #[must_use]
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        TantivyIndex {
+            root: root.as_ref().to_path_buf(),
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The index of `owner`, opened or created on first use
+    fn owner(&self, owner: &str) -> Result<Arc<OwnerIndex>> {
+        let mut indexes = self.indexes.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = indexes.get(owner) {
+            return Ok(Arc::clone(index));
+        }
+        let path = self.root.join(owner);
+        std::fs::create_dir_all(&path)?;
+        let index = Index::open_or_create(MmapDirectory::open(&path)?, schema())?;
+        index.tokenizers().register(
+            TRIGRAM_TOKENIZER,
+            TextAnalyzer::builder(NgramTokenizer::new(3, 3, false)?)
+                .filter(LowerCaser)
+                .build(),
+        );
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let index = Arc::new(OwnerIndex {
+            index,
+            reader,
+            writer: Mutex::new(None),
+        });
+        indexes.insert(owner.to_string(), Arc::clone(&index));
+        Ok(index)
+    }
+}
+
+/// The fields of the index
+fn schema() -> Schema {
+    let text = TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(TRIGRAM_TOKENIZER)
+            .set_index_option(IndexRecordOption::Basic),
+    );
+    let mut schema = Schema::builder();
+    schema.add_text_field("id", STRING | FAST);
+    for name in ["headers", "body", "subject", "from", "to", "cc", "bcc"] {
+        schema.add_text_field(name, text.clone());
+    }
+    schema.build()
+}
+
+/// The index field holding `field`
+const fn index_field(field: TextField) -> &'static str {
+    match field {
+        TextField::Text | TextField::Body => "body",
+        TextField::Subject => "subject",
+        TextField::From => "from",
+        TextField::To => "to",
+        TextField::Cc => "cc",
+        TextField::Bcc => "bcc",
+    }
+}
+
+/// A query matching documents that contain every trigram of `text` in `field`
+///
+/// Returns `None` if `text` is too short to have a trigram.
+fn trigram_query(index: &Index, field: Field, text: &str) -> Result<Option<Box<dyn Query>>> {
+    let mut analyzer = index.tokenizer_for_field(field)?;
+    let mut stream = analyzer.token_stream(text);
+    let mut trigrams = BTreeSet::new();
+    while stream.advance() {
+        trigrams.insert(stream.token().text.clone());
+    }
+    if trigrams.is_empty() {
+        return Ok(None);
+    }
+    let terms: Vec<Box<dyn Query>> = trigrams
+        .into_iter()
+        .map(|trigram| -> Box<dyn Query> {
+            Box::new(TermQuery::new(
+                Term::from_field_text(field, &trigram),
+                IndexRecordOption::Basic,
+            ))
+        })
+        .collect();
+    Ok(Some(Box::new(BooleanQuery::intersection(terms))))
+}
+
+/// The storage ids of the documents `docs` of the segment `segment`
+fn document_ids(
+    searcher: &Searcher,
+    segment: u32,
+    docs: impl Iterator<Item = DocId>,
+    ids: &mut HashSet<String>,
+) -> Result<()> {
+    let Some(column) = searcher.segment_reader(segment).fast_fields().str("id")? else {
+        return Ok(());
+    };
+    for doc in docs {
+        let mut id = String::new();
+        if let Some(ord) = column.term_ords(doc).next() {
+            if column.ord_to_str(ord, &mut id)? {
+                ids.insert(id);
+            }
+        }
+    }
+    Ok(())
+}
+
+impl FullTextIndex for TantivyIndex {
+    fn add(&self, owner: &str, messages: &[(String, MessageText)]) -> Result<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.owner(owner)?.write(|schema, writer| {
+            let id_field = schema.get_field("id")?;
+            for (id, text) in messages {
+                writer.delete_term(Term::from_field_text(id_field, id));
+                let mut document = TantivyDocument::default();
+                document.add_text(id_field, id);
+                document.add_text(schema.get_field("headers")?, text.header_lines());
+                document.add_text(schema.get_field("body")?, &text.body);
+                for header in ["subject", "from", "to", "cc", "bcc"] {
+                    document.add_text(schema.get_field(header)?, text.header(header));
+                }
+                writer.add_document(document)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove(&self, owner: &str, ids: &[&str]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.owner(owner)?.write(|schema, writer| {
+            let id_field = schema.get_field("id")?;
+            for id in ids {
+                writer.delete_term(Term::from_field_text(id_field, id));
+            }
+            Ok(())
+        })
+    }
+
+    fn clear(&self, owner: &str) -> Result<()> {
+        // Unlike `delete_all_documents` this is ordered with the other changes
+        self.owner(owner)?.write(|_, writer| {
+            writer.delete_query(Box::new(AllQuery))?;
+            Ok(())
+        })
+    }
+
+    fn commit(&self, owner: &str) -> Result<()> {
+        let index = self.owner(owner)?;
+        let mut writer = index.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(writer) = &mut *writer {
+            writer.commit()?;
+            index.reader.reload()?;
+        }
+        Ok(())
+    }
+
+    fn candidates(
+        &self,
+        owner: &str,
+        field: TextField,
+        query: &str,
+    ) -> Result<Option<HashSet<String>>> {
+        let owner = self.owner(owner)?;
+        let index = &owner.index;
+        let schema = index.schema();
+        let query = if field == TextField::Text {
+            let headers = trigram_query(index, schema.get_field("headers")?, query)?;
+            let body = trigram_query(index, schema.get_field("body")?, query)?;
+            headers.zip(body).map(|(headers, body)| -> Box<dyn Query> {
+                Box::new(BooleanQuery::union(vec![headers, body]))
+            })
+        } else {
+            trigram_query(index, schema.get_field(index_field(field))?, query)?
+        };
+        let Some(query) = query else {
+            return Ok(None);
+        };
+
+        let searcher = owner.reader.searcher();
+        let mut by_segment: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for address in searcher.search(&query, &DocSetCollector)? {
+            by_segment
+                .entry(address.segment_ord)
+                .or_default()
+                .push(address.doc_id);
+        }
+        let mut ids = HashSet::new();
+        for (segment, docs) in by_segment {
+            document_ids(&searcher, segment, docs.into_iter(), &mut ids)?;
+        }
+        Ok(Some(ids))
+    }
+
+    fn indexed(&self, owner: &str) -> Result<HashSet<String>> {
+        let searcher = self.owner(owner)?.reader.searcher();
+        let mut ids = HashSet::new();
+        for (segment, reader) in (0..).zip(searcher.segment_readers()) {
+            document_ids(&searcher, segment, reader.doc_ids_alive(), &mut ids)?;
+        }
+        Ok(ids)
+    }
+}
+
+/// A change for the [`Indexer`] thread
+#[derive(Debug)]
+enum Job {
+    Add {
+        owner: String,
+        id: String,
+        data: Vec<u8>,
+    },
+    Remove {
+        owner: String,
+        id: String,
+    },
+    Clear {
+        owner: String,
+    },
+    Flush(oneshot::Sender<()>),
+}
+
+/// Applies changes to a [`FullTextIndex`] on a background thread
+///
+/// Storing a message only queues it. The thread parses the queued messages
+/// and commits everything that was queued at the same time at once, so
+/// indexing neither blocks the async tasks nor commits once per message.
+#[derive(Debug, Clone)]
+pub struct Indexer {
+    index: Arc<dyn FullTextIndex>,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Indexer {
+    /// Starts the thread which writes to `index`
+    ///
+    /// The thread stops once all clones of the indexer are dropped.
+    #[must_use]
+    pub fn new(index: Arc<dyn FullTextIndex>) -> Self {
+        let (jobs, queue) = mpsc::channel();
+        let writer = Arc::clone(&index);
+        std::thread::spawn(move || run_jobs(&*writer, &queue));
+        Indexer { index, jobs }
+    }
+
+    /// The index the changes are written to
+    #[must_use]
+    pub fn index(&self) -> &dyn FullTextIndex {
+        &*self.index
+    }
+
+    /// Queues the message `data` of `owner` with the storage id `id`
+    pub fn add(&self, owner: &str, id: &str, data: &[u8]) {
+        self.queue(Job::Add {
+            owner: owner.to_string(),
+            id: id.to_string(),
+            data: data.to_vec(),
+        });
+    }
+
+    /// Queues the removal of the message of `owner` with the storage id `id`
+    pub fn remove(&self, owner: &str, id: &str) {
+        self.queue(Job::Remove {
+            owner: owner.to_string(),
+            id: id.to_string(),
+        });
+    }
+
+    /// Queues dropping all messages of `owner`
+    pub fn clear(&self, owner: &str) {
+        self.queue(Job::Clear {
+            owner: owner.to_string(),
+        });
+    }
+
+    /// Waits until all changes queued before are committed
+    pub async fn flush(&self) {
+        let (done, committed) = oneshot::channel();
+        self.queue(Job::Flush(done));
+        // An error means the thread is gone and nothing is left to wait for
+        let _ = committed.await;
+    }
+
+    fn queue(&self, job: Job) {
+        if self.jobs.send(job).is_err() {
+            error!("The search indexer stopped, the index can be rebuilt with `eroosterctl mailbox reindex`");
+        }
+    }
+}
+
+/// Applies the queued jobs to `index` until all senders are dropped
+fn run_jobs(index: &dyn FullTextIndex, queue: &mpsc::Receiver<Job>) {
+    while let Ok(job) = queue.recv() {
+        let mut changed = BTreeSet::new();
+        let mut flushed = Vec::new();
+        let batch = std::iter::once(job).chain(std::iter::from_fn(|| queue.try_recv().ok()));
+        for job in batch.take(INDEXER_BATCH) {
+            let result = match job {
+                Job::Add { owner, id, data } => {
+                    let result = mailparse::parse_mail(&data)
+                        .map_err(Into::into)
+                        .and_then(|mail| {
+                            index.add(&owner, &[(id.clone(), MessageText::from_parsed(&mail))])
+                        })
+                        .map_err(|e| e.wrap_err(format!("Failed to index message {id}")));
+                    changed.insert(owner);
+                    result
+                }
+                Job::Remove { owner, id } => {
+                    let result = index.remove(&owner, &[&id]).map_err(|e| {
+                        e.wrap_err(format!("Failed to remove message {id} from the index"))
+                    });
+                    changed.insert(owner);
+                    result
+                }
+                Job::Clear { owner } => {
+                    let result = index.clear(&owner);
+                    changed.insert(owner);
+                    result
+                }
+                Job::Flush(done) => {
+                    flushed.push(done);
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                error!("{e:?}");
+            }
+        }
+        for owner in changed {
+            if let Err(e) = index.commit(&owner) {
+                error!("Failed to commit the search index of {owner}: {e}");
+            }
+        }
+        for done in flushed {
+            let _ = done.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{FullTextIndex, Indexer, MessageText, TantivyIndex, TextField};
+    use std::{collections::HashSet, sync::Arc};
+
+    fn text(message: &str) -> MessageText {
+        MessageText::from_parsed(&mailparse::parse_mail(message.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn message_text_is_decoded() {
+        let message = text(concat!(
+            "Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n",
+            "Content-Type: multipart/mixed; boundary=b\r\n",
+            "\r\n",
+            "--b\r\n",
+            "Content-Type: text/plain\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "SGVsbG8gd29ybGQ=\r\n",
+            "--b\r\n",
+            "Content-Type: application/pdf\r\n",
+            "Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n",
+            "\r\n",
+            "%PDF\r\n",
+            "--b--\r\n",
+        ));
+        assert_eq!(message.header("subject"), "Grüße");
+        assert!(message.body.contains("Hello world"));
+        assert!(message.body.contains("invoice.pdf"));
+        assert!(!message.body.contains("%PDF"));
+    }
+
+    #[test]
+    fn candidates_contain_all_trigrams() {
+        let root = format!("/tmp/erooster-fulltext-{}", uuid::Uuid::new_v4().simple());
+        let index = TantivyIndex::new(&root);
+        let owner = "alice@localhost";
+        let message = |id: &str, message| (id.to_string(), text(message));
+        index
+            .add(
+                owner,
+                &[
+                    message("1", "Subject: Banana bread\r\n\r\nRecipe attached\r\n"),
+                    message("2", "Subject: Apples\r\n\r\nNo bananas here\r\n"),
+                ],
+            )
+            .unwrap();
+        index
+            .add("bob@localhost", &[message("3", "Subject: Banana\r\n\r\n")])
+            .unwrap();
+        assert!(index.indexed(owner).unwrap().is_empty());
+        index.commit(owner).unwrap();
+        index.commit("bob@localhost").unwrap();
+        assert_eq!(
+            index.indexed(owner).unwrap(),
+            HashSet::from([String::from("1"), String::from("2")])
+        );
+
+        let candidates = |field, query| {
+            index.candidates(owner, field, query).unwrap().map(|ids| {
+                let mut ids: Vec<String> = ids.into_iter().collect();
+                ids.sort();
+                ids
+            })
+        };
+        assert_eq!(
+            candidates(TextField::Subject, "ANANA"),
+            Some(vec![String::from("1")])
+        );
+        assert_eq!(
+            candidates(TextField::Body, "banana"),
+            Some(vec![String::from("2")])
+        );
+        assert_eq!(
+            candidates(TextField::Text, "banana"),
+            Some(vec![String::from("1"), String::from("2")])
+        );
+        assert_eq!(candidates(TextField::From, "banana"), Some(vec![]));
+        assert_eq!(candidates(TextField::Text, "ba"), None);
+
+        index.remove(owner, &["1"]).unwrap();
+        index.commit(owner).unwrap();
+        assert_eq!(
+            candidates(TextField::Text, "banana"),
+            Some(vec![String::from("2")])
+        );
+        index.clear(owner).unwrap();
+        index.commit(owner).unwrap();
+        assert_eq!(candidates(TextField::Text, "banana"), Some(vec![]));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn indexer_commits_queued_changes() {
+        let root = format!("/tmp/erooster-fulltext-{}", uuid::Uuid::new_v4().simple());
+        let indexer = Indexer::new(Arc::new(TantivyIndex::new(&root)));
+        let owner = "alice@localhost";
+        indexer.add(owner, "1", b"Subject: Banana bread\r\n\r\n");
+        indexer.add(owner, "2", b"Subject: Apples\r\n\r\n");
+        indexer.remove(owner, "2");
+        indexer.flush().await;
+        assert_eq!(
+            indexer.index().indexed(owner).unwrap(),
+            HashSet::from([String::from("1")])
+        );
+
+        indexer.clear(owner);
+        indexer.add(owner, "3", b"Subject: Cherries\r\n\r\n");
+        indexer.flush().await;
+        assert_eq!(
+            indexer.index().indexed(owner).unwrap(),
+            HashSet::from([String::from("3")])
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Server and mailbox annotations (RFC 5464).
+//!
+//! Entries below `/private/` are only visible to the user who set them,
+//! entries below `/shared/` to everybody who has access to the mailbox.
+//! Annotations of the server itself use no mailbox at all.
+
+/// The largest value accepted for a single entry in bytes
+pub const MAX_VALUE_SIZE: usize = 64 * 1024;
+/// The most entries a user may keep on a single mailbox or the server,
+/// counting the private and the shared entries separately
+pub const MAX_ENTRIES: usize = 100;
+
+/// Who can see an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Only the user who set the entry
+    Private,
+    /// Everybody with access to the mailbox
+    Shared,
+}
+
+/// A validated entry name like `/private/comment`, always in lower case
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntryName(String);
+
+impl EntryName {
+    /// Parses an entry name (RFC 5464 §3.2).
+    ///
+    /// Returns `None` if the name is outside of `/private` and `/shared`,
+    /// contains wildcards, empty components or non printable characters.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let valid = !name.ends_with('/')
+            && !name.contains("//")
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_graphic() && b != b'*' && b != b'%');
+        let scoped = name.starts_with("/private/") || name.starts_with("/shared/");
+        (valid && scoped).then_some(EntryName(name))
+    }
+
+    /// Whether the entry is private or shared
+    #[must_use]
+    pub fn scope(&self) -> Scope {
+        if self.0.starts_with("/private/") {
+            Scope::Private
+        } else {
+            Scope::Shared
+        }
+    }
+
+    /// The entry name
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this entry is `root` or below it by at most `depth` levels.
+    ///
+    /// `root` is compared case insensitive and may be one of the bare
+    /// `/private` and `/shared` prefixes. `None` stands for an infinite depth.
+    #[must_use]
+    pub fn is_within(&self, root: &str, depth: Option<usize>) -> bool {
+        let root = root.to_ascii_lowercase();
+        if self.0 == root {
+            return true;
+        }
+        let Some(rest) = self
+            .0
+            .strip_prefix(&root)
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            return false;
+        };
+        depth.is_none_or(|depth| rest.split('/').count() <= depth)
+    }
+}
+
+/// A single annotation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataEntry {
+    /// The name of the entry
+    pub name: EntryName,
+    /// The value of the entry
+    pub value: String,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::{EntryName, Scope};
+
+    #[test]
+    fn entry_names_are_validated() {
+        let comment = EntryName::parse("/Private/Comment").unwrap();
+        assert_eq!(comment.as_str(), "/private/comment");
+        assert_eq!(comment.scope(), Scope::Private);
+        assert_eq!(
+            EntryName::parse("/shared/admin").map(|name| name.scope()),
+            Some(Scope::Shared)
+        );
+        assert_eq!(EntryName::parse("/comment"), None);
+        assert_eq!(EntryName::parse("/private/"), None);
+        assert_eq!(EntryName::parse("/private//comment"), None);
+        assert_eq!(EntryName::parse("/private/*"), None);
+        assert_eq!(EntryName::parse("/private/a b"), None);
+    }
+
+    #[test]
+    fn depth_limits_descendants() {
+        let parse = |name| EntryName::parse(name).unwrap();
+        let root = "/private/Vendor";
+        let child = parse("/private/vendor/client");
+        let grandchild = parse("/private/vendor/client/setting");
+        assert!(parse(root).is_within(root, Some(0)));
+        assert!(!child.is_within(root, Some(0)));
+        assert!(child.is_within(root, Some(1)));
+        assert!(!grandchild.is_within(root, Some(1)));
+        assert!(grandchild.is_within(root, None));
+        assert!(grandchild.is_within("/private", None));
+        assert!(!parse("/private/vendors").is_within(root, None));
+    }
+}
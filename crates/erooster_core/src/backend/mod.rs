@@ -2,14 +2,41 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+/// Access control lists for mailboxes
+pub mod acl;
+
+/// Address aliases, catch-alls and forwarding
+pub mod aliases;
+
+/// Application-specific passwords of the users
+pub mod app_passwords;
+
 /// Admin-facing queries for eroosterctl
 pub mod admin;
 
 /// The database logic of the server
 pub mod database;
 
+/// The hosted mail domains
+pub mod domains;
+
+/// In-process notifications about mailbox changes
+pub mod events;
+
+/// Full-text index of the stored messages
+pub mod fulltext;
+
+/// Server and mailbox annotations
+pub mod metadata;
+
+/// Storage and message count quotas
+pub mod quota;
+
 /// Persistent outbound mail queue
 pub mod queue;
 
+/// The client connections of the running servers
+pub mod sessions;
+
 /// The logic for the mail storages
 pub mod storage;
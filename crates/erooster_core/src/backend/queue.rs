@@ -12,6 +12,8 @@
 //!   see exactly what happened.
 //! - **Manual control**: `force_retry` resets an entry so the worker picks it
 //!   up again immediately; `abandon` marks an entry as permanently dead.
+//! - **Per-recipient state**: `outbound_recipients` records which recipients
+//!   got the message or rejected it for good, so retries only go to the rest.
 //! - **Retry schedule** (loosely following RFC 5321 §4.5.4):
 //!   attempt 0 → immediate, 1 → 5 min, 2 → 15 min, 3 → 1 h,
 //!   4–8 → 4 h each, ≥ MAX_ATTEMPTS → abandoned automatically.
@@ -68,6 +70,56 @@ impl From<&str> for QueueStatus {
     }
 }
 
+/// Delivery state of one recipient of a queue entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipientStatus {
+    /// Not delivered yet; retried with the entry.
+    Pending,
+    /// Accepted by the recipient's server.
+    Delivered,
+    /// Rejected permanently; never retried.
+    Failed,
+}
+
+impl RecipientStatus {
+    /// Returns the status as a lowercase string for database storage.
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl std::fmt::Display for RecipientStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for RecipientStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "delivered" => Self::Delivered,
+            "failed" => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+/// One recipient of a queue entry.
+#[derive(Debug, Clone)]
+pub struct QueueRecipient {
+    /// The recipient address.
+    pub address: String,
+    /// Its delivery state.
+    pub status: RecipientStatus,
+    /// The last reply of the recipient's server, if any.
+    pub last_reply: Option<String>,
+}
+
 /// One entry in the per-attempt delivery history.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(crate = "serde")]
@@ -139,6 +191,14 @@ fn parse_delivery_log(raw: &str) -> Vec<DeliveryAttempt> {
     serde_json::from_str(raw).unwrap_or_default()
 }
 
+/// The addresses of the comma-separated `to_addrs` of an entry.
+fn split_addrs(to_addrs: &str) -> impl Iterator<Item = &str> {
+    to_addrs
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+}
+
 // ---------------------------------------------------------------------------
 // Postgres implementation
 // ---------------------------------------------------------------------------
@@ -147,8 +207,8 @@ fn parse_delivery_log(raw: &str) -> Vec<DeliveryAttempt> {
 #[cfg(feature = "postgres")]
 pub mod postgres {
     use super::{
-        now_utc_iso8601, parse_delivery_log, retry_delay_secs, DeliveryAttempt, QueueEntry,
-        QueueStatus, Result, MAX_ATTEMPTS,
+        now_utc_iso8601, parse_delivery_log, retry_delay_secs, split_addrs, DeliveryAttempt,
+        QueueEntry, QueueRecipient, QueueStatus, RecipientStatus, Result, MAX_ATTEMPTS,
     };
     use sqlx::PgPool;
     use {serde_json, tracing::instrument, uuid::Uuid};
@@ -164,7 +224,8 @@ pub mod postgres {
         String,
     );
 
-    /// Insert a new entry into the queue.
+    /// Insert a new entry into the queue, with each of the comma-separated
+    /// `to_addrs` as a pending recipient.
     #[instrument(skip(pool, payload_json))]
     pub async fn push(
         pool: &PgPool,
@@ -173,6 +234,7 @@ pub mod postgres {
         from_addr: &str,
         to_addrs: &str,
     ) -> Result<()> {
+        let mut tx = pool.begin().await?;
         sqlx::query(
             "INSERT INTO outbound_queue (id, payload, from_addr, to_addrs) \
              VALUES ($1, $2::jsonb, $3, $4)",
@@ -181,8 +243,20 @@ pub mod postgres {
         .bind(payload_json)
         .bind(from_addr)
         .bind(to_addrs)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+        for address in split_addrs(to_addrs) {
+            sqlx::query(
+                "INSERT INTO outbound_recipients (queue_id, address, updated_at) \
+                 VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            )
+            .bind(id)
+            .bind(address)
+            .bind(now_utc_iso8601())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
@@ -305,6 +379,53 @@ pub mod postgres {
         Ok(())
     }
 
+    /// The recipients of an entry ordered by address. Entries queued before
+    /// recipients were tracked have none.
+    #[instrument(skip(pool))]
+    pub async fn recipients(pool: &PgPool, id: &str) -> Result<Vec<QueueRecipient>> {
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT address, status, last_reply FROM outbound_recipients \
+             WHERE queue_id = $1::uuid ORDER BY address",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(address, status, last_reply)| QueueRecipient {
+                address,
+                status: RecipientStatus::from(status.as_str()),
+                last_reply,
+            })
+            .collect())
+    }
+
+    /// Record the outcome of a delivery attempt for one recipient of an entry.
+    #[instrument(skip(pool, reply))]
+    pub async fn set_recipient(
+        pool: &PgPool,
+        id: &str,
+        address: &str,
+        status: &RecipientStatus,
+        reply: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO outbound_recipients (queue_id, address, status, last_reply, updated_at) \
+             VALUES ($1::uuid, $2, $3, $4, $5) \
+             ON CONFLICT (queue_id, address) DO UPDATE \
+             SET status = excluded.status, last_reply = excluded.last_reply, \
+                 updated_at = excluded.updated_at",
+        )
+        .bind(id)
+        .bind(address)
+        .bind(status.as_str())
+        .bind(reply)
+        .bind(now_utc_iso8601())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
     /// Reset a failed/abandoned entry for immediate redelivery (postmaster manual retry).
     #[instrument(skip(pool))]
     pub async fn force_retry(pool: &PgPool, id: &str) -> Result<()> {
@@ -397,8 +518,8 @@ pub mod postgres {
 #[cfg(feature = "sqlite")]
 pub mod sqlite {
     use super::{
-        now_utc_iso8601, parse_delivery_log, retry_delay_secs, DeliveryAttempt, QueueEntry,
-        QueueStatus, Result, MAX_ATTEMPTS,
+        now_utc_iso8601, parse_delivery_log, retry_delay_secs, split_addrs, DeliveryAttempt,
+        QueueEntry, QueueRecipient, QueueStatus, RecipientStatus, Result, MAX_ATTEMPTS,
     };
     use sqlx::SqlitePool;
     use {serde_json, tracing::instrument, uuid::Uuid};
@@ -414,7 +535,8 @@ pub mod sqlite {
         String,
     );
 
-    /// Insert a new entry into the queue.
+    /// Insert a new entry into the queue, with each of the comma-separated
+    /// `to_addrs` as a pending recipient.
     #[instrument(skip(pool, payload_json))]
     pub async fn push(
         pool: &SqlitePool,
@@ -423,6 +545,7 @@ pub mod sqlite {
         from_addr: &str,
         to_addrs: &str,
     ) -> Result<()> {
+        let mut tx = pool.begin().await?;
         sqlx::query(
             "INSERT INTO outbound_queue (id, payload, from_addr, to_addrs) VALUES ($1, $2, $3, $4)",
         )
@@ -430,8 +553,20 @@ pub mod sqlite {
         .bind(payload_json)
         .bind(from_addr)
         .bind(to_addrs)
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
+        for address in split_addrs(to_addrs) {
+            sqlx::query(
+                "INSERT INTO outbound_recipients (queue_id, address, updated_at) \
+                 VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            )
+            .bind(id.to_string())
+            .bind(address)
+            .bind(now_utc_iso8601())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 
@@ -558,6 +693,53 @@ pub mod sqlite {
         Ok(())
     }
 
+    /// The recipients of an entry ordered by address. Entries queued before
+    /// recipients were tracked have none.
+    #[instrument(skip(pool))]
+    pub async fn recipients(pool: &SqlitePool, id: &str) -> Result<Vec<QueueRecipient>> {
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT address, status, last_reply FROM outbound_recipients \
+             WHERE queue_id = $1 ORDER BY address",
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(address, status, last_reply)| QueueRecipient {
+                address,
+                status: RecipientStatus::from(status.as_str()),
+                last_reply,
+            })
+            .collect())
+    }
+
+    /// Record the outcome of a delivery attempt for one recipient of an entry.
+    #[instrument(skip(pool, reply))]
+    pub async fn set_recipient(
+        pool: &SqlitePool,
+        id: &str,
+        address: &str,
+        status: &RecipientStatus,
+        reply: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO outbound_recipients (queue_id, address, status, last_reply, updated_at) \
+             VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (queue_id, address) DO UPDATE \
+             SET status = excluded.status, last_reply = excluded.last_reply, \
+                 updated_at = excluded.updated_at",
+        )
+        .bind(id)
+        .bind(address)
+        .bind(status.as_str())
+        .bind(reply)
+        .bind(now_utc_iso8601())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
     /// Reset a failed/abandoned entry for immediate redelivery.
     #[instrument(skip(pool))]
     pub async fn force_retry(pool: &SqlitePool, id: &str) -> Result<()> {
@@ -639,7 +821,95 @@ pub mod sqlite {
 // ---------------------------------------------------------------------------
 
 #[cfg(feature = "postgres")]
-pub use postgres::{abandon, ack, force_retry, list_all, nack, pop, push, push_local};
+pub use postgres::{
+    abandon, ack, force_retry, list_all, nack, pop, push, push_local, recipients, set_recipient,
+};
 
 #[cfg(all(feature = "sqlite", not(feature = "postgres")))]
-pub use sqlite::{abandon, ack, force_retry, list_all, nack, pop, push, push_local};
+pub use sqlite::{
+    abandon, ack, force_retry, list_all, nack, pop, push, push_local, recipients, set_recipient,
+};
+
+#[cfg(all(test, feature = "sqlite"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{backend::database::Database, test_helpers::setup_test_database};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn recipients_are_tracked_per_entry() {
+        let (_config, database, _storage) = setup_test_database().await.unwrap();
+        let pool = database.get_pool();
+        let id = Uuid::new_v4();
+        push(
+            pool,
+            id,
+            "{}".to_string(),
+            "alice@localhost",
+            "bob@example.com, carol@example.org",
+        )
+        .await
+        .unwrap();
+        let id = id.to_string();
+
+        let status = |recipients: &[QueueRecipient]| {
+            recipients
+                .iter()
+                .map(|r| (r.address.clone(), r.status.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            status(&recipients(pool, &id).await.unwrap()),
+            [
+                ("bob@example.com".to_string(), RecipientStatus::Pending),
+                ("carol@example.org".to_string(), RecipientStatus::Pending),
+            ]
+        );
+
+        set_recipient(
+            pool,
+            &id,
+            "bob@example.com",
+            &RecipientStatus::Delivered,
+            None,
+        )
+        .await
+        .unwrap();
+        set_recipient(
+            pool,
+            &id,
+            "carol@example.org",
+            &RecipientStatus::Failed,
+            Some("550 5.1.1 No such user"),
+        )
+        .await
+        .unwrap();
+        // Entries queued before recipients were tracked get them on the way
+        set_recipient(
+            pool,
+            &id,
+            "dave@example.net",
+            &RecipientStatus::Pending,
+            None,
+        )
+        .await
+        .unwrap();
+        let tracked = recipients(pool, &id).await.unwrap();
+        assert_eq!(
+            status(&tracked),
+            [
+                ("bob@example.com".to_string(), RecipientStatus::Delivered),
+                ("carol@example.org".to_string(), RecipientStatus::Failed),
+                ("dave@example.net".to_string(), RecipientStatus::Pending),
+            ]
+        );
+        assert_eq!(
+            tracked[1].last_reply.as_deref(),
+            Some("550 5.1.1 No such user")
+        );
+
+        ack(pool, &id).await.unwrap();
+        assert!(recipients(pool, &id).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage and message count quotas.
+//!
+//! Limits are stored per user in the `user_quotas` table. Users without an
+//! own row fall back to the default of their domain in `domain_quotas`.
+//! Usage is the sum of the sizes of all messages in all mailboxes of a user.
+
+/// The limits that apply to a user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum number of octets all messages may use together
+    pub storage: Option<u64>,
+    /// Maximum number of messages
+    pub messages: Option<u64>,
+}
+
+/// The resources a user currently uses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Octets used by all messages
+    pub storage: u64,
+    /// Number of messages
+    pub messages: u64,
+}
+
+impl QuotaUsage {
+    /// The usage of a single message of `size` octets
+    #[must_use]
+    pub const fn message(size: u64) -> Self {
+        QuotaUsage {
+            storage: size,
+            messages: 1,
+        }
+    }
+}
+
+impl Quota {
+    /// Whether neither resource is limited
+    #[must_use]
+    pub const fn is_unlimited(&self) -> bool {
+        self.storage.is_none() && self.messages.is_none()
+    }
+
+    /// Whether `additional` still fits into the quota on top of `usage`
+    #[must_use]
+    pub fn allows(&self, usage: &QuotaUsage, additional: &QuotaUsage) -> bool {
+        let storage_ok = self
+            .storage
+            .is_none_or(|limit| usage.storage.saturating_add(additional.storage) <= limit);
+        let messages_ok = self
+            .messages
+            .is_none_or(|limit| usage.messages.saturating_add(additional.messages) <= limit);
+        storage_ok && messages_ok
+    }
+}
+
+/// Converts a limit from the database, where `NULL` means unlimited
+pub(crate) fn limit_from_db(limit: Option<i64>) -> Option<u64> {
+    limit.map(|limit| u64::try_from(limit).unwrap_or(0))
+}
+
+/// Converts a limit to its database representation
+pub(crate) fn limit_to_db(limit: Option<u64>) -> Option<i64> {
+    limit.map(|limit| i64::try_from(limit).unwrap_or(i64::MAX))
+}
+
+/// The domain part of a username, used to look up the domain default
+#[must_use]
+pub fn domain_of(username: &str) -> Option<&str> {
+    username
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|domain| !domain.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{domain_of, Quota, QuotaUsage};
+
+    #[test]
+    fn quota_limits_storage_and_messages() {
+        let usage = QuotaUsage {
+            storage: 900,
+            messages: 9,
+        };
+        assert!(Quota::default().allows(&usage, &QuotaUsage::message(u64::MAX)));
+        let quota = Quota {
+            storage: Some(1000),
+            messages: Some(10),
+        };
+        assert!(quota.allows(&usage, &QuotaUsage::message(100)));
+        assert!(!quota.allows(&usage, &QuotaUsage::message(101)));
+        let full = QuotaUsage {
+            storage: 0,
+            messages: 10,
+        };
+        assert!(!quota.allows(&full, &QuotaUsage::message(1)));
+        assert!(quota.allows(&full, &QuotaUsage::default()));
+    }
+
+    #[test]
+    fn domain_is_taken_from_the_address() {
+        assert_eq!(domain_of("alice@example.com"), Some("example.com"));
+        assert_eq!(domain_of("alice"), None);
+        assert_eq!(domain_of("alice@"), None);
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The client connections of the running servers.
+//!
+//! Every server registers its connections here so `eroosterctl session list`
+//! can show who is connected with which client. The rows only live as long as
+//! the connection, a server removes the leftovers of a previous run on start.
+
+use crate::backend::queue::now_utc_iso8601;
+use color_eyre::eyre::Result;
+use sqlx::Row;
+use std::collections::BTreeMap;
+
+#[cfg(feature = "postgres")]
+use sqlx::PgPool as Pool;
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool as Pool;
+
+/// A single client connection.
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// UUID of the connection, also recorded in the tracing span.
+    pub id: String,
+    /// The protocol spoken on the connection, e.g. `imap`.
+    pub protocol: String,
+    /// Address and port of the client.
+    pub peer: String,
+    /// The authenticated user, if any.
+    pub username: Option<String>,
+    /// The fields the client sent with the IMAP ID command (RFC 2971).
+    pub client_id: BTreeMap<String, String>,
+    /// ISO 8601 timestamp of the connection start.
+    pub started_at: String,
+}
+
+/// Registers a new connection.
+pub async fn open(pool: &Pool, id: &str, protocol: &str, peer: &str) -> Result<()> {
+    sqlx::query("INSERT INTO sessions (id, protocol, peer, started_at) VALUES ($1, $2, $3, $4)")
+        .bind(id)
+        .bind(protocol)
+        .bind(peer)
+        .bind(now_utc_iso8601())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Records the authenticated user and the ID fields of a connection.
+pub async fn update(
+    pool: &Pool,
+    id: &str,
+    username: Option<&str>,
+    client_id: &BTreeMap<String, String>,
+) -> Result<()> {
+    let client_id = if client_id.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(client_id)?)
+    };
+    sqlx::query("UPDATE sessions SET username = $1, client_id = $2 WHERE id = $3")
+        .bind(username)
+        .bind(client_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Removes a closed connection.
+pub async fn close(pool: &Pool, id: &str) -> Result<()> {
+    sqlx::query("DELETE FROM sessions WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Removes all connections of `protocol` which a previous run left behind.
+pub async fn clear(pool: &Pool, protocol: &str) -> Result<()> {
+    sqlx::query("DELETE FROM sessions WHERE protocol = $1")
+        .bind(protocol)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Returns all open connections, oldest first.
+pub async fn list(pool: &Pool) -> Result<Vec<Session>> {
+    let rows = sqlx::query(
+        "SELECT id, protocol, peer, username, client_id, started_at FROM sessions ORDER BY started_at, id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| Session {
+            id: r.get("id"),
+            protocol: r.get("protocol"),
+            peer: r.get("peer"),
+            username: r.get("username"),
+            client_id: r
+                .get::<Option<String>, _>("client_id")
+                .and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or_default(),
+            started_at: r.get("started_at"),
+        })
+        .collect())
+}
+
+/// The ID fields as `name/version` followed by the other fields.
+#[must_use]
+pub fn client_description(client_id: &BTreeMap<String, String>) -> String {
+    let mut description = match (client_id.get("name"), client_id.get("version")) {
+        (Some(name), Some(version)) => format!("{name}/{version}"),
+        (Some(name), None) => name.clone(),
+        _ => String::new(),
+    };
+    for (field, value) in client_id {
+        if field == "name" || field == "version" {
+            continue;
+        }
+        if !description.is_empty() {
+            description.push(' ');
+        }
+        description = format!("{description}{field}={value}");
+    }
+    description
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{backend::database::Database, test_helpers::setup_test_database};
+
+    #[tokio::test]
+    async fn sessions_are_listed_until_closed() {
+        let (_config, database, _storage) = setup_test_database().await.unwrap();
+        let pool = database.get_pool();
+        open(pool, "a", "imap", "127.0.0.1:1234").await.unwrap();
+        open(pool, "b", "imap", "127.0.0.1:1235").await.unwrap();
+        let client_id = BTreeMap::from([
+            (String::from("name"), String::from("Thunderbird")),
+            (String::from("version"), String::from("128.0")),
+        ]);
+        update(pool, "a", Some("alice@localhost"), &client_id)
+            .await
+            .unwrap();
+
+        let sessions = list(pool).await.unwrap();
+        let a = sessions.iter().find(|session| session.id == "a").unwrap();
+        assert_eq!(a.username.as_deref(), Some("alice@localhost"));
+        assert_eq!(a.client_id, client_id);
+
+        close(pool, "a").await.unwrap();
+        let sessions = list(pool).await.unwrap();
+        assert!(sessions.iter().all(|session| session.id != "a"));
+        clear(pool, "imap").await.unwrap();
+        assert!(list(pool).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn client_description_starts_with_name_and_version() {
+        let client_id = BTreeMap::from([
+            (String::from("name"), String::from("Thunderbird")),
+            (String::from("os"), String::from("Linux")),
+            (String::from("version"), String::from("128.0")),
+        ]);
+        assert_eq!(client_description(&client_id), "Thunderbird/128.0 os=Linux");
+        assert_eq!(
+            client_description(&BTreeMap::from([(
+                String::from("vendor"),
+                String::from("Example")
+            )])),
+            "vendor=Example"
+        );
+    }
+}
@@ -4,15 +4,28 @@
 
 use crate::{
     backend::{
+        acl::{effective_rights, AclEntry, MailboxName, Rights, ANYONE},
         database::{Database, DB},
+        events::{EventBus, MailboxEvent},
+        fulltext::{Indexer, MessageText, TantivyIndex, TextField},
+        metadata::{EntryName, MetadataEntry, Scope},
+        quota::QuotaUsage,
         storage::{MailEntry, MailState, MailStorage},
     },
     config::Config,
 };
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use {
-    color_eyre,
+    color_eyre::{
+        self,
+        eyre::{bail, ContextCompat},
+    },
     futures::{StreamExt, TryStreamExt},
     maildir::{self, Maildir},
     mailparse::{self, ParsedMail},
@@ -24,13 +37,23 @@ use {
     },
     tokio_stream::wrappers::LinesStream,
     tracing::{debug, error, instrument},
+    uuid::Uuid,
 };
 
+/// How many messages `reindex` queues for the full-text index before it waits for them
+const REINDEX_BATCH: usize = 500;
+
+/// Allocates the next modification sequence of a mailbox. A mailbox without
+/// a counter row reports 1, so the first change gets 2.
+const NEXT_MODSEQ: &str = "INSERT INTO mailbox_modseq_counter (mailbox, modseq) VALUES ($1, 2) ON CONFLICT (mailbox) DO UPDATE SET modseq = mailbox_modseq_counter.modseq + 1 RETURNING modseq";
+
 /// The Storage handler for the maildir format
 #[derive(Debug, Clone)]
 pub struct MaildirStorage {
     db: DB,
     config: Config,
+    events: EventBus,
+    index: Option<Indexer>,
 }
 
 impl MaildirStorage {
@@ -38,8 +61,344 @@ impl MaildirStorage {
     #[must_use]
     #[instrument(skip(db))]
     pub fn new(db: DB, config: Config) -> Self {
-        MaildirStorage { db, config }
+        let index = config
+            .search_index
+            .as_ref()
+            .map(|search_index| Indexer::new(Arc::new(TantivyIndex::new(&search_index.path))));
+        MaildirStorage {
+            db,
+            config,
+            events: EventBus::new(),
+            index,
+        }
+    }
+
+    /// Queues a newly stored message for the full-text index.
+    ///
+    /// Failures are only logged as the message itself was stored, the index
+    /// can be rebuilt with `eroosterctl mailbox reindex`.
+    #[instrument(skip(self, data))]
+    fn index_message(&self, mailbox: &str, id: &str, data: &[u8]) {
+        let (Some(index), Some(mailbox)) = (&self.index, MailboxName::from_id(mailbox)) else {
+            return;
+        };
+        index.add(&mailbox.owner, id, data);
+    }
+
+    /// Allocates the next modification sequence for the mailbox
+    #[instrument(skip(self))]
+    /// The Message-ID of a new message and the THREADID it belongs to
+    ///
+    /// A reply joins the thread of the first message of the same owner it
+    /// references, every other message starts a new thread.
+    async fn thread_of(
+        &self,
+        mailbox: &str,
+        data: &[u8],
+    ) -> color_eyre::eyre::Result<(Option<String>, String)> {
+        let Ok((headers, _)) = mailparse::parse_headers(data) else {
+            return Ok((None, object_id('T')));
+        };
+        let header = |name: &str| {
+            headers
+                .iter()
+                .filter(|header| header.get_key_ref().eq_ignore_ascii_case(name))
+                .flat_map(|header| message_ids(&header.get_value()))
+                .collect::<Vec<_>>()
+        };
+        let message_id = header("Message-ID").into_iter().next();
+        let owner = MailboxName::from_id(mailbox).map_or_else(String::new, |name| name.owner);
+        for reference in header("In-Reply-To")
+            .into_iter()
+            .chain(header("References"))
+        {
+            let thread_id: Option<String> = sqlx::query_scalar(
+                "SELECT thread_id FROM mails WHERE message_id = $1 AND substr(mailbox, 1, length($2)) = $2 AND thread_id IS NOT NULL LIMIT 1",
+            )
+            .bind(reference)
+            .bind(format!("{owner}/"))
+            .fetch_optional(self.db.get_pool())
+            .await?;
+            if let Some(thread_id) = thread_id {
+                return Ok((message_id, thread_id));
+            }
+        }
+        Ok((message_id, object_id('T')))
+    }
+
+    async fn next_modseq(&self, mailbox: &str) -> color_eyre::eyre::Result<i64> {
+        let modseq: i64 = sqlx::query_scalar(NEXT_MODSEQ)
+            .bind(mailbox)
+            .fetch_one(self.db.get_pool())
+            .await?;
+        Ok(modseq)
+    }
+
+    /// Writes the messages to `tmp` and records them in a single
+    /// transaction. Returns their UIDs.
+    ///
+    /// The written files are added to `staged`, so the caller can remove
+    /// them if recording fails.
+    async fn record_staged(
+        &self,
+        mailbox: &str,
+        path: &Path,
+        messages: &[(&[u8], Vec<String>)],
+        staged: &mut Vec<Staged>,
+    ) -> color_eyre::eyre::Result<Vec<u32>> {
+        let mut threads = Vec::with_capacity(messages.len());
+        for (data, imap_flags) in messages {
+            staged.push(stage(path, data, &imap_flags_to_maildir(imap_flags)).await?);
+            // Looked up before the transaction, which may lock the table
+            threads.push(self.thread_of(mailbox, data).await?);
+        }
+
+        let mut transaction = self.db.get_pool().begin().await?;
+        let mut uids = Vec::with_capacity(messages.len());
+        for ((message, (message_id, thread_id)), (data, imap_flags)) in
+            staged.iter().zip(threads).zip(messages)
+        {
+            let next_uid: i32 = sqlx::query_scalar(
+                "SELECT COALESCE(MAX(uid), 0) + 1 FROM mails WHERE mailbox = $1",
+            )
+            .bind(mailbox)
+            .fetch_one(&mut *transaction)
+            .await?;
+            let modseq: i64 = sqlx::query_scalar(NEXT_MODSEQ)
+                .bind(mailbox)
+                .fetch_one(&mut *transaction)
+                .await?;
+            // Postgres assigns the uid in a trigger so we need to read it back
+            let uid: i32 = sqlx::query_scalar(
+                "INSERT INTO mails (maildir_id, modseq, mailbox, uid, size, keywords, email_id, thread_id, message_id, save_date) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING uid",
+            )
+            .bind(message.id.as_str())
+            .bind(modseq)
+            .bind(mailbox)
+            .bind(next_uid)
+            .bind(i64::try_from(data.len())?)
+            .bind(imap_keywords(imap_flags).join(" "))
+            .bind(object_id('M'))
+            .bind(thread_id)
+            .bind(message_id)
+            .bind(now())
+            .fetch_one(&mut *transaction)
+            .await?;
+            uids.push(uid.cast_unsigned());
+        }
+        transaction.commit().await?;
+        Ok(uids)
+    }
+
+    /// Assigns a fresh modification sequence to a message after its flags changed
+    #[instrument(skip(self))]
+    async fn touch_modseq(&self, id: &str) -> color_eyre::eyre::Result<()> {
+        let mailboxes: Vec<String> =
+            sqlx::query_scalar("SELECT mailbox FROM mails WHERE maildir_id = $1")
+                .bind(id)
+                .fetch_all(self.db.get_pool())
+                .await?;
+        for mailbox in mailboxes {
+            let modseq = self.next_modseq(&mailbox).await?;
+            sqlx::query("UPDATE mails SET modseq = $1 WHERE maildir_id = $2 AND mailbox = $3")
+                .bind(modseq)
+                .bind(id)
+                .bind(mailbox)
+                .execute(self.db.get_pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Tells subscribers about the current flags of a message
+    #[instrument(skip(self, path))]
+    async fn publish_flags(&self, path: &Path, id: &str) {
+        if let Some(mail) = self.find(path, id).await {
+            self.events.publish(MailboxEvent::FlagsChanged {
+                uid: mail.uid(),
+                modseq: mail.modseq(),
+                flags: maildir_flags_to_imap(&mail)
+                    .into_iter()
+                    .chain(mail.keywords.iter().cloned())
+                    .collect(),
+                mailbox: mail.mailbox,
+            });
+        }
+    }
+
+    /// Applies a change of the user-defined keywords to all copies of a message
+    #[instrument(skip(self))]
+    async fn update_keywords(
+        &self,
+        id: &str,
+        change: KeywordChange,
+        keywords: &[String],
+    ) -> color_eyre::eyre::Result<()> {
+        let current: Option<String> =
+            sqlx::query_scalar("SELECT keywords FROM mails WHERE maildir_id = $1")
+                .bind(id)
+                .fetch_optional(self.db.get_pool())
+                .await?;
+        let mut updated = parse_keywords(current.as_deref().unwrap_or_default());
+        match change {
+            KeywordChange::Add => {
+                for keyword in keywords {
+                    if !updated.iter().any(|k| k.eq_ignore_ascii_case(keyword)) {
+                        updated.push(keyword.clone());
+                    }
+                }
+            }
+            KeywordChange::Set => updated = keywords.to_vec(),
+            KeywordChange::Remove => {
+                updated.retain(|k| !keywords.iter().any(|r| r.eq_ignore_ascii_case(k)));
+            }
+        }
+        sqlx::query("UPDATE mails SET keywords = $1 WHERE maildir_id = $2")
+            .bind(updated.join(" "))
+            .bind(id)
+            .execute(self.db.get_pool())
+            .await?;
+        Ok(())
+    }
+}
+
+/// How a flag change affects the stored keywords
+#[derive(Debug, Clone, Copy)]
+enum KeywordChange {
+    Add,
+    Set,
+    Remove,
+}
+
+/// Splits the `keywords` column into the single keywords
+/// A message written to `tmp`, which only shows up in the mailbox once it is
+/// moved to `cur`
+struct Staged {
+    id: String,
+    tmp: PathBuf,
+    cur: PathBuf,
+}
+
+/// Writes a message to the `tmp` folder of the maildir at `path`
+async fn stage(path: &Path, data: &[u8], maildir_flags: &str) -> std::io::Result<Staged> {
+    let id = format!(
+        "{}.{}.erooster,S={}",
+        now(),
+        Uuid::new_v4().simple(),
+        data.len()
+    );
+    let mut flags: Vec<char> = maildir_flags.chars().collect();
+    flags.sort_unstable();
+    flags.dedup();
+    let staged = Staged {
+        tmp: path.join("tmp").join(&id),
+        cur: path
+            .join("cur")
+            .join(format!("{id}:2,{}", flags.into_iter().collect::<String>())),
+        id,
+    };
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&staged.tmp)
+        .await?;
+    let written = async {
+        file.write_all(data).await?;
+        file.sync_all().await
+    }
+    .await;
+    if let Err(e) = written {
+        tokio::fs::remove_file(&staged.tmp).await.ok();
+        return Err(e);
+    }
+    Ok(staged)
+}
+
+/// A new object identifier (RFC 8474 §4) starting with `prefix`
+fn object_id(prefix: char) -> String {
+    format!("{prefix}{}", Uuid::new_v4().simple())
+}
+
+/// The current time as unix timestamp
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| i64::try_from(time.as_secs()).unwrap_or(i64::MAX))
+}
+
+/// The `<...>` message ids of a Message-ID, In-Reply-To or References header
+fn message_ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|part| part.split_once('>'))
+        .map(|(id, _)| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+fn parse_keywords(keywords: &str) -> Vec<String> {
+    keywords.split_whitespace().map(String::from).collect()
+}
+
+/// Picks the user-defined keywords from a list of IMAP flags.
+///
+/// Everything that isn't a system flag (those start with a backslash) is a
+/// keyword. Duplicates are dropped ignoring case.
+fn imap_keywords<T: AsRef<str>>(imap_flags: &[T]) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for flag in imap_flags {
+        let keyword = flag.as_ref().replace(['(', ')'], "");
+        if keyword.is_empty()
+            || keyword.starts_with('\\')
+            || keywords.iter().any(|k| k.eq_ignore_ascii_case(&keyword))
+        {
+            continue;
+        }
+        keywords.push(keyword);
     }
+    keywords
+}
+
+/// Maps the maildir info of a message back to IMAP system flags.
+fn maildir_flags_to_imap(mail: &MaildirMailEntry) -> Vec<String> {
+    [
+        (mail.is_draft(), "\\Draft"),
+        (mail.is_flagged(), "\\Flagged"),
+        (mail.is_seen(), "\\Seen"),
+        (mail.is_replied(), "\\Answered"),
+        (mail.is_trashed(), "\\Deleted"),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, flag)| String::from(flag))
+    .collect()
+}
+
+/// Maps IMAP system flags to their maildir info characters.
+///
+/// Flags without a maildir equivalent are dropped.
+fn imap_flags_to_maildir<T: AsRef<str>>(imap_flags: &[T]) -> String {
+    imap_flags
+        .iter()
+        .filter_map(|flag| {
+            let normalized_flag = flag.as_ref().to_lowercase().replace(['(', ')'], "");
+            if normalized_flag == "\\seen" {
+                Some("S")
+            } else if normalized_flag == "\\deleted" {
+                Some("T")
+            } else if normalized_flag == "\\flagged" {
+                Some("F")
+            } else if normalized_flag == "\\draft" {
+                Some("D")
+            } else if normalized_flag == "\\answered" {
+                Some("R")
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("")
 }
 
 impl MailStorage<MaildirMailEntry> for MaildirStorage {
@@ -54,6 +413,32 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
         Ok(max_uid.unwrap_or(0).cast_unsigned())
     }
 
+    #[instrument(skip(self, mailbox))]
+    async fn get_highest_modseq(&self, mailbox: &str) -> color_eyre::eyre::Result<u64> {
+        let modseq: Option<i64> =
+            sqlx::query_scalar("SELECT modseq FROM mailbox_modseq_counter WHERE mailbox = $1")
+                .bind(mailbox)
+                .fetch_optional(self.db.get_pool())
+                .await?;
+        Ok(modseq.unwrap_or(1).cast_unsigned())
+    }
+
+    #[instrument(skip(self, mailbox))]
+    async fn get_vanished_since(
+        &self,
+        mailbox: &str,
+        modseq: u64,
+    ) -> color_eyre::eyre::Result<Vec<u32>> {
+        let uids: Vec<i32> = sqlx::query_scalar(
+            "SELECT uid FROM expunged_mails WHERE mailbox = $1 AND modseq > $2 ORDER BY uid",
+        )
+        .bind(mailbox)
+        .bind(modseq.cast_signed())
+        .fetch_all(self.db.get_pool())
+        .await?;
+        Ok(uids.into_iter().map(i32::cast_unsigned).collect())
+    }
+
     async fn find(&self, path: &Path, id: &str) -> Option<MaildirMailEntry> {
         let maildir = Maildir::from(path.to_path_buf());
         let mail: Vec<_> =
@@ -77,6 +462,10 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
             let uid: i32 = db_item.map_or(0, |y| y.uid);
             let mailbox: String = db_item.map_or(String::from("unknown"), |y| y.mailbox.clone());
             let modseq = db_item.map_or(0, |y| y.modseq);
+            let keywords = db_item.map_or_else(Vec::new, |y| parse_keywords(&y.keywords));
+            let email_id = db_item.and_then(|y| y.email_id.clone());
+            let thread_id = db_item.and_then(|y| y.thread_id.clone());
+            let save_date = db_item.and_then(|y| y.save_date);
 
             let mail_state = if entry.is_seen() {
                 MailState::Read
@@ -87,6 +476,10 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
                 uid: uid.try_into().expect("Invalid UID"),
                 mailbox,
                 modseq: modseq.try_into().expect("Invalid UID"),
+                keywords,
+                email_id,
+                thread_id,
+                save_date,
                 entry,
                 mail_state,
                 sequence_number: None,
@@ -159,41 +552,53 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
         data: &[u8],
         imap_flags: Vec<String>,
     ) -> color_eyre::eyre::Result<String> {
-        let maildir = Maildir::from(path.to_path_buf());
-        let maildir_flags = imap_flags
-            .iter()
-            .filter_map(|flag| {
-                let normalized_flag = flag.to_lowercase().replace(['(', ')'], "");
-                if normalized_flag == "\\seen" {
-                    Some("S")
-                } else if normalized_flag == "\\deleted" {
-                    Some("T")
-                } else if normalized_flag == "\\flagged" {
-                    Some("F")
-                } else if normalized_flag == "\\draft" {
-                    Some("D")
-                } else if normalized_flag == "\\answered" {
-                    Some("R")
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("");
-        let maildir_id = maildir.store_cur_with_flags(data, &maildir_flags)?;
-        let next_uid: i32 =
-            sqlx::query_scalar("SELECT COALESCE(MAX(uid), 0) + 1 FROM mails WHERE mailbox = $1")
-                .bind(mailbox.as_str())
-                .fetch_one(self.db.get_pool())
-                .await?;
-        sqlx::query("INSERT INTO mails (maildir_id, modseq, mailbox, uid) VALUES ($1, $2, $3, $4)")
-            .bind(maildir_id.clone())
-            .bind(1i64)
-            .bind(mailbox)
-            .bind(next_uid)
-            .execute(self.db.get_pool())
+        let stored = self
+            .store_all_cur_with_flags(mailbox, path, &[(data, imap_flags)])
             .await?;
-        Ok(maildir_id)
+        stored
+            .into_iter()
+            .next()
+            .map(|(id, _)| id)
+            .context("The stored message is missing")
+    }
+
+    #[instrument(skip(self, mailbox, path, messages))]
+    async fn store_all_cur_with_flags(
+        &self,
+        mailbox: String,
+        path: &Path,
+        messages: &[(&[u8], Vec<String>)],
+    ) -> color_eyre::eyre::Result<Vec<(String, u32)>> {
+        let mut staged = Vec::with_capacity(messages.len());
+        let uids = match self
+            .record_staged(&mailbox, path, messages, &mut staged)
+            .await
+        {
+            Ok(uids) => uids,
+            Err(e) => {
+                for message in staged {
+                    if let Err(e) = tokio::fs::remove_file(&message.tmp).await {
+                        error!("Failed to remove unrecorded message {}: {e}", message.id);
+                    }
+                }
+                return Err(e);
+            }
+        };
+        for message in &staged {
+            tokio::fs::rename(&message.tmp, &message.cur).await?;
+        }
+        for ((message, uid), (data, _)) in staged.iter().zip(&uids).zip(messages) {
+            self.index_message(&mailbox, &message.id, data);
+            self.events.publish(MailboxEvent::Exists {
+                mailbox: mailbox.clone(),
+                uid: *uid,
+            });
+        }
+        Ok(staged
+            .into_iter()
+            .map(|message| message.id)
+            .zip(uids)
+            .collect())
     }
 
     #[instrument(skip(self, mailbox, path, data))]
@@ -211,16 +616,29 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
                 .bind(mailbox.as_str())
                 .fetch_one(self.db.get_pool())
                 .await?;
-        sqlx::query(
-            "INSERT INTO mails (maildir_id, modseq, mailbox, uid, dkim_status) VALUES ($1, $2, $3, $4, $5)",
+        let modseq = self.next_modseq(&mailbox).await?;
+        let (message_id, thread_id) = self.thread_of(&mailbox, data).await?;
+        // Postgres assigns the uid in a trigger so we need to read it back
+        let uid: i32 = sqlx::query_scalar(
+            "INSERT INTO mails (maildir_id, modseq, mailbox, uid, dkim_status, size, email_id, thread_id, message_id, save_date) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) RETURNING uid",
         )
         .bind(maildir_id.clone())
-        .bind(1i64)
-        .bind(mailbox)
+        .bind(modseq)
+        .bind(mailbox.as_str())
         .bind(next_uid)
         .bind(dkim_status)
-        .execute(self.db.get_pool())
+        .bind(i64::try_from(data.len())?)
+        .bind(object_id('M'))
+        .bind(thread_id)
+        .bind(message_id)
+        .bind(now())
+        .fetch_one(self.db.get_pool())
         .await?;
+        self.index_message(&mailbox, &maildir_id, data);
+        self.events.publish(MailboxEvent::Exists {
+            mailbox,
+            uid: uid.cast_unsigned(),
+        });
         Ok(maildir_id)
     }
 
@@ -277,10 +695,18 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
                     let mailbox: String =
                         db_item.map_or(String::from("unknown"), |y| y.mailbox.clone());
                     let modseq = db_item.map_or(0, |y| y.modseq);
+                    let keywords = db_item.map_or_else(Vec::new, |y| parse_keywords(&y.keywords));
+                    let email_id = db_item.and_then(|y| y.email_id.clone());
+                    let thread_id = db_item.and_then(|y| y.thread_id.clone());
+                    let save_date = db_item.and_then(|y| y.save_date);
 
                     Some(MaildirMailEntry {
                         uid: uid.try_into().expect("Invalid UID"),
                         modseq: modseq.try_into().expect("Invalid UID"),
+                        keywords,
+                        email_id,
+                        thread_id,
+                        save_date,
                         entry,
                         mailbox,
                         mail_state: MailState::Read,
@@ -333,10 +759,18 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
                     let mailbox: String =
                         db_item.map_or(String::from("unknown"), |y| y.mailbox.clone());
                     let modseq = db_item.map_or(0, |y| y.modseq);
+                    let keywords = db_item.map_or_else(Vec::new, |y| parse_keywords(&y.keywords));
+                    let email_id = db_item.and_then(|y| y.email_id.clone());
+                    let thread_id = db_item.and_then(|y| y.thread_id.clone());
+                    let save_date = db_item.and_then(|y| y.save_date);
 
                     Some(MaildirMailEntry {
                         uid: uid.try_into().expect("Invalid UID"),
                         modseq: modseq.try_into().expect("Invalid UID"),
+                        keywords,
+                        email_id,
+                        thread_id,
+                        save_date,
                         entry,
                         mailbox,
                         mail_state: MailState::New,
@@ -390,6 +824,10 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
                     let mailbox: String =
                         db_item.map_or(String::from("unknown"), |y| y.mailbox.clone());
                     let modseq = db_item.map_or(0, |y| y.modseq);
+                    let keywords = db_item.map_or_else(Vec::new, |y| parse_keywords(&y.keywords));
+                    let email_id = db_item.and_then(|y| y.email_id.clone());
+                    let thread_id = db_item.and_then(|y| y.thread_id.clone());
+                    let save_date = db_item.and_then(|y| y.save_date);
                     let state = if entry.is_seen() {
                         MailState::Read
                     } else {
@@ -398,6 +836,10 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
                     Some(MaildirMailEntry {
                         uid: uid.try_into().expect("Invalid UID"),
                         modseq: modseq.try_into().expect("Invalid Modseq"),
+                        keywords,
+                        email_id,
+                        thread_id,
+                        save_date,
                         entry,
                         mailbox,
                         mail_state: state,
@@ -423,138 +865,483 @@ impl MailStorage<MaildirMailEntry> for MaildirStorage {
     }
 
     #[instrument(skip(self, path))]
-    fn move_new_to_cur_with_flags(
+    async fn move_new_to_cur_with_flags(
         &self,
         path: &Path,
         id: &str,
         imap_flags: &[&str],
     ) -> color_eyre::eyre::Result<()> {
         let maildir = Maildir::from(path.to_path_buf());
-        let maildir_flags = imap_flags
-            .iter()
-            .filter_map(|flag| {
-                let normalized_flag = flag.to_lowercase().replace(['(', ')'], "");
-                if normalized_flag == "\\seen" {
-                    Some("S")
-                } else if normalized_flag == "\\deleted" {
-                    Some("T")
-                } else if normalized_flag == "\\flagged" {
-                    Some("F")
-                } else if normalized_flag == "\\draft" {
-                    Some("D")
-                } else if normalized_flag == "\\answered" {
-                    Some("R")
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("");
+        let maildir_flags = imap_flags_to_maildir(imap_flags);
         maildir.move_new_to_cur_with_flags(id, &maildir_flags)?;
+        self.update_keywords(id, KeywordChange::Add, &imap_keywords(imap_flags))
+            .await?;
+        self.touch_modseq(id).await?;
+        self.publish_flags(path, id).await;
         Ok(())
     }
 
     #[instrument(skip(self, path))]
-    fn add_flags(
+    async fn add_flags(
         &self,
         path: &Path,
         id: &str,
         imap_flags: &[&str],
     ) -> color_eyre::eyre::Result<()> {
         let maildir = Maildir::from(path.to_path_buf());
-        let maildir_flags = imap_flags
-            .iter()
-            .filter_map(|flag| {
-                let normalized_flag = flag.to_lowercase().replace(['(', ')'], "");
-                if normalized_flag == "\\seen" {
-                    Some("S")
-                } else if normalized_flag == "\\deleted" {
-                    Some("T")
-                } else if normalized_flag == "\\flagged" {
-                    Some("F")
-                } else if normalized_flag == "\\draft" {
-                    Some("D")
-                } else if normalized_flag == "\\answered" {
-                    Some("R")
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("");
+        let maildir_flags = imap_flags_to_maildir(imap_flags);
         debug!("flags: {:?}", maildir_flags);
         maildir.add_flags(id, &maildir_flags)?;
+        self.update_keywords(id, KeywordChange::Add, &imap_keywords(imap_flags))
+            .await?;
+        self.touch_modseq(id).await?;
+        self.publish_flags(path, id).await;
         Ok(())
     }
 
     #[instrument(skip(self, path))]
-    fn set_flags(
+    async fn set_flags(
         &self,
         path: &Path,
         id: &str,
         imap_flags: &[&str],
     ) -> color_eyre::eyre::Result<()> {
         let maildir = Maildir::from(path.to_path_buf());
-        let maildir_flags = imap_flags
-            .iter()
-            .filter_map(|flag| {
-                let normalized_flag = flag.to_lowercase().replace(['(', ')'], "");
-                if normalized_flag == "\\seen" {
-                    Some("S")
-                } else if normalized_flag == "\\deleted" {
-                    Some("T")
-                } else if normalized_flag == "\\flagged" {
-                    Some("F")
-                } else if normalized_flag == "\\draft" {
-                    Some("D")
-                } else if normalized_flag == "\\answered" {
-                    Some("R")
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("");
+        let maildir_flags = imap_flags_to_maildir(imap_flags);
         maildir.set_flags(id, &maildir_flags)?;
+        self.update_keywords(id, KeywordChange::Set, &imap_keywords(imap_flags))
+            .await?;
+        self.touch_modseq(id).await?;
+        self.publish_flags(path, id).await;
         Ok(())
     }
 
     #[instrument(skip(self, path))]
-    fn remove_flags(
+    async fn remove_flags(
         &self,
         path: &Path,
         id: &str,
         imap_flags: &[&str],
     ) -> color_eyre::eyre::Result<()> {
         let maildir = Maildir::from(path.to_path_buf());
-        let maildir_flags = imap_flags
-            .iter()
-            .filter_map(|flag| {
-                let normalized_flag = flag.to_lowercase().replace(['(', ')'], "");
-                if normalized_flag == "\\seen" {
-                    Some("S")
-                } else if normalized_flag == "\\deleted" {
-                    Some("T")
-                } else if normalized_flag == "\\flagged" {
-                    Some("F")
-                } else if normalized_flag == "\\draft" {
-                    Some("D")
-                } else if normalized_flag == "\\answered" {
-                    Some("R")
-                } else {
-                    None
-                }
+        let maildir_flags = imap_flags_to_maildir(imap_flags);
+        maildir.remove_flags(id, &maildir_flags)?;
+        self.update_keywords(id, KeywordChange::Remove, &imap_keywords(imap_flags))
+            .await?;
+        self.touch_modseq(id).await?;
+        self.publish_flags(path, id).await;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_keywords(&self, mailbox: &str) -> color_eyre::eyre::Result<Vec<String>> {
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT keywords FROM mails WHERE mailbox = $1 AND keywords != ''",
+        )
+        .bind(mailbox)
+        .fetch_all(self.db.get_pool())
+        .await?;
+        let keywords: Vec<&str> = rows.iter().flat_map(|row| row.split_whitespace()).collect();
+        Ok(imap_keywords(&keywords))
+    }
+
+    #[instrument(skip(self, path))]
+    async fn expunge(&self, path: &Path, id: &str) -> color_eyre::eyre::Result<()> {
+        let maildir = Maildir::from(path.to_path_buf());
+        maildir.delete(id)?;
+        let rows: Vec<(String, i32)> =
+            sqlx::query_as("SELECT mailbox, uid FROM mails WHERE maildir_id = $1")
+                .bind(id)
+                .fetch_all(self.db.get_pool())
+                .await?;
+        let mut expunged = Vec::with_capacity(rows.len());
+        let mut owners = BTreeSet::new();
+        for (mailbox, uid) in rows {
+            owners.extend(MailboxName::from_id(&mailbox).map(|mailbox| mailbox.owner));
+            let modseq = self.next_modseq(&mailbox).await?;
+            sqlx::query("INSERT INTO expunged_mails (mailbox, uid, modseq) VALUES ($1, $2, $3)")
+                .bind(mailbox.as_str())
+                .bind(uid)
+                .bind(modseq)
+                .execute(self.db.get_pool())
+                .await?;
+            expunged.push(MailboxEvent::Expunged {
+                mailbox,
+                uid: uid.cast_unsigned(),
+            });
+        }
+        sqlx::query("DELETE FROM mails WHERE maildir_id = $1")
+            .bind(id)
+            .execute(self.db.get_pool())
+            .await?;
+        if let Some(index) = &self.index {
+            for owner in owners {
+                index.remove(&owner, id);
+            }
+        }
+        for event in expunged {
+            self.events.publish(event);
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_quota_usage(&self, username: &str) -> color_eyre::eyre::Result<QuotaUsage> {
+        let (storage, messages): (i64, i64) = sqlx::query_as(
+            // LIKE would treat `_` and `%` in the name as wildcards
+            "SELECT COALESCE(SUM(size), 0), COUNT(*) FROM mails WHERE substr(mailbox, 1, length($1)) = $1",
+        )
+        .bind(format!("{username}/"))
+        .fetch_one(self.db.get_pool())
+        .await?;
+        Ok(QuotaUsage {
+            storage: u64::try_from(storage)?,
+            messages: u64::try_from(messages)?,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn quota_allows(
+        &self,
+        username: &str,
+        additional: &QuotaUsage,
+    ) -> color_eyre::eyre::Result<bool> {
+        let quota = self.db.get_quota(username).await?;
+        if quota.is_unlimited() {
+            return Ok(true);
+        }
+        let usage = self.get_quota_usage(username).await?;
+        Ok(quota.allows(&usage, additional))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_acl(&self, mailbox: &MailboxName) -> color_eyre::eyre::Result<Vec<AclEntry>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT identifier, rights FROM mailbox_acl WHERE mailbox = $1 ORDER BY identifier",
+        )
+        .bind(mailbox.id())
+        .fetch_all(self.db.get_pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(identifier, rights)| AclEntry {
+                identifier,
+                rights: Rights::parse(&rights).unwrap_or_default(),
             })
-            .collect::<Vec<_>>()
-            .join("");
-        maildir.set_flags(id, &maildir_flags)?;
+            .collect())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_acl(
+        &self,
+        mailbox: &MailboxName,
+        identifier: &str,
+        rights: Rights,
+    ) -> color_eyre::eyre::Result<()> {
+        if rights.is_empty() {
+            sqlx::query("DELETE FROM mailbox_acl WHERE mailbox = $1 AND identifier = $2")
+                .bind(mailbox.id())
+                .bind(identifier)
+                .execute(self.db.get_pool())
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO mailbox_acl (mailbox, identifier, rights) VALUES ($1, $2, $3) ON CONFLICT (mailbox, identifier) DO UPDATE SET rights = excluded.rights",
+            )
+            .bind(mailbox.id())
+            .bind(identifier)
+            .bind(rights.to_string())
+            .execute(self.db.get_pool())
+            .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn my_rights(
+        &self,
+        mailbox: &MailboxName,
+        username: &str,
+    ) -> color_eyre::eyre::Result<Rights> {
+        // Owners never need a lookup
+        if mailbox.is_owned_by(username) {
+            return Ok(Rights::ALL);
+        }
+        let entries = self.get_acl(mailbox).await?;
+        Ok(effective_rights(&mailbox.owner, username, &entries))
+    }
+
+    #[instrument(skip(self))]
+    async fn shared_mailboxes(&self, username: &str) -> color_eyre::eyre::Result<Vec<MailboxName>> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT mailbox, identifier, rights FROM mailbox_acl WHERE identifier IN ($1, $2, $3, $4)",
+        )
+        .bind(username)
+        .bind(format!("-{username}"))
+        .bind(ANYONE)
+        .bind(format!("-{ANYONE}"))
+        .fetch_all(self.db.get_pool())
+        .await?;
+        let mut acls: BTreeMap<String, Vec<AclEntry>> = BTreeMap::new();
+        for (mailbox, identifier, rights) in rows {
+            acls.entry(mailbox).or_default().push(AclEntry {
+                identifier,
+                rights: Rights::parse(&rights).unwrap_or_default(),
+            });
+        }
+
+        let mut mailboxes = Vec::new();
+        for (id, entries) in acls {
+            let Some(mailbox) = MailboxName::from_id(&id) else {
+                continue;
+            };
+            if mailbox.is_owned_by(username)
+                || !effective_rights(&mailbox.owner, username, &entries).contains(Rights::LOOKUP)
+            {
+                continue;
+            }
+            let path = Path::new(&self.config.mail.maildir_folders)
+                .join(&mailbox.owner)
+                .join(self.to_ondisk_path_name(mailbox.name.clone())?);
+            if path.exists() {
+                mailboxes.push(mailbox);
+            }
+        }
+        Ok(mailboxes)
+    }
+
+    #[instrument(skip(self))]
+    async fn move_acl(
+        &self,
+        from: &MailboxName,
+        to: Option<&MailboxName>,
+    ) -> color_eyre::eyre::Result<()> {
+        if let Some(to) = to {
+            sqlx::query("UPDATE mailbox_acl SET mailbox = $1 WHERE mailbox = $2")
+                .bind(to.id())
+                .bind(from.id())
+                .execute(self.db.get_pool())
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM mailbox_acl WHERE mailbox = $1")
+                .bind(from.id())
+                .execute(self.db.get_pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn mailbox_object_id(&self, mailbox: &MailboxName) -> color_eyre::eyre::Result<String> {
+        sqlx::query(
+            "INSERT INTO mailbox_ids (mailbox, object_id) VALUES ($1, $2) ON CONFLICT (mailbox) DO NOTHING",
+        )
+        .bind(mailbox.id())
+        .bind(object_id('F'))
+        .execute(self.db.get_pool())
+        .await?;
+        let object_id: String =
+            sqlx::query_scalar("SELECT object_id FROM mailbox_ids WHERE mailbox = $1")
+                .bind(mailbox.id())
+                .fetch_one(self.db.get_pool())
+                .await?;
+        Ok(object_id)
+    }
+
+    #[instrument(skip(self))]
+    async fn move_mailbox(
+        &self,
+        from: &MailboxName,
+        to: Option<&MailboxName>,
+    ) -> color_eyre::eyre::Result<()> {
+        let Some(to) = to else {
+            sqlx::query("DELETE FROM mailbox_ids WHERE mailbox = $1")
+                .bind(from.id())
+                .execute(self.db.get_pool())
+                .await?;
+            return Ok(());
+        };
+        for query in [
+            "UPDATE mails SET mailbox = $1 WHERE mailbox = $2",
+            "UPDATE mailbox_ids SET mailbox = $1 WHERE mailbox = $2",
+            "UPDATE mailbox_uid_counter SET mailbox = $1 WHERE mailbox = $2",
+            "UPDATE mailbox_modseq_counter SET mailbox = $1 WHERE mailbox = $2",
+            "UPDATE expunged_mails SET mailbox = $1 WHERE mailbox = $2",
+        ] {
+            sqlx::query(query)
+                .bind(to.id())
+                .bind(from.id())
+                .execute(self.db.get_pool())
+                .await?;
+        }
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn inherit_object_ids(
+        &self,
+        source_id: &str,
+        copy_id: &str,
+    ) -> color_eyre::eyre::Result<()> {
+        let ids: Option<(Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT email_id, thread_id FROM mails WHERE maildir_id = $1")
+                .bind(source_id)
+                .fetch_optional(self.db.get_pool())
+                .await?;
+        if let Some((Some(email_id), Some(thread_id))) = ids {
+            sqlx::query("UPDATE mails SET email_id = $1, thread_id = $2 WHERE maildir_id = $3")
+                .bind(email_id)
+                .bind(thread_id)
+                .bind(copy_id)
+                .execute(self.db.get_pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_metadata(
+        &self,
+        mailbox: Option<&MailboxName>,
+        username: &str,
+    ) -> color_eyre::eyre::Result<Vec<MetadataEntry>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT entry, value FROM metadata WHERE mailbox = $1 AND (owner = $2 OR owner = '') ORDER BY entry",
+        )
+        .bind(mailbox.map(MailboxName::id).unwrap_or_default())
+        .bind(username)
+        .fetch_all(self.db.get_pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(entry, value)| {
+                let name = EntryName::parse(&entry)?;
+                Some(MetadataEntry { name, value })
+            })
+            .collect())
+    }
+
+    #[instrument(skip(self, value))]
+    async fn set_metadata(
+        &self,
+        mailbox: Option<&MailboxName>,
+        username: &str,
+        entry: &EntryName,
+        value: Option<&str>,
+    ) -> color_eyre::eyre::Result<()> {
+        let mailbox = mailbox.map(MailboxName::id).unwrap_or_default();
+        // Shared entries are stored without an owner
+        let owner = match entry.scope() {
+            Scope::Private => username,
+            Scope::Shared => "",
+        };
+        if let Some(value) = value {
+            sqlx::query(
+                "INSERT INTO metadata (mailbox, owner, entry, value) VALUES ($1, $2, $3, $4) ON CONFLICT (mailbox, owner, entry) DO UPDATE SET value = excluded.value",
+            )
+            .bind(mailbox)
+            .bind(owner)
+            .bind(entry.as_str())
+            .bind(value)
+            .execute(self.db.get_pool())
+            .await?;
+        } else {
+            sqlx::query("DELETE FROM metadata WHERE mailbox = $1 AND owner = $2 AND entry = $3")
+                .bind(mailbox)
+                .bind(owner)
+                .bind(entry.as_str())
+                .execute(self.db.get_pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn move_metadata(
+        &self,
+        from: &MailboxName,
+        to: Option<&MailboxName>,
+    ) -> color_eyre::eyre::Result<()> {
+        if let Some(to) = to {
+            sqlx::query("UPDATE metadata SET mailbox = $1 WHERE mailbox = $2")
+                .bind(to.id())
+                .bind(from.id())
+                .execute(self.db.get_pool())
+                .await?;
+        } else {
+            sqlx::query("DELETE FROM metadata WHERE mailbox = $1")
+                .bind(from.id())
+                .execute(self.db.get_pool())
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    #[instrument(skip(self))]
+    fn index_candidates(
+        &self,
+        mailbox: &str,
+        field: TextField,
+        query: &str,
+    ) -> color_eyre::eyre::Result<Option<HashSet<String>>> {
+        let (Some(index), Some(mailbox)) = (&self.index, MailboxName::from_id(mailbox)) else {
+            return Ok(None);
+        };
+        index.index().candidates(&mailbox.owner, field, query)
+    }
+
+    #[instrument(skip(self))]
+    fn indexed_messages(&self, mailbox: &str) -> color_eyre::eyre::Result<HashSet<String>> {
+        let (Some(index), Some(mailbox)) = (&self.index, MailboxName::from_id(mailbox)) else {
+            return Ok(HashSet::new());
+        };
+        index.index().indexed(&mailbox.owner)
+    }
+
+    #[instrument(skip(self))]
+    async fn reindex(&self, owner: &str) -> color_eyre::eyre::Result<usize> {
+        let Some(index) = &self.index else {
+            bail!("No search index is configured");
+        };
+        index.clear(owner);
+        let mailboxes: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT mailbox FROM mails WHERE substr(mailbox, 1, length($1)) = $1",
+        )
+        .bind(format!("{owner}/"))
+        .fetch_all(self.db.get_pool())
+        .await?;
+        let mut indexed = 0;
+        for mailbox in mailboxes {
+            let Some(name) = MailboxName::from_id(&mailbox) else {
+                continue;
+            };
+            let path = Path::new(&self.config.mail.maildir_folders)
+                .join(&name.owner)
+                .join(self.to_ondisk_path_name(name.name)?);
+            let mails = self.list_all(mailbox.clone(), &path).await;
+            for batch in mails.chunks(REINDEX_BATCH) {
+                for mail in batch {
+                    let data = tokio::fs::read(mail.entry.path()).await?;
+                    index.add(owner, mail.id(), &data);
+                }
+                indexed += batch.len();
+                // Bounds the memory the queued messages take
+                index.flush().await;
+            }
+        }
+        index.flush().await;
+        Ok(indexed)
+    }
+
     fn to_ondisk_path(&self, path: String, username: String) -> color_eyre::eyre::Result<PathBuf> {
-        let folder = self.to_ondisk_path_name(path)?;
+        let mailbox = MailboxName::resolve(&path, &username);
+        let folder = self.to_ondisk_path_name(mailbox.name)?;
         let mailbox_path = Path::new(&self.config.mail.maildir_folders)
-            .join(username)
+            .join(mailbox.owner)
             .join(folder);
         Ok(mailbox_path)
     }
@@ -576,6 +1363,10 @@ struct DbMails {
     modseq: i64,
     uid: i32,
     mailbox: String,
+    keywords: String,
+    email_id: Option<String>,
+    thread_id: Option<String>,
+    save_date: Option<i64>,
 }
 
 /// Wrapper for the mailentries from the Maildir crate
@@ -585,6 +1376,10 @@ pub struct MaildirMailEntry {
     /// The mailbox folder it is in
     pub mailbox: String,
     modseq: u64,
+    keywords: Vec<String>,
+    email_id: Option<String>,
+    thread_id: Option<String>,
+    save_date: Option<i64>,
     /// The sequence number. It is None until used
     pub sequence_number: Option<u32>,
     mail_state: MailState,
@@ -641,6 +1436,26 @@ impl MailEntry for MaildirMailEntry {
         self.entry.flags()
     }
 
+    #[instrument(skip(self))]
+    fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    #[instrument(skip(self))]
+    fn email_id(&self) -> Option<&str> {
+        self.email_id.as_deref()
+    }
+
+    #[instrument(skip(self))]
+    fn thread_id(&self) -> Option<&str> {
+        self.thread_id.as_deref()
+    }
+
+    #[instrument(skip(self))]
+    fn save_date(&self) -> Option<i64> {
+        self.save_date
+    }
+
     #[instrument(skip(self))]
     fn is_draft(&self) -> bool {
         self.entry.is_draft()
@@ -679,11 +1494,7 @@ impl MailEntry for MaildirMailEntry {
     #[instrument(skip(self))]
     fn body_contains(&mut self, string: &str) -> bool {
         if let Ok(mail) = self.entry.parsed() {
-            if let Ok(body) = mail.get_body() {
-                body.contains(string)
-            } else {
-                false
-            }
+            MessageText::from_parsed(&mail).body.contains(string)
         } else {
             false
         }
@@ -725,7 +1536,14 @@ async fn lines_from_file(filename: impl AsRef<Path>) -> std::io::Result<Vec<Stri
 #[cfg(all(test, feature = "sqlite"))]
 mod tests {
     use crate::{
-        backend::storage::{MailEntry, MailStorage},
+        backend::{
+            acl::MailboxName,
+            database::Database,
+            events::MailboxEvent,
+            fulltext::TextField,
+            quota::{Quota, QuotaUsage},
+            storage::{MailEntry, MailStorage},
+        },
         test_helpers::setup_test_storage,
     };
 
@@ -891,4 +1709,400 @@ mod tests {
         let uid = storage.get_uid_for_folder(mailbox).await.unwrap();
         assert_eq!(uid, 1, "first message in .Sent should get UID 1");
     }
+
+    #[tokio::test]
+    async fn modseq_advances_on_store_and_flag_change() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let mailbox = "test@localhost/INBOX";
+        let path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("test@localhost")
+            .join("INBOX");
+        storage.create_dirs(&path).unwrap();
+        assert_eq!(storage.get_highest_modseq(mailbox).await.unwrap(), 1);
+
+        let id = storage
+            .store_cur_with_flags(mailbox.to_string(), &path, MSG, vec![])
+            .await
+            .unwrap();
+        let after_store = storage.get_highest_modseq(mailbox).await.unwrap();
+        assert!(after_store > 1, "storing a message must bump HIGHESTMODSEQ");
+
+        storage.add_flags(&path, &id, &["\\Seen"]).await.unwrap();
+        let after_flags = storage.get_highest_modseq(mailbox).await.unwrap();
+        assert!(after_flags > after_store, "flag change must bump HIGHESTMODSEQ");
+
+        let mails = storage.list_all(mailbox.to_string(), &path).await;
+        assert_eq!(mails[0].modseq(), after_flags);
+        assert!(mails[0].is_seen());
+    }
+
+    #[tokio::test]
+    async fn remove_flags_only_removes_given_flags() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let mailbox = "test@localhost/INBOX";
+        let path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("test@localhost")
+            .join("INBOX");
+        storage.create_dirs(&path).unwrap();
+
+        let id = storage
+            .store_cur_with_flags(
+                mailbox.to_string(),
+                &path,
+                MSG,
+                vec!["\\Seen".to_string(), "\\Flagged".to_string()],
+            )
+            .await
+            .unwrap();
+        storage.remove_flags(&path, &id, &["\\Seen"]).await.unwrap();
+
+        let mails = storage.list_all(mailbox.to_string(), &path).await;
+        assert!(!mails[0].is_seen());
+        assert!(mails[0].is_flagged());
+    }
+
+    #[tokio::test]
+    async fn keywords_are_persisted_next_to_system_flags() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let mailbox = "test@localhost/INBOX";
+        let path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("test@localhost")
+            .join("INBOX");
+        storage.create_dirs(&path).unwrap();
+
+        let id = storage
+            .store_cur_with_flags(
+                mailbox.to_string(),
+                &path,
+                MSG,
+                vec!["(\\Seen".to_string(), "$Junk)".to_string()],
+            )
+            .await
+            .unwrap();
+        storage
+            .add_flags(&path, &id, &["$Label1", "$junk"])
+            .await
+            .unwrap();
+        let mails = storage.list_all(mailbox.to_string(), &path).await;
+        assert!(mails[0].is_seen());
+        assert_eq!(mails[0].keywords(), ["$Junk", "$Label1"]);
+
+        storage.remove_flags(&path, &id, &["$JUNK"]).await.unwrap();
+        let mail = storage.find(&path, &id).await.unwrap();
+        assert_eq!(mail.keywords(), ["$Label1"]);
+
+        storage
+            .set_flags(&path, &id, &["\\Seen", "$NotJunk"])
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get_keywords(mailbox).await.unwrap(),
+            vec![String::from("$NotJunk")]
+        );
+    }
+
+    #[tokio::test]
+    async fn expunge_records_vanished_uid() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let mailbox = "test@localhost/INBOX";
+        let path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("test@localhost")
+            .join("INBOX");
+        storage.create_dirs(&path).unwrap();
+
+        storage
+            .store_new(mailbox.to_string(), &path, MSG, None)
+            .await
+            .unwrap();
+        let id = storage
+            .store_new(mailbox.to_string(), &path, MSG, None)
+            .await
+            .unwrap();
+        let before = storage.get_highest_modseq(mailbox).await.unwrap();
+
+        storage.expunge(&path, &id).await.unwrap();
+        assert_eq!(storage.count_new(&path), 1);
+        assert_eq!(
+            storage.get_vanished_since(mailbox, before).await.unwrap(),
+            vec![2]
+        );
+        assert!(storage
+            .get_vanished_since(mailbox, before + 1)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn stored_messages_are_indexed() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let inbox = "index@localhost/INBOX";
+        let inbox_path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("index@localhost")
+            .join("INBOX");
+        let archive = "index@localhost/Archive";
+        let archive_path = storage
+            .to_ondisk_path(String::from("Archive"), String::from("index@localhost"))
+            .unwrap();
+        storage.create_dirs(&inbox_path).unwrap();
+        storage.create_dirs(&archive_path).unwrap();
+
+        let first = storage
+            .store_new(inbox.to_string(), &inbox_path, MSG, None)
+            .await
+            .unwrap();
+        let second = storage
+            .store_cur_with_flags(archive.to_string(), &archive_path, MSG, vec![])
+            .await
+            .unwrap();
+        let indexer = storage.index.as_ref().unwrap();
+        indexer.flush().await;
+        let candidates = || {
+            let mut ids: Vec<String> = storage
+                .index_candidates(inbox, TextField::Body, "hello")
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .collect();
+            ids.sort();
+            ids
+        };
+        let mut both = vec![first.clone(), second.clone()];
+        both.sort();
+        assert_eq!(candidates(), both);
+        assert_eq!(
+            storage.indexed_messages(archive).unwrap(),
+            both.iter().cloned().collect()
+        );
+
+        storage.expunge(&inbox_path, &first).await.unwrap();
+        indexer.flush().await;
+        assert_eq!(candidates(), vec![second.clone()]);
+
+        assert_eq!(storage.reindex("index@localhost").await.unwrap(), 1);
+        assert_eq!(candidates(), vec![second]);
+        // `_` is no wildcard for the owner
+        assert_eq!(storage.reindex("index_localhost").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn changes_are_published_to_subscribers() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let mailbox = "test@localhost/INBOX";
+        let path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("test@localhost")
+            .join("INBOX");
+        storage.create_dirs(&path).unwrap();
+        let mut events = storage.events().subscribe();
+
+        let id = storage
+            .store_cur_with_flags(mailbox.to_string(), &path, MSG, vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            MailboxEvent::Exists {
+                mailbox: mailbox.to_string(),
+                uid: 1,
+            }
+        );
+
+        storage.add_flags(&path, &id, &["\\Seen"]).await.unwrap();
+        let modseq = storage.get_highest_modseq(mailbox).await.unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            MailboxEvent::FlagsChanged {
+                mailbox: mailbox.to_string(),
+                uid: 1,
+                modseq,
+                flags: vec![String::from("\\Seen")],
+            }
+        );
+
+        storage.expunge(&path, &id).await.unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            MailboxEvent::Expunged {
+                mailbox: mailbox.to_string(),
+                uid: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn quota_only_counts_mailboxes_of_the_user() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let root = std::path::Path::new(&config.mail.maildir_folders);
+        let path = root.join("axb").join("INBOX");
+        storage.create_dirs(&path).unwrap();
+        storage
+            .store_new(String::from("axb/INBOX"), &path, MSG, None)
+            .await
+            .unwrap();
+
+        assert_eq!(storage.get_quota_usage("a_b").await.unwrap().messages, 0);
+        assert_eq!(storage.get_quota_usage("a%").await.unwrap().messages, 0);
+        assert_eq!(storage.get_quota_usage("axb").await.unwrap().messages, 1);
+    }
+
+    #[tokio::test]
+    async fn quota_counts_all_mailboxes_of_the_user() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let user = "test@localhost";
+        let root = std::path::Path::new(&config.mail.maildir_folders).join(user);
+        let inbox_path = root.join("INBOX");
+        let sent_path = root.join(".Sent");
+        storage.create_dirs(&inbox_path).unwrap();
+        storage.create_dirs(&sent_path).unwrap();
+        let size = MSG.len() as u64;
+
+        assert!(storage
+            .quota_allows(user, &QuotaUsage::message(u64::MAX))
+            .await
+            .unwrap());
+        storage
+            .store_new(format!("{user}/INBOX"), &inbox_path, MSG, None)
+            .await
+            .unwrap();
+        storage
+            .store_cur_with_flags(format!("{user}/.Sent"), &sent_path, MSG, vec![])
+            .await
+            .unwrap();
+        let usage = storage.get_quota_usage(user).await.unwrap();
+        assert_eq!(usage.storage, 2 * size);
+        assert_eq!(usage.messages, 2);
+
+        // The domain default applies until the user gets an own quota
+        storage
+            .db
+            .set_domain_quota(
+                "localhost",
+                Quota {
+                    storage: None,
+                    messages: Some(2),
+                },
+            )
+            .await
+            .unwrap();
+        assert!(!storage
+            .quota_allows(user, &QuotaUsage::message(1))
+            .await
+            .unwrap());
+        storage
+            .db
+            .set_user_quota(
+                user,
+                Quota {
+                    storage: Some(3 * size),
+                    messages: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(storage
+            .quota_allows(user, &QuotaUsage::message(size))
+            .await
+            .unwrap());
+        assert!(!storage
+            .quota_allows(user, &QuotaUsage::message(size + 1))
+            .await
+            .unwrap());
+
+        storage
+            .db
+            .set_user_quota(user, Quota::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.db.get_quota(user).await.unwrap(),
+            Quota {
+                storage: None,
+                messages: Some(2),
+            }
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::unwrap_used)]
+    async fn object_ids_follow_threads_and_renames() {
+        let (config, storage) = setup_test_storage().await.unwrap();
+        let user = "objectid@localhost";
+        let path = std::path::Path::new(&config.mail.maildir_folders)
+            .join(user)
+            .join("INBOX");
+        storage.create_dirs(&path).unwrap();
+
+        let first = b"Message-ID: <first@localhost>\r\nSubject: a\r\n\r\nHello\r\n";
+        let reply = b"Message-ID: <reply@localhost>\r\nIn-Reply-To: <first@localhost>\r\nSubject: Re: a\r\n\r\nHi\r\n";
+        let first_id = storage
+            .store_new(format!("{user}/INBOX"), &path, first, None)
+            .await
+            .unwrap();
+        storage
+            .store_new(format!("{user}/INBOX"), &path, reply, None)
+            .await
+            .unwrap();
+        storage
+            .store_new(format!("{user}/INBOX"), &path, MSG, None)
+            .await
+            .unwrap();
+
+        let mails = storage.list_all(format!("{user}/INBOX"), &path).await;
+        let email_ids: std::collections::HashSet<_> =
+            mails.iter().filter_map(MailEntry::email_id).collect();
+        assert_eq!(email_ids.len(), 3, "every message gets an own EMAILID");
+        assert!(mails.iter().all(|mail| mail.save_date().is_some()));
+        let threads: std::collections::HashSet<_> =
+            mails.iter().filter_map(MailEntry::thread_id).collect();
+        assert_eq!(threads.len(), 2, "the reply joins the thread of the first");
+
+        // A reply of another user never joins the thread, even if the owner
+        // names would match as LIKE pattern
+        let other_path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("objectid_localhost")
+            .join("INBOX");
+        storage.create_dirs(&other_path).unwrap();
+        storage
+            .store_new(
+                String::from("objectid_localhost/INBOX"),
+                &other_path,
+                reply,
+                None,
+            )
+            .await
+            .unwrap();
+        let other = storage
+            .list_all(String::from("objectid_localhost/INBOX"), &other_path)
+            .await;
+        assert!(!threads.contains(other[0].thread_id().unwrap()));
+
+        let inbox = MailboxName::from_id(&format!("{user}/INBOX")).unwrap();
+        let archive = MailboxName::from_id(&format!("{user}/.Archive")).unwrap();
+        let mailbox_id = storage.mailbox_object_id(&inbox).await.unwrap();
+        assert!(mailbox_id.starts_with('F'));
+        assert_eq!(storage.mailbox_object_id(&inbox).await.unwrap(), mailbox_id);
+
+        let email_id = storage
+            .find(&path, &first_id)
+            .await
+            .unwrap()
+            .email_id()
+            .map(ToString::to_string);
+        storage.move_mailbox(&inbox, Some(&archive)).await.unwrap();
+        assert_eq!(
+            storage.mailbox_object_id(&archive).await.unwrap(),
+            mailbox_id
+        );
+        let archived = storage.list_all(archive.id(), &path).await;
+        assert_eq!(archived.len(), 3);
+        let first = archived.iter().find(|mail| mail.id() == first_id).unwrap();
+        assert_eq!(first.uid(), 1);
+        assert_eq!(first.email_id().map(ToString::to_string), email_id);
+
+        storage.move_mailbox(&archive, None).await.unwrap();
+        assert_ne!(
+            storage.mailbox_object_id(&archive).await.unwrap(),
+            mailbox_id
+        );
+    }
 }
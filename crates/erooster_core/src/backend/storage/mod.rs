@@ -2,7 +2,17 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::path::{Path, PathBuf};
+use crate::backend::{
+    acl::{AclEntry, MailboxName, Rights},
+    events::EventBus,
+    fulltext::TextField,
+    metadata::{EntryName, MetadataEntry},
+    quota::QuotaUsage,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 use {
     color_eyre,
     mailparse::{MailHeader, ParsedMail},
@@ -62,6 +72,14 @@ pub trait MailEntry {
     fn sent(&mut self) -> color_eyre::eyre::Result<i64>;
     /// The flags of the email
     fn flags(&self) -> &str;
+    /// The user-defined keywords of the email (e.g. `$Junk`)
+    fn keywords(&self) -> &[String];
+    /// The EMAILID of the email (RFC 8474)
+    fn email_id(&self) -> Option<&str>;
+    /// The THREADID of the email (RFC 8474)
+    fn thread_id(&self) -> Option<&str>;
+    /// When the email was saved in its mailbox as unix timestamp (RFC 8514)
+    fn save_date(&self) -> Option<i64>;
     /// Whether the email is a draft
     fn is_draft(&self) -> bool;
     /// Whether the email is flagged
@@ -78,7 +96,7 @@ pub trait MailEntry {
     fn path(&self) -> &PathBuf;
     /// If the whole message contains a certain string (case sensitive)
     fn text_contains(&mut self, string: &str) -> bool;
-    /// If the body contains a certain string (case sensitive)
+    /// If the decoded text parts or attachment names contain a certain string (case sensitive)
     fn body_contains(&mut self, string: &str) -> bool;
     /// The size of the raw body in octets
     fn body_size(&mut self) -> u64;
@@ -92,6 +110,16 @@ pub trait MailEntry {
 pub trait MailStorage<M: MailEntry> {
     /// Get the highest assigned UID in the folder (or 0 if empty).
     async fn get_uid_for_folder(&self, mailbox: &str) -> color_eyre::eyre::Result<u32>;
+    /// Get the highest modification sequence of the folder (RFC 7162).
+    ///
+    /// Folders that never saw a change report 1.
+    async fn get_highest_modseq(&self, mailbox: &str) -> color_eyre::eyre::Result<u64>;
+    /// Get the UIDs expunged from the folder with a modification sequence above `modseq`.
+    async fn get_vanished_since(
+        &self,
+        mailbox: &str,
+        modseq: u64,
+    ) -> color_eyre::eyre::Result<Vec<u32>>;
     /// Get the current flags for the folder
     async fn get_flags(&self, path: &Path) -> std::io::Result<Vec<String>>;
     /// Set a new flag for the folder
@@ -116,6 +144,14 @@ pub trait MailStorage<M: MailEntry> {
         data: &[u8],
         flags: Vec<String>,
     ) -> color_eyre::eyre::Result<String>;
+    /// Store several messages at once, either all of them or none as
+    /// MULTIAPPEND (RFC 3502) requires. Returns the id and UID of each message.
+    async fn store_all_cur_with_flags(
+        &self,
+        mailbox: String,
+        path: &Path,
+        messages: &[(&[u8], Vec<String>)],
+    ) -> color_eyre::eyre::Result<Vec<(String, u32)>>;
     /// List the subfolders
     fn list_subdirs(&self, path: &Path) -> color_eyre::eyre::Result<Vec<PathBuf>>;
     /// Count of current messages
@@ -131,26 +167,140 @@ pub trait MailStorage<M: MailEntry> {
     /// Get message by non unique id
     async fn find(&self, path: &Path, id: &str) -> Option<M>;
     /// Move mail to current folder and set flags
-    fn move_new_to_cur_with_flags(
+    async fn move_new_to_cur_with_flags(
         &self,
         path: &Path,
         id: &str,
         imap_flags: &[&str],
     ) -> color_eyre::eyre::Result<()>;
     /// Add flags to email
-    fn add_flags(&self, path: &Path, id: &str, imap_flags: &[&str])
-        -> color_eyre::eyre::Result<()>;
+    async fn add_flags(
+        &self,
+        path: &Path,
+        id: &str,
+        imap_flags: &[&str],
+    ) -> color_eyre::eyre::Result<()>;
     /// Set flags of an email
-    fn set_flags(&self, path: &Path, id: &str, imap_flags: &[&str])
-        -> color_eyre::eyre::Result<()>;
+    async fn set_flags(
+        &self,
+        path: &Path,
+        id: &str,
+        imap_flags: &[&str],
+    ) -> color_eyre::eyre::Result<()>;
     /// Remove flags of an email
-    fn remove_flags(
+    async fn remove_flags(
         &self,
         path: &Path,
         id: &str,
         imap_flags: &[&str],
     ) -> color_eyre::eyre::Result<()>;
-    /// Converts the imap path to a local path
+    /// Get the keywords used by any message in the folder
+    async fn get_keywords(&self, mailbox: &str) -> color_eyre::eyre::Result<Vec<String>>;
+    /// Permanently remove an email and remember its UID as vanished
+    async fn expunge(&self, path: &Path, id: &str) -> color_eyre::eyre::Result<()>;
+    /// The storage used by all mailboxes of the user
+    async fn get_quota_usage(&self, username: &str) -> color_eyre::eyre::Result<QuotaUsage>;
+    /// Whether `additional` messages can be stored for the user without
+    /// exceeding the user's quota
+    async fn quota_allows(
+        &self,
+        username: &str,
+        additional: &QuotaUsage,
+    ) -> color_eyre::eyre::Result<bool>;
+    /// The access control list of a mailbox (RFC 4314)
+    async fn get_acl(&self, mailbox: &MailboxName) -> color_eyre::eyre::Result<Vec<AclEntry>>;
+    /// Sets the rights of `identifier` on a mailbox. Empty rights remove the entry.
+    async fn set_acl(
+        &self,
+        mailbox: &MailboxName,
+        identifier: &str,
+        rights: Rights,
+    ) -> color_eyre::eyre::Result<()>;
+    /// The rights `username` has on a mailbox
+    async fn my_rights(
+        &self,
+        mailbox: &MailboxName,
+        username: &str,
+    ) -> color_eyre::eyre::Result<Rights>;
+    /// The existing mailboxes of other owners that `username` may look up
+    async fn shared_mailboxes(&self, username: &str) -> color_eyre::eyre::Result<Vec<MailboxName>>;
+    /// Moves the access control list of a renamed mailbox or, without a new
+    /// name, drops the list of a deleted one
+    async fn move_acl(
+        &self,
+        from: &MailboxName,
+        to: Option<&MailboxName>,
+    ) -> color_eyre::eyre::Result<()>;
+    /// The MAILBOXID of a mailbox (RFC 8474), assigned when first requested
+    async fn mailbox_object_id(&self, mailbox: &MailboxName) -> color_eyre::eyre::Result<String>;
+    /// Moves the messages and the MAILBOXID of a renamed mailbox or, without
+    /// a new name, drops the MAILBOXID of a deleted one
+    async fn move_mailbox(
+        &self,
+        from: &MailboxName,
+        to: Option<&MailboxName>,
+    ) -> color_eyre::eyre::Result<()>;
+    /// Gives the message `copy_id` the EMAILID and THREADID of `source_id`
+    /// as COPY and MOVE keep them (RFC 8474 §5.1)
+    async fn inherit_object_ids(
+        &self,
+        source_id: &str,
+        copy_id: &str,
+    ) -> color_eyre::eyre::Result<()>;
+    /// The annotations of a mailbox, or of the server without a mailbox (RFC 5464)
+    ///
+    /// Returns the private entries of `username` and all shared entries,
+    /// ordered by their name.
+    async fn get_metadata(
+        &self,
+        mailbox: Option<&MailboxName>,
+        username: &str,
+    ) -> color_eyre::eyre::Result<Vec<MetadataEntry>>;
+    /// Sets an annotation of a mailbox or the server. `None` removes the entry.
+    async fn set_metadata(
+        &self,
+        mailbox: Option<&MailboxName>,
+        username: &str,
+        entry: &EntryName,
+        value: Option<&str>,
+    ) -> color_eyre::eyre::Result<()>;
+    /// Moves the annotations of a renamed mailbox or, without a new name,
+    /// drops the annotations of a deleted one
+    async fn move_metadata(
+        &self,
+        from: &MailboxName,
+        to: Option<&MailboxName>,
+    ) -> color_eyre::eyre::Result<()>;
+    /// The ids of the messages the full-text index found `query` in
+    ///
+    /// The index covers all mailboxes of the owner of `mailbox`. The messages
+    /// still have to be checked as the index may return more messages than
+    /// match. Returns `None` if there is no index or it can not narrow down
+    /// the messages.
+    fn index_candidates(
+        &self,
+        mailbox: &str,
+        field: TextField,
+        query: &str,
+    ) -> color_eyre::eyre::Result<Option<HashSet<String>>>;
+    /// The ids of the messages of the owner of `mailbox` in the full-text index
+    ///
+    /// Messages are indexed in the background, so new messages may be
+    /// missing. Messages missing here are not covered by [`index_candidates`]
+    /// and have to be checked in full.
+    ///
+    /// [`index_candidates`]: MailStorage::index_candidates
+    fn indexed_messages(&self, mailbox: &str) -> color_eyre::eyre::Result<HashSet<String>>;
+    /// Rebuilds the full-text index of all mailboxes of `owner`
+    ///
+    /// Returns the number of indexed messages.
+    async fn reindex(&self, owner: &str) -> color_eyre::eyre::Result<usize>;
+    /// The bus that changes to stored messages are published to
+    fn events(&self) -> &EventBus;
+    /// Converts the imap path as seen by `username` to a local path
+    ///
+    /// Paths in the other users and shared namespaces resolve to the
+    /// directory of the mailbox owner.
     fn to_ondisk_path(&self, path: String, username: String) -> color_eyre::eyre::Result<PathBuf>;
     /// Converts the imap path to a local path name
     fn to_ondisk_path_name(&self, path: String) -> color_eyre::eyre::Result<String>;
@@ -0,0 +1,311 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Raw deflate transport compression as used by IMAP `COMPRESS=DEFLATE` (RFC 4978).
+//!
+//! The streams in here sit below the framed [`LinesCodec`](crate::line_codec::LinesCodec)
+//! so that the codec keeps operating on plain text while the bytes on the wire are compressed.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use {
+    bytes::{Buf, BytesMut},
+    flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status},
+    tokio::io::{AsyncRead, AsyncWrite, ReadBuf},
+};
+
+/// Size of the chunks read from the underlying stream
+const READ_CHUNK: usize = 8192;
+
+/// A stream which inflates everything read from and deflates everything written to `S`.
+///
+/// The deflate data is raw (RFC 1951) without a zlib or gzip header.
+/// Every flush ends the current deflate block with a sync flush so the peer
+/// is able to decode a response as soon as it was sent.
+pub struct DeflateStream<S> {
+    inner: S,
+    compress: Compress,
+    decompress: Decompress,
+    /// Compressed bytes which were read but not yet inflated
+    read_buf: BytesMut,
+    /// Compressed bytes which still need to be written to `inner`
+    write_buf: Vec<u8>,
+    /// Whether data was written since the last sync flush
+    needs_sync: bool,
+    read_eof: bool,
+}
+
+impl<S> DeflateStream<S> {
+    /// Wraps `inner`. `pending` holds compressed bytes which were already read
+    /// from `inner` before compression got enabled.
+    #[must_use]
+    pub fn new(inner: S, pending: &[u8]) -> Self {
+        DeflateStream {
+            inner,
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            read_buf: BytesMut::from(pending),
+            write_buf: Vec::new(),
+            needs_sync: false,
+            read_eof: false,
+        }
+    }
+
+    /// Returns the wrapped stream
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncWrite + Unpin> DeflateStream<S> {
+    /// Writes the buffered compressed bytes to the underlying stream
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buf.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write_buf))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buf.drain(..written);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Deflates `input` into the write buffer using the given flush mode
+    fn deflate(&mut self, mut input: &[u8], flush: FlushCompress) -> io::Result<()> {
+        loop {
+            self.write_buf.reserve(input.len().max(256));
+            let before = self.compress.total_in();
+            self.compress
+                .compress_vec(input, &mut self.write_buf, flush)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let consumed = usize::try_from(self.compress.total_in() - before)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            input = &input[consumed..];
+            // Output space left over means the compressor is done with this input
+            if input.is_empty() && self.write_buf.len() < self.write_buf.capacity() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            if !this.read_buf.is_empty() {
+                let before_in = this.decompress.total_in();
+                let before_out = this.decompress.total_out();
+                let status = this
+                    .decompress
+                    .decompress(
+                        &this.read_buf,
+                        buf.initialize_unfilled(),
+                        FlushDecompress::None,
+                    )
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let consumed = usize::try_from(this.decompress.total_in() - before_in)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let produced = usize::try_from(this.decompress.total_out() - before_out)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                this.read_buf.advance(consumed);
+                buf.advance(produced);
+                if produced > 0 || status == Status::StreamEnd {
+                    return Poll::Ready(Ok(()));
+                }
+                if consumed > 0 {
+                    continue;
+                }
+            }
+            if this.read_eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                this.read_eof = true;
+            } else {
+                this.read_buf.extend_from_slice(chunk_buf.filled());
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // Don't let the buffer grow unbounded if the peer isn't reading
+        ready!(this.poll_drain(cx))?;
+        this.deflate(data, FlushCompress::None)?;
+        this.needs_sync = true;
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.needs_sync {
+            this.deflate(&[], FlushCompress::Sync)?;
+            this.needs_sync = false;
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// A stream which starts out uncompressed and can be switched to deflate once.
+pub enum CompressibleStream<S> {
+    /// Data is passed through unchanged
+    Plain(S),
+    /// Data is compressed using raw deflate
+    Deflate(DeflateStream<S>),
+}
+
+impl<S> CompressibleStream<S> {
+    /// Switches the stream to deflate. `pending` holds bytes which were already
+    /// read from the stream (e.g. by a framed reader) after the switch point.
+    #[must_use]
+    pub fn into_deflate(self, pending: &[u8]) -> Self {
+        match self {
+            CompressibleStream::Plain(inner) => {
+                CompressibleStream::Deflate(DeflateStream::new(inner, pending))
+            }
+            deflate @ CompressibleStream::Deflate(_) => deflate,
+        }
+    }
+
+    /// Whether compression is active
+    #[must_use]
+    pub const fn is_compressed(&self) -> bool {
+        matches!(self, CompressibleStream::Deflate(_))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CompressibleStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            CompressibleStream::Plain(inner) => Pin::new(inner).poll_read(cx, buf),
+            CompressibleStream::Deflate(inner) => Pin::new(inner).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CompressibleStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            CompressibleStream::Plain(inner) => Pin::new(inner).poll_write(cx, data),
+            CompressibleStream::Deflate(inner) => Pin::new(inner).poll_write(cx, data),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            CompressibleStream::Plain(inner) => Pin::new(inner).poll_flush(cx),
+            CompressibleStream::Deflate(inner) => Pin::new(inner).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            CompressibleStream::Plain(inner) => Pin::new(inner).poll_shutdown(cx),
+            CompressibleStream::Deflate(inner) => Pin::new(inner).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::line_codec::LinesCodec;
+    use futures::{SinkExt, StreamExt};
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::Framed;
+
+    /// Raw deflates `data` with a sync flush, like a client would
+    fn client_deflate(compress: &mut Compress, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 64);
+        compress
+            .compress_vec(data, &mut out, FlushCompress::Sync)
+            .unwrap();
+        out
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn deflate_stream_roundtrips_lines() {
+        let (server, mut client) = duplex(64 * 1024);
+        let mut lines = Framed::new(
+            CompressibleStream::Plain(server),
+            LinesCodec::new_with_max_length(crate::LINE_LIMIT),
+        );
+
+        // The command and the first compressed bytes arrive in the same read
+        let mut client_compress = Compress::new(Compression::default(), false);
+        let mut first = b"a COMPRESS DEFLATE\r\n".to_vec();
+        first.extend(client_deflate(&mut client_compress, b"b NOOP\r\n"));
+        client.write_all(&first).await.unwrap();
+
+        assert_eq!(lines.next().await.unwrap().unwrap(), "a COMPRESS DEFLATE");
+        lines
+            .send(String::from("a OK DEFLATE active"))
+            .await
+            .unwrap();
+        let mut plain = [0u8; 21];
+        client.read_exact(&mut plain).await.unwrap();
+        assert_eq!(&plain, b"a OK DEFLATE active\r\n");
+
+        let parts = lines.into_parts();
+        assert!(!parts.io.is_compressed());
+        let io = parts.io.into_deflate(&parts.read_buf);
+        assert!(io.is_compressed());
+        let mut lines = Framed::new(io, LinesCodec::new_with_max_length(crate::LINE_LIMIT));
+
+        assert_eq!(lines.next().await.unwrap().unwrap(), "b NOOP");
+        let second = client_deflate(&mut client_compress, b"c CAPABILITY\r\n");
+        client.write_all(&second).await.unwrap();
+        assert_eq!(lines.next().await.unwrap().unwrap(), "c CAPABILITY");
+
+        lines
+            .send(String::from("b OK NOOP completed"))
+            .await
+            .unwrap();
+        let mut compressed = vec![0u8; 1024];
+        let read = client.read(&mut compressed).await.unwrap();
+        let mut client_decompress = Decompress::new(false);
+        let mut inflated = Vec::with_capacity(1024);
+        client_decompress
+            .decompress_vec(&compressed[..read], &mut inflated, FlushDecompress::Sync)
+            .unwrap();
+        assert_eq!(inflated, b"b OK NOOP completed\r\n");
+    }
+}
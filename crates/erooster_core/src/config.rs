@@ -20,6 +20,10 @@ const fn default_max_message_size() -> MessageSize {
     MessageSize(25 * 1_048_576) // 25 MB
 }
 
+fn default_username_claim() -> String {
+    String::from("email")
+}
+
 /// A message size value that can be written in the config as a human-readable
 /// string (`"25 MB"`, `"1 GB"`, `"500 KB"`) or as a plain number (bytes).
 ///
@@ -135,6 +139,20 @@ pub struct Config {
     /// Remove this section entirely if you are not running Rspamd.
     pub rspamd: Option<Rspamd>,
 
+    /// Optional full-text index that speeds up searching in large mailboxes.
+    ///
+    /// Remove this section to search by reading the messages instead.
+    #[serde(default)]
+    pub search_index: Option<SearchIndex>,
+
+    /// Optional single sign-on with an OAuth 2.0 identity provider.
+    ///
+    /// When configured, mail clients can log in with an access token of the
+    /// provider using the `OAUTHBEARER` and `XOAUTH2` mechanisms. Remove this
+    /// section to only allow logins with a password.
+    #[serde(default)]
+    pub oauth: Option<OAuth>,
+
     /// Folder on disk where background task state is kept.
     ///
     /// This is used internally by the mail queue. You usually do not need to
@@ -222,6 +240,75 @@ pub struct Rspamd {
     pub address: String,
 }
 
+/// Full-text search index settings.
+///
+/// When configured, Erooster indexes every message as it is stored so IMAP
+/// `SEARCH` does not need to read whole mailboxes. Messages stored before the
+/// index was enabled can be added with `eroosterctl mailbox reindex`. The
+/// server keeps the index open for writing, so stop it before reindexing.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(crate = "self::serde")]
+pub struct SearchIndex {
+    /// Folder on disk where the index is kept.
+    ///
+    /// Each user gets their own sub-folder here. Make sure the folder exists
+    /// and is writable by the server process.
+    ///
+    /// Example: `"/var/lib/erooster/search_index"`
+    pub path: String,
+}
+
+/// OAuth 2.0 single sign-on settings.
+///
+/// An access token is accepted when it is valid and names an existing
+/// Erooster user in `username_claim`. Tokens in the JSON Web Token format are
+/// checked against the signing keys at `jwks_url`, all other tokens are sent
+/// to the `introspection_url` of the provider (RFC 7662). Configure at least
+/// one of both.
+///
+/// Example for a Keycloak realm:
+/// ```yaml
+/// oauth:
+///   jwks_url: "https://sso.example.com/realms/mail/protocol/openid-connect/certs"
+///   issuer: "https://sso.example.com/realms/mail"
+///   audience: "erooster"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(crate = "self::serde")]
+pub struct OAuth {
+    /// URL of the JSON Web Key Set with the signing keys of the provider.
+    ///
+    /// Tokens signed with `RS256` or `ES256` are supported.
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+
+    /// URL of the token introspection endpoint of the provider.
+    #[serde(default)]
+    pub introspection_url: Option<String>,
+
+    /// Client id Erooster authenticates with at the introspection endpoint.
+    #[serde(default)]
+    pub client_id: Option<String>,
+
+    /// Client secret Erooster authenticates with at the introspection endpoint.
+    #[serde(default)]
+    pub client_secret: Option<String>,
+
+    /// The issuer (`iss`) the tokens must have. Leave this out to accept
+    /// tokens of any issuer using the keys above.
+    #[serde(default)]
+    pub issuer: Option<String>,
+
+    /// The audience (`aud`) the tokens must be issued for. Leave this out to
+    /// accept tokens for any audience.
+    #[serde(default)]
+    pub audience: Option<String>,
+
+    /// The claim holding the email address of the user. Defaults to `email`.
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+}
+
 /// Core mail server settings.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(crate = "self::serde")]
@@ -236,7 +323,8 @@ pub struct Mail {
 
     /// The domain name of your mail server (what appears after the `@`).
     ///
-    /// This must match the domain you set up MX records for.
+    /// This must match the domain you set up MX records for. It is always
+    /// hosted; further domains are added with `eroosterctl domain add`.
     ///
     /// Example: `"mail.example.com"`
     pub hostname: String,
@@ -291,6 +379,55 @@ pub struct Mail {
     /// Accepted units: `B`, `KB`, `MB`, `GB`, `TB` (and `KiB`/`MiB`/`GiB`/`TiB`).
     #[serde(default = "default_max_message_size")]
     pub max_message_size: MessageSize,
+
+    /// Accounts that may administer other accounts over IMAP, for example to
+    /// change their quota with `SETQUOTA`. Admins may also log in as another
+    /// user with their own password by naming that user as the SASL
+    /// authorization identity.
+    ///
+    /// Leave this out if nobody should have administrative rights. Quotas can
+    /// then only be managed directly in the database.
+    ///
+    /// ```yaml
+    /// admins:
+    ///   - "postmaster@example.com"
+    /// ```
+    #[serde(default)]
+    pub admins: Vec<String>,
+
+    /// Secret for rewriting the sender of forwarded mail with SRS (Sender
+    /// Rewriting Scheme).
+    ///
+    /// Mail forwarded by an alias to an external address keeps SPF passing
+    /// by being sent as `SRS0=…@your-domain`, and bounces to that address are
+    /// routed back to the original sender. Use a long random string and keep
+    /// it when restarting, otherwise bounces of recently forwarded mail are
+    /// rejected.
+    ///
+    /// Leave this out to forward with the original sender, which makes SPF
+    /// fail at the receiving server.
+    ///
+    /// ```yaml
+    /// srs_secret: "change me to something long and random"
+    /// ```
+    #[serde(default)]
+    pub srs_secret: Option<String>,
+
+    /// Secret for the SCRAM salts of unknown users.
+    ///
+    /// SCRAM tells the client the salt of a user before checking the
+    /// password. Unknown users get a salt derived from this secret and their
+    /// name, which looks like the salt of an existing user. Use a long random
+    /// string and keep it when restarting.
+    ///
+    /// Leave this out to choose a new secret whenever the server starts, which
+    /// changes the salts of unknown users with every restart.
+    ///
+    /// ```yaml
+    /// scram_secret: "change me to something long and random"
+    /// ```
+    #[serde(default)]
+    pub scram_secret: Option<String>,
 }
 
 impl Config {
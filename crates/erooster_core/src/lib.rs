@@ -29,6 +29,9 @@ pub mod line_codec;
 /// An custom panic handler for erooster
 pub mod panic_handler;
 
+/// Transport compression for the line based protocols
+pub mod compression;
+
 /// The backend logic of the server
 pub mod backend;
 
@@ -39,6 +42,12 @@ pub mod test_helpers;
 /// The configuration file for the server
 pub mod config;
 
+/// SASL authentication for the IMAP and SMTP servers
+pub mod sasl;
+
+/// Sender rewriting for forwarded mail
+pub mod srs;
+
 /// Returns the config struct from the provided location or defaults
 #[instrument(skip(config_path))]
 pub async fn get_config(config_path: String) -> Result<config::Config> {
@@ -3,6 +3,8 @@
 // SPDX-License-Identifier: Apache-2.0
 // SPDX-License-Identifier: MIT
 
+use crate::BASE64_DECODER;
+use base64::Engine;
 use std::{cmp, fmt, io, str};
 use {
     bytes::{Buf, BufMut, BytesMut},
@@ -10,6 +12,9 @@ use {
     tokio_util::codec::{Decoder, Encoder},
 };
 
+/// Encloses octets which a line carries unchanged, see [`raw`]
+const RAW: char = '\0';
+
 /// A simple [`Decoder`] and [`Encoder`] implementation that splits up data into lines.
 ///
 /// [`Decoder`]: crate::codec::Decoder
@@ -31,6 +36,10 @@ pub struct LinesCodec {
     /// Are we currently discarding the remainder of a line which was over
     /// the length limit?
     is_discarding: bool,
+
+    /// Whether lines may carry octets which aren't UTF-8 text, see
+    /// [`LinesCodec::binary`]
+    binary: bool,
 }
 
 impl LinesCodec {
@@ -50,6 +59,7 @@ impl LinesCodec {
             next_index: 0,
             max_length: usize::MAX,
             is_discarding: false,
+            binary: false,
         }
     }
 
@@ -99,6 +109,91 @@ impl LinesCodec {
     pub const fn max_length(&self) -> usize {
         self.max_length
     }
+
+    /// Lets lines carry arbitrary octets, as needed for the literals of IMAP
+    /// BINARY (RFC 3516).
+    ///
+    /// Octets embedded with [`raw`] are written unchanged. A received line
+    /// which isn't UTF-8 text, contains NUL or doesn't end with CRLF is
+    /// returned as [`raw`] octets including its line break. [`octets`] and
+    /// [`text`] turn these lines back into octets or text.
+    #[must_use]
+    pub const fn binary(self) -> Self {
+        LinesCodec {
+            binary: true,
+            ..self
+        }
+    }
+
+    /// Turns the octets of a received line into the item
+    fn line(&self, line: &[u8], line_break: &[u8]) -> Result<String, LinesCodecError> {
+        if self.binary {
+            let text = from_utf8(line).ok().filter(|text| !text.contains(RAW));
+            match text {
+                Some(text) if line_break == b"\r\n" || line_break.is_empty() => {
+                    Ok(text.to_string())
+                }
+                _ => Ok(raw(&[line, line_break].concat())),
+            }
+        } else {
+            Ok(utf8(line)?.to_string())
+        }
+    }
+}
+
+/// Embeds octets into a line. A [`LinesCodec::binary`] codec writes them
+/// unchanged, even if they aren't UTF-8 text.
+#[must_use]
+pub fn raw(octets: &[u8]) -> String {
+    format!("{RAW}{}{RAW}", BASE64_DECODER.encode(octets))
+}
+
+/// The octets of a line, with the [`raw`] parts decoded
+fn expand(line: &str) -> Vec<u8> {
+    let mut octets = Vec::with_capacity(line.len());
+    for (index, part) in line.split(RAW).enumerate() {
+        if index % 2 == 0 {
+            octets.extend(part.as_bytes());
+            continue;
+        }
+        match BASE64_DECODER.decode(part) {
+            Ok(decoded) => octets.extend(decoded),
+            // Not written by `raw`, keep the line as it was
+            Err(_) => octets.extend(format!("{RAW}{part}{RAW}").as_bytes()),
+        }
+    }
+    octets
+}
+
+/// The octets of a line as they are sent or were received, including the
+/// line break
+#[must_use]
+pub fn octets(line: &str) -> Vec<u8> {
+    if line.starts_with(RAW) {
+        // Received lines which aren't text include their line break
+        expand(line)
+    } else {
+        let mut octets = expand(line);
+        octets.extend(b"\r\n");
+        octets
+    }
+}
+
+/// The text of a received line without the line break, or `None` if the
+/// line isn't UTF-8 text
+#[must_use]
+pub fn text(line: String) -> Option<String> {
+    if !line.starts_with(RAW) {
+        return Some(line);
+    }
+    let octets = expand(&line);
+    let octets = octets
+        .strip_suffix(b"\r\n")
+        .or_else(|| octets.strip_suffix(b"\n"))
+        .unwrap_or(&octets);
+    String::from_utf8(octets.to_vec())
+        .ok()
+        .filter(|text| !text.contains(RAW))
 }
 
 fn utf8(buf: &[u8]) -> Result<&str, io::Error> {
@@ -152,10 +247,9 @@ impl Decoder for LinesCodec {
                     let newline_index = offset + self.next_index;
                     self.next_index = 0;
                     let line = buf.split_to(newline_index + 1);
-                    let line = &line[..line.len() - 1];
-                    let line = without_carriage_return(line);
-                    let line = utf8(line)?;
-                    return Ok(Some(line.into()));
+                    let content = without_carriage_return(&line[..line.len() - 1]);
+                    let line = self.line(content, &line[content.len()..])?;
+                    return Ok(Some(line));
                 }
                 (false, None) if buf.len() > self.max_length => {
                     // Reached the maximum length without finding a
@@ -183,10 +277,10 @@ impl Decoder for LinesCodec {
                     None
                 } else {
                     let line_bytes = buf.split_to(buf.len());
-                    let line_bytes = without_carriage_return(&line_bytes);
-                    let line = utf8(line_bytes)?;
+                    let content = without_carriage_return(&line_bytes);
+                    let line = self.line(content, &line_bytes[content.len()..])?;
                     self.next_index = 0;
-                    Some(line.into())
+                    Some(line)
                 }
             }
         })
@@ -198,7 +292,11 @@ impl Encoder<String> for LinesCodec {
 
     fn encode(&mut self, line: String, buf: &mut BytesMut) -> Result<(), LinesCodecError> {
         buf.reserve(line.len() + 2);
-        buf.put(line.as_bytes());
+        if self.binary && line.contains(RAW) {
+            buf.put(expand(&line).as_slice());
+        } else {
+            buf.put(line.as_bytes());
+        }
         buf.put_u8(b'\r');
         buf.put_u8(b'\n');
         //debug!("sending line: {:?}", buf);
@@ -237,3 +335,43 @@ impl From<io::Error> for LinesCodecError {
 }
 
 impl std::error::Error for LinesCodecError {}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[test]
+    fn binary_lines_keep_their_octets() {
+        let mut codec = LinesCodec::new().binary();
+        let mut buf = BytesMut::from(&b"a1 NOOP\r\n\xff\0abc\r\ndef\nghi"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), "a1 NOOP");
+
+        let line = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(octets(&line), b"\xff\0abc\r\n");
+        assert!(text(line).is_none());
+
+        // A bare LF is kept as well, the text drops it
+        let line = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(octets(&line), b"def\n");
+        assert_eq!(text(line).unwrap(), "def");
+
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(codec.decode_eof(&mut buf).unwrap().unwrap(), "ghi");
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[test]
+    fn binary_lines_are_written_unchanged() {
+        let mut buf = BytesMut::new();
+        let line = format!("* 1 FETCH (BINARY[1] ~{{3}}\r\n{})", raw(b"\xff\0a"));
+        LinesCodec::new().binary().encode(line, &mut buf).unwrap();
+        assert_eq!(&buf[..], b"* 1 FETCH (BINARY[1] ~{3}\r\n\xff\0a)\r\n");
+
+        // Text codecs don't interpret the octets
+        let mut codec = LinesCodec::new();
+        let mut buf = BytesMut::from(&b"\xff\r\n"[..]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Failed authentication attempts of all protocols.
+//!
+//! A client address which failed [`MAX_FAILURES`] times for a user is locked
+//! out of that user for [`LOCKOUT`], even with the right password, which
+//! slows down guessing passwords over many connections. The same user can
+//! still log in from other addresses, so nobody can lock others out of their
+//! account. Failures are counted and reported the same way whether the user
+//! exists or not, so a lockout doesn't tell which accounts exist. The counts
+//! are kept in memory for at most [`MAX_ENTRIES`] pairs of user and address
+//! and start over when the server restarts.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The failed attempts after which a client address is locked out
+pub const MAX_FAILURES: u32 = 5;
+/// How long failed attempts are remembered and a client address stays locked
+/// out
+pub const LOCKOUT: Duration = Duration::from_mins(15);
+/// How many pairs of user and client address are remembered. Beyond that the
+/// least recent ones are forgotten.
+pub const MAX_ENTRIES: usize = 100_000;
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// The failures by user and client address
+static FAILURES: Mutex<BTreeMap<(String, String), Failures>> = Mutex::new(BTreeMap::new());
+
+fn key(username: &str, peer: &str) -> (String, String) {
+    (username.to_lowercase(), peer.to_string())
+}
+
+/// Whether the client address failed too often for the user recently
+pub fn is_locked(username: &str, peer: &str) -> bool {
+    let failures = FAILURES.lock().unwrap_or_else(PoisonError::into_inner);
+    failures
+        .get(&key(username, peer))
+        .is_some_and(|failures| failures.count >= MAX_FAILURES && failures.last.elapsed() < LOCKOUT)
+}
+
+/// Records a failed attempt and returns whether the client address is locked
+/// out of the user now
+pub fn record_failure(username: &str, peer: &str) -> bool {
+    let mut failures = FAILURES.lock().unwrap_or_else(PoisonError::into_inner);
+    // Forget old attempts so the map does not grow without bound
+    failures.retain(|_, failures| failures.last.elapsed() < LOCKOUT);
+    let key = key(username, peer);
+    if failures.len() >= MAX_ENTRIES && !failures.contains_key(&key) {
+        let oldest = failures
+            .iter()
+            .min_by_key(|(_, failures)| failures.last)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            failures.remove(&oldest);
+        }
+    }
+    let entry = failures.entry(key).or_insert(Failures {
+        count: 0,
+        last: Instant::now(),
+    });
+    entry.count += 1;
+    entry.last = Instant::now();
+    entry.count >= MAX_FAILURES
+}
+
+/// Forgets the failed attempts of the client address after a successful login
+pub fn reset(username: &str, peer: &str) {
+    let mut failures = FAILURES.lock().unwrap_or_else(PoisonError::into_inner);
+    failures.remove(&key(username, peer));
+}
@@ -0,0 +1,35 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The LOGIN mechanism (draft-murchison-sasl-login).
+
+use crate::sasl::{plain::verify, Context, Outcome, SaslMechanism};
+use color_eyre::eyre::Result;
+use simdutf8::compat::from_utf8;
+
+/// Asks for the user name and then for the password
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Login {
+    username: Option<String>,
+}
+
+impl SaslMechanism for Login {
+    fn initial_challenge(&self) -> Vec<u8> {
+        b"Username:".to_vec()
+    }
+
+    async fn step(&mut self, context: &Context<'_>, response: &[u8]) -> Result<Outcome> {
+        let Ok(response) = from_utf8(response) else {
+            return Ok(Outcome::Failed(self.username.take()));
+        };
+        match self.username.take() {
+            None if response.is_empty() => Ok(Outcome::Failed(None)),
+            None => {
+                self.username = Some(response.to_string());
+                Ok(Outcome::Challenge(b"Password:".to_vec()))
+            }
+            Some(username) => verify(context, &username, response, "").await,
+        }
+    }
+}
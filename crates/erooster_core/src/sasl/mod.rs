@@ -0,0 +1,856 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! SASL mechanisms shared by the IMAP and SMTP servers.
+//!
+//! The protocols only relay the client responses to an [`Exchange`], send
+//! back the challenges it returns and report its [`AuthError`]s with their own
+//! response codes. That way IMAP and SMTP offer the same mechanisms and share
+//! the accounting of failed attempts.
+
+use crate::{
+    backend::database::{Database, DB},
+    config::Config,
+    BASE64_DECODER,
+};
+use base64::Engine;
+use color_eyre::eyre::{eyre, Result};
+use ring::digest;
+use tracing::{debug, error, info, instrument};
+
+mod attempts;
+pub mod login;
+pub mod oauth;
+pub mod plain;
+pub mod scram;
+
+/// The SASL mechanisms of the servers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    /// PLAIN (RFC 4616)
+    Plain,
+    /// The obsolete LOGIN mechanism many mail clients still use
+    Login,
+    /// SCRAM-SHA-256 (RFC 7677)
+    ScramSha256,
+    /// SCRAM-SHA-256 bound to the TLS channel (RFC 7677, RFC 5929)
+    ScramSha256Plus,
+    /// OAUTHBEARER (RFC 7628)
+    OAuthBearer,
+    /// The OAuth 2.0 mechanism of Google and Microsoft
+    XOAuth2,
+}
+
+impl Mechanism {
+    /// Looks up a mechanism by the name the client sent
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "PLAIN" => Some(Mechanism::Plain),
+            "LOGIN" => Some(Mechanism::Login),
+            "SCRAM-SHA-256" => Some(Mechanism::ScramSha256),
+            "SCRAM-SHA-256-PLUS" => Some(Mechanism::ScramSha256Plus),
+            "OAUTHBEARER" => Some(Mechanism::OAuthBearer),
+            "XOAUTH2" => Some(Mechanism::XOAuth2),
+            _ => None,
+        }
+    }
+
+    /// The name of the mechanism as advertised to clients
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::Login => "LOGIN",
+            Mechanism::ScramSha256 => "SCRAM-SHA-256",
+            Mechanism::ScramSha256Plus => "SCRAM-SHA-256-PLUS",
+            Mechanism::OAuthBearer => "OAUTHBEARER",
+            Mechanism::XOAuth2 => "XOAUTH2",
+        }
+    }
+
+    /// Whether the mechanism sends the password or token in the clear, or
+    /// binds to the TLS channel, and may only be used on encrypted connections
+    #[must_use]
+    pub const fn requires_encryption(self) -> bool {
+        !matches!(self, Mechanism::ScramSha256)
+    }
+
+    /// The mechanisms offered on encrypted connections. The OAuth mechanisms
+    /// need an identity provider in the config.
+    #[must_use]
+    pub fn available(config: &Config) -> Vec<Self> {
+        let mut mechanisms = vec![
+            Mechanism::Plain,
+            Mechanism::Login,
+            Mechanism::ScramSha256,
+            Mechanism::ScramSha256Plus,
+        ];
+        if config.oauth.is_some() {
+            mechanisms.extend([Mechanism::OAuthBearer, Mechanism::XOAuth2]);
+        }
+        mechanisms
+    }
+}
+
+/// The protocol an exchange logs in to, app passwords may be limited to one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Reading mail
+    Imap,
+    /// Sending mail with SMTP submission
+    Smtp,
+}
+
+/// Why an authentication failed. The protocols report each reason with their
+/// own response codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// The mechanism is unknown or not configured
+    UnsupportedMechanism,
+    /// The mechanism sends secrets in the clear and the connection is not
+    /// encrypted
+    EncryptionRequired,
+    /// A client response could not be decoded
+    Malformed,
+    /// The client canceled the exchange
+    Canceled,
+    /// Unknown user, wrong password or invalid token
+    InvalidCredentials,
+    /// The user may not act as the requested user
+    NotAuthorized,
+    /// The user failed too often and has to wait
+    TooManyAttempts,
+    /// The database or identity provider is unreachable
+    Unavailable,
+}
+
+/// What to do after relaying a client response to an [`Exchange`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Send the base64 encoded challenge and relay the next client response
+    Challenge(String),
+    /// The client is logged in as the user
+    Success(String),
+    /// The authentication failed
+    Failure(AuthError),
+}
+
+/// The users a mechanism authenticated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// The user whose credentials were checked
+    pub authcid: String,
+    /// The user the client wants to act as, if it told
+    pub authzid: Option<String>,
+}
+
+/// What a mechanism found out about a client response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The client has to answer the challenge
+    Challenge(Vec<u8>),
+    /// The credentials are valid
+    Authenticated(Identity),
+    /// The credentials of the user, if known, are invalid
+    Failed(Option<String>),
+}
+
+/// The surroundings of an exchange
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    /// The server configuration
+    pub config: &'a Config,
+    /// The database holding the users
+    pub database: &'a DB,
+    /// The protocol the client logs in to
+    pub protocol: Protocol,
+    /// Whether the connection is encrypted
+    pub secure: bool,
+}
+
+/// A SASL mechanism
+///
+/// Mechanisms only check credentials. Decoding, authorization and the
+/// accounting of failed attempts are done by the [`Exchange`] for all of them.
+#[allow(async_fn_in_trait)]
+pub trait SaslMechanism {
+    /// The challenge sent when the client starts without an initial response
+    fn initial_challenge(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Handles the decoded response of the client. Errors mean the
+    /// authentication is temporarily impossible.
+    async fn step(&mut self, context: &Context<'_>, response: &[u8]) -> Result<Outcome>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Running {
+    Plain(plain::Plain),
+    Login(login::Login),
+    Scram(scram::Scram),
+    Bearer(oauth::Bearer),
+}
+
+/// A running authentication of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    mechanism: Mechanism,
+    protocol: Protocol,
+    secure: bool,
+    peer: String,
+    running: Running,
+}
+
+impl Exchange {
+    /// Starts an exchange with the mechanism the client named. `secure`
+    /// tells whether the connection is encrypted, `peer` is the address of
+    /// the client.
+    pub fn start(
+        config: &Config,
+        protocol: Protocol,
+        name: &str,
+        secure: bool,
+        peer: &str,
+    ) -> Result<Self, AuthError> {
+        let Some(mechanism) = Mechanism::from_name(name)
+            .filter(|mechanism| Mechanism::available(config).contains(mechanism))
+        else {
+            return Err(AuthError::UnsupportedMechanism);
+        };
+        if mechanism.requires_encryption() && !secure {
+            return Err(AuthError::EncryptionRequired);
+        }
+        let running = match mechanism {
+            Mechanism::Plain => Running::Plain(plain::Plain),
+            Mechanism::Login => Running::Login(login::Login::default()),
+            Mechanism::ScramSha256 => Running::Scram(scram::Scram::new(false)),
+            Mechanism::ScramSha256Plus => Running::Scram(scram::Scram::new(true)),
+            Mechanism::OAuthBearer | Mechanism::XOAuth2 => {
+                Running::Bearer(oauth::Bearer::new(mechanism))
+            }
+        };
+        Ok(Exchange {
+            mechanism,
+            protocol,
+            secure,
+            peer: peer.to_string(),
+            running,
+        })
+    }
+
+    /// The mechanism of the exchange
+    #[must_use]
+    pub const fn mechanism(&self) -> Mechanism {
+        self.mechanism
+    }
+
+    /// The base64 encoded challenge for clients without an initial response
+    #[must_use]
+    pub fn initial_challenge(&self) -> String {
+        let challenge = match &self.running {
+            Running::Plain(mechanism) => mechanism.initial_challenge(),
+            Running::Login(mechanism) => mechanism.initial_challenge(),
+            Running::Scram(mechanism) => mechanism.initial_challenge(),
+            Running::Bearer(mechanism) => mechanism.initial_challenge(),
+        };
+        BASE64_DECODER.encode(challenge)
+    }
+
+    /// Relays a client response as sent on the wire: base64, `=` for an
+    /// empty initial response or `*` to cancel.
+    #[instrument(skip(self, config, database, line))]
+    pub async fn respond(&mut self, config: &Config, database: &DB, line: &str) -> Step {
+        let line = line.trim_end();
+        if line == "*" {
+            return Step::Failure(AuthError::Canceled);
+        }
+        let response = if line == "=" {
+            Vec::new()
+        } else {
+            match BASE64_DECODER.decode(line) {
+                Ok(response) => response,
+                Err(_) => return Step::Failure(AuthError::Malformed),
+            }
+        };
+
+        let context = Context {
+            config,
+            database,
+            protocol: self.protocol,
+            secure: self.secure,
+        };
+        let outcome = match &mut self.running {
+            Running::Plain(mechanism) => mechanism.step(&context, &response).await,
+            Running::Login(mechanism) => mechanism.step(&context, &response).await,
+            Running::Scram(mechanism) => mechanism.step(&context, &response).await,
+            Running::Bearer(mechanism) => mechanism.step(&context, &response).await,
+        };
+        match outcome {
+            Ok(Outcome::Challenge(challenge)) => Step::Challenge(BASE64_DECODER.encode(challenge)),
+            Ok(Outcome::Authenticated(identity)) => authorize(&context, &self.peer, identity).await,
+            Ok(Outcome::Failed(authcid)) => {
+                debug!("[SASL] {} failed", self.mechanism.name());
+                // Unknown users are counted as well, so the responses don't
+                // tell which accounts exist
+                let locked = authcid.is_some_and(|authcid| {
+                    attempts::is_locked(&authcid, &self.peer)
+                        || attempts::record_failure(&authcid, &self.peer)
+                });
+                Step::Failure(if locked {
+                    AuthError::TooManyAttempts
+                } else {
+                    AuthError::InvalidCredentials
+                })
+            }
+            Err(e) => {
+                error!("[SASL] Unable to authenticate: {e}");
+                Step::Failure(AuthError::Unavailable)
+            }
+        }
+    }
+}
+
+/// Decides as whom an authenticated client is logged in. Admins may act as
+/// any other user by sending its name as authorization identity.
+async fn authorize(context: &Context<'_>, peer: &str, identity: Identity) -> Step {
+    let Identity { authcid, authzid } = identity;
+    if attempts::is_locked(&authcid, peer) {
+        return Step::Failure(AuthError::TooManyAttempts);
+    }
+    attempts::reset(&authcid, peer);
+    match authzid {
+        Some(authzid) if authzid != authcid => {
+            let admin = context.config.mail.admins.contains(&authcid);
+            if admin && context.database.user_exists(&authzid).await {
+                info!("[SASL] {authcid} logged in as {authzid}");
+                Step::Success(authzid)
+            } else {
+                Step::Failure(AuthError::NotAuthorized)
+            }
+        }
+        _ => Step::Success(authcid),
+    }
+}
+
+/// The `tls-server-end-point` channel binding data (RFC 5929 §4.1): the
+/// SHA-256 hash of the first certificate in `tls.cert_path`.
+///
+/// Certificates signed with SHA-384 or SHA-512 would need those hashes
+/// instead, they are not supported for SCRAM-SHA-256-PLUS.
+pub async fn tls_server_end_point(config: &Config) -> Result<Vec<u8>> {
+    let pem = tokio::fs::read_to_string(&config.tls.cert_path).await?;
+    let certificate: String = pem
+        .lines()
+        .skip_while(|line| line.trim() != "-----BEGIN CERTIFICATE-----")
+        .skip(1)
+        .take_while(|line| line.trim() != "-----END CERTIFICATE-----")
+        .map(str::trim)
+        .collect();
+    if certificate.is_empty() {
+        return Err(eyre!("No certificate found in {}", config.tls.cert_path));
+    }
+    let der = BASE64_DECODER.decode(certificate)?;
+    Ok(digest::digest(&digest::SHA256, &der).as_ref().to_vec())
+}
+
+/// Decodes the `=2C` and `=3D` escapes of a SASL name (RFC 5802 §5.1)
+fn decode_saslname(name: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(position) = rest.find('=') {
+        decoded.push_str(&rest[..position]);
+        match rest.get(position..position + 3)? {
+            "=2C" => decoded.push(','),
+            "=3D" => decoded.push('='),
+            _ => return None,
+        }
+        rest = &rest[position + 3..];
+    }
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::app_passwords, config::OAuth, sasl::oauth::now, test_helpers::setup_test_database,
+    };
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use ring::{
+        hmac, pbkdf2,
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use secrecy::{ExposeSecret, SecretString};
+    use serde_json::{json, Value};
+    use std::num::NonZeroU32;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    async fn add_user(database: &DB, username: &str, password: &str) {
+        database.add_user(username).await.unwrap();
+        database
+            .change_password(username, SecretString::new(Box::from(password)))
+            .await
+            .unwrap();
+    }
+
+    /// Sends a raw client response like a protocol would
+    async fn respond(
+        exchange: &mut Exchange,
+        config: &Config,
+        database: &DB,
+        response: &[u8],
+    ) -> Step {
+        let line = BASE64_DECODER.encode(response);
+        exchange.respond(config, database, &line).await
+    }
+
+    fn decode(challenge: &str) -> String {
+        String::from_utf8(BASE64_DECODER.decode(challenge).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_plain_and_login() {
+        let (mut config, database, _storage) = setup_test_database().await.unwrap();
+        add_user(&database, "carol@localhost", "secret").await;
+        add_user(&database, "admin@localhost", "admin").await;
+        config.mail.admins = vec![String::from("admin@localhost")];
+
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "plain", true, "127.0.0.1").unwrap();
+        assert_eq!(exchange.initial_challenge(), "");
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"\0carol@localhost\0secret"
+            )
+            .await,
+            Step::Success(String::from("carol@localhost"))
+        );
+
+        // Admins may act as other users, others may not
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"carol@localhost\0admin@localhost\0admin"
+            )
+            .await,
+            Step::Success(String::from("carol@localhost"))
+        );
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"admin@localhost\0carol@localhost\0secret"
+            )
+            .await,
+            Step::Failure(AuthError::NotAuthorized)
+        );
+
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "LOGIN", true, "127.0.0.1").unwrap();
+        assert_eq!(decode(&exchange.initial_challenge()), "Username:");
+        let Step::Challenge(challenge) =
+            respond(&mut exchange, &config, &database, b"carol@localhost").await
+        else {
+            panic!("Expected the password challenge");
+        };
+        assert_eq!(decode(&challenge), "Password:");
+        assert_eq!(
+            respond(&mut exchange, &config, &database, b"secret").await,
+            Step::Success(String::from("carol@localhost"))
+        );
+
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            exchange.respond(&config, &database, "*").await,
+            Step::Failure(AuthError::Canceled)
+        );
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            exchange.respond(&config, &database, "not base64!").await,
+            Step::Failure(AuthError::Malformed)
+        );
+        assert_eq!(
+            Exchange::start(&config, Protocol::Imap, "PLAIN", false, "127.0.0.1"),
+            Err(AuthError::EncryptionRequired)
+        );
+        assert_eq!(
+            Exchange::start(&config, Protocol::Imap, "XOAUTH2", true, "127.0.0.1"),
+            Err(AuthError::UnsupportedMechanism)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_app_passwords() {
+        let (config, database, _storage) = setup_test_database().await.unwrap();
+        add_user(&database, "frank@localhost", "secret").await;
+        let password = app_passwords::create(
+            database.get_pool(),
+            "frank@localhost",
+            "mail client",
+            app_passwords::Scope::Smtp,
+        )
+        .await
+        .unwrap();
+        let response = format!("\0frank@localhost\0{}", password.expose_secret());
+
+        let mut exchange =
+            Exchange::start(&config, Protocol::Smtp, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(&mut exchange, &config, &database, response.as_bytes()).await,
+            Step::Success(String::from("frank@localhost"))
+        );
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(&mut exchange, &config, &database, response.as_bytes()).await,
+            Step::Failure(AuthError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_lock_out() {
+        let (config, database, _storage) = setup_test_database().await.unwrap();
+        add_user(&database, "dave@localhost", "secret").await;
+
+        for attempt in 1..=attempts::MAX_FAILURES {
+            let mut exchange =
+                Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+            let expected = if attempt == attempts::MAX_FAILURES {
+                AuthError::TooManyAttempts
+            } else {
+                AuthError::InvalidCredentials
+            };
+            assert_eq!(
+                respond(
+                    &mut exchange,
+                    &config,
+                    &database,
+                    b"\0dave@localhost\0wrong"
+                )
+                .await,
+                Step::Failure(expected)
+            );
+        }
+        // Even the right password is refused now
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"\0Dave@localhost\0secret"
+            )
+            .await,
+            Step::Failure(AuthError::TooManyAttempts)
+        );
+        // Other client addresses are not locked out of the user
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "PLAIN", true, "192.0.2.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"\0dave@localhost\0secret"
+            )
+            .await,
+            Step::Success(String::from("dave@localhost"))
+        );
+
+        // Unknown users are locked out the same way, so the responses don't
+        // tell whether an account exists
+        for attempt in 1..=attempts::MAX_FAILURES {
+            let mut exchange =
+                Exchange::start(&config, Protocol::Imap, "PLAIN", true, "127.0.0.1").unwrap();
+            let expected = if attempt == attempts::MAX_FAILURES {
+                AuthError::TooManyAttempts
+            } else {
+                AuthError::InvalidCredentials
+            };
+            assert_eq!(
+                respond(
+                    &mut exchange,
+                    &config,
+                    &database,
+                    b"\0nobody@localhost\0wrong"
+                )
+                .await,
+                Step::Failure(expected)
+            );
+        }
+    }
+
+    /// Computes the client-final-message for `password`
+    fn client_final(password: &str, client_first_bare: &str, server_first: &str) -> String {
+        let mut attributes = server_first.split(',');
+        let nonce = attributes.next().unwrap().strip_prefix("r=").unwrap();
+        let salt = BASE64_DECODER
+            .decode(attributes.next().unwrap().strip_prefix("s=").unwrap())
+            .unwrap();
+        let iterations: NonZeroU32 = attributes
+            .next()
+            .unwrap()
+            .strip_prefix("i=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut salted_password = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password.as_bytes(),
+            &mut salted_password,
+        );
+        let client_key = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, &salted_password),
+            b"Client Key",
+        );
+        let stored_key = digest::digest(&digest::SHA256, client_key.as_ref());
+        let without_proof = format!("c=biws,r={nonce}");
+        let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+        let client_signature = hmac::sign(
+            &hmac::Key::new(hmac::HMAC_SHA256, stored_key.as_ref()),
+            auth_message.as_bytes(),
+        );
+        let proof: Vec<u8> = client_key
+            .as_ref()
+            .iter()
+            .zip(client_signature.as_ref())
+            .map(|(key, signature)| key ^ signature)
+            .collect();
+        format!("{without_proof},p={}", BASE64_DECODER.encode(proof))
+    }
+
+    async fn scram_login(config: &Config, database: &DB, password: &str) -> Step {
+        let mut exchange =
+            Exchange::start(config, Protocol::Imap, "SCRAM-SHA-256", true, "127.0.0.1").unwrap();
+        let Step::Challenge(server_first) = respond(
+            &mut exchange,
+            config,
+            database,
+            b"n,,n=alice@localhost,r=clientnonce",
+        )
+        .await
+        else {
+            panic!("Expected the server-first-message");
+        };
+        let client_final = client_final(
+            password,
+            "n=alice@localhost,r=clientnonce",
+            &decode(&server_first),
+        );
+        match respond(&mut exchange, config, database, client_final.as_bytes()).await {
+            Step::Challenge(server_final) => {
+                assert!(decode(&server_final).starts_with("v="));
+                exchange.respond(config, database, "").await
+            }
+            step => step,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scram_exchange() {
+        let (config, database, _storage) = setup_test_database().await.unwrap();
+        add_user(&database, "alice@localhost", "correct horse").await;
+
+        assert_eq!(
+            scram_login(&config, &database, "correct horse").await,
+            Step::Success(String::from("alice@localhost"))
+        );
+        assert_eq!(
+            scram_login(&config, &database, "wrong horse").await,
+            Step::Failure(AuthError::InvalidCredentials)
+        );
+
+        // Clients able to bind the channel must not be downgraded on TLS
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "SCRAM-SHA-256", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"y,,n=alice@localhost,r=abc"
+            )
+            .await,
+            Step::Failure(AuthError::InvalidCredentials)
+        );
+        let mut exchange = Exchange::start(
+            &config,
+            Protocol::Imap,
+            "SCRAM-SHA-256-PLUS",
+            true,
+            "127.0.0.1",
+        )
+        .unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"n,,n=alice@localhost,r=abc"
+            )
+            .await,
+            Step::Failure(AuthError::InvalidCredentials)
+        );
+    }
+
+    /// Answers HTTP requests with the JSON documents of `routes`
+    async fn identity_provider(routes: Vec<(&'static str, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let body = routes
+                    .iter()
+                    .find(|(route, _)| *route == path)
+                    .map_or_else(String::new, |(_, body)| body.clone());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{address}")
+    }
+
+    fn sign_token(key: &EcdsaKeyPair, claims: &Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","kid":"test"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let message = format!("{header}.{payload}");
+        let signature = key.sign(&SystemRandom::new(), message.as_bytes()).unwrap();
+        format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref()))
+    }
+
+    #[tokio::test]
+    async fn test_oauth_exchange() {
+        let (mut config, database, _storage) = setup_test_database().await.unwrap();
+        database.add_user("erin@localhost").await.unwrap();
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        let point = key.public_key().as_ref();
+        let jwks = json!({"keys": [{
+            "kty": "EC",
+            "kid": "test",
+            "crv": "P-256",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        }]});
+        let introspection = json!({
+            "active": true,
+            "iss": "https://sso.example.com",
+            "aud": "erooster",
+            "email": "erin@localhost",
+        });
+        let url = identity_provider(vec![
+            ("/jwks", jwks.to_string()),
+            ("/introspect", introspection.to_string()),
+        ])
+        .await;
+        config.oauth = Some(OAuth {
+            jwks_url: Some(format!("{url}/jwks")),
+            introspection_url: Some(format!("{url}/introspect")),
+            client_id: Some(String::from("erooster")),
+            client_secret: Some(String::from("secret")),
+            issuer: Some(String::from("https://sso.example.com")),
+            audience: Some(String::from("erooster")),
+            username_claim: String::from("email"),
+        });
+
+        let exp = now() + 300;
+        let token = sign_token(
+            &key,
+            &json!({"iss": "https://sso.example.com", "aud": ["erooster"], "exp": exp, "email": "erin@localhost"}),
+        );
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "OAUTHBEARER", true, "127.0.0.1").unwrap();
+        let response =
+            format!("n,a=erin@localhost,\x01host=localhost\x01auth=Bearer {token}\x01\x01");
+        assert_eq!(
+            respond(&mut exchange, &config, &database, response.as_bytes()).await,
+            Step::Success(String::from("erin@localhost"))
+        );
+
+        // Tokens for another audience get an error challenge, then fail
+        let token = sign_token(
+            &key,
+            &json!({"iss": "https://sso.example.com", "aud": "other", "exp": exp, "email": "erin@localhost"}),
+        );
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "XOAUTH2", true, "127.0.0.1").unwrap();
+        let response = format!("user=erin@localhost\x01auth=Bearer {token}\x01\x01");
+        assert_eq!(
+            respond(&mut exchange, &config, &database, response.as_bytes()).await,
+            Step::Challenge(BASE64_DECODER.encode(oauth::error(Mechanism::XOAuth2)))
+        );
+        assert_eq!(
+            exchange.respond(&config, &database, "").await,
+            Step::Failure(AuthError::InvalidCredentials)
+        );
+
+        // Opaque tokens are introspected
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "XOAUTH2", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"user=erin@localhost\x01auth=Bearer opaque\x01\x01"
+            )
+            .await,
+            Step::Success(String::from("erin@localhost"))
+        );
+        // A token is only good for its own user
+        let mut exchange =
+            Exchange::start(&config, Protocol::Imap, "XOAUTH2", true, "127.0.0.1").unwrap();
+        assert_eq!(
+            respond(
+                &mut exchange,
+                &config,
+                &database,
+                b"user=bob@localhost\x01auth=Bearer opaque\x01\x01"
+            )
+            .await,
+            Step::Failure(AuthError::NotAuthorized)
+        );
+    }
+}
@@ -0,0 +1,308 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Access tokens of OAUTHBEARER (RFC 7628) and XOAUTH2.
+//!
+//! JSON Web Tokens are verified with the keys of the identity provider, other
+//! tokens are looked up at its introspection endpoint (RFC 7662).
+
+use crate::{
+    backend::database::Database,
+    config::OAuth,
+    sasl::{decode_saslname, Context, Identity, Mechanism, Outcome, SaslMechanism},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre::Result;
+use reqwest::header::CONTENT_TYPE;
+use ring::signature::{
+    RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED, RSA_PKCS1_2048_8192_SHA256,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use simdutf8::compat::from_utf8;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// The initial response of the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// The user the client wants to log in as, if it told. It only differs
+    /// from the user of the token when an admin acts as another user.
+    pub user: Option<String>,
+    /// The bearer token
+    pub token: String,
+}
+
+impl Request {
+    /// Parses the initial client response of the mechanism, `None` if it is
+    /// malformed
+    #[must_use]
+    pub fn parse(mechanism: Mechanism, message: &[u8]) -> Option<Self> {
+        let message = from_utf8(message).ok()?;
+        let (user, pairs) = match mechanism {
+            Mechanism::OAuthBearer => {
+                // gs2-header, channel binding is not supported
+                let (gs2_header, pairs) = message.split_once('\x01')?;
+                let mut gs2 = gs2_header.split(',');
+                if !matches!(gs2.next()?, "n" | "y") {
+                    return None;
+                }
+                let user = match gs2.next()? {
+                    "" => None,
+                    authzid => Some(decode_saslname(authzid.strip_prefix("a=")?)?),
+                };
+                (user, pairs)
+            }
+            Mechanism::XOAuth2 => {
+                let (user, pairs) = message.split_once('\x01')?;
+                (Some(user.strip_prefix("user=")?.to_string()), pairs)
+            }
+            _ => return None,
+        };
+        let auth = pairs
+            .split('\x01')
+            .find_map(|pair| pair.strip_prefix("auth="))?;
+        let (scheme, token) = auth.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") || token.is_empty() {
+            return None;
+        }
+        Some(Request {
+            user,
+            token: token.to_string(),
+        })
+    }
+}
+
+/// An OAUTHBEARER or XOAUTH2 exchange
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bearer {
+    mechanism: Mechanism,
+    /// The error challenge was sent, the next response ends the exchange
+    rejected: bool,
+    user: Option<String>,
+}
+
+impl Bearer {
+    /// Starts an exchange of one of the OAuth mechanisms
+    #[must_use]
+    pub const fn new(mechanism: Mechanism) -> Self {
+        Bearer {
+            mechanism,
+            rejected: false,
+            user: None,
+        }
+    }
+}
+
+impl SaslMechanism for Bearer {
+    async fn step(&mut self, context: &Context<'_>, response: &[u8]) -> Result<Outcome> {
+        if self.rejected {
+            return Ok(Outcome::Failed(self.user.take()));
+        }
+        let (Some(config), Some(request)) = (
+            &context.config.oauth,
+            Request::parse(self.mechanism, response),
+        ) else {
+            return Ok(Outcome::Failed(None));
+        };
+        if let Some(username) = validate(config, &request.token).await? {
+            if context.database.user_exists(&username).await {
+                return Ok(Outcome::Authenticated(Identity {
+                    authcid: username,
+                    authzid: request.user,
+                }));
+            }
+        }
+        // The client gets the reason as a challenge and has to acknowledge it
+        self.rejected = true;
+        self.user = request.user;
+        Ok(Outcome::Challenge(error(self.mechanism)))
+    }
+}
+
+/// The error challenge sent when a token is rejected
+#[must_use]
+pub fn error(mechanism: Mechanism) -> Vec<u8> {
+    let status = if mechanism == Mechanism::XOAuth2 {
+        "401"
+    } else {
+        "invalid_token"
+    };
+    format!("{{\"status\":\"{status}\",\"schemes\":\"bearer\"}}").into_bytes()
+}
+
+/// Validates a token and returns the user it belongs to, `None` if the
+/// token is invalid. Errors mean the identity provider is unreachable.
+pub async fn validate(config: &OAuth, token: &str) -> Result<Option<String>> {
+    let claims = match (&config.jwks_url, &config.introspection_url) {
+        (Some(jwks_url), _) if token.split('.').count() == 3 => verify_jwt(jwks_url, token).await?,
+        (_, Some(introspection_url)) => introspect(config, introspection_url, token).await?,
+        _ => None,
+    };
+    Ok(claims.and_then(|claims| user_of(config, &claims, now())))
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KeySet {
+    keys: Vec<Key>,
+}
+
+#[derive(Deserialize)]
+struct Key {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+impl Key {
+    /// Checks the signature of `message`, `None` if the key does not fit the
+    /// algorithm
+    fn verify(&self, algorithm: &str, message: &[u8], signature: &[u8]) -> Option<bool> {
+        match (algorithm, self.kty.as_str()) {
+            ("RS256", "RSA") => {
+                let key = RsaPublicKeyComponents {
+                    n: URL_SAFE_NO_PAD.decode(self.n.as_ref()?).ok()?,
+                    e: URL_SAFE_NO_PAD.decode(self.e.as_ref()?).ok()?,
+                };
+                Some(
+                    key.verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+                        .is_ok(),
+                )
+            }
+            ("ES256", "EC") if self.crv.as_deref() == Some("P-256") => {
+                let mut point = vec![0x04];
+                point.extend(URL_SAFE_NO_PAD.decode(self.x.as_ref()?).ok()?);
+                point.extend(URL_SAFE_NO_PAD.decode(self.y.as_ref()?).ok()?);
+                let key = UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point);
+                Some(key.verify(message, signature).is_ok())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns the claims of a JSON Web Token signed by one of the keys at
+/// `jwks_url`
+async fn verify_jwt(jwks_url: &str, token: &str) -> Result<Option<Value>> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Ok(None);
+    };
+    let Some(decoded_header) = URL_SAFE_NO_PAD
+        .decode(header)
+        .ok()
+        .and_then(|header| serde_json::from_slice::<Header>(&header).ok())
+    else {
+        return Ok(None);
+    };
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return Ok(None);
+    };
+
+    let keys: KeySet = reqwest::get(jwks_url)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let message = format!("{header}.{payload}");
+    let verified = keys
+        .keys
+        .iter()
+        .filter(|key| decoded_header.kid.is_none() || key.kid == decoded_header.kid)
+        .filter_map(|key| key.verify(&decoded_header.alg, message.as_bytes(), &signature))
+        .any(|valid| valid);
+    if !verified {
+        debug!("[OAuth] Token signature is invalid");
+        return Ok(None);
+    }
+
+    let claims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|payload| serde_json::from_slice::<Value>(&payload).ok());
+    // Tokens which never expire are not accepted
+    Ok(claims.filter(|claims| claims.get("exp").is_some()))
+}
+
+/// Returns the claims of an active token as told by the introspection
+/// endpoint
+async fn introspect(config: &OAuth, introspection_url: &str, token: &str) -> Result<Option<Value>> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("token", token)
+        .append_pair("token_type_hint", "access_token")
+        .finish();
+    let mut request = reqwest::Client::new()
+        .post(introspection_url)
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(body);
+    if let Some(client_id) = &config.client_id {
+        request = request.basic_auth(client_id, config.client_secret.as_ref());
+    }
+    let claims: Value = request.send().await?.error_for_status()?.json().await?;
+    let active = claims.get("active").and_then(Value::as_bool) == Some(true);
+    Ok(active.then_some(claims))
+}
+
+/// Checks the validity period, issuer and audience of the claims and returns
+/// the user
+fn user_of(config: &OAuth, claims: &Value, now: u64) -> Option<String> {
+    let time = |claim: &str| claims.get(claim).and_then(Value::as_u64);
+    if time("exp").is_some_and(|exp| exp <= now) || time("nbf").is_some_and(|nbf| nbf > now) {
+        debug!("[OAuth] Token is expired or not yet valid");
+        return None;
+    }
+    if !issued_for(config, claims) {
+        return None;
+    }
+    claims
+        .get(&config.username_claim)
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned)
+}
+
+/// Checks the issuer and audience of the claims
+fn issued_for(config: &OAuth, claims: &Value) -> bool {
+    if let Some(issuer) = &config.issuer {
+        if claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str()) {
+            debug!("[OAuth] Token has a different issuer");
+            return false;
+        }
+    }
+    if let Some(audience) = &config.audience {
+        let audience_matches = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(aud)) => aud.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !audience_matches {
+            debug!("[OAuth] Token is meant for a different audience");
+            return false;
+        }
+    }
+    true
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
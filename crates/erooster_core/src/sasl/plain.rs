@@ -0,0 +1,95 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The PLAIN mechanism (RFC 4616).
+
+use crate::{
+    backend::{app_passwords, database::Database},
+    sasl::{Context, Identity, Outcome, SaslMechanism},
+};
+use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use color_eyre::eyre::Result;
+use rand_core::OsRng;
+use secrecy::{ExposeSecret, SecretString};
+use simdutf8::compat::from_utf8;
+use std::sync::LazyLock;
+
+/// Checks `authzid NUL authcid NUL password` in a single response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plain;
+
+impl SaslMechanism for Plain {
+    async fn step(&mut self, context: &Context<'_>, response: &[u8]) -> Result<Outcome> {
+        let Ok(message) = from_utf8(response) else {
+            return Ok(Outcome::Failed(None));
+        };
+        let mut fields = message.split('\0');
+        // authzid NUL authcid NUL password
+        let (Some(act_as), Some(username), Some(password), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Ok(Outcome::Failed(None));
+        };
+        if username.is_empty() || password.is_empty() {
+            return Ok(Outcome::Failed(None));
+        }
+        verify(context, username, password, act_as).await
+    }
+}
+
+/// Checks the account password or an app password of `username`, who wants
+/// to act as `act_as` if it is not empty. Shared with LOGIN.
+pub(super) async fn verify(
+    context: &Context<'_>,
+    username: &str,
+    password: &str,
+    act_as: &str,
+) -> Result<Outcome> {
+    let password = SecretString::new(Box::from(password));
+    if !context.database.user_exists(username).await {
+        // Take as long as for a wrong password, so the response time doesn't
+        // tell which accounts exist
+        dummy_verify(&password);
+        return Ok(Outcome::Failed(Some(username.to_string())));
+    }
+    let valid = context
+        .database
+        .verify_user(username, password.clone())
+        .await
+        || app_passwords::verify(
+            context.database.get_pool(),
+            username,
+            &password,
+            context.protocol,
+        )
+        .await?;
+    Ok(if valid {
+        Outcome::Authenticated(Identity {
+            authcid: username.to_string(),
+            authzid: (!act_as.is_empty()).then(|| act_as.to_string()),
+        })
+    } else {
+        Outcome::Failed(Some(username.to_string()))
+    })
+}
+
+/// A hash of the same kind as the stored passwords, to verify against for
+/// unknown users
+static DUMMY_HASH: LazyLock<Option<String>> = LazyLock::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(b"erooster", &salt)
+        .ok()
+        .map(|hash| hash.to_string())
+});
+
+/// Runs a password verification which always fails
+fn dummy_verify(password: &SecretString) {
+    if let Some(hash) = DUMMY_HASH
+        .as_deref()
+        .and_then(|hash| PasswordHash::new(hash).ok())
+    {
+        let _ = Argon2::default().verify_password(password.expose_secret().as_bytes(), &hash);
+    }
+}
@@ -0,0 +1,489 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! SCRAM-SHA-256 and SCRAM-SHA-256-PLUS (RFC 5802, RFC 7677).
+//!
+//! Only the salted credentials are stored, so neither the database nor the
+//! exchange reveals the password. Passwords are not normalised with the
+//! profile of RFC 4013, which makes no difference for ASCII passwords.
+
+use crate::{
+    backend::database::Database,
+    config::Config,
+    sasl::{decode_saslname, tls_server_end_point, Context, Identity, Outcome, SaslMechanism},
+    BASE64_DECODER,
+};
+use base64::Engine;
+use color_eyre::eyre::{eyre, Result};
+use ring::{
+    digest, hmac, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use simdutf8::compat::from_utf8;
+use std::{fmt, num::NonZeroU32, str::FromStr, sync::LazyLock};
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+#[cfg(feature = "postgres")]
+use sqlx::PgPool as Pool;
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool as Pool;
+
+/// The iteration count of new credentials, the minimum RFC 7677 asks for
+const ITERATIONS: u32 = 4096;
+const PREFIX: &str = "SCRAM-SHA-256";
+
+/// The channel binding type of SCRAM-SHA-256-PLUS (RFC 5929 §4)
+pub const CHANNEL_BINDING: &str = "tls-server-end-point";
+
+/// The salted credentials of a user as stored in `users.scram_sha256`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    iterations: u32,
+    salt: Vec<u8>,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl Credentials {
+    /// Derives the credentials of a password with a new random salt
+    pub fn new(password: &str) -> Result<Self> {
+        let mut salt = [0; 16];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| eyre!("Unable to generate a salt"))?;
+        Ok(Self::derive(password, &salt, ITERATIONS))
+    }
+
+    fn derive(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let mut salted_password = [0; digest::SHA256_OUTPUT_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN),
+            salt,
+            password.as_bytes(),
+            &mut salted_password,
+        );
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &salted_password);
+        let client_key = hmac::sign(&key, b"Client Key");
+        let server_key = hmac::sign(&key, b"Server Key");
+        Credentials {
+            iterations,
+            salt: salt.to_vec(),
+            stored_key: digest::digest(&digest::SHA256, client_key.as_ref())
+                .as_ref()
+                .to_vec(),
+            server_key: server_key.as_ref().to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{PREFIX}${}:{}${}:{}",
+            self.iterations,
+            BASE64_DECODER.encode(&self.salt),
+            BASE64_DECODER.encode(&self.stored_key),
+            BASE64_DECODER.encode(&self.server_key)
+        )
+    }
+}
+
+impl FromStr for Credentials {
+    type Err = color_eyre::eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('$');
+        let (Some(PREFIX), Some(parameters), Some(keys), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(eyre!("Unknown SCRAM credentials format"));
+        };
+        let (iterations, salt) = parameters
+            .split_once(':')
+            .ok_or_else(|| eyre!("Missing SCRAM salt"))?;
+        let (stored_key, server_key) = keys
+            .split_once(':')
+            .ok_or_else(|| eyre!("Missing SCRAM server key"))?;
+        Ok(Credentials {
+            iterations: iterations.parse()?,
+            salt: BASE64_DECODER.decode(salt)?,
+            stored_key: BASE64_DECODER.decode(stored_key)?,
+            server_key: BASE64_DECODER.decode(server_key)?,
+        })
+    }
+}
+
+/// Returns the stored credentials of a user. Users who did not set their
+/// password since SCRAM was added have none.
+pub async fn credentials(pool: &Pool, username: &str) -> Result<Option<Credentials>> {
+    let row: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT scram_sha256 FROM users WHERE username = $1")
+            .bind(username)
+            .fetch_optional(pool)
+            .await?;
+    row.and_then(|(credentials,)| credentials)
+        .map(|credentials| credentials.parse())
+        .transpose()
+}
+
+/// The secret of [`fake_salt`] if `mail.scram_secret` is not set
+static STARTUP_SECRET: LazyLock<[u8; 32]> = LazyLock::new(|| {
+    let mut secret = [0; 32];
+    if SystemRandom::new().fill(&mut secret).is_err() {
+        warn!("[SCRAM] Unable to generate a secret for the salts of unknown users");
+    }
+    secret
+});
+
+/// The salt sent for a user without credentials. It stays the same for the
+/// same name, so repeated attempts can't tell unknown users from existing
+/// ones.
+#[must_use]
+pub fn fake_salt(config: &Config, username: &str) -> Vec<u8> {
+    let key = match &config.mail.scram_secret {
+        Some(secret) => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+        None => hmac::Key::new(hmac::HMAC_SHA256, STARTUP_SECRET.as_slice()),
+    };
+    let tag = hmac::sign(&key, username.to_lowercase().as_bytes());
+    // As long as the salts of `Credentials::new`
+    tag.as_ref()[..16].to_vec()
+}
+
+/// Returns a new random server nonce
+pub fn nonce() -> Result<String> {
+    let mut nonce = [0; 18];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| eyre!("Unable to generate a nonce"))?;
+    Ok(BASE64_DECODER.encode(nonce))
+}
+
+/// The channel binding flag of the GS2 header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelBinding {
+    /// `n`: the client does not support channel binding
+    None,
+    /// `y`: the client supports channel binding but thinks the server does not
+    Unused,
+    /// `p=<type>`: the client binds the exchange to the channel
+    Required(String),
+}
+
+/// The first message of the client (RFC 5802 §7)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientFirst<'a> {
+    /// The channel binding the client asks for
+    pub channel_binding: ChannelBinding,
+    /// The user the client wants to act as, if it differs from `username`
+    pub authzid: Option<String>,
+    /// The user whose credentials are used
+    pub username: String,
+    gs2_header: &'a str,
+    bare: &'a str,
+    nonce: &'a str,
+}
+
+impl<'a> ClientFirst<'a> {
+    /// Parses the client-first-message, `None` if it is malformed
+    #[must_use]
+    pub fn parse(message: &'a str) -> Option<Self> {
+        let (flag, rest) = message.split_once(',')?;
+        let (authzid, bare) = rest.split_once(',')?;
+        let gs2_header = &message[..message.len() - bare.len()];
+        let channel_binding = match flag {
+            "n" => ChannelBinding::None,
+            "y" => ChannelBinding::Unused,
+            _ => ChannelBinding::Required(flag.strip_prefix("p=")?.to_string()),
+        };
+        let authzid = if authzid.is_empty() {
+            None
+        } else {
+            Some(decode_saslname(authzid.strip_prefix("a=")?)?)
+        };
+        let mut attributes = bare.split(',');
+        let username = decode_saslname(attributes.next()?.strip_prefix("n=")?)?;
+        let nonce = attributes.next()?.strip_prefix("r=")?;
+        if username.is_empty() || nonce.is_empty() {
+            return None;
+        }
+        Some(ClientFirst {
+            channel_binding,
+            authzid,
+            username,
+            gs2_header,
+            bare,
+            nonce,
+        })
+    }
+}
+
+/// An exchange after the server sent its first message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerFirst {
+    username: String,
+    authzid: Option<String>,
+    gs2_header: String,
+    client_first_bare: String,
+    message: String,
+    nonce: String,
+    credentials: Option<Credentials>,
+}
+
+impl ServerFirst {
+    /// Answers the first message of the client. Unknown users get the salt
+    /// of [`fake_salt`] and fail only with the final message, like a wrong
+    /// password.
+    #[must_use]
+    pub fn new(
+        client_first: &ClientFirst<'_>,
+        credentials: Option<Credentials>,
+        fake_salt: &[u8],
+        server_nonce: &str,
+    ) -> Self {
+        let (salt, iterations) = if let Some(credentials) = &credentials {
+            (credentials.salt.as_slice(), credentials.iterations)
+        } else {
+            (fake_salt, ITERATIONS)
+        };
+        let nonce = format!("{}{server_nonce}", client_first.nonce);
+        let message = format!("r={nonce},s={},i={iterations}", BASE64_DECODER.encode(salt));
+        ServerFirst {
+            username: client_first.username.clone(),
+            authzid: client_first.authzid.clone(),
+            gs2_header: client_first.gs2_header.to_string(),
+            client_first_bare: client_first.bare.to_string(),
+            message,
+            nonce,
+            credentials,
+        }
+    }
+
+    /// The server-first-message to send to the client
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The user whose credentials are used
+    #[must_use]
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Verifies the final message of the client and returns the
+    /// server-final-message proving the server knows the credentials too.
+    ///
+    /// `channel_binding` is the channel binding data of the connection when
+    /// the client asked for it.
+    #[must_use]
+    pub fn finish(&self, client_final: &str, channel_binding: Option<&[u8]>) -> Option<String> {
+        let (without_proof, proof) = client_final.rsplit_once(",p=")?;
+        let mut attributes = without_proof.split(',');
+        let binding = BASE64_DECODER
+            .decode(attributes.next()?.strip_prefix("c=")?)
+            .ok()?;
+        let nonce = attributes.next()?.strip_prefix("r=")?;
+        let mut expected_binding = self.gs2_header.as_bytes().to_vec();
+        expected_binding.extend_from_slice(channel_binding.unwrap_or_default());
+        if binding != expected_binding || nonce != self.nonce {
+            return None;
+        }
+        let credentials = self.credentials.as_ref()?;
+        let proof = BASE64_DECODER.decode(proof).ok()?;
+
+        let auth_message = format!(
+            "{},{},{without_proof}",
+            self.client_first_bare, self.message
+        );
+        let stored_key = hmac::Key::new(hmac::HMAC_SHA256, &credentials.stored_key);
+        let client_signature = hmac::sign(&stored_key, auth_message.as_bytes());
+        if proof.len() != client_signature.as_ref().len() {
+            return None;
+        }
+        let client_key: Vec<u8> = proof
+            .iter()
+            .zip(client_signature.as_ref())
+            .map(|(proof, signature)| proof ^ signature)
+            .collect();
+        let hashed_key = digest::digest(&digest::SHA256, &client_key);
+        if !bool::from(hashed_key.as_ref().ct_eq(&credentials.stored_key)) {
+            return None;
+        }
+
+        let server_key = hmac::Key::new(hmac::HMAC_SHA256, &credentials.server_key);
+        let server_signature = hmac::sign(&server_key, auth_message.as_bytes());
+        Some(format!(
+            "v={}",
+            BASE64_DECODER.encode(server_signature.as_ref())
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Started,
+    /// The server-first-message was sent
+    ServerFirst(Box<ServerFirst>),
+    /// The server-final-message was sent, the client only confirms it with an
+    /// empty response
+    ServerFinal(Identity),
+    Done,
+}
+
+/// A SCRAM-SHA-256 exchange, bound to the TLS channel for
+/// SCRAM-SHA-256-PLUS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scram {
+    plus: bool,
+    state: State,
+}
+
+impl Scram {
+    /// Starts an exchange, `plus` for SCRAM-SHA-256-PLUS
+    #[must_use]
+    pub const fn new(plus: bool) -> Self {
+        Scram {
+            plus,
+            state: State::Started,
+        }
+    }
+
+    async fn first(&mut self, context: &Context<'_>, response: &[u8]) -> Result<Outcome> {
+        let Some(client_first) = from_utf8(response).ok().and_then(ClientFirst::parse) else {
+            return Ok(Outcome::Failed(None));
+        };
+        let binding_allowed = match &client_first.channel_binding {
+            ChannelBinding::None => !self.plus,
+            // The client would bind if it knew we can, this is a downgrade
+            ChannelBinding::Unused => !self.plus && !context.secure,
+            ChannelBinding::Required(name) => {
+                self.plus && context.secure && name == CHANNEL_BINDING
+            }
+        };
+        if !binding_allowed {
+            return Ok(Outcome::Failed(Some(client_first.username)));
+        }
+
+        let credentials = credentials(context.database.get_pool(), &client_first.username).await?;
+        let fake_salt = fake_salt(context.config, &client_first.username);
+        let server_first = ServerFirst::new(&client_first, credentials, &fake_salt, &nonce()?);
+        let challenge = server_first.message().as_bytes().to_vec();
+        self.state = State::ServerFirst(Box::new(server_first));
+        Ok(Outcome::Challenge(challenge))
+    }
+
+    async fn last(
+        &mut self,
+        context: &Context<'_>,
+        server_first: &ServerFirst,
+        response: &[u8],
+    ) -> Result<Outcome> {
+        let channel_binding = if self.plus {
+            Some(tls_server_end_point(context.config).await?)
+        } else {
+            None
+        };
+        let username = server_first.username().to_string();
+        let server_final = from_utf8(response)
+            .ok()
+            .and_then(|client_final| server_first.finish(client_final, channel_binding.as_deref()));
+        let Some(server_final) = server_final else {
+            return Ok(Outcome::Failed(Some(username)));
+        };
+        self.state = State::ServerFinal(Identity {
+            authcid: username,
+            authzid: server_first.authzid.clone(),
+        });
+        Ok(Outcome::Challenge(server_final.into_bytes()))
+    }
+}
+
+impl SaslMechanism for Scram {
+    async fn step(&mut self, context: &Context<'_>, response: &[u8]) -> Result<Outcome> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::Started => self.first(context, response).await,
+            State::ServerFirst(server_first) => self.last(context, &server_first, response).await,
+            State::ServerFinal(identity) if response.is_empty() => {
+                Ok(Outcome::Authenticated(identity))
+            }
+            State::ServerFinal(identity) => Ok(Outcome::Failed(Some(identity.authcid))),
+            State::Done => Ok(Outcome::Failed(None)),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// The example exchange of RFC 7677 §3
+    #[test]
+    fn test_rfc7677_exchange() {
+        let salt = BASE64_DECODER.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let credentials = Credentials::derive("pencil", &salt, 4096);
+        let stored = credentials.to_string();
+        assert_eq!(stored.parse::<Credentials>().unwrap(), credentials);
+
+        let client_first = ClientFirst::parse("n,,n=user,r=rOprNGfwEbeRWgbNEkqO").unwrap();
+        assert_eq!(client_first.channel_binding, ChannelBinding::None);
+        assert_eq!(client_first.username, "user");
+        let server_first = ServerFirst::new(
+            &client_first,
+            Some(credentials),
+            &[],
+            "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0",
+        );
+        assert_eq!(
+            server_first.message(),
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+        );
+        assert_eq!(
+            server_first
+                .finish(
+                    "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+                    None
+                )
+                .as_deref(),
+            Some("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+        );
+        // A wrong proof or a binding the client did not announce fail
+        assert!(server_first
+            .finish(
+                "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=AHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+                None
+            )
+            .is_none());
+        assert!(server_first
+            .finish(
+                "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+                Some(b"binding")
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn test_client_first_parsing() {
+        let client_first =
+            ClientFirst::parse("p=tls-server-end-point,a=admin=2Cx,n=us=3Der,r=abc,ext=1").unwrap();
+        assert_eq!(
+            client_first.channel_binding,
+            ChannelBinding::Required(String::from(CHANNEL_BINDING))
+        );
+        assert_eq!(client_first.authzid.as_deref(), Some("admin,x"));
+        assert_eq!(client_first.username, "us=er");
+        assert_eq!(
+            client_first.gs2_header,
+            "p=tls-server-end-point,a=admin=2Cx,"
+        );
+
+        assert!(ClientFirst::parse("x,,n=user,r=abc").is_none());
+        assert!(ClientFirst::parse("n,,m=ext,n=user,r=abc").is_none());
+        assert!(ClientFirst::parse("n,,n=us=er,r=abc").is_none());
+        assert!(ClientFirst::parse("n,,n=user").is_none());
+    }
+}
@@ -0,0 +1,156 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The Sender Rewriting Scheme (SRS) for forwarded mail.
+//!
+//! Forwarding a message with its original envelope sender makes SPF fail at
+//! the next hop, as this server may not send mail for the sender's domain.
+//! The sender is rewritten to an address of the forwarding domain instead,
+//! `SRS0=HHHH=TT=example.com=alice@forwarder.example`, which routes bounces
+//! back to this server. The hash and the timestamp make sure only recent
+//! addresses created by this server are turned back into the original
+//! sender, so it can't be abused as an open relay.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::hmac;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The letters of the base32 encoded timestamp
+const TIMESTAMP_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// Two base32 letters count the days modulo 1024
+const TIMESTAMP_PERIOD: u64 = 1024;
+/// How long bounces to a rewritten sender are accepted
+const MAX_AGE_DAYS: u64 = 21;
+/// The letters of the hash kept in the address
+const HASH_LENGTH: usize = 4;
+
+/// Rewrites `sender` to an address at `forwarding_domain`. The null sender
+/// of bounces stays as it is.
+#[must_use]
+pub fn forward(secret: &str, sender: &str, forwarding_domain: &str) -> String {
+    forward_at(secret, sender, forwarding_domain, today())
+}
+
+/// Returns the original sender of an address created by [`forward`], `None`
+/// if it is no SRS address, was not created with `secret` or is too old.
+#[must_use]
+pub fn reverse(secret: &str, address: &str) -> Option<String> {
+    reverse_at(secret, address, today())
+}
+
+fn forward_at(secret: &str, sender: &str, forwarding_domain: &str, today: u64) -> String {
+    let Some((local, domain)) = sender.rsplit_once('@') else {
+        return sender.to_string();
+    };
+    let timestamp = encode_timestamp(today);
+    let hash = hash(secret, &timestamp, domain, local);
+    format!("SRS0={hash}={timestamp}={domain}={local}@{forwarding_domain}")
+}
+
+fn reverse_at(secret: &str, address: &str, today: u64) -> Option<String> {
+    let (local, _) = address.rsplit_once('@')?;
+    let prefix = local.get(..5)?;
+    if !prefix.eq_ignore_ascii_case("SRS0=") {
+        return None;
+    }
+    // The original local part may contain `=` itself
+    let mut parts = local[5..].splitn(4, '=');
+    let (hash_part, timestamp, domain, original_local) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+
+    let age = (today + TIMESTAMP_PERIOD - decode_timestamp(timestamp)?) % TIMESTAMP_PERIOD;
+    if age > MAX_AGE_DAYS {
+        return None;
+    }
+    // Some servers change the case of the address
+    if !hash_part.eq_ignore_ascii_case(&hash(secret, timestamp, domain, original_local)) {
+        return None;
+    }
+    Some(format!("{original_local}@{domain}"))
+}
+
+/// The start of the base64 encoded HMAC of the other parts
+fn hash(secret: &str, timestamp: &str, domain: &str, local: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret.as_bytes());
+    let message = format!(
+        "{}{}{}",
+        timestamp.to_uppercase(),
+        domain.to_lowercase(),
+        local.to_lowercase()
+    );
+    let mut hash = STANDARD.encode(hmac::sign(&key, message.as_bytes()));
+    hash.truncate(HASH_LENGTH);
+    hash
+}
+
+fn encode_timestamp(day: u64) -> String {
+    let day = day % TIMESTAMP_PERIOD;
+    [day >> 5, day & 31]
+        .iter()
+        .map(|&letter| char::from(TIMESTAMP_ALPHABET[usize::try_from(letter).unwrap_or(0)]))
+        .collect()
+}
+
+fn decode_timestamp(timestamp: &str) -> Option<u64> {
+    if timestamp.len() != 2 {
+        return None;
+    }
+    timestamp.bytes().try_fold(0, |day, letter| {
+        let position = TIMESTAMP_ALPHABET
+            .iter()
+            .position(|&c| c == letter.to_ascii_uppercase())?;
+        Some(day << 5 | position as u64)
+    })
+}
+
+/// Days since the Unix epoch
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86_400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewritten_senders_are_reversed_while_recent() {
+        let day = 20_000;
+        let rewritten = forward_at("secret", "alice=x@example.com", "example.org", day);
+        assert!(rewritten.starts_with("SRS0="));
+        assert!(rewritten.ends_with("=example.com=alice=x@example.org"));
+
+        assert_eq!(
+            reverse_at("secret", &rewritten, day + MAX_AGE_DAYS).as_deref(),
+            Some("alice=x@example.com")
+        );
+        assert_eq!(
+            reverse_at("secret", &rewritten.to_lowercase(), day).as_deref(),
+            Some("alice=x@example.com")
+        );
+        assert_eq!(
+            reverse_at("secret", &rewritten, day + MAX_AGE_DAYS + 1),
+            None
+        );
+        assert_eq!(reverse_at("other", &rewritten, day), None);
+        assert_eq!(
+            reverse_at("secret", &rewritten.replace("alice", "mallory"), day),
+            None
+        );
+        assert_eq!(reverse_at("secret", "alice@example.org", day), None);
+    }
+
+    #[test]
+    fn null_sender_stays() {
+        assert_eq!(forward_at("secret", "", "example.org", 1), "");
+    }
+
+    #[test]
+    fn timestamps_wrap_around() {
+        assert_eq!(encode_timestamp(TIMESTAMP_PERIOD + 33), "BB");
+        assert_eq!(decode_timestamp("bb"), Some(33));
+        assert_eq!(decode_timestamp("B1"), None);
+    }
+}
@@ -12,7 +12,7 @@ use crate::{
         database::{get_database, DB},
         storage::{self, Storage},
     },
-    config::{Config, Database, Mail, MessageSize, Tls, Webserver},
+    config::{Config, Database, Mail, MessageSize, SearchIndex, Tls, Webserver},
 };
 use {color_eyre::Result, uuid::Uuid};
 
@@ -26,6 +26,16 @@ use {color_eyre::Result, uuid::Uuid};
 ///
 /// Returns an error if the database cannot be initialised (migrations fail, etc.)
 pub async fn setup_test_storage() -> Result<(Config, Storage)> {
+    let (config, _database, storage) = setup_test_database().await?;
+    Ok((config, storage))
+}
+
+/// Like [`setup_test_storage`] but also returns the database the storage uses.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be initialised (migrations fail, etc.)
+pub async fn setup_test_database() -> Result<(Config, DB, Storage)> {
     let id = Uuid::new_v4().simple().to_string();
     let config = Config {
         database: Database {
@@ -38,6 +48,9 @@ pub async fn setup_test_storage() -> Result<(Config, Storage)> {
             dkim_key_path: "/tmp/dkim.private".to_string(),
             dkim_key_selector: "default".to_string(),
             max_message_size: MessageSize(25 * 1_048_576),
+            admins: vec![],
+            srs_secret: Some("test secret".to_string()),
+            scram_secret: Some("test secret".to_string()),
         },
         tls: Tls {
             key_path: "./certs/key.pem".to_string(),
@@ -48,10 +61,14 @@ pub async fn setup_test_storage() -> Result<(Config, Storage)> {
             tls: false,
         },
         rspamd: None,
+        search_index: Some(SearchIndex {
+            path: format!("/tmp/erooster-index-{id}"),
+        }),
+        oauth: None,
         task_folder: format!("/tmp/erooster-tasks-{id}"),
         listen_ips: None,
     };
     let database: DB = get_database(&config).await?;
-    let storage = storage::get_storage(database, config.clone());
-    Ok((config, storage))
+    let storage = storage::get_storage(database.clone(), config.clone());
+    Ok((config, database, storage))
 }
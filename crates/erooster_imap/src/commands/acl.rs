@@ -0,0 +1,565 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! ACL extension (RFC 4314).
+//!
+//! The owner of a mailbox always has all rights, so the owner is never stored
+//! in the ACL and can't be changed with SETACL or DELETEACL. Everybody else
+//! needs an entry for themselves or for `anyone`. Only users with the `a`
+//! right may look at or change the ACL of a mailbox.
+
+use crate::commands::{quota::authenticated_user, CommandData, Data};
+use erooster_core::backend::{
+    acl::{MailboxName, Rights, SHARED_OWNER},
+    storage::{MailStorage, Storage},
+};
+use {
+    futures::{Sink, SinkExt},
+    tracing::instrument,
+};
+
+/// Checks that `username` has all of `needed` on `mailbox` and answers with a
+/// tagged NO otherwise.
+///
+/// Mailboxes the user can't look up are reported as nonexistent so that
+/// their existence isn't disclosed.
+pub async fn require_rights<S, E>(
+    lines: &mut S,
+    storage: &Storage,
+    username: &str,
+    mailbox: &MailboxName,
+    needed: Rights,
+    tag: &str,
+) -> color_eyre::eyre::Result<bool>
+where
+    E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+    S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+{
+    let rights = storage.my_rights(mailbox, username).await?;
+    if rights.contains(needed) {
+        return Ok(true);
+    }
+    let response = if rights.contains(Rights::LOOKUP) {
+        "NO [NOPERM] Permission denied"
+    } else {
+        "NO [NONEXISTENT] No such mailbox"
+    };
+    lines.send(format!("{tag} {response}")).await?;
+    Ok(false)
+}
+
+/// The rights needed to set or clear the given flags
+#[must_use]
+pub fn flag_rights(flags: &[&str]) -> Rights {
+    flags
+        .iter()
+        .map(|flag| flag.trim_matches(|c| c == '(' || c == ')'))
+        .filter(|flag| !flag.is_empty())
+        .fold(Rights::NONE, |rights, flag| {
+            rights.union(if flag.eq_ignore_ascii_case("\\Seen") {
+                Rights::SEEN
+            } else if flag.eq_ignore_ascii_case("\\Deleted") {
+                Rights::DELETE_MESSAGES
+            } else {
+                Rights::WRITE
+            })
+        })
+}
+
+/// Resolves the mailbox argument of an ACL command and checks that it exists
+/// and that the user has `needed` on it
+async fn acl_mailbox<S, E>(
+    lines: &mut S,
+    storage: &Storage,
+    username: &str,
+    mailbox: &str,
+    needed: Rights,
+    tag: &str,
+) -> color_eyre::eyre::Result<Option<MailboxName>>
+where
+    E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+    S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+{
+    let resolved = MailboxName::resolve(mailbox, username);
+    if !require_rights(lines, storage, username, &resolved, needed, tag).await? {
+        return Ok(None);
+    }
+    let path = storage.to_ondisk_path(mailbox.to_string(), username.to_string())?;
+    if !path.exists() {
+        lines
+            .send(format!("{tag} NO [NONEXISTENT] No such mailbox"))
+            .await?;
+        return Ok(None);
+    }
+    Ok(Some(resolved))
+}
+
+pub struct SetAcl<'a> {
+    pub data: &'a Data,
+}
+
+impl SetAcl<'_> {
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let &[mailbox, identifier, rights] = command_data.arguments else {
+            lines
+                .send(format!(
+                    "{tag} BAD SETACL requires a mailbox, an identifier and rights"
+                ))
+                .await?;
+            return Ok(());
+        };
+        let identifier = identifier.trim_matches('"');
+        let rights = rights.trim_matches('"');
+        let (letters, change): (&str, fn(Rights, Rights) -> Rights) =
+            if let Some(letters) = rights.strip_prefix('+') {
+                (letters, Rights::union)
+            } else if let Some(letters) = rights.strip_prefix('-') {
+                (letters, Rights::difference)
+            } else {
+                (rights, |_, rights| rights)
+            };
+        let Some(rights) = Rights::parse(letters) else {
+            lines
+                .send(format!("{tag} BAD [CLIENTBUG] Unknown right in {letters}"))
+                .await?;
+            return Ok(());
+        };
+        let Some(mailbox) =
+            acl_mailbox(lines, storage, username, mailbox, Rights::ADMINISTER, tag).await?
+        else {
+            return Ok(());
+        };
+        if identifier.is_empty() || identifier == mailbox.owner {
+            lines
+                .send(format!("{tag} NO [CANNOT] The owner always has all rights"))
+                .await?;
+            return Ok(());
+        }
+
+        let current = storage
+            .get_acl(&mailbox)
+            .await?
+            .into_iter()
+            .find(|entry| entry.identifier == identifier)
+            .map_or(Rights::NONE, |entry| entry.rights);
+        storage
+            .set_acl(&mailbox, identifier, change(current, rights))
+            .await?;
+        lines.send(format!("{tag} OK SETACL completed")).await?;
+        Ok(())
+    }
+}
+
+pub struct DeleteAcl<'a> {
+    pub data: &'a Data,
+}
+
+impl DeleteAcl<'_> {
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let &[mailbox, identifier] = command_data.arguments else {
+            lines
+                .send(format!(
+                    "{tag} BAD DELETEACL requires a mailbox and an identifier"
+                ))
+                .await?;
+            return Ok(());
+        };
+        let identifier = identifier.trim_matches('"');
+        let Some(mailbox) =
+            acl_mailbox(lines, storage, username, mailbox, Rights::ADMINISTER, tag).await?
+        else {
+            return Ok(());
+        };
+        if identifier == mailbox.owner {
+            lines
+                .send(format!("{tag} NO [CANNOT] The owner always has all rights"))
+                .await?;
+            return Ok(());
+        }
+
+        storage.set_acl(&mailbox, identifier, Rights::NONE).await?;
+        lines.send(format!("{tag} OK DELETEACL completed")).await?;
+        Ok(())
+    }
+}
+
+pub struct GetAcl<'a> {
+    pub data: &'a Data,
+}
+
+impl GetAcl<'_> {
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let &[mailbox_arg] = command_data.arguments else {
+            lines
+                .send(format!("{tag} BAD GETACL requires a mailbox"))
+                .await?;
+            return Ok(());
+        };
+        let Some(mailbox) = acl_mailbox(
+            lines,
+            storage,
+            username,
+            mailbox_arg,
+            Rights::ADMINISTER,
+            tag,
+        )
+        .await?
+        else {
+            return Ok(());
+        };
+
+        let mut parts = vec![format!("\"{}\"", mailbox_arg.trim_matches('"'))];
+        if mailbox.owner != SHARED_OWNER {
+            parts.push(format!("{} {}", mailbox.owner, Rights::ALL));
+        }
+        parts.extend(
+            storage
+                .get_acl(&mailbox)
+                .await?
+                .iter()
+                .map(|entry| format!("{} {}", entry.identifier, entry.rights)),
+        );
+        lines.feed(format!("* ACL {}", parts.join(" "))).await?;
+        lines.feed(format!("{tag} OK GETACL completed")).await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct ListRights<'a> {
+    pub data: &'a Data,
+}
+
+impl ListRights<'_> {
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let &[mailbox_arg, identifier] = command_data.arguments else {
+            lines
+                .send(format!(
+                    "{tag} BAD LISTRIGHTS requires a mailbox and an identifier"
+                ))
+                .await?;
+            return Ok(());
+        };
+        let identifier = identifier.trim_matches('"');
+        let Some(mailbox) = acl_mailbox(
+            lines,
+            storage,
+            username,
+            mailbox_arg,
+            Rights::ADMINISTER,
+            tag,
+        )
+        .await?
+        else {
+            return Ok(());
+        };
+
+        // The owner can't lose any right, everybody else may get each of them
+        let rights = if identifier == mailbox.owner {
+            Rights::ALL.to_string()
+        } else {
+            let optional: Vec<String> = Rights::ALL.iter().map(|r| r.to_string()).collect();
+            format!("\"\" {}", optional.join(" "))
+        };
+        lines
+            .feed(format!(
+                "* LISTRIGHTS \"{}\" {identifier} {rights}",
+                mailbox_arg.trim_matches('"')
+            ))
+            .await?;
+        lines.feed(format!("{tag} OK LISTRIGHTS completed")).await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct MyRights<'a> {
+    pub data: &'a Data,
+}
+
+impl MyRights<'_> {
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let &[mailbox_arg] = command_data.arguments else {
+            lines
+                .send(format!("{tag} BAD MYRIGHTS requires a mailbox"))
+                .await?;
+            return Ok(());
+        };
+        let Some(mailbox) =
+            acl_mailbox(lines, storage, username, mailbox_arg, Rights::LOOKUP, tag).await?
+        else {
+            return Ok(());
+        };
+
+        let rights = storage.my_rights(&mailbox, username).await?;
+        lines
+            .feed(format!(
+                "* MYRIGHTS \"{}\" {rights}",
+                mailbox_arg.trim_matches('"')
+            ))
+            .await?;
+        lines.feed(format!("{tag} OK MYRIGHTS completed")).await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::commands::Commands;
+    use crate::servers::state::{Connection, State};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    fn logged_in(username: &str) -> Data {
+        Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(username.to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_flag_rights() {
+        assert_eq!(flag_rights(&["(\\Seen)"]), Rights::SEEN);
+        assert_eq!(
+            flag_rights(&["(\\Deleted", "$Junk)"]),
+            Rights::DELETE_MESSAGES.union(Rights::WRITE)
+        );
+        assert_eq!(flag_rights(&["()"]), Rights::NONE);
+    }
+
+    #[allow(clippy::too_many_lines)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_acl_commands() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let path = storage
+            .to_ondisk_path(String::from("INBOX"), String::from("alice"))
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        let alice = logged_in("alice");
+        let bob = logged_in("bob");
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        // Bob can't see Alice's INBOX until she shares it
+        let cmd_data = CommandData {
+            tag: "b1",
+            command: Commands::MyRights,
+            arguments: &["Users/alice/INBOX"],
+        };
+        MyRights { data: &bob }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("b1 NO [NONEXISTENT] No such mailbox"))
+        );
+
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::SetAcl,
+            arguments: &["INBOX", "bob", "lrs"],
+        };
+        SetAcl { data: &alice }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 OK SETACL completed"))
+        );
+        let cmd_data = CommandData {
+            tag: "a2",
+            command: Commands::SetAcl,
+            arguments: &["INBOX", "bob", "+w"],
+        };
+        SetAcl { data: &alice }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a2 OK SETACL completed"))
+        );
+
+        let cmd_data = CommandData {
+            tag: "a3",
+            command: Commands::GetAcl,
+            arguments: &["INBOX"],
+        };
+        GetAcl { data: &alice }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("* ACL \"INBOX\" alice lrswipkxtea bob lrsw"))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a3 OK GETACL completed"))
+        );
+
+        let cmd_data = CommandData {
+            tag: "b2",
+            command: Commands::MyRights,
+            arguments: &["Users/alice/INBOX"],
+        };
+        MyRights { data: &bob }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("* MYRIGHTS \"Users/alice/INBOX\" lrsw"))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("b2 OK MYRIGHTS completed"))
+        );
+
+        // Without the "a" right Bob may not change or read the ACL
+        let cmd_data = CommandData {
+            tag: "b3",
+            command: Commands::SetAcl,
+            arguments: &["Users/alice/INBOX", "bob", "+a"],
+        };
+        SetAcl { data: &bob }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("b3 NO [NOPERM] Permission denied"))
+        );
+
+        let cmd_data = CommandData {
+            tag: "a4",
+            command: Commands::ListRights,
+            arguments: &["INBOX", "bob"],
+        };
+        ListRights { data: &alice }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "* LISTRIGHTS \"INBOX\" bob \"\" l r s w i p k x t e a"
+            ))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a4 OK LISTRIGHTS completed"))
+        );
+
+        let cmd_data = CommandData {
+            tag: "a5",
+            command: Commands::DeleteAcl,
+            arguments: &["INBOX", "bob"],
+        };
+        DeleteAcl { data: &alice }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a5 OK DELETEACL completed"))
+        );
+        let rights = storage
+            .my_rights(&MailboxName::resolve("Users/alice/INBOX", "bob"), "bob")
+            .await
+            .unwrap();
+        assert!(rights.is_empty());
+    }
+}
@@ -3,14 +3,26 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{parsers::append_arguments, select::get_or_create_uidvalidity, CommandData, Data},
-    servers::state::{AppendingState, State},
+    commands::{
+        acl::require_rights,
+        copy::uid_set_string,
+        parsers::{append_arguments, catenate_part, AppendData, CatenatePart, LiteralSize},
+        select::get_or_create_uidvalidity,
+        CommandData, Data,
+    },
+    servers::state::{AppendMessage, AppendingState, State},
 };
 use erooster_core::{
-    backend::storage::{MailStorage, Storage},
+    backend::{
+        acl::{MailboxName, Rights},
+        quota::QuotaUsage,
+        storage::{MailEntry, MailStorage, Storage},
+    },
     config::Config,
+    line_codec,
 };
-use std::{io::Write, path::Path};
+use mailparse::{body::Body, ParsedMail};
+use std::path::{Path, PathBuf};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -19,15 +31,159 @@ use {
     tracing::{debug, error, instrument},
 };
 
+/// An IMAP URL (RFC 5092) of a message or a part of it on this server
+#[derive(Debug, PartialEq, Eq)]
+struct ImapUrl {
+    mailbox: String,
+    uidvalidity: Option<u32>,
+    uid: u32,
+    section: Option<String>,
+}
+
+/// Resolves the `%XX` escapes of an URL component
+fn percent_decode(input: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut iter = input.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Parses an absolute or server relative IMAP URL naming a single message.
+///
+/// Partial fetches, searches and URLAUTH are not supported.
+fn parse_imap_url(url: &str) -> Option<ImapUrl> {
+    // The authority can only be this server
+    let path = match url.strip_prefix("imap://") {
+        Some(rest) => &rest[rest.find('/')?..],
+        None => url,
+    };
+    let (mailbox, parameters) = path.strip_prefix('/')?.split_once("/;")?;
+    let (mailbox, uidvalidity) = match mailbox.split_once(';') {
+        Some((mailbox, uidvalidity)) => {
+            let (key, value) = uidvalidity.split_once('=')?;
+            if !key.eq_ignore_ascii_case("UIDVALIDITY") {
+                return None;
+            }
+            (mailbox, Some(value.parse().ok()?))
+        }
+        None => (mailbox, None),
+    };
+    let mut uid = None;
+    let mut section = None;
+    for parameter in parameters.split("/;") {
+        let (key, value) = parameter.split_once('=')?;
+        if key.eq_ignore_ascii_case("UID") && uid.is_none() {
+            uid = Some(value.parse().ok()?);
+        } else if key.eq_ignore_ascii_case("SECTION") && uid.is_some() {
+            section = Some(percent_decode(value)?);
+        } else {
+            return None;
+        }
+    }
+    Some(ImapUrl {
+        mailbox: percent_decode(mailbox)?,
+        uidvalidity,
+        uid: uid?,
+        section,
+    })
+}
+
+/// The undecoded body of a message or MIME part
+fn raw_body<'a>(part: &'a ParsedMail<'a>) -> &'a [u8] {
+    match part.get_body_encoded() {
+        Body::Base64(body) | Body::QuotedPrintable(body) => body.get_raw(),
+        Body::SevenBit(body) | Body::EightBit(body) => body.get_raw(),
+        Body::Binary(body) => body.get_raw(),
+    }
+}
+
+/// The octets of a message section like `HEADER`, `TEXT` or `1.2`
+fn section_bytes<'a>(mail: &'a ParsedMail<'a>, section: Option<&str>) -> Option<&'a [u8]> {
+    let Some(section) = section else {
+        return Some(mail.raw_bytes);
+    };
+    if section.eq_ignore_ascii_case("HEADER") {
+        let body = raw_body(mail);
+        return mail.raw_bytes.get(..mail.raw_bytes.len() - body.len());
+    }
+    if section.eq_ignore_ascii_case("TEXT") {
+        return Some(raw_body(mail));
+    }
+    let mut part = mail;
+    for number in section.split('.') {
+        let number: usize = number.parse().ok()?;
+        // A message which isn't multipart only has the part 1
+        part = if part.subparts.is_empty() && number == 1 {
+            part
+        } else {
+            part.subparts.get(number.checked_sub(1)?)?
+        };
+    }
+    Some(raw_body(part))
+}
+
+/// Fetches the octets an IMAP URL refers to, if the user may read them
+async fn resolve_url(
+    storage: &Storage,
+    username: &str,
+    url: &str,
+) -> color_eyre::eyre::Result<Option<Vec<u8>>> {
+    let Some(url) = parse_imap_url(url) else {
+        return Ok(None);
+    };
+    let mailbox = MailboxName::resolve(&url.mailbox, username);
+    if !storage
+        .my_rights(&mailbox, username)
+        .await?
+        .contains(Rights::READ)
+    {
+        return Ok(None);
+    }
+    let mailbox_path = storage.to_ondisk_path(url.mailbox.clone(), username.to_string())?;
+    if !mailbox_path.exists() {
+        return Ok(None);
+    }
+    if let Some(uidvalidity) = url.uidvalidity {
+        if get_or_create_uidvalidity(&mailbox_path).await? != uidvalidity {
+            return Ok(None);
+        }
+    }
+    let mut mails = storage.list_all(mailbox.id(), &mailbox_path).await;
+    let Some(mail) = mails.iter_mut().find(|mail| mail.uid() == url.uid) else {
+        return Ok(None);
+    };
+    let Ok(parsed) = mail.parsed() else {
+        return Ok(None);
+    };
+    Ok(section_bytes(&parsed, url.section.as_deref()).map(<[u8]>::to_vec))
+}
+
+/// The quota usage of the given messages
+fn usage_of<'a>(messages: impl Iterator<Item = &'a AppendMessage>) -> QuotaUsage {
+    messages.fold(QuotaUsage::default(), |usage, message| QuotaUsage {
+        storage: usage.storage + message.data.len() as u64,
+        messages: usage.messages + 1,
+    })
+}
+
 pub struct Append<'a> {
     pub data: &'a mut Data,
 }
 
 impl Append<'_> {
-    #[instrument(skip(self, lines, storage, command_data))]
+    #[allow(clippy::too_many_lines)]
+    #[instrument(skip(self, lines, config, storage, command_data))]
     pub async fn exec<S, E>(
         &mut self,
         lines: &mut S,
+        config: &Config,
         storage: &Storage,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
@@ -43,7 +199,7 @@ impl Append<'_> {
                 "[Append] User added {} arguments",
                 command_data.arguments.len()
             );
-            if command_data.arguments.len() < 3 {
+            if command_data.arguments.len() < 2 {
                 lines
                     .send(format!("{} NO invalid argument count", command_data.tag))
                     .await?;
@@ -51,87 +207,248 @@ impl Append<'_> {
             }
             let folder = command_data.arguments[0].replace('"', "");
             debug!("[Append] User wants to append to folder: {}", folder);
-            let mailbox_path = storage.to_ondisk_path(
-                folder.clone(),
-                self.data
-                    .con_state
-                    .username
-                    .clone()
-                    .context("Username missing in internal State")?,
-            )?;
-            let folder = storage.to_ondisk_path_name(folder)?;
+            let username = self
+                .data
+                .con_state
+                .username
+                .clone()
+                .context("Username missing in internal State")?;
+            let mailbox = MailboxName::resolve(&folder, &username);
+            if !require_rights(
+                lines,
+                storage,
+                &username,
+                &mailbox,
+                Rights::INSERT,
+                command_data.tag,
+            )
+            .await?
+            {
+                return Ok(());
+            }
+            let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
+            let ondisk_name = storage.to_ondisk_path_name(folder.clone())?;
             debug!("Appending to folder: {:?}", mailbox_path);
             // Spec violation but thunderbird would prompt a user error otherwise :/
+            // Only done for personal mailboxes, others need to be created explicitly.
             let is_new = !mailbox_path.exists();
+            if is_new && !mailbox.is_owned_by(&username) {
+                lines
+                    .send(format!(
+                        "{} NO [TRYCREATE] No such mailbox",
+                        command_data.tag
+                    ))
+                    .await?;
+                return Ok(());
+            }
             storage.create_dirs(&mailbox_path)?;
             if is_new {
-                if folder.to_lowercase() == ".sent" {
+                if ondisk_name.to_lowercase() == ".sent" {
                     storage.add_flag(&mailbox_path, "\\Sent").await?;
                     storage.add_flag(&mailbox_path, "\\Subscribed").await?;
-                } else if folder.to_lowercase() == ".junk" {
+                } else if ondisk_name.to_lowercase() == ".junk" {
                     storage.add_flag(&mailbox_path, "\\Junk").await?;
                     storage.add_flag(&mailbox_path, "\\Subscribed").await?;
-                } else if folder.to_lowercase() == ".drafts" {
+                } else if ondisk_name.to_lowercase() == ".drafts" {
                     storage.add_flag(&mailbox_path, "\\Drafts").await?;
                     storage.add_flag(&mailbox_path, "\\Subscribed").await?;
-                } else if folder.to_lowercase() == ".archive" {
+                } else if ondisk_name.to_lowercase() == ".archive" {
                     storage.add_flag(&mailbox_path, "\\Archive").await?;
                     storage.add_flag(&mailbox_path, "\\Subscribed").await?;
-                } else if folder.to_lowercase() == ".trash" {
+                } else if ondisk_name.to_lowercase() == ".trash" {
                     storage.add_flag(&mailbox_path, "\\Trash").await?;
                     storage.add_flag(&mailbox_path, "\\Subscribed").await?;
                 }
             }
 
+            let previous_state = self.data.con_state.state.clone();
+            self.data.con_state.state = State::Appending(AppendingState {
+                folder,
+                messages: Vec::new(),
+                current: None,
+                literal: None,
+                error: None,
+                tag: command_data.tag.to_string(),
+                previous_state: Box::new(previous_state),
+            });
             let append_args = command_data.arguments[1..].join(" ");
-            let append_args_borrow: &str = &append_args;
-            debug!("Append args: {}", append_args_borrow);
-            match append_arguments(append_args_borrow).finish() {
-                Ok((left, (flags, datetime, literal))) => {
-                    debug!("[Append] leftover: {}", left);
-                    let previous_state = self.data.con_state.state.clone();
-                    self.data.con_state.state = State::Appending(AppendingState {
-                        folder: folder.clone(),
-                        flags: flags.map(|x| x.iter().map(ToString::to_string).collect::<Vec<_>>()),
+            debug!("Append args: {}", append_args);
+            self.continue_command(lines, config, storage, &append_args)
+                .await?;
+        } else {
+            lines
+                .send(format!("{} NO invalid state", command_data.tag))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Receives a line of a literal or, once the literal is complete, the
+    /// rest of the command line following it.
+    #[instrument(skip(self, lines, config, storage, append_data))]
+    pub async fn append<S, E>(
+        &mut self,
+        lines: &mut S,
+        config: &Config,
+        storage: &Storage,
+        append_data: &str,
+        tag: String,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let State::Appending(ref mut state) = self.data.con_state.state else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let Some(remaining) = state.literal else {
+            let Some(text) = line_codec::text(append_data.to_string()) else {
+                return self.abort(lines, "BAD Command is not UTF-8 text").await;
+            };
+            return self.continue_command(lines, config, storage, &text).await;
+        };
+
+        let mut received = line_codec::octets(append_data);
+        let length = received.len();
+        let rest = received.split_off(remaining.min(length));
+        debug!("Literal octets still expected: {}", remaining);
+        if let (None, Some(current)) = (&state.error, &mut state.current) {
+            current.data.extend(received);
+        }
+        if remaining > length {
+            state.literal = Some(remaining - length);
+            return Ok(());
+        }
+        state.literal = None;
+        if let Some(current) = state.current.take_if(|current| !current.catenate) {
+            state.messages.push(current);
+        }
+        // A literal ending with a line break is followed by the rest of the
+        // command on the next line.
+        if rest.is_empty() {
+            return Ok(());
+        }
+        // The literal may have consumed the CR of the line break, leaving
+        // only the LF behind.
+        let rest = rest
+            .strip_suffix(b"\r\n")
+            .or_else(|| rest.strip_suffix(b"\n"))
+            .unwrap_or(&rest);
+        let Ok(rest) = String::from_utf8(rest.to_vec()) else {
+            return self.abort(lines, "BAD Command is not UTF-8 text").await;
+        };
+        self.continue_command(lines, config, storage, &rest).await
+    }
+
+    /// Works through the command text following the mailbox name or a
+    /// literal until another literal is needed or the command ends.
+    #[allow(clippy::too_many_lines)]
+    #[instrument(skip(self, lines, config, storage, text))]
+    async fn continue_command<S, E>(
+        &mut self,
+        lines: &mut S,
+        config: &Config,
+        storage: &Storage,
+        text: &str,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let username = self
+            .data
+            .con_state
+            .username
+            .clone()
+            .context("Username missing in internal State")?;
+        let mut text = text.trim_start();
+        loop {
+            let State::Appending(ref mut state) = self.data.con_state.state else {
+                return Ok(());
+            };
+
+            // Inside of a CATENATE list
+            if let Some(current) = state.current.as_mut().filter(|current| current.catenate) {
+                match catenate_part(text).finish() {
+                    Ok((rest, CatenatePart::Url(url))) => {
+                        if state.error.is_none() {
+                            match resolve_url(storage, &username, &url).await? {
+                                Some(part) => current.data.extend(part),
+                                None => {
+                                    state.error = Some(format!(
+                                        "NO [BADURL \"{url}\"] Unable to resolve the URL"
+                                    ));
+                                }
+                            }
+                            if current.data.len() as u64 > config.mail.max_message_size.as_bytes() {
+                                state.error = Some(String::from(
+                                    "NO [TOOBIG] Message exceeds the APPENDLIMIT",
+                                ));
+                            }
+                        }
+                        text = rest.trim_start();
+                    }
+                    Ok((rest, CatenatePart::Text(literal))) if rest.trim().is_empty() => {
+                        return self.start_literal(lines, config, storage, &literal).await;
+                    }
+                    Ok((rest, CatenatePart::End)) => {
+                        current.catenate = false;
+                        if let Some(current) = state.current.take() {
+                            state.messages.push(current);
+                        }
+                        text = rest;
+                    }
+                    Ok(_) | Err(_) => {
+                        return self
+                            .abort(lines, "BAD failed to parse CATENATE parts")
+                            .await;
+                    }
+                }
+                continue;
+            }
+
+            // The UTF8 extension wraps a literal in parentheses
+            let next = text.strip_prefix(')').unwrap_or(text).trim_start();
+            if next.is_empty() {
+                return self.finish(lines, storage).await;
+            }
+            match append_arguments(next).finish() {
+                Ok((rest, (flags, datetime, data))) => {
+                    state.current = Some(AppendMessage {
+                        flags: flags.map(|x| x.iter().map(ToString::to_string).collect()),
                         datetime,
-                        data: None,
-                        datalen: literal.length,
-                        tag: command_data.tag.to_string(),
-                        previous_state: Box::new(previous_state),
+                        data: Vec::new(),
+                        catenate: matches!(data, AppendData::Catenate),
                     });
-                    if !literal.continuation {
-                        lines.send(String::from("+ Ready for literal data")).await?;
+                    match data {
+                        AppendData::Literal(literal) if rest.trim().is_empty() => {
+                            return self.start_literal(lines, config, storage, &literal).await;
+                        }
+                        AppendData::Literal(_) => {
+                            return self.abort(lines, "BAD failed to parse arguments").await;
+                        }
+                        AppendData::Catenate => text = rest.trim_start(),
                     }
                 }
                 Err(e) => {
                     error!(
                         "[Append] Error parsing arguments: {}",
-                        convert_error(append_args_borrow, e)
+                        convert_error(next, e)
                     );
-                    lines
-                        .send(format!(
-                            "{} BAD failed to parse arguments",
-                            command_data.tag
-                        ))
-                        .await?;
+                    return self.abort(lines, "BAD failed to parse arguments").await;
                 }
             }
-        } else {
-            lines
-                .send(format!("{} NO invalid state", command_data.tag))
-                .await?;
         }
-        Ok(())
     }
 
-    #[instrument(skip(self, lines, storage, config, append_data))]
-    pub async fn append<S, E>(
+    /// Prepares receiving a literal of the current message
+    async fn start_literal<S, E>(
         &mut self,
         lines: &mut S,
-        storage: &Storage,
-        append_data: &str,
         config: &Config,
-        tag: String,
+        storage: &Storage,
+        literal: &LiteralSize,
     ) -> color_eyre::eyre::Result<()>
     where
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
@@ -143,62 +460,164 @@ impl Append<'_> {
             .username
             .clone()
             .context("Username missing in internal State")?;
-        // Extract all needed data from Appending state before mutating.
-        let completion = if let State::Appending(ref mut state) = self.data.con_state.state {
-            if let Some(ref mut buffer) = state.data {
-                write!(buffer, "{append_data}\r\n")?;
-                debug!("Buffer length: {}", buffer.len());
-                debug!("expected: {}", state.datalen);
-                if buffer.len() >= state.datalen {
-                    debug!("[Append] Saving data");
-                    let folder = state.folder.clone();
-                    let imap_flags = state.flags.clone().unwrap_or_default();
-                    let previous_state = *state.previous_state.clone();
-                    let completion_tag = state.tag.clone();
-                    let mut buf = buffer.clone();
-                    buf.truncate(buf.len() - 2);
-                    Some((folder, imap_flags, previous_state, completion_tag, buf))
-                } else {
-                    None
-                }
-            } else {
-                let mut buffer = Vec::with_capacity(state.datalen);
-                write!(buffer, "{append_data}\r\n")?;
-                state.data = Some(buffer);
-                return Ok(());
+        let State::Appending(ref mut state) = self.data.con_state.state else {
+            return Ok(());
+        };
+        if state.error.is_none() {
+            let mut usage = usage_of(state.messages.iter().chain(&state.current));
+            usage.storage += literal.length as u64;
+            let size = state
+                .current
+                .as_ref()
+                .map_or(0, |current| current.data.len());
+            if (size + literal.length) as u64 > config.mail.max_message_size.as_bytes() {
+                state.error = Some(String::from("NO [TOOBIG] Message exceeds the APPENDLIMIT"));
+            } else if !storage
+                .quota_allows(
+                    &MailboxName::resolve(&state.folder, &username).owner,
+                    &usage,
+                )
+                .await?
+            {
+                state.error = Some(String::from("NO [OVERQUOTA] Quota exceeded"));
             }
-        } else {
-            lines.send(format!("{tag} NO invalid state")).await?;
+        }
+        // With a synchronizing literal we can refuse the message before the
+        // client sends it. Other literals have to be read nonetheless.
+        if !literal.continuation {
+            if let Some(error) = state.error.clone() {
+                return self.abort(lines, &error).await;
+            }
+        }
+        if literal.length == 0 {
             return Ok(());
+        }
+        state.literal = Some(literal.length);
+        if !literal.continuation {
+            lines.send(String::from("+ Ready for literal data")).await?;
+        }
+        Ok(())
+    }
+
+    /// Stores all messages of the command once it is complete
+    async fn finish<S, E>(
+        &mut self,
+        lines: &mut S,
+        storage: &Storage,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let username = self
+            .data
+            .con_state
+            .username
+            .clone()
+            .context("Username missing in internal State")?;
+        let Some(state) = self.leave() else {
+            return Ok(());
+        };
+        let response = match prepare_mailbox(storage, &username, &state).await? {
+            Ok((mailbox_id, mailbox_path)) => {
+                store_messages(storage, mailbox_id, &mailbox_path, &state.messages).await?
+            }
+            Err(refusal) => refusal,
         };
+        lines.send(format!("{} {response}", state.tag)).await?;
+        Ok(())
+    }
 
-        if let Some((folder, imap_flags, previous_state, completion_tag, buffer)) = completion {
-            let mailbox_path = Path::new(&config.mail.maildir_folders)
-                .join(username.clone())
-                .join(folder.clone());
-            debug!("[Append] Mailbox path: {:?}", mailbox_path);
-            storage.create_dirs(&mailbox_path)?;
-            // Use the IMAP folder name (no leading dot) as the DB key so it
-            // matches what SELECT/FETCH use (State::Selected stores "Sent", not ".Sent").
-            let db_folder = folder.trim_start_matches('.');
-            let mailbox_id = format!("{username}/{db_folder}");
-            let message_id = storage
-                .store_cur_with_flags(mailbox_id.clone(), &mailbox_path, &buffer, imap_flags)
-                .await?;
-            debug!("Stored message via append: {}", message_id);
-            // Restore the state we were in before APPEND (RFC 9051: APPEND does
-            // not change the selected mailbox).
-            self.data.con_state.state = previous_state;
-            let uidvalidity = get_or_create_uidvalidity(&mailbox_path).await?;
-            let uid = storage.get_uid_for_folder(&mailbox_id).await?;
-            lines
-                .send(format!(
-                    "{completion_tag} OK [APPENDUID {uidvalidity} {uid}] APPEND completed"
-                ))
-                .await?;
+    /// Ends the command early with the given response
+    async fn abort<S, E>(&mut self, lines: &mut S, response: &str) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        if let Some(state) = self.leave() {
+            lines.send(format!("{} {response}", state.tag)).await?;
         }
         Ok(())
     }
+
+    /// Restores the state from before APPEND (RFC 9051: APPEND does not
+    /// change the selected mailbox) and returns the appending state.
+    fn leave(&mut self) -> Option<AppendingState> {
+        let State::Appending(state) = &mut self.data.con_state.state else {
+            return None;
+        };
+        let previous_state = std::mem::replace(&mut *state.previous_state, State::NotAuthenticated);
+        let State::Appending(state) =
+            std::mem::replace(&mut self.data.con_state.state, previous_state)
+        else {
+            return None;
+        };
+        Some(state)
+    }
+}
+
+/// Checks the complete command and creates the mailbox if needed. Returns
+/// the mailbox or the response refusing the command.
+async fn prepare_mailbox(
+    storage: &Storage,
+    username: &str,
+    state: &AppendingState,
+) -> color_eyre::eyre::Result<Result<(String, PathBuf), String>> {
+    if let Some(error) = &state.error {
+        return Ok(Err(error.clone()));
+    }
+    if state.messages.is_empty() || state.current.is_some() {
+        return Ok(Err(String::from("BAD failed to parse arguments")));
+    }
+    let mailbox = MailboxName::resolve(&state.folder, username);
+    if !storage
+        .quota_allows(&mailbox.owner, &usage_of(state.messages.iter()))
+        .await?
+    {
+        return Ok(Err(String::from("NO [OVERQUOTA] Quota exceeded")));
+    }
+    let mailbox_path = storage.to_ondisk_path(state.folder.clone(), username.to_string())?;
+    debug!("[Append] Mailbox path: {:?}", mailbox_path);
+    storage.create_dirs(&mailbox_path)?;
+    // Use the IMAP folder name (no leading dot) as the DB key so it
+    // matches what SELECT/FETCH use (State::Selected stores "Sent", not ".Sent").
+    Ok(Ok((mailbox.id(), mailbox_path)))
+}
+
+/// Stores the messages of the command and returns the response. MULTIAPPEND
+/// is all or nothing (RFC 3502 §3), so either all messages show up or none.
+async fn store_messages(
+    storage: &Storage,
+    mailbox_id: String,
+    mailbox_path: &Path,
+    messages: &[AppendMessage],
+) -> color_eyre::eyre::Result<String> {
+    let messages: Vec<(&[u8], Vec<String>)> = messages
+        .iter()
+        .map(|message| {
+            (
+                message.data.as_slice(),
+                message.flags.clone().unwrap_or_default(),
+            )
+        })
+        .collect();
+    let stored = match storage
+        .store_all_cur_with_flags(mailbox_id, mailbox_path, &messages)
+        .await
+    {
+        Ok(stored) => stored,
+        Err(e) => {
+            error!("[Append] Failed to store the messages: {}", e);
+            return Ok(String::from("NO [SERVERBUG] Unable to store the messages"));
+        }
+    };
+    debug!("Stored messages via append: {:?}", stored);
+    let uids: Vec<u32> = stored.into_iter().map(|(_, uid)| uid).collect();
+    let uidvalidity = get_or_create_uidvalidity(mailbox_path).await?;
+    Ok(format!(
+        "OK [APPENDUID {uidvalidity} {}] APPEND completed",
+        uid_set_string(&uids)
+    ))
 }
 
 #[cfg(test)]
@@ -207,6 +626,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Access, Connection};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[allow(clippy::unwrap_used)]
@@ -220,6 +640,11 @@ mod tests {
                     secure: true,
                     username: None,
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -228,11 +653,11 @@ mod tests {
             command: Commands::Append,
             arguments: &[],
         };
-        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let res = caps.exec(&mut tx, &storage, &cmd_data).await;
+        let res = caps.exec(&mut tx, &config, &storage, &cmd_data).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(rx.next().await, Some(String::from("a1 NO invalid state")));
     }
@@ -248,6 +673,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("meow")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -256,11 +686,11 @@ mod tests {
             command: Commands::Append,
             arguments: &[],
         };
-        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let res = caps.exec(&mut tx, &storage, &cmd_data).await;
+        let res = caps.exec(&mut tx, &config, &storage, &cmd_data).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
             rx.next().await,
@@ -279,6 +709,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("meow")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -288,11 +723,11 @@ mod tests {
             arguments: &["INBOX", "(\\Seen)", "{326}"],
         };
 
-        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let res = caps.exec(&mut tx, &storage, &cmd_data).await;
+        let res = caps.exec(&mut tx, &config, &storage, &cmd_data).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
             rx.next().await,
@@ -312,6 +747,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("meow")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -325,7 +765,7 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let res = caps.exec(&mut tx, &storage, &cmd_data).await;
+        let res = caps.exec(&mut tx, &config, &storage, &cmd_data).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
             rx.next().await,
@@ -337,9 +777,9 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "Date: Mon, 7 Feb 1994 21:52:25 -0800 (PST)",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
@@ -347,9 +787,9 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "From: Fred Foobar <foobar@Blurdybloop.example>",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
@@ -357,9 +797,9 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "Subject: afternoon meeting",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
@@ -367,9 +807,9 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "To: mooch@owatagu.siam.edu.example",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
@@ -377,9 +817,9 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "Message-Id: <B27397-0100000@Blurdybloop.example>",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
@@ -387,9 +827,9 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "MIME-Version: 1.0",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
@@ -397,29 +837,29 @@ mod tests {
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "Content-Type: TEXT/PLAIN; CHARSET=US-ASCII",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
         assert!(res.is_ok(), "{:?}", res);
         let res = caps
-            .append(&mut tx, &storage, "", &config, cmd_data.tag.to_string())
+            .append(&mut tx, &config, &storage, "", cmd_data.tag.to_string())
             .await;
         assert!(res.is_ok(), "{:?}", res);
         let res = caps
             .append(
                 &mut tx,
+                &config,
                 &storage,
                 "Hello Joe, do you think we can meet at 3:30 tomorrow?",
-                &config,
                 cmd_data.tag.to_string(),
             )
             .await;
         assert!(res.is_ok(), "{:?}", res);
         let res = caps
-            .append(&mut tx, &storage, "", &config, cmd_data.tag.to_string())
+            .append(&mut tx, &config, &storage, "", cmd_data.tag.to_string())
             .await;
         assert!(res.is_ok(), "{:?}", res);
         let reply = rx.next().await.unwrap_or_default();
@@ -443,6 +883,11 @@ mod tests {
             secure: true,
             username: Some(String::from("meow")),
             active_capabilities: vec![],
+            notify: None,
+            compressed: false,
+            saved_result: vec![],
+            client_id: BTreeMap::new(),
+            peer_addr: String::new(),
         };
         let mut data = Data {
             con_state: connection,
@@ -460,11 +905,14 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, _rx) = mpsc::unbounded();
-        caps.exec(&mut tx, &storage, &cmd_data).await.unwrap();
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
 
+        // The final empty line ends the command after the literal
         let (mut tx, mut rx) = mpsc::unbounded();
-        for line in &["From: a@b.com", "Subject: test", "", "test body"] {
-            caps.append(&mut tx, &storage, line, &config, "t1".to_string())
+        for line in &["From: a@b.com", "Subject: test", "", "test body", ""] {
+            caps.append(&mut tx, &config, &storage, line, "t1".to_string())
                 .await
                 .unwrap();
         }
@@ -493,6 +941,11 @@ mod tests {
             secure: true,
             username: Some(String::from("meow")),
             active_capabilities: vec![],
+            notify: None,
+            compressed: false,
+            saved_result: vec![],
+            client_id: BTreeMap::new(),
+            peer_addr: String::new(),
         };
         let mut data = Data {
             con_state: connection,
@@ -508,11 +961,14 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, _rx) = mpsc::unbounded();
-        caps.exec(&mut tx, &storage, &cmd_data).await.unwrap();
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
 
+        // The final empty line ends the command after the literal
         let (mut tx, mut rx) = mpsc::unbounded();
-        for line in &["From: a@b.com", "Subject: test", "", "test body"] {
-            caps.append(&mut tx, &storage, line, &config, "t2".to_string())
+        for line in &["From: a@b.com", "Subject: test", "", "test body", ""] {
+            caps.append(&mut tx, &config, &storage, line, "t2".to_string())
                 .await
                 .unwrap();
         }
@@ -534,4 +990,274 @@ mod tests {
             "nothing should be stored under ondisk name meow/.Sent"
         );
     }
+
+    #[test]
+    fn test_parse_imap_url() {
+        assert_eq!(
+            parse_imap_url("/INBOX;UIDVALIDITY=385759045/;UID=20/;SECTION=1.2"),
+            Some(ImapUrl {
+                mailbox: String::from("INBOX"),
+                uidvalidity: Some(385_759_045),
+                uid: 20,
+                section: Some(String::from("1.2")),
+            })
+        );
+        assert_eq!(
+            parse_imap_url("imap://bob@example.com/Sent%20Items/;uid=3"),
+            Some(ImapUrl {
+                mailbox: String::from("Sent Items"),
+                uidvalidity: None,
+                uid: 3,
+                section: None,
+            })
+        );
+        assert_eq!(parse_imap_url("/INBOX/;UID=20/;PARTIAL=0.10"), None);
+        assert_eq!(parse_imap_url("/INBOX"), None);
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[allow(clippy::too_many_lines)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_multiappend_and_catenate() {
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test_multiappend")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let mut caps = Append { data: &mut data };
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        // Two messages of 14 octets in one command
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Append,
+            arguments: &["Drafts", "(\\Seen)", "{14}"],
+        };
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        for line in ["Subject: one", " {14}"] {
+            caps.append(&mut tx, &config, &storage, line, String::from("a1"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        for line in ["Subject: two", ""] {
+            caps.append(&mut tx, &config, &storage, line, String::from("a1"))
+                .await
+                .unwrap();
+        }
+        let reply = rx.next().await.unwrap();
+        assert!(
+            reply.starts_with("a1 OK [APPENDUID ") && reply.ends_with(" 1:2] APPEND completed"),
+            "{reply}"
+        );
+
+        // The first message followed by new text
+        let cmd_data = CommandData {
+            tag: "a2",
+            command: Commands::Append,
+            arguments: &[
+                "Drafts",
+                "CATENATE",
+                "(URL",
+                "\"/Drafts/;UID=1\"",
+                "TEXT",
+                "{7+}",
+            ],
+        };
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        for line in ["extra", ")"] {
+            caps.append(&mut tx, &config, &storage, line, String::from("a2"))
+                .await
+                .unwrap();
+        }
+        let reply = rx.next().await.unwrap();
+        assert!(reply.ends_with(" 3] APPEND completed"), "{reply}");
+        let mailbox_path = storage
+            .to_ondisk_path(String::from("Drafts"), String::from("test_multiappend"))
+            .unwrap();
+        let mut mails = storage
+            .list_all(String::from("test_multiappend/Drafts"), &mailbox_path)
+            .await;
+        let mail = mails.iter_mut().find(|mail| mail.uid() == 3).unwrap();
+        assert_eq!(
+            mail.parsed().unwrap().raw_bytes,
+            b"Subject: one\r\nextra\r\n"
+        );
+
+        let cmd_data = CommandData {
+            tag: "a3",
+            command: Commands::Append,
+            arguments: &["Drafts", "CATENATE", "(URL", "\"/Drafts/;UID=99\")"],
+        };
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a3 NO [BADURL \"/Drafts/;UID=99\"] Unable to resolve the URL"
+        );
+
+        let cmd_data = CommandData {
+            tag: "a4",
+            command: Commands::Append,
+            arguments: &["Drafts", "{999999999}"],
+        };
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a4 NO [TOOBIG] Message exceeds the APPENDLIMIT"
+        );
+        assert_eq!(caps.data.con_state.state, State::Authenticated);
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_multiappend_is_atomic() {
+        use erooster_core::backend::database::Database;
+
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test_multiappend_atomic")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let mut caps = Append { data: &mut data };
+        let (config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        // Storing the second message fails
+        sqlx::query(
+            "CREATE TRIGGER fail_second_append BEFORE INSERT ON mails WHEN (SELECT COUNT(*) FROM mails) >= 1 BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+        )
+        .execute(database.get_pool())
+        .await
+        .unwrap();
+        let mut events = storage.events().subscribe();
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Append,
+            arguments: &["INBOX", "{14}"],
+        };
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        for line in ["Subject: one", " {14}"] {
+            caps.append(&mut tx, &config, &storage, line, String::from("a1"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        for line in ["Subject: two", ""] {
+            caps.append(&mut tx, &config, &storage, line, String::from("a1"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a1 NO [SERVERBUG] Unable to store the messages"
+        );
+
+        let mailbox_path = storage
+            .to_ondisk_path(
+                String::from("INBOX"),
+                String::from("test_multiappend_atomic"),
+            )
+            .unwrap();
+        assert!(storage
+            .list_all(String::from("test_multiappend_atomic/INBOX"), &mailbox_path)
+            .await
+            .is_empty());
+        assert_eq!(storage.count_cur(&mailbox_path), 0);
+        // Neither staged files nor events are left behind
+        assert!(std::fs::read_dir(mailbox_path.join("tmp"))
+            .unwrap()
+            .next()
+            .is_none());
+        assert!(events.try_recv().is_err());
+        assert_eq!(caps.data.con_state.state, State::Authenticated);
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_append_binary_literal() {
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test_append_binary")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let mut caps = Append { data: &mut data };
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Append,
+            arguments: &["INBOX", "~{16}"],
+        };
+        caps.exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        // The codec passes the line which isn't text on as raw octets
+        let binary = line_codec::raw(b"\xff\0\r\n");
+        for line in ["Subject: a", "", &binary] {
+            caps.append(&mut tx, &config, &storage, line, String::from("a1"))
+                .await
+                .unwrap();
+        }
+        let reply = rx.next().await.unwrap();
+        assert!(reply.starts_with("a1 OK [APPENDUID "), "{reply}");
+
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_append_binary".to_string())
+            .unwrap();
+        let mails = storage
+            .list_all(String::from("test_append_binary/INBOX"), &path)
+            .await;
+        assert_eq!(
+            std::fs::read(mails[0].path()).unwrap(),
+            b"Subject: a\r\n\r\n\xff\0"
+        );
+    }
 }
@@ -7,129 +7,70 @@ use crate::{
     servers::state::State,
 };
 use erooster_core::{
-    backend::database::{Database, DB},
-    BASE64_DECODER,
+    backend::database::DB,
+    config::Config,
+    sasl::{AuthError, Exchange, Protocol, Step},
 };
 use {
-    base64::Engine,
     color_eyre,
     futures::{Sink, SinkExt},
-    secrecy::SecretString,
-    simdutf8::compat::from_utf8,
-    tracing::{debug, error, instrument},
+    tracing::{debug, instrument},
 };
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub enum AuthenticationMethod {
-    Plain,
-}
-
 pub struct Authenticate<'a> {
     pub data: &'a mut Data,
     pub auth_data: &'a str,
 }
 
 impl Authenticate<'_> {
-    #[instrument(skip(self, lines, database, command_data))]
-    pub async fn plain<S, E>(
+    /// Relays a client response to the SASL exchange
+    #[instrument(skip(self, lines, config, database, command_data, exchange))]
+    pub async fn respond<S, E>(
         &mut self,
         lines: &mut S,
+        config: &Config,
         database: &DB,
         command_data: &CommandData<'_>,
+        mut exchange: Exchange,
     ) -> color_eyre::eyre::Result<()>
     where
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
-        let bytes = BASE64_DECODER.decode(self.auth_data.as_bytes());
-        match bytes {
-            Ok(bytes) => {
-                let auth_data_vec: Vec<&str> = bytes
-                    .split(|x| *x == b'\0')
-                    .filter_map(|string| {
-                        let new_string = from_utf8(string);
-                        if let Ok(new_string) = new_string {
-                            if new_string.is_empty() {
-                                return None;
-                            }
-                            return Some(new_string);
-                        }
-                        None
-                    })
-                    .collect();
-
-                if auth_data_vec.len() == 2 {
-                    let username = auth_data_vec[0];
-                    let password = SecretString::new(Box::from(auth_data_vec[1]));
-
-                    debug!("[IMAP] Making sure user exists");
-                    if database.user_exists(username).await {
-                        debug!("[IMAP] Verify credentials");
-                        if !database.verify_user(username, password).await {
-                            {
-                                self.data.con_state.state = State::NotAuthenticated;
-                            };
-                            lines
-                                .send(format!(
-                                    "{} NO [AUTHENTICATIONFAILED] Invalid user or password",
-                                    command_data.tag
-                                ))
-                                .await?;
-                            debug!("[IMAP] Invalid user or password");
-                            return Ok(());
-                        }
-                        {
-                            self.data.con_state.username = Some(username.to_string());
-                            self.data.con_state.state = State::Authenticated;
-                        };
-                        let secure = self.data.con_state.secure;
-                        if secure {
-                            lines
-                                .send(format!("{} OK Success (tls protection)", command_data.tag))
-                                .await?;
-                        } else {
-                            lines
-                                .send(format!("{} OK Success (unprotected)", command_data.tag))
-                                .await?;
-                        }
-                    } else {
-                        {
-                            self.data.con_state.state = State::NotAuthenticated;
-                        };
-                        lines
-                            .send(format!(
-                                "{} NO [AUTHENTICATIONFAILED] Invalid user or password",
-                                command_data.tag
-                            ))
-                            .await?;
-                    }
-                } else {
-                    {
-                        self.data.con_state.state = State::NotAuthenticated;
-                    };
-                    lines
-                        .send(format!("{} BAD Invalid arguments", command_data.tag))
-                        .await?;
-                }
+        self.data.con_state.state = State::NotAuthenticated;
+        match exchange.respond(config, database, self.auth_data).await {
+            Step::Challenge(challenge) => {
+                self.data.con_state.state =
+                    State::Authenticating(exchange, command_data.tag.to_string());
+                lines.send(format!("+ {challenge}")).await?;
             }
-            Err(e) => {
-                {
-                    self.data.con_state.state = State::NotAuthenticated;
+            Step::Success(username) => {
+                debug!("[IMAP] Authenticated with {}", exchange.mechanism().name());
+                self.data.con_state.username = Some(username);
+                self.data.con_state.state = State::Authenticated;
+                let protection = if self.data.con_state.secure {
+                    "tls protection"
+                } else {
+                    "unprotected"
                 };
-                error!("Error logging in: {}", e);
                 lines
-                    .send(format!("{} BAD Invalid arguments", command_data.tag))
+                    .send(format!("{} OK Success ({protection})", command_data.tag))
+                    .await?;
+            }
+            Step::Failure(error) => {
+                lines
+                    .send(format!("{} {}", command_data.tag, response(error)))
                     .await?;
             }
         }
-
         Ok(())
     }
 
-    #[instrument(skip(self, lines, database, command_data))]
+    #[instrument(skip(self, lines, config, database, command_data))]
     pub async fn exec<S, E>(
         &mut self,
         lines: &mut S,
+        config: &Config,
         database: &DB,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
@@ -137,36 +78,153 @@ impl Authenticate<'_> {
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
-        if self.data.con_state.state == State::NotAuthenticated {
-            let args = &command_data.arguments;
-            if args.len() == 1 {
-                if args[0].to_lowercase() == "plain" {
-                    debug!("[IMAP] Update state to Authenticating");
-                    {
-                        let command_data = command_data;
-                        self.data.con_state.state = State::Authenticating(
-                            AuthenticationMethod::Plain,
-                            command_data.tag.to_string(),
-                        );
-                    };
-                    debug!("[IMAP] Sending continuation request");
-                    lines.send(String::from("+ ")).await?;
-                } else {
-                    self.plain(lines, database, command_data).await?;
-                }
-            } else {
-                lines
-                    .send(format!(
-                        "{} BAD [SERVERBUG] unable to parse command",
-                        command_data.tag
-                    ))
-                    .await?;
-            }
-        } else {
+        if self.data.con_state.state != State::NotAuthenticated {
             lines
                 .send(format!("{} NO invalid state", command_data.tag))
                 .await?;
+            return Ok(());
+        }
+        let args = &command_data.arguments;
+        if !matches!(args.len(), 1 | 2) {
+            lines
+                .send(format!(
+                    "{} BAD [SERVERBUG] unable to parse command",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+
+        match Exchange::start(
+            config,
+            Protocol::Imap,
+            args[0],
+            self.data.con_state.secure,
+            &self.data.con_state.peer_addr,
+        ) {
+            Ok(exchange) if args.len() == 2 => {
+                // SASL-IR (RFC 4959), `auth_data` is the initial response
+                self.respond(lines, config, database, command_data, exchange)
+                    .await?;
+            }
+            Ok(exchange) => {
+                debug!("[IMAP] Update state to Authenticating");
+                let challenge = exchange.initial_challenge();
+                self.data.con_state.state =
+                    State::Authenticating(exchange, command_data.tag.to_string());
+                lines.send(format!("+ {challenge}")).await?;
+            }
+            Err(error) => {
+                lines
+                    .send(format!("{} {}", command_data.tag, response(error)))
+                    .await?;
+            }
         }
         Ok(())
     }
 }
+
+/// The tagged response for a failed authentication
+pub const fn response(error: AuthError) -> &'static str {
+    match error {
+        AuthError::UnsupportedMechanism => "NO [CANNOT] Unsupported authentication mechanism",
+        AuthError::EncryptionRequired => {
+            "NO [PRIVACYREQUIRED] Encryption is required for this mechanism"
+        }
+        AuthError::Malformed => "BAD Invalid arguments",
+        AuthError::Canceled => "BAD Authentication canceled",
+        AuthError::InvalidCredentials => "NO [AUTHENTICATIONFAILED] Invalid user or password",
+        AuthError::NotAuthorized => {
+            "NO [AUTHORIZATIONFAILED] Not authorized to act as the requested user"
+        }
+        AuthError::TooManyAttempts => "NO [UNAVAILABLE] Too many failed attempts, try again later",
+        AuthError::Unavailable => "NO [UNAVAILABLE] Authentication is temporarily unavailable",
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use crate::{
+        commands::{Data, Response},
+        servers::state::{Connection, State},
+    };
+    use base64::Engine;
+    use erooster_core::BASE64_DECODER;
+    use futures::{channel::mpsc, StreamExt};
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_authenticate_scram() {
+        let (config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        let mut data = Data {
+            con_state: Connection::new(true, String::new()),
+        };
+        let (mut tx, mut rx) = mpsc::unbounded::<String>();
+
+        for line in ["a1 AUTHENTICATE SCRAM-SHA-256", "*"] {
+            let response = data
+                .parse(&mut tx, &config, &database, &storage, line.to_string())
+                .await;
+            assert_eq!(response.unwrap(), Response::Continue);
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ ");
+        assert_eq!(rx.next().await.unwrap(), "a1 BAD Authentication canceled");
+        assert_eq!(data.con_state.state, State::NotAuthenticated);
+
+        // Unknown users get a server-first-message and fail with the proof
+        let client_first = BASE64_DECODER.encode("n,,n=nobody@localhost,r=abc");
+        let line = format!("a2 AUTHENTICATE SCRAM-SHA-256 {client_first}");
+        data.parse(&mut tx, &config, &database, &storage, line)
+            .await
+            .unwrap();
+        let challenge = rx.next().await.unwrap();
+        let server_first =
+            String::from_utf8(BASE64_DECODER.decode(&challenge[2..]).unwrap()).unwrap();
+        assert!(server_first.starts_with("r=abc"), "{server_first}");
+        let nonce = server_first.split(',').next().unwrap();
+        // The salt doesn't change between attempts like that of an existing user
+        let line = format!(
+            "a0 AUTHENTICATE SCRAM-SHA-256 {}",
+            BASE64_DECODER.encode("n,,n=Nobody@localhost,r=def")
+        );
+        let mut again = Data {
+            con_state: Connection::new(true, String::new()),
+        };
+        again
+            .parse(&mut tx, &config, &database, &storage, line)
+            .await
+            .unwrap();
+        let challenge = rx.next().await.unwrap();
+        let repeated = String::from_utf8(BASE64_DECODER.decode(&challenge[2..]).unwrap()).unwrap();
+        assert_eq!(
+            repeated.split(',').skip(1).collect::<Vec<_>>(),
+            server_first.split(',').skip(1).collect::<Vec<_>>()
+        );
+        let client_final = BASE64_DECODER.encode(format!("c=biws,{nonce},p=AAAA"));
+        data.parse(&mut tx, &config, &database, &storage, client_final)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a2 NO [AUTHENTICATIONFAILED] Invalid user or password"
+        );
+        assert_eq!(data.con_state.state, State::NotAuthenticated);
+
+        data.parse(
+            &mut tx,
+            &config,
+            &database,
+            &storage,
+            String::from("a3 AUTHENTICATE XOAUTH2"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a3 NO [CANNOT] Unsupported authentication mechanism"
+        );
+    }
+}
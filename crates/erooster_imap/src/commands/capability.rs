@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::commands::CommandData;
+use erooster_core::{config::Config, sasl::Mechanism};
 use {
     color_eyre,
     futures::{Sink, SinkExt},
@@ -12,10 +13,11 @@ use {
 pub struct Capability;
 
 impl Capability {
-    #[instrument(skip(self, lines, command_data))]
+    #[instrument(skip(self, lines, config, command_data))]
     pub async fn exec<S, E>(
         &self,
         lines: &mut S,
+        config: &Config,
         command_data: &CommandData<'_>,
         secure: bool,
     ) -> color_eyre::eyre::Result<()>
@@ -24,9 +26,9 @@ impl Capability {
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
         let capabilities = if secure {
-            get_capabilities()
+            capabilities(config)
         } else {
-            get_unencrypted_capabilities()
+            get_unencrypted_capabilities().to_string()
         };
         lines.feed(format!("* {capabilities}")).await?;
         lines
@@ -38,14 +40,33 @@ impl Capability {
 }
 
 pub const fn get_capabilities() -> &'static str {
-    "CAPABILITY AUTH=PLAIN LOGINDISABLED UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 IDLE ESEARCH NAMESPACE UNSELECT MOVE"
+    "CAPABILITY UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 LITERAL+ IDLE ID ESEARCH SEARCHRES SEARCH=FUZZY NAMESPACE UNSELECT MOVE CONDSTORE QRESYNC NOTIFY QUOTA QUOTA=RES-STORAGE QUOTA=RES-MESSAGE QUOTASET SORT SORT=DISPLAY ESORT PARTIAL THREAD=ORDEREDSUBJECT THREAD=REFERENCES COMPRESS=DEFLATE ACL RIGHTS=texk METADATA SPECIAL-USE CREATE-SPECIAL-USE LIST-EXTENDED LIST-STATUS MULTIAPPEND CATENATE OBJECTID SAVEDATE BINARY"
 }
 
+/// The capabilities of encrypted connections including the limits and SASL
+/// mechanisms from the config
+pub fn capabilities(config: &Config) -> String {
+    let mechanisms: Vec<String> = Mechanism::available(config)
+        .into_iter()
+        .map(|mechanism| format!("AUTH={}", mechanism.name()))
+        .collect();
+    format!(
+        "{} {} APPENDLIMIT={}",
+        get_capabilities(),
+        mechanisms.join(" "),
+        config.mail.max_message_size.as_bytes()
+    )
+}
+
+/// The capabilities of unencrypted connections. LOGIN and the mechanisms
+/// which send the password in the clear or bind to the TLS channel need
+/// STARTTLS first, see [`Mechanism::requires_encryption`].
 pub const fn get_unencrypted_capabilities() -> &'static str {
-    "CAPABILITY LOGINDISABLED UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 STARTTLS"
+    "CAPABILITY LOGINDISABLED UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 LITERAL+ STARTTLS AUTH=SCRAM-SHA-256"
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
     use crate::commands::{CommandData, Commands};
@@ -63,13 +84,16 @@ mod tests {
             command: Commands::Capability,
             arguments: &[],
         };
+        let (config, _storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let res = caps.exec(&mut tx, &cmd_data, true).await;
+        let res = caps.exec(&mut tx, &config, &cmd_data, true).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
             rx.next().await,
             Some(String::from(
-                "* CAPABILITY AUTH=PLAIN LOGINDISABLED UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 IDLE ESEARCH NAMESPACE UNSELECT MOVE"
+                "* CAPABILITY UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 LITERAL+ IDLE ID ESEARCH SEARCHRES SEARCH=FUZZY NAMESPACE UNSELECT MOVE CONDSTORE QRESYNC NOTIFY QUOTA QUOTA=RES-STORAGE QUOTA=RES-MESSAGE QUOTASET SORT SORT=DISPLAY ESORT PARTIAL THREAD=ORDEREDSUBJECT THREAD=REFERENCES COMPRESS=DEFLATE ACL RIGHTS=texk METADATA SPECIAL-USE CREATE-SPECIAL-USE LIST-EXTENDED LIST-STATUS MULTIAPPEND CATENATE OBJECTID SAVEDATE BINARY AUTH=PLAIN AUTH=LOGIN AUTH=SCRAM-SHA-256 AUTH=SCRAM-SHA-256-PLUS APPENDLIMIT=26214400"
             ))
         );
         assert_eq!(
@@ -87,13 +111,16 @@ mod tests {
             command: Commands::Capability,
             arguments: &[],
         };
+        let (config, _storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let res = caps.exec(&mut tx, &cmd_data, false).await;
+        let res = caps.exec(&mut tx, &config, &cmd_data, false).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
             rx.next().await,
             Some(String::from(
-                "* CAPABILITY LOGINDISABLED UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 STARTTLS"
+                "* CAPABILITY LOGINDISABLED UTF8=ONLY ENABLE IMAP4rev2 IMAP4rev1 LITERAL+ STARTTLS AUTH=SCRAM-SHA-256"
             ))
         );
         assert_eq!(
@@ -6,7 +6,10 @@ use crate::{
     commands::{CommandData, Data},
     servers::state::State,
 };
-use erooster_core::backend::storage::{MailEntryType, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::mailbox_id,
+    storage::{MailEntryType, MailStorage, Storage},
+};
 use {
     color_eyre,
     futures::{Sink, SinkExt},
@@ -32,7 +35,6 @@ impl Check<'_> {
         // This is an Imap4rev1 feature. It does the same as Noop for us as we have no memory gc.
         // It also only is allowed in selected state
         if let State::Selected(folder, _) = &self.data.con_state.state {
-            let folder = folder.replace('/', ".");
             let Some(username) = self.data.con_state.username.clone() else {
                 lines
                     .send(format!("{} NO invalid state", command_data.tag))
@@ -41,12 +43,12 @@ impl Check<'_> {
             };
             let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
             let mails: Vec<MailEntryType> = storage
-                .list_new(format!("{username}/{folder}"), &mailbox_path)
+                .list_new(mailbox_id(folder, &username), &mailbox_path)
                 .await;
             let got_new = !mails.is_empty();
             if got_new {
                 let mails: Vec<MailEntryType> = storage
-                    .list_all(format!("{username}/{folder}"), &mailbox_path)
+                    .list_all(mailbox_id(folder, &username), &mailbox_path)
                     .await;
                 lines.send(format!("* {} EXISTS", mails.len())).await?;
             }
@@ -70,6 +72,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Access, Connection};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[cfg_attr(coverage_nightly, coverage(off))]
@@ -82,6 +85,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("test")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -109,6 +117,11 @@ mod tests {
                     secure: true,
                     username: None,
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -6,11 +6,13 @@ use crate::{
     commands::{CommandData, Data},
     servers::state::{Access, State},
 };
-use erooster_core::backend::storage::{MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName, Rights},
+    storage::{MailEntry, MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
-    tokio::fs,
     tracing::instrument,
 };
 
@@ -46,20 +48,25 @@ impl Close<'_> {
                 .context("Username missing in internal State")?;
             let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
 
+            // Without the expunge right CLOSE silently skips the expunge (RFC 4314 §4)
+            let may_expunge = storage
+                .my_rights(&MailboxName::resolve(folder, &username), &username)
+                .await?
+                .contains(Rights::EXPUNGE);
+
             // We need to check all messages it seems?
             let mails = storage
-                .list_cur(format!("{username}/{folder}"), &mailbox_path)
+                .list_cur(mailbox_id(folder, &username), &mailbox_path)
                 .await
                 .into_iter()
                 .chain(
                     storage
-                        .list_new(format!("{username}/{folder}"), &mailbox_path)
+                        .list_new(mailbox_id(folder, &username), &mailbox_path)
                         .await,
                 );
             for mail in mails {
-                if mail.is_trashed() {
-                    let path = mail.path();
-                    fs::remove_file(path).await?;
+                if may_expunge && mail.is_trashed() {
+                    storage.expunge(&mailbox_path, mail.id()).await?;
                 }
             }
 
@@ -86,6 +93,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Access, Connection};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     // TODO: A test with data to delete is missing
@@ -100,6 +108,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("test")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -127,6 +140,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("test")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -157,6 +175,11 @@ mod tests {
                     secure: true,
                     username: None,
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
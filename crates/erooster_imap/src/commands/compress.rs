@@ -0,0 +1,163 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! COMPRESS command (RFC 4978).
+//!
+//! Only validates the request and answers it. Once the tagged OK was sent the
+//! server loop switches the transport to raw deflate in both directions.
+
+use crate::commands::{CommandData, Data};
+use {
+    futures::{Sink, SinkExt},
+    tracing::instrument,
+};
+
+pub struct Compress<'a> {
+    pub data: &'a mut Data,
+}
+
+impl Compress<'_> {
+    /// Returns true if the transport needs to be switched to deflate
+    #[instrument(skip(self, lines, command_data))]
+    pub async fn exec<S, E>(
+        &mut self,
+        lines: &mut S,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<bool>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        if command_data.arguments.len() != 1
+            || !command_data.arguments[0].eq_ignore_ascii_case("DEFLATE")
+        {
+            lines
+                .send(format!(
+                    "{} BAD Unsupported compression mechanism",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(false);
+        }
+        if self.data.con_state.compressed {
+            lines
+                .send(format!(
+                    "{} NO [COMPRESSIONACTIVE] DEFLATE active via COMPRESS",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(false);
+        }
+        // The cleartext port can still upgrade with STARTTLS which must not
+        // happen below a compression layer
+        if !self.data.con_state.secure {
+            lines
+                .send(format!(
+                    "{} NO COMPRESS requires a TLS connection",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(false);
+        }
+
+        self.data.con_state.compressed = true;
+        lines
+            .send(format!("{} OK DEFLATE active", command_data.tag))
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::commands::{CommandData, Commands};
+    use crate::servers::state::{Connection, State};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_compress() {
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        let unknown = CommandData {
+            tag: "a1",
+            command: Commands::Compress,
+            arguments: &["GZIP"],
+        };
+        let switch = Compress { data: &mut data }
+            .exec(&mut tx, &unknown)
+            .await
+            .unwrap();
+        assert!(!switch);
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 BAD Unsupported compression mechanism"))
+        );
+
+        let deflate = CommandData {
+            tag: "a2",
+            command: Commands::Compress,
+            arguments: &["deflate"],
+        };
+        let switch = Compress { data: &mut data }
+            .exec(&mut tx, &deflate)
+            .await
+            .unwrap();
+        assert!(switch);
+        assert!(data.con_state.compressed);
+        assert_eq!(rx.next().await, Some(String::from("a2 OK DEFLATE active")));
+
+        let switch = Compress { data: &mut data }
+            .exec(&mut tx, &deflate)
+            .await
+            .unwrap();
+        assert!(!switch);
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "a2 NO [COMPRESSIONACTIVE] DEFLATE active via COMPRESS"
+            ))
+        );
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_compress_requires_tls() {
+        let mut data = Data {
+            con_state: Connection::new(false, String::new()),
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let deflate = CommandData {
+            tag: "a1",
+            command: Commands::Compress,
+            arguments: &["DEFLATE"],
+        };
+        let switch = Compress { data: &mut data }
+            .exec(&mut tx, &deflate)
+            .await
+            .unwrap();
+        assert!(!switch);
+        assert!(!data.con_state.compressed);
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 NO COMPRESS requires a TLS connection"))
+        );
+    }
+}
@@ -10,11 +10,16 @@
 
 use crate::{
     commands::{
-        parsers::parse_selected_range, select::get_or_create_uidvalidity, CommandData, Data,
+        acl::require_rights, parsers::parse_selected_range, search::resolve_saved_result,
+        select::get_or_create_uidvalidity, CommandData, Data,
     },
     servers::state::{Access, State},
 };
-use erooster_core::backend::storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName, Rights},
+    quota::QuotaUsage,
+    storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -82,12 +87,17 @@ impl Copy<'_> {
         let src_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
 
         let mut mails = storage
-            .list_all(format!("{username}/{folder}"), &src_path)
+            .list_all(mailbox_id(folder, &username), &src_path)
             .await;
         mails.sort_by_key(MaildirMailEntry::uid);
 
-        let seq_arg = command_data.arguments[offset];
-        let Ok((_, ranges)) = parse_selected_range(seq_arg).finish() else {
+        let seq_arg = resolve_saved_result(
+            command_data.arguments[offset],
+            &self.data.con_state.saved_result,
+            &mails,
+            is_uid,
+        );
+        let Ok((_, ranges)) = parse_selected_range(&seq_arg).finish() else {
             lines
                 .send(format!(
                     "{} BAD [PARSE] Invalid sequence set: {seq_arg}",
@@ -98,6 +108,19 @@ impl Copy<'_> {
         };
 
         let dest_raw = command_data.arguments[1 + offset].replace('"', "");
+        let destination = MailboxName::resolve(&dest_raw, &username);
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &destination,
+            Rights::INSERT,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
         let dest_path = storage.to_ondisk_path(dest_raw.clone(), username.clone())?;
 
         if !dest_path.exists() {
@@ -110,12 +133,13 @@ impl Copy<'_> {
             return Ok(());
         }
 
-        let dest_db_name = format!("{username}/{dest_raw}");
-
-        let mut src_uids: Vec<u32> = Vec::new();
-        let mut dest_uids: Vec<u32> = Vec::new();
+        let dest_db_name = destination.id();
 
-        for (index, mail) in mails.iter_mut().enumerate() {
+        // Read all matching messages first so the quota can be checked for
+        // the whole copy; COPY either copies everything or nothing.
+        let mut selected = Vec::new();
+        let mut additional = QuotaUsage::default();
+        for (index, mail) in mails.iter().enumerate() {
             let in_range = ranges.iter().any(|r| {
                 if is_uid {
                     r.contains(&mail.uid())
@@ -129,10 +153,35 @@ impl Copy<'_> {
             }
 
             let bytes = fs::read(mail.path()).await?;
-            let imap_flags = maildir_flags_to_imap(mail.flags());
-            storage
+            additional.storage += bytes.len() as u64;
+            additional.messages += 1;
+            selected.push((mail, bytes));
+        }
+
+        if !selected.is_empty()
+            && !storage
+                .quota_allows(&destination.owner, &additional)
+                .await?
+        {
+            lines
+                .send(format!(
+                    "{} NO [OVERQUOTA] Quota exceeded",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+
+        let mut src_uids: Vec<u32> = Vec::new();
+        let mut dest_uids: Vec<u32> = Vec::new();
+
+        for (mail, bytes) in selected {
+            let mut imap_flags = maildir_flags_to_imap(mail.flags());
+            imap_flags.extend_from_slice(mail.keywords());
+            let copy_id = storage
                 .store_cur_with_flags(dest_db_name.clone(), &dest_path, &bytes, imap_flags)
                 .await?;
+            storage.inherit_object_ids(mail.id(), &copy_id).await?;
 
             src_uids.push(mail.uid());
             let dest_uid = storage.get_uid_for_folder(&dest_db_name).await?;
@@ -213,6 +262,7 @@ pub fn uid_set_string(uids: &[u32]) -> String {
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
     use tokio;
 
     #[test]
@@ -244,6 +294,11 @@ mod tests {
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
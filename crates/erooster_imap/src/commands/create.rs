@@ -3,13 +3,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{CommandData, Data},
+    commands::{
+        acl::require_rights, list::SPECIAL_USE_ATTRIBUTES, parsers::create_arguments, CommandData,
+        Data,
+    },
     servers::state::State,
 };
-use erooster_core::backend::storage::{MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{MailboxName, Rights, SHARED_OWNER},
+    events::MailboxEvent,
+    storage::{MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
+    nom::Finish,
     tracing::{error, instrument},
 };
 
@@ -17,6 +25,7 @@ pub struct Create<'a> {
     pub data: &'a Data,
 }
 impl Create<'_> {
+    #[allow(clippy::too_many_lines)]
     #[instrument(skip(self, lines, storage, command_data))]
     pub async fn exec<S, E>(
         &self,
@@ -31,20 +40,68 @@ impl Create<'_> {
         if matches!(self.data.con_state.state, State::Authenticated)
             || matches!(self.data.con_state.state, State::Selected(_, _))
         {
-            let arguments = &command_data.arguments;
-            assert!(arguments.len() == 1);
-            if arguments.len() == 1 {
-                let folder = arguments[0].replace('/', ".");
+            let arguments = command_data.arguments.join(" ");
+            if let Ok(("", (folder, use_attributes))) = create_arguments(&arguments).finish() {
+                let Some(use_attributes) = use_attributes
+                    .iter()
+                    .map(|attr| {
+                        SPECIAL_USE_ATTRIBUTES
+                            .into_iter()
+                            .find(|known| known.eq_ignore_ascii_case(attr))
+                    })
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    lines
+                        .send(format!(
+                            "{} NO [USEATTR] Unsupported special-use attribute",
+                            command_data.tag
+                        ))
+                        .await?;
+                    return Ok(());
+                };
+                let folder = folder.as_str();
+                let username = self
+                    .data
+                    .con_state
+                    .username
+                    .clone()
+                    .context("Username missing in internal State")?;
 
-                let mailbox_path = storage.to_ondisk_path(
-                    folder.clone(),
-                    self.data
-                        .con_state
-                        .username
-                        .clone()
-                        .context("Username missing in internal State")?,
-                )?;
-                let folder = storage.to_ondisk_path_name(folder)?;
+                let mailbox = MailboxName::resolve(folder, &username);
+                if !mailbox.is_owned_by(&username) {
+                    // Children need the create right on their parent. Anyone may
+                    // start a new top level shared mailbox and administers it.
+                    if let Some((parent, _)) = mailbox.name.rsplit_once('.') {
+                        let parent = MailboxName {
+                            owner: mailbox.owner.clone(),
+                            name: parent.to_string(),
+                        };
+                        if !require_rights(
+                            lines,
+                            storage,
+                            &username,
+                            &parent,
+                            Rights::CREATE,
+                            command_data.tag,
+                        )
+                        .await?
+                        {
+                            return Ok(());
+                        }
+                    } else if mailbox.owner != SHARED_OWNER {
+                        lines
+                            .send(format!(
+                                "{} NO [NOPERM] Permission denied",
+                                command_data.tag
+                            ))
+                            .await?;
+                        return Ok(());
+                    }
+                }
+
+                let mailbox_path = storage.to_ondisk_path(folder.to_string(), username.clone())?;
+                let mailbox_id = mailbox.id();
+                let folder = storage.to_ondisk_path_name(folder.to_string())?;
 
                 match storage.create_dirs(&mailbox_path) {
                     Ok(()) => {
@@ -64,8 +121,21 @@ impl Create<'_> {
                             storage.add_flag(&mailbox_path, "\\Trash").await?;
                             storage.add_flag(&mailbox_path, "\\Subscribed").await?;
                         }
+                        for attr in use_attributes {
+                            storage.add_flag(&mailbox_path, attr).await?;
+                        }
+                        if mailbox.owner == SHARED_OWNER && !mailbox.name.contains('.') {
+                            storage.set_acl(&mailbox, &username, Rights::ALL).await?;
+                        }
+                        let object_id = storage.mailbox_object_id(&mailbox).await?;
+                        storage.events().publish(MailboxEvent::Created {
+                            mailbox: mailbox_id,
+                        });
                         lines
-                            .send(format!("{} OK CREATE completed", command_data.tag))
+                            .send(format!(
+                                "{} OK [MAILBOXID ({object_id})] CREATE completed",
+                                command_data.tag
+                            ))
                             .await?;
                     }
                     Err(e) => {
@@ -79,7 +149,7 @@ impl Create<'_> {
             } else {
                 lines
                     .send(format!(
-                        "{} BAD [SERVERBUG] invalid arguments",
+                        "{} BAD [PARSE] Expected a mailbox name and optional USE attributes",
                         command_data.tag
                     ))
                     .await?;
@@ -94,12 +164,14 @@ impl Create<'_> {
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
     use crate::{
         commands::{CommandData, Commands},
         servers::state::{Connection, State},
     };
+    use std::collections::BTreeMap;
     use {
         futures::{channel::mpsc, StreamExt},
         tokio,
@@ -118,6 +190,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("test")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -132,10 +209,9 @@ mod tests {
             .unwrap();
         let res = caps.exec(&mut tx, &storage, &cmd_data).await;
         assert!(res.is_ok(), "{:?}", res);
-        assert_eq!(
-            rx.next().await,
-            Some(String::from("a1 OK CREATE completed"))
-        );
+        let response = rx.next().await.unwrap();
+        assert!(response.starts_with("a1 OK [MAILBOXID (F"), "{response}");
+        assert!(response.ends_with(")] CREATE completed"), "{response}");
     }
 
     /// Since the state Authenticated is only required read-only selected is allowed to create rooms!
@@ -152,6 +228,11 @@ mod tests {
                     secure: true,
                     username: Some(String::from("test")),
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
@@ -166,9 +247,45 @@ mod tests {
             .unwrap();
         let res = caps.exec(&mut tx, &storage, &cmd_data).await;
         assert!(res.is_ok(), "{:?}", res);
+        let response = rx.next().await.unwrap();
+        assert!(response.starts_with("a1 OK [MAILBOXID (F"), "{response}");
+        assert!(response.ends_with(")] CREATE completed"), "{response}");
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_create_unknown_use_attribute() {
+        let caps = Create {
+            data: &mut Data {
+                con_state: Connection {
+                    state: State::Authenticated,
+                    secure: true,
+                    username: Some(String::from("test")),
+                    active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
+                },
+            },
+        };
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Create,
+            arguments: &["Important", "(USE", "(\\Important))"],
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let res = caps.exec(&mut tx, &storage, &cmd_data).await;
+        assert!(res.is_ok(), "{res:?}");
         assert_eq!(
             rx.next().await,
-            Some(String::from("a1 OK CREATE completed"))
+            Some(String::from(
+                "a1 NO [USEATTR] Unsupported special-use attribute"
+            ))
         );
     }
 
@@ -182,6 +299,11 @@ mod tests {
                     secure: true,
                     username: None,
                     active_capabilities: vec![],
+                    notify: None,
+                    compressed: false,
+                    saved_result: vec![],
+                    client_id: BTreeMap::new(),
+                    peer_addr: String::new(),
                 },
             },
         };
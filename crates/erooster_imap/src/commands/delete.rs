@@ -2,8 +2,12 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::commands::{CommandData, Data};
-use erooster_core::backend::storage::{MailStorage, Storage};
+use crate::commands::{acl::require_rights, CommandData, Data};
+use erooster_core::backend::{
+    acl::{MailboxName, Rights},
+    events::MailboxEvent,
+    storage::{MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -48,14 +52,26 @@ impl Delete<'_> {
             return Ok(());
         }
 
-        let folder = mailbox_arg.replace('/', ".");
         let username = self
             .data
             .con_state
             .username
             .clone()
             .context("Username missing in internal State")?;
-        let mailbox_path = storage.to_ondisk_path(folder.clone(), username)?;
+        let mailbox = MailboxName::resolve(mailbox_arg, &username);
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &mailbox,
+            Rights::DELETE_MAILBOX,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
+        let mailbox_path = storage.to_ondisk_path(mailbox_arg.to_string(), username.clone())?;
 
         if !mailbox_path.exists() {
             lines
@@ -83,6 +99,12 @@ impl Delete<'_> {
         }
 
         fs::remove_dir_all(&mailbox_path).await?;
+        storage.move_acl(&mailbox, None).await?;
+        storage.move_metadata(&mailbox, None).await?;
+        storage.move_mailbox(&mailbox, None).await?;
+        storage.events().publish(MailboxEvent::Deleted {
+            mailbox: mailbox.id(),
+        });
         lines
             .send(format!("{} OK DELETE completed", command_data.tag))
             .await?;
@@ -33,6 +33,15 @@ impl Enable<'_> {
                     .con_state
                     .active_capabilities
                     .push(Capabilities::UTF8);
+            } else if arg.eq_ignore_ascii_case("CONDSTORE") {
+                self.data.con_state.enable_condstore();
+            } else if arg.eq_ignore_ascii_case("QRESYNC") {
+                // RFC 7162 §3.2.3: enabling QRESYNC implies CONDSTORE.
+                self.data.con_state.enable_condstore();
+                self.data
+                    .con_state
+                    .active_capabilities
+                    .push(Capabilities::QResync);
             } else {
                 self.data
                     .con_state
@@ -53,6 +62,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Connection, State};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[allow(clippy::unwrap_used)]
@@ -65,6 +75,11 @@ mod tests {
                 secure: true,
                 username: None,
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let mut caps = Enable { data: state };
@@ -96,6 +111,11 @@ mod tests {
                 secure: true,
                 username: None,
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let mut caps = Enable { data: state };
@@ -116,4 +136,38 @@ mod tests {
             .active_capabilities
             .contains(&Capabilities::Other(String::from("Random"))));
     }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_qresync_implies_condstore() {
+        let state = &mut Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let mut caps = Enable { data: state };
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Enable,
+            arguments: &["QRESYNC"],
+        };
+
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = caps.exec(&mut tx, &cmd_data).await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(rx.next().await, Some(String::from("* ENABLED QRESYNC")));
+        assert_eq!(rx.next().await, Some(String::from("a1 OK")));
+
+        assert!(state.con_state.qresync_enabled());
+        assert!(state.con_state.condstore_enabled());
+    }
 }
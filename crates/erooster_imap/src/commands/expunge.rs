@@ -9,14 +9,16 @@
 //! the current (post-shift) sequence number at the time of removal.
 
 use crate::{
-    commands::{CommandData, Data},
+    commands::{acl::require_rights, copy::uid_set_string, CommandData, Data},
     servers::state::{Access, State},
 };
-use erooster_core::backend::storage::{MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName, Rights},
+    storage::{MailEntry, MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
-    tokio::fs,
     tracing::instrument,
 };
 
@@ -62,15 +64,28 @@ impl Expunge<'_> {
             .username
             .clone()
             .context("Username missing in internal State")?;
+        let mailbox = MailboxName::resolve(folder, &username);
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &mailbox,
+            Rights::EXPUNGE,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
         let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
 
         let mails: Vec<_> = storage
-            .list_cur(format!("{username}/{folder}"), &mailbox_path)
+            .list_cur(mailbox_id(folder, &username), &mailbox_path)
             .await
             .into_iter()
             .chain(
                 storage
-                    .list_new(format!("{username}/{folder}"), &mailbox_path)
+                    .list_new(mailbox_id(folder, &username), &mailbox_path)
                     .await,
             )
             .collect();
@@ -78,16 +93,29 @@ impl Expunge<'_> {
         // Walk messages in order; sequence numbers start at 1. When a message
         // is deleted, all subsequent numbers shift down — so we do NOT advance
         // the counter for deleted messages.
+        let qresync = self.data.con_state.qresync_enabled();
+        let mut vanished = Vec::new();
         let mut seq = 1u32;
         for mail in mails {
             if mail.is_trashed() {
-                fs::remove_file(mail.path()).await?;
-                lines.feed(format!("* {seq} EXPUNGE")).await?;
+                storage.expunge(&mailbox_path, mail.id()).await?;
+                if qresync {
+                    vanished.push(mail.uid());
+                } else {
+                    lines.feed(format!("* {seq} EXPUNGE")).await?;
+                }
                 // seq stays the same — the next message slides into this slot
             } else {
                 seq += 1;
             }
         }
+        // RFC 7162 §3.2.10: once QRESYNC is enabled, VANISHED replaces EXPUNGE.
+        if !vanished.is_empty() {
+            vanished.sort_unstable();
+            lines
+                .feed(format!("* VANISHED {}", uid_set_string(&vanished)))
+                .await?;
+        }
 
         lines.flush().await?;
         lines
@@ -104,6 +132,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Access, Connection, State};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[cfg_attr(coverage_nightly, coverage(off))]
@@ -115,6 +144,11 @@ mod tests {
                 secure: true,
                 username: None,
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -147,6 +181,11 @@ mod tests {
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -5,15 +5,24 @@
 use crate::{
     commands::{
         parsers::{
-            fetch_arguments, parse_selected_range, FetchArguments, FetchAttributes, SectionText,
+            fetch_arguments, fetch_modifiers, parse_selected_range, FetchArguments,
+            FetchAttributes, FetchModifiers, SectionText,
         },
+        acl::require_rights,
+        copy::uid_set_string,
+        search::resolve_saved_result,
         CommandData, Data,
     },
     servers::state::State,
 };
-use erooster_core::backend::storage::{
-    maildir::MaildirMailEntry, MailEntry, MailEntryType, MailStorage, Storage,
+use erooster_core::{
+    backend::{
+        acl::{mailbox_id, MailboxName, Rights},
+        storage::{maildir::MaildirMailEntry, MailEntry, MailEntryType, MailStorage, Storage},
+    },
+    line_codec,
 };
+use mailparse::{parse_mail, ParsedMail};
 use time::{format_description::well_known::Rfc2822, OffsetDateTime};
 use {
     color_eyre::{
@@ -27,6 +36,19 @@ use {
     tracing::{debug, error, instrument, warn},
 };
 
+/// The content transfer encoding of a part requested with BINARY is unknown
+/// (RFC 3516 §4.3)
+#[derive(Debug)]
+struct UnknownCte;
+
+impl std::fmt::Display for UnknownCte {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown content transfer encoding")
+    }
+}
+
+impl std::error::Error for UnknownCte {}
+
 pub struct Fetch<'a> {
     pub data: &'a Data,
 }
@@ -48,21 +70,38 @@ impl Fetch<'_> {
         let offset = usize::from(is_uid);
         // TODO handle the various request types defined in https://www.rfc-editor.org/rfc/rfc9051.html#name-fetch-command
         if let State::Selected(folder, _) = &self.data.con_state.state {
-            let folder = folder.replace('/', ".");
             let username = self
                 .data
                 .con_state
                 .username
                 .clone()
                 .context("Username missing in internal State")?;
+            // The read right may have been revoked since the mailbox was selected
+            if !require_rights(
+                lines,
+                storage,
+                &username,
+                &MailboxName::resolve(folder, &username),
+                Rights::READ,
+                command_data.tag,
+            )
+            .await?
+            {
+                return Ok(());
+            }
             let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
             let mut mails: Vec<MailEntryType> = storage
-                .list_all(format!("{username}/{folder}"), &mailbox_path)
+                .list_all(mailbox_id(folder, &username), &mailbox_path)
                 .await;
             mails.sort_by_key(MaildirMailEntry::uid);
 
-            let arguments_borrow = command_data.arguments[offset];
-            let ranges = parse_selected_range(arguments_borrow).finish();
+            let arguments_borrow = resolve_saved_result(
+                command_data.arguments[offset],
+                &self.data.con_state.saved_result,
+                &mails,
+                is_uid,
+            );
+            let ranges = parse_selected_range(&arguments_borrow).finish();
             debug!("Range: {:?}; Mails: {}", ranges, mails.len());
 
             match ranges {
@@ -92,9 +131,60 @@ impl Fetch<'_> {
                         })
                         .collect::<Vec<_>>();
 
-                    let mut fetch_args: String = command_data.arguments[1 + offset..].join(" ");
-                    fetch_args.pop(); // remove last
-                    if !fetch_args.is_empty() {
+                    let joined_args = command_data.arguments[1 + offset..].join(" ");
+                    let (fetch_args, modifiers) = split_fetch_modifiers(&joined_args);
+                    let modifiers = match modifiers.map(|x| fetch_modifiers(x).finish()) {
+                        None => FetchModifiers::default(),
+                        Some(Ok((_, modifiers))) => modifiers,
+                        Some(Err(e)) => {
+                            error!(
+                                "Failed to parse fetch modifiers: {}",
+                                convert_error(joined_args.as_str(), e)
+                            );
+                            lines
+                                .send(format!("{} BAD Unable to parse", command_data.tag))
+                                .await?;
+                            return Ok(());
+                        }
+                    };
+                    // RFC 7162 §3.2.6: VANISHED is only valid in UID FETCH with CHANGEDSINCE
+                    // after QRESYNC was enabled.
+                    if modifiers.vanished
+                        && (!is_uid
+                            || modifiers.changed_since.is_none()
+                            || !self.data.con_state.qresync_enabled())
+                    {
+                        lines
+                            .send(format!(
+                                "{} BAD [CLIENTBUG] VANISHED requires UID FETCH with CHANGEDSINCE and QRESYNC enabled",
+                                command_data.tag
+                            ))
+                            .await?;
+                        return Ok(());
+                    }
+                    if let Some(changed_since) = modifiers.changed_since {
+                        filtered_mails.retain(|mail| mail.modseq() > changed_since);
+                        if modifiers.vanished {
+                            let vanished: Vec<u32> = storage
+                                .get_vanished_since(&mailbox_id(folder, &username), changed_since)
+                                .await?
+                                .into_iter()
+                                .filter(|uid| ranges.iter().any(|range| range.contains(uid)))
+                                .collect();
+                            if !vanished.is_empty() {
+                                lines
+                                    .feed(format!(
+                                        "* VANISHED (EARLIER) {}",
+                                        uid_set_string(&vanished)
+                                    ))
+                                    .await?;
+                            }
+                        }
+                    }
+
+                    let mut fetch_args = fetch_args.to_string();
+                    if fetch_args.starts_with('(') && fetch_args.ends_with(')') {
+                        fetch_args.pop(); // remove last
                         fetch_args.remove(0); // remove first
                     }
                     let fetch_args = fetch_args.as_str();
@@ -106,7 +196,24 @@ impl Fetch<'_> {
                                 let uid = mail.uid();
                                 let sequence =
                                     mail.sequence_number().context("Sequence number missing")?;
-                                if let Some(resp) = generate_response(args.clone(), mail)? {
+                                let response = match generate_response(args.clone(), mail) {
+                                    Err(e) if e.is::<UnknownCte>() => {
+                                        lines
+                                            .send(format!(
+                                                "{} NO [UNKNOWN-CTE] Unable to decode the content transfer encoding",
+                                                command_data.tag
+                                            ))
+                                            .await?;
+                                        return Ok(());
+                                    }
+                                    response => response?,
+                                };
+                                if let Some(mut resp) = response {
+                                    // RFC 7162 §3.1.4.1: CHANGEDSINCE implies the MODSEQ item.
+                                    if modifiers.changed_since.is_some() && !resp.contains("MODSEQ")
+                                    {
+                                        resp = format!("{resp} MODSEQ ({})", mail.modseq());
+                                    }
                                     // RFC 9051 Appendix E.21: all FETCH responses MUST include UID
                                     if resp.contains("UID") {
                                         lines.feed(format!("* {sequence} FETCH ({resp})")).await?;
@@ -143,7 +250,7 @@ impl Fetch<'_> {
                 Err(e) => {
                     error!(
                         "Failed to parse fetch arguments: {}",
-                        convert_error(arguments_borrow, e)
+                        convert_error(arguments_borrow.as_ref(), e)
                     );
                     lines
                         .send(format!("{} BAD Unable to parse", command_data.tag))
@@ -167,6 +274,19 @@ impl Fetch<'_> {
     }
 }
 
+/// Splits trailing FETCH modifiers such as `(CHANGEDSINCE 12)` off the
+/// attribute list.
+fn split_fetch_modifiers(args: &str) -> (&str, Option<&str>) {
+    let upper = args.to_uppercase();
+    ["(CHANGEDSINCE", "(VANISHED"]
+        .iter()
+        .filter_map(|modifier| upper.rfind(&format!(" {modifier}")))
+        .min()
+        .map_or((args, None), |index| {
+            (args[..index].trim_end(), Some(args[index + 1..].trim()))
+        })
+}
+
 #[instrument(skip(arg, mail))]
 pub fn generate_response(arg: FetchArguments, mail: &mut MailEntryType) -> Result<Option<String>> {
     match arg {
@@ -244,6 +364,7 @@ fn generate_response_for_attributes(
             if mail.is_trashed() {
                 flag_parts.push("\\Deleted");
             }
+            flag_parts.extend(mail.keywords().iter().map(String::as_str));
             Ok(Some(format!("FLAGS ({})", flag_parts.join(" "))))
         }
         FetchAttributes::RFC822Size => {
@@ -255,6 +376,7 @@ fn generate_response_for_attributes(
             }
         }
         FetchAttributes::Uid => Ok(Some(format!("UID {}", mail.uid()))),
+        FetchAttributes::Modseq => Ok(Some(format!("MODSEQ ({})", mail.modseq()))),
         FetchAttributes::InternalDate => match mail.received() {
             Ok(ts) => {
                 let dt =
@@ -272,10 +394,39 @@ fn generate_response_for_attributes(
                 Ok(Some(String::from("INTERNALDATE NIL")))
             }
         },
+        FetchAttributes::EmailId => Ok(mail
+            .email_id()
+            .map(|email_id| format!("EMAILID ({email_id})"))),
+        FetchAttributes::ThreadId => Ok(Some(mail.thread_id().map_or_else(
+            || String::from("THREADID NIL"),
+            |thread_id| format!("THREADID ({thread_id})"),
+        ))),
+        FetchAttributes::SaveDate => Ok(Some(
+            mail.save_date()
+                .and_then(|ts| OffsetDateTime::from_unix_timestamp(ts).ok())
+                .and_then(|dt| dt.format(&Rfc2822).ok())
+                .map_or_else(
+                    || String::from("SAVEDATE NIL"),
+                    |s| format!("SAVEDATE \"{s}\""),
+                ),
+        )),
         FetchAttributes::BodySection(section_text, range) => {
             Ok(Some(body(section_text, range, mail)))
         }
         FetchAttributes::BodyPeek(section_text, range) => Ok(Some(body(section_text, range, mail))),
+        FetchAttributes::Binary(part, range) | FetchAttributes::BinaryPeek(part, range) => {
+            Ok(Some(binary(&part, range, mail)?))
+        }
+        FetchAttributes::BinarySize(part) => {
+            let size = match mail.parsed() {
+                Ok(parsed) => binary_section(&parsed, &part)?.map_or(0, |data| data.len()),
+                Err(_) => 0,
+            };
+            Ok(Some(format!(
+                "BINARY.SIZE[{}] {size}",
+                part_number_string(&part)
+            )))
+        }
         _ => Ok(None),
     }
 }
@@ -379,3 +530,224 @@ fn body(
         String::from("BODY[] NIL\r\n")
     }
 }
+
+/// Formats a part number like `1.2`
+fn part_number_string(part: &[u32]) -> String {
+    part.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The content of a BINARY section with its content transfer encoding
+/// removed (RFC 3516 §4.2)
+#[allow(clippy::cast_possible_truncation)]
+fn binary(part: &[u32], range: Option<(u64, u64)>, mail: &mut MailEntryType) -> Result<String> {
+    let name = match range {
+        Some((origin, _)) => format!("BINARY[{}]<{origin}>", part_number_string(part)),
+        None => format!("BINARY[{}]", part_number_string(part)),
+    };
+    let Ok(parsed) = mail.parsed() else {
+        return Ok(format!("{name} NIL"));
+    };
+    let Some(mut data) = binary_section(&parsed, part)? else {
+        return Ok(format!("{name} NIL"));
+    };
+    if let Some((origin, octets)) = range {
+        let start = (origin as usize).min(data.len());
+        let end = start.saturating_add(octets as usize).min(data.len());
+        data = data[start..end].to_vec();
+    }
+    match String::from_utf8(data) {
+        Ok(text) if !text.contains('\0') => Ok(format!("{name} {{{}}}\r\n{text}", text.len())),
+        // Other data needs a binary literal (RFC 3516 §4.2). Its octets are
+        // passed to the transport unchanged.
+        text => {
+            let data = text.map_or_else(std::string::FromUtf8Error::into_bytes, String::into_bytes);
+            Ok(format!(
+                "{name} ~{{{}}}\r\n{}",
+                data.len(),
+                line_codec::raw(&data)
+            ))
+        }
+    }
+}
+
+/// Finds the part `part` of `mail` and removes its content transfer
+/// encoding. The empty part number addresses the whole message, which is
+/// returned unchanged.
+///
+/// Returns `None` if there is no such part and an [`UnknownCte`] error if
+/// the encoding of the part is unknown.
+fn binary_section(mail: &ParsedMail<'_>, part: &[u32]) -> Result<Option<Vec<u8>>> {
+    let Some((&number, rest)) = part.split_first() else {
+        return Ok(Some(mail.raw_bytes.to_vec()));
+    };
+    let index = (number as usize).wrapping_sub(1);
+    if mail.ctype.mimetype.starts_with("multipart/") {
+        return match mail.subparts.get(index) {
+            Some(subpart) if rest.is_empty() => decode_part(subpart).map(Some),
+            // The parts of an attached message are numbered like those of a
+            // message
+            Some(subpart) if subpart.ctype.mimetype == "message/rfc822" => {
+                let body = subpart.get_body_raw()?;
+                binary_section(&parse_mail(&body)?, rest)
+            }
+            Some(subpart) => binary_section(subpart, rest),
+            None => Ok(None),
+        };
+    }
+    // A message which isn't multipart only has the part 1
+    if index != 0 {
+        return Ok(None);
+    }
+    if rest.is_empty() {
+        return decode_part(mail).map(Some);
+    }
+    if mail.ctype.mimetype == "message/rfc822" {
+        let body = mail.get_body_raw()?;
+        return binary_section(&parse_mail(&body)?, rest);
+    }
+    Ok(None)
+}
+
+/// Removes the content transfer encoding of a single part
+fn decode_part(part: &ParsedMail<'_>) -> Result<Vec<u8>> {
+    let encoding = part
+        .headers
+        .iter()
+        .find(|header| {
+            header
+                .get_key_ref()
+                .eq_ignore_ascii_case("Content-Transfer-Encoding")
+        })
+        .map(|header| header.get_value().trim().to_lowercase());
+    match encoding.as_deref() {
+        None | Some("7bit" | "8bit" | "binary" | "base64" | "quoted-printable") => {
+            Ok(part.get_body_raw()?)
+        }
+        Some(_) => Err(UnknownCte.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_fetch_modifiers() {
+        assert_eq!(
+            split_fetch_modifiers("(FLAGS) (CHANGEDSINCE 12345 VANISHED)"),
+            ("(FLAGS)", Some("(CHANGEDSINCE 12345 VANISHED)"))
+        );
+        assert_eq!(
+            split_fetch_modifiers("(UID FLAGS)"),
+            ("(UID FLAGS)", None)
+        );
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_fetch_object_ids_and_save_date() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_fetch_objectid".to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        storage
+            .store_new(
+                String::from("test_fetch_objectid/INBOX"),
+                &path,
+                b"Subject: a\r\n\r\nb\r\n",
+                None,
+            )
+            .await
+            .unwrap();
+        let mut mails = storage
+            .list_all(String::from("test_fetch_objectid/INBOX"), &path)
+            .await;
+        let (_, args) = fetch_arguments("(EMAILID THREADID SAVEDATE)")
+            .finish()
+            .unwrap();
+
+        let response = generate_response(args, &mut mails[0]).unwrap().unwrap();
+        let email_id = mails[0].email_id().unwrap();
+        let thread_id = mails[0].thread_id().unwrap();
+        assert!(
+            response.starts_with(&format!(
+                "EMAILID ({email_id}) THREADID ({thread_id}) SAVEDATE \""
+            )),
+            "{response}"
+        );
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[test]
+    fn test_binary_section_decoding() {
+        let message = b"Content-Type: multipart/mixed; boundary=b\r\n\r\n--b\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\nGr=C3=BC=C3=9Fe\r\n--b\r\nContent-Type: application/octet-stream\r\nContent-Transfer-Encoding: base64\r\n\r\nAGFiYw==\r\n--b\r\nContent-Type: text/plain\r\nContent-Transfer-Encoding: x-uuencode\r\n\r\nabc\r\n--b--\r\n";
+        let parsed = parse_mail(message).unwrap();
+
+        assert_eq!(
+            binary_section(&parsed, &[1]).unwrap().unwrap(),
+            "Grüße\r\n".as_bytes()
+        );
+        assert_eq!(binary_section(&parsed, &[2]).unwrap().unwrap(), b"\0abc");
+        assert_eq!(binary_section(&parsed, &[]).unwrap().unwrap(), message);
+        assert!(binary_section(&parsed, &[4]).unwrap().is_none());
+        assert!(binary_section(&parsed, &[3])
+            .unwrap_err()
+            .is::<UnknownCte>());
+
+        let parsed =
+            parse_mail(b"Subject: a\r\nContent-Transfer-Encoding: base64\r\n\r\nYWJj\r\n").unwrap();
+        assert_eq!(binary_section(&parsed, &[1]).unwrap().unwrap(), b"abc");
+        assert!(binary_section(&parsed, &[2]).unwrap().is_none());
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_binary_section_not_utf8() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_fetch_binary".to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        storage
+            .store_new(
+                String::from("test_fetch_binary/INBOX"),
+                &path,
+                b"Subject: a\r\nContent-Type: image/jpeg\r\nContent-Transfer-Encoding: base64\r\n\r\n/9j/4A==\r\n",
+                None,
+            )
+            .await
+            .unwrap();
+        let mut mails = storage
+            .list_all(String::from("test_fetch_binary/INBOX"), &path)
+            .await;
+
+        let parsed = mails[0].parsed().unwrap();
+        assert_eq!(
+            binary_section(&parsed, &[1]).unwrap().unwrap(),
+            [0xff, 0xd8, 0xff, 0xe0]
+        );
+        drop(parsed);
+        // The octets must not be replaced by U+FFFD
+        let (_, args) = fetch_arguments("(BINARY[1])").finish().unwrap();
+        let response = generate_response(args, &mut mails[0]).unwrap().unwrap();
+        assert_eq!(
+            line_codec::octets(&response),
+            b"BINARY[1] ~{4}\r\n\xff\xd8\xff\xe0\r\n"
+        );
+        let (_, args) = fetch_arguments("(BINARY.SIZE[1])").finish().unwrap();
+        assert_eq!(
+            generate_response(args, &mut mails[0]).unwrap().unwrap(),
+            "BINARY.SIZE[1] 4"
+        );
+    }
+}
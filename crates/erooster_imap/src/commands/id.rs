@@ -0,0 +1,149 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! ID command (RFC 2971) — exchanges the name and version of client and server.
+
+use crate::commands::{parsers::id_arguments, CommandData, Data};
+use {
+    futures::{Sink, SinkExt},
+    nom::Finish,
+    tracing::{info, instrument},
+};
+
+/// The limits of RFC 2971 §3.3. Clients exceeding them only get the fields
+/// within the limits recorded.
+const MAX_FIELDS: usize = 30;
+const MAX_FIELD_LENGTH: usize = 30;
+const MAX_VALUE_LENGTH: usize = 1024;
+
+pub struct Id<'a> {
+    pub data: &'a mut Data,
+}
+
+impl Id<'_> {
+    #[instrument(skip(self, lines, command_data))]
+    pub async fn exec<S, E>(
+        &mut self,
+        lines: &mut S,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let arguments = command_data.arguments.join(" ");
+        let Ok((_, fields)) = id_arguments(&arguments).finish() else {
+            lines
+                .send(format!(
+                    "{} BAD [PARSE] Expected NIL or a list of field value pairs",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+        let client_id = fields
+            .into_iter()
+            .filter_map(|(field, value)| Some((field, value?)))
+            .filter(|(field, value)| {
+                field.len() <= MAX_FIELD_LENGTH && value.len() <= MAX_VALUE_LENGTH
+            })
+            .take(MAX_FIELDS)
+            .collect();
+        self.data.con_state.client_id = client_id;
+        info!(
+            "[IMAP] Client identified as {:?}",
+            self.data.con_state.client_id
+        );
+
+        lines
+            .feed(format!(
+                "* ID (\"name\" \"erooster\" \"version\" \"{}\")",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .await?;
+        lines
+            .feed(format!("{} OK ID completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::commands::Commands;
+    use crate::servers::state::Connection;
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_id_records_client_fields() {
+        let mut data = Data {
+            con_state: Connection::new(true, String::new()),
+        };
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Id,
+            arguments: &[
+                "(\"name\"",
+                "\"Thunderbird\"",
+                "\"version\"",
+                "\"128.0",
+                "esr\"",
+                "\"os\"",
+                "NIL)",
+            ],
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        Id { data: &mut data }
+            .exec(&mut tx, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            format!(
+                "* ID (\"name\" \"erooster\" \"version\" \"{}\")",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert_eq!(rx.next().await.unwrap(), "a1 OK ID completed");
+        assert_eq!(
+            data.con_state.client_id,
+            BTreeMap::from([
+                (String::from("name"), String::from("Thunderbird")),
+                (String::from("version"), String::from("128.0 esr")),
+            ])
+        );
+
+        let cmd_data = CommandData {
+            tag: "a2",
+            command: Commands::Id,
+            arguments: &["NIL"],
+        };
+        Id { data: &mut data }
+            .exec(&mut tx, &cmd_data)
+            .await
+            .unwrap();
+        rx.next().await.unwrap();
+        assert_eq!(rx.next().await.unwrap(), "a2 OK ID completed");
+        assert!(data.con_state.client_id.is_empty());
+
+        let cmd_data = CommandData {
+            tag: "a3",
+            command: Commands::Id,
+            arguments: &["(\"name\")"],
+        };
+        Id { data: &mut data }
+            .exec(&mut tx, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a3 BAD [PARSE] Expected NIL or a list of field value pairs"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    commands::{notify::send_event, Data},
+    servers::state::State,
+};
+use erooster_core::{
+    backend::{
+        acl::mailbox_id,
+        events::MailboxEvent,
+        storage::{MailEntry, MailStorage, Storage},
+    },
+    line_codec,
+};
+use std::path::{Path, PathBuf};
+use {
+    color_eyre::{self, eyre::ContextCompat},
+    futures::{Sink, SinkExt, Stream, StreamExt},
+    tokio::{self, sync::broadcast::error::RecvError},
+    tracing::{instrument, warn},
+};
+
+/// The IDLE loop (RFC 2177) that runs after `+ idling` was sent.
+///
+/// Changes to the selected mailbox are taken from the storage event bus and
+/// reported as untagged `EXISTS`, `EXPUNGE` and `FETCH` responses until the
+/// client sends `DONE`. Changes to other mailboxes are passed on to NOTIFY.
+pub struct Idle<'a> {
+    pub data: &'a Data,
+}
+
+/// The view of the selected mailbox the client currently has
+struct IdleMailbox {
+    id: String,
+    path: PathBuf,
+    /// UIDs ordered by sequence number
+    uids: Vec<u32>,
+}
+
+impl Idle<'_> {
+    #[instrument(skip(self, lines, reader, storage, tag))]
+    pub async fn exec<S, E, R, RE>(
+        &self,
+        lines: &mut S,
+        reader: &mut R,
+        storage: &Storage,
+        tag: &str,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+        R: Stream<Item = Result<String, RE>> + std::marker::Unpin,
+    {
+        // Subscribe before reading the mailbox so no change falls in between
+        let mut events = storage.events().subscribe();
+        let mut events_open = true;
+
+        let mut selected = if let State::Selected(folder, _) = &self.data.con_state.state {
+            let username = self
+                .data
+                .con_state
+                .username
+                .clone()
+                .context("Username missing in internal State")?;
+            let path = storage.to_ondisk_path(folder.clone(), username.clone())?;
+            let id = mailbox_id(folder, &username);
+            let uids = mailbox_uids(storage, &id, &path).await;
+            Some(IdleMailbox { id, path, uids })
+        } else {
+            // No mailbox selected — just wait for DONE without sending updates.
+            None
+        };
+
+        loop {
+            tokio::select! {
+                event = events.recv(), if events_open => {
+                    match (event, selected.as_mut()) {
+                        (Ok(event), Some(mailbox)) if event.mailbox() == mailbox.id => {
+                            if let Some(response) = self.respond(mailbox, event) {
+                                lines.send(response).await?;
+                            }
+                        }
+                        (Ok(event), _) => send_event(self.data, lines, storage, event).await?,
+                        (Err(RecvError::Lagged(skipped)), Some(mailbox)) => {
+                            // Expunges we missed can't be reported anymore, but at
+                            // least let the client know about new messages.
+                            warn!("[IMAP] IDLE: missed {skipped} mailbox events");
+                            let known = mailbox.uids.len();
+                            mailbox.uids = mailbox_uids(storage, &mailbox.id, &mailbox.path).await;
+                            if mailbox.uids.len() > known {
+                                lines.send(format!("* {} EXISTS", mailbox.uids.len())).await?;
+                            }
+                        }
+                        (Err(RecvError::Closed), _) => events_open = false,
+                        _ => {}
+                    }
+                }
+                line = reader.next() => {
+                    match line {
+                        Some(Ok(l))
+                            if line_codec::text(l.clone())
+                                .is_some_and(|l| l.trim().eq_ignore_ascii_case("done")) =>
+                        {
+                            lines.send(format!("{tag} OK IDLE terminated")).await?;
+                            break;
+                        }
+                        None => break,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns an event into the untagged response for the client, if it concerns
+    /// the selected mailbox.
+    fn respond(&self, mailbox: &mut IdleMailbox, event: MailboxEvent) -> Option<String> {
+        if event.mailbox() != mailbox.id {
+            return None;
+        }
+        match event {
+            MailboxEvent::Exists { uid, .. } => {
+                if mailbox.uids.contains(&uid) {
+                    return None;
+                }
+                mailbox.uids.push(uid);
+                Some(format!("* {} EXISTS", mailbox.uids.len()))
+            }
+            MailboxEvent::Expunged { uid, .. } => {
+                let index = mailbox.uids.iter().position(|known| *known == uid)?;
+                mailbox.uids.remove(index);
+                if self.data.con_state.qresync_enabled() {
+                    Some(format!("* VANISHED {uid}"))
+                } else {
+                    Some(format!("* {} EXPUNGE", index + 1))
+                }
+            }
+            MailboxEvent::FlagsChanged {
+                uid, modseq, flags, ..
+            } => {
+                let index = mailbox.uids.iter().position(|known| *known == uid)?;
+                Some(fetch_flags_response(
+                    self.data,
+                    index + 1,
+                    uid,
+                    modseq,
+                    &flags,
+                ))
+            }
+            MailboxEvent::Created { .. }
+            | MailboxEvent::Deleted { .. }
+            | MailboxEvent::Renamed { .. } => None,
+        }
+    }
+}
+
+/// The unsolicited FETCH response announcing new flags of a message
+pub fn fetch_flags_response(
+    data: &Data,
+    sequence: usize,
+    uid: u32,
+    modseq: u64,
+    flags: &[String],
+) -> String {
+    let mut items = Vec::new();
+    // RFC 7162 §3.2.4 requires the UID once QRESYNC is enabled
+    if data.con_state.qresync_enabled() {
+        items.push(format!("UID {uid}"));
+    }
+    items.push(format!("FLAGS ({})", flags.join(" ")));
+    if data.con_state.condstore_enabled() {
+        items.push(format!("MODSEQ ({modseq})"));
+    }
+    format!("* {sequence} FETCH ({})", items.join(" "))
+}
+
+/// The UIDs of the mailbox in sequence number order
+pub async fn mailbox_uids(storage: &Storage, mailbox_id: &str, path: &Path) -> Vec<u32> {
+    let mut uids: Vec<u32> = storage
+        .list_all(mailbox_id.to_string(), path)
+        .await
+        .iter()
+        .map(MailEntry::uid)
+        .collect();
+    uids.sort_unstable();
+    uids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servers::state::{Access, Capabilities, Connection};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    const MSG: &[u8] = b"From: a@localhost\r\nTo: b@localhost\r\nSubject: test\r\n\r\nHello\r\n";
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_idle_reports_changes_from_other_sessions() {
+        let data = Data {
+            con_state: Connection {
+                state: State::Selected("INBOX".to_string(), Access::ReadWrite),
+                secure: true,
+                username: Some("test_idle_user".to_string()),
+                active_capabilities: vec![Capabilities::Condstore],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let mailbox_id = "test_idle_user/INBOX";
+        let mailbox_path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_idle_user".to_string())
+            .unwrap();
+        storage.create_dirs(&mailbox_path).unwrap();
+        storage
+            .store_cur_with_flags(mailbox_id.to_string(), &mailbox_path, MSG, vec![])
+            .await
+            .unwrap();
+
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let (client, mut reader) = mpsc::unbounded::<Result<String, std::io::Error>>();
+        let idle = Idle { data: &data };
+        let session = async {
+            let res = idle.exec(&mut tx, &mut reader, &storage, "a1").await;
+            assert!(res.is_ok(), "{res:?}");
+        };
+        let other_session = async {
+            // Give the IDLE loop a chance to load the mailbox first
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            let id = storage
+                .store_cur_with_flags(mailbox_id.to_string(), &mailbox_path, MSG, vec![])
+                .await
+                .unwrap();
+            assert_eq!(rx.next().await, Some(String::from("* 2 EXISTS")));
+
+            storage
+                .add_flags(&mailbox_path, &id, &["\\Seen"])
+                .await
+                .unwrap();
+            let modseq = storage.get_highest_modseq(mailbox_id).await.unwrap();
+            assert_eq!(
+                rx.next().await,
+                Some(format!("* 2 FETCH (FLAGS (\\Seen) MODSEQ ({modseq}))"))
+            );
+
+            storage.expunge(&mailbox_path, &id).await.unwrap();
+            assert_eq!(rx.next().await, Some(String::from("* 2 EXPUNGE")));
+
+            client.unbounded_send(Ok(String::from("DONE"))).unwrap();
+            assert_eq!(rx.next().await, Some(String::from("a1 OK IDLE terminated")));
+        };
+        tokio::join!(session, other_session);
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_idle_ignores_other_mailboxes() {
+        let data = Data {
+            con_state: Connection {
+                state: State::Selected("INBOX".to_string(), Access::ReadWrite),
+                secure: true,
+                username: Some("test_idle_other_user".to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let mut mailbox = IdleMailbox {
+            id: String::from("test_idle_other_user/INBOX"),
+            path: PathBuf::new(),
+            uids: vec![1],
+        };
+        let idle = Idle { data: &data };
+        assert_eq!(
+            idle.respond(
+                &mut mailbox,
+                MailboxEvent::Exists {
+                    mailbox: String::from("test_idle_other_user/Sent"),
+                    uid: 2,
+                }
+            ),
+            None
+        );
+        assert_eq!(
+            idle.respond(
+                &mut mailbox,
+                MailboxEvent::Expunged {
+                    mailbox: String::from("test_idle_other_user/INBOX"),
+                    uid: 1,
+                }
+            ),
+            Some(String::from("* 1 EXPUNGE"))
+        );
+        assert!(mailbox.uids.is_empty());
+    }
+}
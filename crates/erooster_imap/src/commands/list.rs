@@ -3,18 +3,26 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{CommandData, Commands, Data},
+    commands::{
+        parsers::{list_arguments, ListReturnOption, ListSelectOption},
+        status::status_items,
+        CommandData, Commands, Data,
+    },
     servers::state::State,
 };
 use erooster_core::{
-    backend::storage::{MailStorage, Storage},
+    backend::{
+        acl::{MailboxName, Rights, OTHER_USERS_NAMESPACE, SHARED_NAMESPACE},
+        storage::{MailStorage, Storage},
+    },
     config::Config,
 };
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
-    tracing::{debug, instrument},
+    nom::Finish,
+    tracing::instrument,
 };
 
 /// Returns the RFC 6154 special-use attribute for a folder, if any.
@@ -31,34 +39,136 @@ fn special_use_flag(display_name: &str) -> Option<&'static str> {
     }
 }
 
+/// The RFC 6154 special-use attributes which can be assigned on CREATE
+pub const SPECIAL_USE_ATTRIBUTES: [&str; 7] = [
+    "\\All",
+    "\\Archive",
+    "\\Drafts",
+    "\\Flagged",
+    "\\Junk",
+    "\\Sent",
+    "\\Trash",
+];
+
+/// Stored flags from the `.flags` file together with the RFC 6154
+/// special-use attribute derived from the display name.
+async fn mailbox_attributes(storage: &Storage, path: &Path, display_name: &str) -> Vec<String> {
+    let mut flags: Vec<String> = storage.get_flags(path).await.unwrap_or_default();
+
+    // Special-use
+    if let Some(attr) = special_use_flag(display_name) {
+        if !flags.iter().any(|f| f == attr) {
+            flags.push(attr.to_string());
+        }
+    }
+
+    flags
+}
+
 /// Builds the full flag list for a folder path.
 ///
-/// Combines stored flags from the `.flags` file, `\HasChildren` / `\NoChildren`
-/// derived from the directory structure, and RFC 6154 special-use attributes
-/// derived from the display name.
+/// Combines the [`mailbox_attributes`] with `\HasChildren` / `\HasNoChildren`
+/// derived from the directory structure.
 async fn folder_flags(storage: &Storage, path: &Path, display_name: &str) -> Vec<String> {
-    let mut flags: Vec<String> = storage.get_flags(path).await.unwrap_or_default();
+    let mut flags = mailbox_attributes(storage, path, display_name).await;
 
-    // HasChildren / NoChildren
+    // HasChildren / HasNoChildren
     let has_children = storage
         .list_subdirs(path)
         .is_ok_and(|subs| !subs.is_empty());
-    if has_children {
-        if !flags.iter().any(|f| f == "\\HasChildren") {
-            flags.push(String::from("\\HasChildren"));
+    let children = if has_children {
+        "\\HasChildren"
+    } else {
+        "\\HasNoChildren"
+    };
+    if !flags.iter().any(|f| f == children) {
+        flags.push(String::from(children));
+    }
+
+    flags
+}
+
+/// All mailboxes of the user, INBOX first
+pub fn personal_mailboxes(config: &Config, storage: &Storage, username: &str) -> Vec<String> {
+    let root = Path::new(&config.mail.maildir_folders).join(username);
+    let mut names = vec![String::from("INBOX")];
+    if let Ok(sub_folders) = storage.list_subdirs(&root) {
+        names.extend(sub_folders.iter().filter_map(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().trim_start_matches('.').to_string())
+        }));
+    }
+    names
+}
+
+/// Matches a mailbox name against a LIST pattern.
+///
+/// `*` matches any characters, `%` any characters except the `delimiter`.
+fn matches_pattern(pattern: &[u8], name: &[u8], delimiter: u8) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => {
+            (0..=name.len()).any(|i| matches_pattern(rest, &name[i..], delimiter))
         }
-    } else if !flags.iter().any(|f| f == "\\NoChildren") {
-        flags.push(String::from("\\NoChildren"));
+        Some((b'%', rest)) => (0..=name.len())
+            .take_while(|i| *i == 0 || name[i - 1] != delimiter)
+            .any(|i| matches_pattern(rest, &name[i..], delimiter)),
+        Some((c, rest)) => name
+            .split_first()
+            .is_some_and(|(n, name)| n == c && matches_pattern(rest, name, delimiter)),
     }
+}
 
-    // Special-use
-    if let Some(attr) = special_use_flag(display_name) {
-        if !flags.iter().any(|f| f == attr) {
-            flags.push(attr.to_string());
+/// The mailboxes of other users and the shared mailboxes which are visible
+/// to the user, mapped to whether the mailbox itself is accessible.
+///
+/// Parents which are only listed to reach their children map to `false`.
+async fn shared_names(
+    storage: &Storage,
+    username: &str,
+) -> color_eyre::eyre::Result<BTreeMap<String, bool>> {
+    let mut names = BTreeMap::new();
+    for mailbox in storage.shared_mailboxes(username).await? {
+        let display = mailbox.display(username);
+        let mut parent = display.as_str();
+        while let Some((prefix, _)) = parent.rsplit_once('/') {
+            names.entry(prefix.to_string()).or_insert(false);
+            parent = prefix;
         }
+        names.insert(display, true);
     }
+    Ok(names)
+}
 
-    flags
+/// Lists the mailboxes of other users and the shared mailboxes which are
+/// visible to the user and match `pattern`.
+///
+/// Parents which can't be selected themselves are listed as `\Noselect`.
+async fn list_shared<S, E>(
+    lines: &mut S,
+    storage: &Storage,
+    username: &str,
+    pattern: &str,
+) -> color_eyre::eyre::Result<()>
+where
+    E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+    S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+{
+    for (name, selectable) in shared_names(storage, username).await? {
+        if !matches_pattern(pattern.as_bytes(), name.as_bytes(), b'/') {
+            continue;
+        }
+        let flags = if selectable {
+            let path = storage.to_ondisk_path(name.clone(), username.to_string())?;
+            folder_flags(storage, &path, &name).await
+        } else {
+            vec![String::from("\\Noselect"), String::from("\\HasChildren")]
+        };
+        lines
+            .feed(format!("* LIST ({}) \"/\" \"{name}\"", flags.join(" ")))
+            .await?;
+    }
+    Ok(())
 }
 
 #[allow(clippy::too_many_lines)]
@@ -103,18 +213,32 @@ where
     let reference_name = reference_name.replace('"', "");
     let mailbox_patterns = arguments[1];
     let mailbox_patterns = mailbox_patterns.replace('"', "");
+    let username = data
+        .con_state
+        .username
+        .clone()
+        .context("Username missing in internal State")?;
+
+    // Subscriptions only exist for personal mailboxes
+    let pattern = format!("{reference_name}{mailbox_patterns}");
+    let lists_shared = command_resp == "LIST" && !mailbox_patterns.is_empty();
+    if lists_shared
+        && (pattern.starts_with(OTHER_USERS_NAMESPACE) || pattern.starts_with(SHARED_NAMESPACE))
+    {
+        list_shared(lines, storage, &username, &pattern).await?;
+        lines
+            .feed(format!("{} OK {command_resp} completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        return Ok(());
+    }
 
     if mailbox_patterns.is_empty() {
         lines
             .feed(format!("* {command_resp} (\\Noselect) \".\" \"\""))
             .await?;
     } else if mailbox_patterns.ends_with('*') {
-        let mut folder = Path::new(&config.mail.maildir_folders).join(
-            data.con_state
-                .username
-                .clone()
-                .context("Username missing in internal State")?,
-        );
+        let mut folder = Path::new(&config.mail.maildir_folders).join(&username);
         if !reference_name.is_empty() {
             let mut reference_name_folder = reference_name.clone();
             reference_name_folder.insert(0, '.');
@@ -151,12 +275,7 @@ where
                 .await?;
         }
     } else if mailbox_patterns.ends_with('%') {
-        let mut folder = Path::new(&config.mail.maildir_folders).join(
-            data.con_state
-                .username
-                .clone()
-                .context("Username missing in internal State")?,
-        );
+        let mut folder = Path::new(&config.mail.maildir_folders).join(&username);
         if !reference_name.is_empty() {
             let mut reference_name_folder = reference_name.clone();
             reference_name_folder.insert(0, '.');
@@ -206,12 +325,7 @@ where
                 .await?;
         }
     } else {
-        let mut folder = Path::new(&config.mail.maildir_folders).join(
-            data.con_state
-                .username
-                .clone()
-                .context("Username missing in internal State")?,
-        );
+        let mut folder = Path::new(&config.mail.maildir_folders).join(&username);
         if !reference_name.is_empty() {
             let mut reference_name_folder = reference_name.clone();
             reference_name_folder.retain(|c| c != '"');
@@ -243,6 +357,9 @@ where
             ))
             .await?;
     }
+    if lists_shared {
+        list_shared(lines, storage, &username, &pattern).await?;
+    }
     lines
         .feed(format!("{} OK {command_resp} completed", command_data.tag))
         .await?;
@@ -257,34 +374,232 @@ fn display_name_for(path: &Path) -> String {
         .unwrap_or_default()
 }
 
+/// A mailbox which may show up in an extended LIST response
+struct ListEntry {
+    name: String,
+    delimiter: char,
+    flags: Vec<String>,
+    /// Whether the mailbox exists and the user may select it
+    selectable: bool,
+}
+
+impl ListEntry {
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f.eq_ignore_ascii_case(flag))
+    }
+
+    fn is_special_use(&self) -> bool {
+        SPECIAL_USE_ATTRIBUTES
+            .into_iter()
+            .any(|attr| self.has_flag(attr))
+    }
+
+    /// Whether `other` is a descendant of this mailbox
+    fn is_parent_of(&self, other: &ListEntry) -> bool {
+        self.delimiter == other.delimiter
+            && other
+                .name
+                .strip_prefix(&self.name)
+                .is_some_and(|rest| rest.starts_with(self.delimiter))
+    }
+
+    /// Whether the entry matches one of the LIST patterns
+    fn matches(&self, reference: &str, patterns: &[String]) -> bool {
+        patterns.iter().any(|pattern| {
+            let mut pattern = format!("{reference}{pattern}");
+            if pattern.eq_ignore_ascii_case("INBOX") {
+                pattern = String::from("INBOX");
+            }
+            // Personal mailboxes are stored with `.` but may be named with `/`
+            if self.delimiter == '.' {
+                pattern = pattern.replace('/', ".");
+            }
+            matches_pattern(
+                pattern.as_bytes(),
+                self.name.as_bytes(),
+                self.delimiter as u8,
+            )
+        })
+    }
+}
+
+/// Collects the personal mailboxes followed by the shared ones with
+/// their attributes and `\HasChildren` / `\HasNoChildren`.
+async fn list_entries(
+    config: &Config,
+    storage: &Storage,
+    username: &str,
+) -> color_eyre::eyre::Result<Vec<ListEntry>> {
+    let mut entries = Vec::new();
+    for name in personal_mailboxes(config, storage, username) {
+        let path = storage.to_ondisk_path(name.clone(), username.to_string())?;
+        let flags = mailbox_attributes(storage, &path, &name).await;
+        entries.push(ListEntry {
+            name,
+            delimiter: '.',
+            flags,
+            selectable: true,
+        });
+    }
+    for (name, selectable) in shared_names(storage, username).await? {
+        let flags = if selectable {
+            let path = storage.to_ondisk_path(name.clone(), username.to_string())?;
+            let leaf = name.rsplit('/').next().unwrap_or_default();
+            mailbox_attributes(storage, &path, leaf).await
+        } else {
+            vec![String::from("\\Noselect")]
+        };
+        entries.push(ListEntry {
+            name,
+            delimiter: '/',
+            flags,
+            selectable,
+        });
+    }
+
+    let has_children: Vec<bool> = entries
+        .iter()
+        .map(|entry| entries.iter().any(|other| entry.is_parent_of(other)))
+        .collect();
+    for (entry, has_children) in entries.iter_mut().zip(has_children) {
+        entry
+            .flags
+            .retain(|f| f != "\\HasChildren" && f != "\\HasNoChildren" && f != "\\NoChildren");
+        entry.flags.push(String::from(if has_children {
+            "\\HasChildren"
+        } else {
+            "\\HasNoChildren"
+        }));
+    }
+    Ok(entries)
+}
+
 pub struct List<'a> {
     pub data: &'a Data,
 }
 
 impl List<'_> {
-    #[instrument(skip(self, lines, command_data))]
+    /// The extended LIST of RFC 5258 including the `SPECIAL-USE` options of
+    /// RFC 6154 and the `STATUS` return option of RFC 5819.
+    ///
+    /// All known attributes are always returned, so the `SUBSCRIBED`,
+    /// `CHILDREN` and `SPECIAL-USE` return options need no extra work.
+    #[allow(clippy::too_many_lines)]
+    #[instrument(skip(self, lines, config, storage, command_data))]
     pub async fn extended<S, E>(
         &self,
         lines: &mut S,
+        config: &Config,
+        storage: &Storage,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
     where
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
-        debug!("extended LIST not yet implemented");
         if self.data.con_state.state == State::NotAuthenticated {
             lines
                 .send(format!("{} BAD Not Authenticated", command_data.tag))
                 .await?;
-        } else {
+            return Ok(());
+        }
+        let arguments = command_data.arguments.join(" ");
+        let Ok(("", arguments)) = list_arguments(&arguments).finish() else {
+            lines
+                .send(format!(
+                    "{} BAD [PARSE] Invalid extended LIST arguments",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+        let subscribed_only = arguments.selection.contains(&ListSelectOption::Subscribed);
+        let special_use_only = arguments.selection.contains(&ListSelectOption::SpecialUse);
+        let recursive = arguments
+            .selection
+            .contains(&ListSelectOption::RecursiveMatch);
+        // RECURSIVEMATCH needs a selection option it can apply to
+        if recursive && !subscribed_only {
             lines
                 .send(format!(
-                    "{} BAD LIST extended form not supported",
+                    "{} BAD RECURSIVEMATCH requires the SUBSCRIBED selection option",
                     command_data.tag
                 ))
                 .await?;
+            return Ok(());
+        }
+        let requested_status: Option<Vec<&str>> =
+            arguments
+                .return_options
+                .iter()
+                .find_map(|option| match option {
+                    ListReturnOption::Status(items) => {
+                        Some(items.iter().map(String::as_str).collect())
+                    }
+                    _ => None,
+                });
+        let username = self
+            .data
+            .con_state
+            .username
+            .clone()
+            .context("Username missing in internal State")?;
+
+        if arguments.patterns.iter().any(String::is_empty) {
+            lines
+                .feed(String::from("* LIST (\\Noselect) \".\" \"\""))
+                .await?;
+        }
+        let entries = list_entries(config, storage, &username).await?;
+        for entry in &entries {
+            if !entry.matches(&arguments.reference, &arguments.patterns) {
+                continue;
+            }
+            let selected = (!subscribed_only || entry.has_flag("\\Subscribed"))
+                && (!special_use_only || entry.is_special_use());
+            let subscribed_children = recursive
+                && entries
+                    .iter()
+                    .any(|other| entry.is_parent_of(other) && other.has_flag("\\Subscribed"));
+            if !selected && !subscribed_children {
+                continue;
+            }
+
+            let mut response = format!(
+                "* LIST ({}) \"{}\" \"{}\"",
+                entry.flags.join(" "),
+                entry.delimiter,
+                entry.name
+            );
+            if subscribed_children {
+                response.push_str(" (\"CHILDINFO\" (\"SUBSCRIBED\"))");
+            }
+            lines.feed(response).await?;
+
+            let Some(requested_status) = &requested_status else {
+                continue;
+            };
+            if !selected || !entry.selectable {
+                continue;
+            }
+            let mailbox = MailboxName::resolve(&entry.name, &username);
+            if !mailbox.is_owned_by(&username)
+                && !storage
+                    .my_rights(&mailbox, &username)
+                    .await?
+                    .contains(Rights::READ)
+            {
+                continue;
+            }
+            let parts = status_items(storage, &username, &entry.name, requested_status).await?;
+            lines
+                .feed(format!("* STATUS \"{}\" ({})", entry.name, parts.join(" ")))
+                .await?;
         }
+        lines
+            .feed(format!("{} OK LIST completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
         Ok(())
     }
 }
@@ -312,17 +627,10 @@ impl List<'_> {
                 .await?;
             return Ok(());
         }
-        if arguments.len() == 2 {
+        if arguments.len() == 2 && !arguments.iter().any(|arg| arg.starts_with('(')) {
             basic(self.data, lines, config, storage, command_data).await?;
-        } else if arguments.len() >= 4 {
-            self.extended(lines, command_data).await?;
         } else {
-            lines
-                .send(format!(
-                    "{} BAD [SERVERBUG] invalid arguments",
-                    command_data.tag
-                ))
-                .await?;
+            self.extended(lines, config, storage, command_data).await?;
         }
         Ok(())
     }
@@ -363,6 +671,7 @@ impl LSub<'_> {
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
     use tokio;
 
     #[test]
@@ -404,6 +713,93 @@ mod tests {
         assert_eq!(special_use_flag("My Custom Folder"), None);
     }
 
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern(b"*", b"Users/bob/INBOX", b'/'));
+        assert!(matches_pattern(b"Shared/%", b"Shared/support", b'/'));
+        assert!(!matches_pattern(
+            b"Shared/%",
+            b"Shared/support/tickets",
+            b'/'
+        ));
+        assert!(matches_pattern(b"%", b"Users", b'/'));
+        assert!(!matches_pattern(b"%", b"Users/bob", b'/'));
+        assert!(matches_pattern(b"Users/*/INBOX", b"Users/bob/INBOX", b'/'));
+        assert!(matches_pattern(b"Archive.%", b"Archive.2026", b'.'));
+        assert!(!matches_pattern(b"%", b"Archive.2026", b'.'));
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_list_extended_special_use_and_status() {
+        use crate::commands::create::Create;
+        use futures::StreamExt;
+
+        let data = Data {
+            con_state: crate::servers::state::Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test_list_extended")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = futures::channel::mpsc::unbounded();
+        for arguments in [&["Outbox", "(USE", "(\\Sent))"][..], &["Projects"][..]] {
+            let cmd_data = CommandData {
+                tag: "a1",
+                command: Commands::Create,
+                arguments,
+            };
+            Create { data: &data }
+                .exec(&mut tx, &storage, &cmd_data)
+                .await
+                .unwrap();
+            assert!(rx.next().await.unwrap().ends_with("] CREATE completed"));
+        }
+
+        let cmd_data = CommandData {
+            tag: "a2",
+            command: Commands::List,
+            arguments: &[
+                "(SPECIAL-USE)",
+                "\"\"",
+                "*",
+                "RETURN",
+                "(STATUS",
+                "(MESSAGES))",
+            ],
+        };
+        List { data: &data }
+            .exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "* LIST (\\Sent \\HasNoChildren) \".\" \"Outbox\""
+        );
+        assert_eq!(rx.next().await.unwrap(), "* STATUS \"Outbox\" (MESSAGES 0)");
+        assert_eq!(rx.next().await.unwrap(), "a2 OK LIST completed");
+
+        let cmd_data = CommandData {
+            tag: "a3",
+            command: Commands::List,
+            arguments: &["(RECURSIVEMATCH)", "\"\"", "*"],
+        };
+        List { data: &data }
+            .exec(&mut tx, &config, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert!(rx.next().await.unwrap().starts_with("a3 BAD"));
+    }
+
     #[cfg_attr(coverage_nightly, coverage(off))]
     #[tokio::test]
     async fn test_list_not_authenticated() {
@@ -413,6 +809,11 @@ mod tests {
                 secure: true,
                 username: None,
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -2,32 +2,199 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::commands::CommandData;
+use crate::{
+    commands::{
+        auth::{response, Authenticate},
+        parsers::login_arguments,
+        CommandData, Data,
+    },
+    servers::state::State,
+};
+use base64::Engine;
+use erooster_core::{
+    backend::database::DB,
+    config::Config,
+    sasl::{Exchange, Mechanism, Protocol},
+    BASE64_DECODER,
+};
 use {
     color_eyre,
     futures::{Sink, SinkExt},
+    nom::Finish,
     tracing::instrument,
 };
 
-pub struct Login;
+pub struct Login<'a> {
+    pub data: &'a mut Data,
+}
 
-impl Login {
-    #[instrument(skip(self, lines, command_data))]
+impl Login<'_> {
+    /// Checks the credentials like AUTHENTICATE PLAIN, so LOGIN shares its
+    /// accounting of failed attempts
+    #[instrument(skip(self, lines, config, database, command_data))]
     pub async fn exec<S, E>(
-        &self,
+        &mut self,
         lines: &mut S,
+        config: &Config,
+        database: &DB,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
     where
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
-        lines
-            .send(format!(
-                "{} NO [PRIVACYREQUIRED] LOGIN COMMAND DISABLED FOR SECURITY. USE AUTH",
-                command_data.tag
-            ))
-            .await?;
-        Ok(())
+        if !self.data.con_state.secure {
+            lines
+                .send(format!(
+                    "{} NO [PRIVACYREQUIRED] LOGIN COMMAND DISABLED FOR SECURITY. USE AUTH",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        if self.data.con_state.state != State::NotAuthenticated {
+            lines
+                .send(format!("{} NO invalid state", command_data.tag))
+                .await?;
+            return Ok(());
+        }
+        let arguments = command_data.arguments.join(" ");
+        let Ok(("", (username, password))) = login_arguments(&arguments).finish() else {
+            lines
+                .send(format!("{} BAD Invalid arguments", command_data.tag))
+                .await?;
+            return Ok(());
+        };
+
+        let exchange = match Exchange::start(
+            config,
+            Protocol::Imap,
+            Mechanism::Plain.name(),
+            self.data.con_state.secure,
+            &self.data.con_state.peer_addr,
+        ) {
+            Ok(exchange) => exchange,
+            Err(error) => {
+                lines
+                    .send(format!("{} {}", command_data.tag, response(error)))
+                    .await?;
+                return Ok(());
+            }
+        };
+        let plain = BASE64_DECODER.encode(format!("\0{username}\0{password}"));
+        Authenticate {
+            data: self.data,
+            auth_data: &plain,
+        }
+        .respond(lines, config, database, command_data, exchange)
+        .await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use crate::{
+        commands::{Data, Response},
+        servers::state::{Connection, State},
+    };
+    use erooster_core::backend::database::Database;
+    use futures::{channel::mpsc, StreamExt};
+    use secrecy::SecretString;
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_login_counts_failures_like_authenticate() {
+        let (config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        database.add_user("erin@localhost").await.unwrap();
+        database
+            .change_password("erin@localhost", SecretString::new(Box::from("secret")))
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = mpsc::unbounded::<String>();
+
+        // Not without encryption
+        let mut data = Data {
+            con_state: Connection::new(false, String::from("192.0.2.10")),
+        };
+        data.parse(
+            &mut tx,
+            &config,
+            &database,
+            &storage,
+            String::from("a1 LOGIN erin@localhost secret"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a1 NO [PRIVACYREQUIRED] LOGIN COMMAND DISABLED FOR SECURITY. USE AUTH"
+        );
+
+        let mut data = Data {
+            con_state: Connection::new(true, String::from("192.0.2.10")),
+        };
+        for _ in 1..5 {
+            data.parse(
+                &mut tx,
+                &config,
+                &database,
+                &storage,
+                String::from("a2 LOGIN erin@localhost wrong"),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                rx.next().await.unwrap(),
+                "a2 NO [AUTHENTICATIONFAILED] Invalid user or password"
+            );
+        }
+        // The fifth failure is one of AUTHENTICATE
+        let response = data
+            .parse(
+                &mut tx,
+                &config,
+                &database,
+                &storage,
+                String::from("a3 AUTHENTICATE PLAIN AGVyaW5AbG9jYWxob3N0AHdyb25n"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, Response::Continue);
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a3 NO [UNAVAILABLE] Too many failed attempts, try again later"
+        );
+        data.parse(
+            &mut tx,
+            &config,
+            &database,
+            &storage,
+            String::from("a4 LOGIN erin@localhost \"secret\""),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            "a4 NO [UNAVAILABLE] Too many failed attempts, try again later"
+        );
+
+        // Other client addresses can still log in
+        let mut data = Data {
+            con_state: Connection::new(true, String::from("192.0.2.11")),
+        };
+        data.parse(
+            &mut tx,
+            &config,
+            &database,
+            &storage,
+            String::from("a5 LOGIN erin@localhost secret"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rx.next().await.unwrap(), "a5 OK Success (tls protection)");
+        assert_eq!(data.con_state.state, State::Authenticated);
     }
 }
@@ -0,0 +1,470 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! METADATA extension (RFC 5464).
+//!
+//! The empty mailbox name addresses the server annotations. Private entries
+//! of a mailbox need the lookup right, reading shared entries needs the read
+//! and changing them the write right. Shared server entries can only be
+//! changed by admins.
+
+use crate::commands::{
+    acl::require_rights,
+    parsers::{getmetadata_arguments, setmetadata_arguments},
+    quota::{authenticated_user, is_admin},
+    CommandData, Data,
+};
+use erooster_core::{
+    backend::{
+        acl::{MailboxName, Rights},
+        metadata::{EntryName, Scope, MAX_ENTRIES, MAX_VALUE_SIZE},
+        storage::{MailStorage, Storage},
+    },
+    config::Config,
+};
+use std::collections::BTreeMap;
+use {
+    futures::{Sink, SinkExt},
+    nom::Finish,
+    tracing::instrument,
+};
+
+/// Formats a value as quoted string or, if it can't be quoted, as literal
+fn metadata_value(value: &str) -> String {
+    if value
+        .bytes()
+        .all(|b| b.is_ascii() && b != b'\r' && b != b'\n')
+    {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        format!("{{{}}}\r\n{value}", value.len())
+    }
+}
+
+/// Resolves the mailbox of a metadata command and checks that it exists and
+/// that the user has `needed` on it.
+///
+/// Returns `Some(None)` for the server annotations.
+async fn metadata_mailbox<S, E>(
+    lines: &mut S,
+    storage: &Storage,
+    username: &str,
+    mailbox: &str,
+    needed: Rights,
+    tag: &str,
+) -> color_eyre::eyre::Result<Option<Option<MailboxName>>>
+where
+    E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+    S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+{
+    if mailbox.is_empty() {
+        return Ok(Some(None));
+    }
+    let resolved = MailboxName::resolve(mailbox, username);
+    if !require_rights(lines, storage, username, &resolved, needed, tag).await? {
+        return Ok(None);
+    }
+    let path = storage.to_ondisk_path(mailbox.to_string(), username.to_string())?;
+    if !path.exists() {
+        lines
+            .send(format!("{tag} NO [NONEXISTENT] No such mailbox"))
+            .await?;
+        return Ok(None);
+    }
+    Ok(Some(Some(resolved)))
+}
+
+pub struct GetMetadata<'a> {
+    pub data: &'a Data,
+}
+
+impl GetMetadata<'_> {
+    #[allow(clippy::too_many_lines)]
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let arguments = command_data.arguments.join(" ");
+        let Ok((_, arguments)) = getmetadata_arguments(&arguments).finish() else {
+            lines
+                .send(format!("{tag} BAD failed to parse arguments"))
+                .await?;
+            return Ok(());
+        };
+        if let Some(entry) = arguments.entries.iter().find(|entry| {
+            !entry.eq_ignore_ascii_case("/private")
+                && !entry.eq_ignore_ascii_case("/shared")
+                && EntryName::parse(entry).is_none()
+        }) {
+            lines
+                .send(format!("{tag} BAD Invalid entry name {entry}"))
+                .await?;
+            return Ok(());
+        }
+        let Some(mailbox) = metadata_mailbox(
+            lines,
+            storage,
+            username,
+            &arguments.mailbox,
+            Rights::LOOKUP,
+            tag,
+        )
+        .await?
+        else {
+            return Ok(());
+        };
+        let may_read_shared = match &mailbox {
+            Some(mailbox) => storage
+                .my_rights(mailbox, username)
+                .await?
+                .contains(Rights::READ),
+            None => true,
+        };
+
+        // Requested entries which don't exist are returned as NIL
+        let mut found: BTreeMap<String, Option<String>> = arguments
+            .entries
+            .iter()
+            .filter_map(|entry| EntryName::parse(entry))
+            .filter(|entry| may_read_shared || entry.scope() == Scope::Private)
+            .map(|entry| (entry.as_str().to_string(), None))
+            .collect();
+        let mut longest_omitted = 0;
+        for entry in storage.get_metadata(mailbox.as_ref(), username).await? {
+            if !may_read_shared && entry.name.scope() == Scope::Shared {
+                continue;
+            }
+            if !arguments
+                .entries
+                .iter()
+                .any(|root| entry.name.is_within(root, arguments.depth))
+            {
+                continue;
+            }
+            if arguments
+                .max_size
+                .is_some_and(|max_size| entry.value.len() > max_size)
+            {
+                longest_omitted = longest_omitted.max(entry.value.len());
+                found.remove(entry.name.as_str());
+                continue;
+            }
+            found.insert(entry.name.as_str().to_string(), Some(entry.value));
+        }
+
+        if !found.is_empty() {
+            let values: Vec<String> = found
+                .iter()
+                .map(|(name, value)| {
+                    let value = value
+                        .as_deref()
+                        .map_or_else(|| String::from("NIL"), metadata_value);
+                    format!("{name} {value}")
+                })
+                .collect();
+            lines
+                .feed(format!(
+                    "* METADATA \"{}\" ({})",
+                    arguments.mailbox,
+                    values.join(" ")
+                ))
+                .await?;
+        }
+        if longest_omitted > 0 {
+            lines
+                .feed(format!(
+                    "{tag} OK [METADATA LONGENTRIES {longest_omitted}] GETMETADATA completed"
+                ))
+                .await?;
+        } else {
+            lines
+                .feed(format!("{tag} OK GETMETADATA completed"))
+                .await?;
+        }
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct SetMetadata<'a> {
+    pub data: &'a Data,
+}
+
+impl SetMetadata<'_> {
+    #[instrument(skip(self, lines, config, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        config: &Config,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let tag = command_data.tag;
+        let Some(username) = authenticated_user(self.data) else {
+            lines.send(format!("{tag} NO invalid state")).await?;
+            return Ok(());
+        };
+        let arguments = command_data.arguments.join(" ");
+        let Ok((_, (mailbox_arg, changes))) = setmetadata_arguments(&arguments).finish() else {
+            lines
+                .send(format!("{tag} BAD failed to parse arguments"))
+                .await?;
+            return Ok(());
+        };
+        let mut entries = Vec::with_capacity(changes.len());
+        for (name, value) in changes {
+            let Some(name) = EntryName::parse(&name) else {
+                lines
+                    .send(format!("{tag} BAD Invalid entry name {name}"))
+                    .await?;
+                return Ok(());
+            };
+            entries.push((name, value));
+        }
+
+        let shared = entries
+            .iter()
+            .any(|(name, _)| name.scope() == Scope::Shared);
+        let needed = if shared {
+            Rights::LOOKUP.union(Rights::WRITE)
+        } else {
+            Rights::LOOKUP
+        };
+        let Some(mailbox) =
+            metadata_mailbox(lines, storage, username, &mailbox_arg, needed, tag).await?
+        else {
+            return Ok(());
+        };
+        if mailbox.is_none() && shared && !is_admin(config, username) {
+            lines
+                .send(format!(
+                    "{tag} NO [NOPERM] Only admins may change shared server annotations"
+                ))
+                .await?;
+            return Ok(());
+        }
+
+        // Check all limits before changing anything
+        if entries
+            .iter()
+            .any(|(_, value)| value.as_ref().is_some_and(|v| v.len() > MAX_VALUE_SIZE))
+        {
+            lines
+                .send(format!(
+                    "{tag} NO [METADATA MAXSIZE {MAX_VALUE_SIZE}] Value too long"
+                ))
+                .await?;
+            return Ok(());
+        }
+        let mut existing: BTreeMap<EntryName, bool> = storage
+            .get_metadata(mailbox.as_ref(), username)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.name, true))
+            .collect();
+        for (name, value) in &entries {
+            existing.insert(name.clone(), value.is_some());
+        }
+        for scope in [Scope::Private, Scope::Shared] {
+            let count = existing
+                .iter()
+                .filter(|(name, exists)| **exists && name.scope() == scope)
+                .count();
+            if count > MAX_ENTRIES {
+                lines
+                    .send(format!("{tag} NO [METADATA TOOMANY] Too many annotations"))
+                    .await?;
+                return Ok(());
+            }
+        }
+
+        for (name, value) in &entries {
+            storage
+                .set_metadata(mailbox.as_ref(), username, name, value.as_deref())
+                .await?;
+        }
+        lines
+            .send(format!("{tag} OK SETMETADATA completed"))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::commands::{CommandData, Commands};
+    use crate::servers::state::{Connection, State};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    fn connection(username: &str) -> Data {
+        Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(username.to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_metadata_value() {
+        assert_eq!(metadata_value("Say \"hi\""), "\"Say \\\"hi\\\"\"");
+        assert_eq!(metadata_value("a\r\nb"), "{4}\r\na\r\nb");
+    }
+
+    #[allow(clippy::too_many_lines)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_metadata_commands() {
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let alice = connection("alice");
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        let set = CommandData {
+            tag: "a1",
+            command: Commands::SetMetadata,
+            arguments: &["\"\"", "(/private/comment", "\"My", "notes\")"],
+        };
+        SetMetadata { data: &alice }
+            .exec(&mut tx, &config, &storage, &set)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 OK SETMETADATA completed"))
+        );
+
+        let set_shared = CommandData {
+            tag: "a2",
+            command: Commands::SetMetadata,
+            arguments: &["\"\"", "(/shared/comment", "\"Hello\")"],
+        };
+        SetMetadata { data: &alice }
+            .exec(&mut tx, &config, &storage, &set_shared)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "a2 NO [NOPERM] Only admins may change shared server annotations"
+            ))
+        );
+
+        let get = CommandData {
+            tag: "a3",
+            command: Commands::GetMetadata,
+            arguments: &[
+                "(DEPTH",
+                "infinity)",
+                "\"\"",
+                "(/private",
+                "/shared/comment)",
+            ],
+        };
+        GetMetadata { data: &alice }
+            .exec(&mut tx, &storage, &get)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "* METADATA \"\" (/private/comment \"My notes\" /shared/comment NIL)"
+            ))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a3 OK GETMETADATA completed"))
+        );
+
+        // Private entries are not visible to other users
+        let bob = connection("bob");
+        GetMetadata { data: &bob }
+            .exec(&mut tx, &storage, &get)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("* METADATA \"\" (/shared/comment NIL)"))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a3 OK GETMETADATA completed"))
+        );
+
+        let get_small = CommandData {
+            tag: "a4",
+            command: Commands::GetMetadata,
+            arguments: &["(MAXSIZE", "2)", "\"\"", "/private/comment"],
+        };
+        GetMetadata { data: &alice }
+            .exec(&mut tx, &storage, &get_small)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "a4 OK [METADATA LONGENTRIES 8] GETMETADATA completed"
+            ))
+        );
+
+        let too_long = "x".repeat(MAX_VALUE_SIZE + 1);
+        let too_long = format!("\"{too_long}\")");
+        let set_long = CommandData {
+            tag: "a5",
+            command: Commands::SetMetadata,
+            arguments: &["\"\"", "(/private/comment", &too_long],
+        };
+        SetMetadata { data: &alice }
+            .exec(&mut tx, &config, &storage, &set_long)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(format!(
+                "a5 NO [METADATA MAXSIZE {MAX_VALUE_SIZE}] Value too long"
+            ))
+        );
+
+        let missing = CommandData {
+            tag: "a6",
+            command: Commands::GetMetadata,
+            arguments: &["Nope", "/private/comment"],
+        };
+        GetMetadata { data: &alice }
+            .exec(&mut tx, &storage, &missing)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a6 NO [NONEXISTENT] No such mailbox"))
+        );
+    }
+}
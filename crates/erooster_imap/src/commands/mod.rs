@@ -4,38 +4,48 @@
 
 use crate::{
     commands::{
+        acl::{DeleteAcl, GetAcl, ListRights, MyRights, SetAcl},
         append::Append,
-        auth::{Authenticate, AuthenticationMethod},
+        auth::Authenticate,
         capability::Capability,
         check::Check,
         close::Close,
+        compress::Compress,
         copy::Copy,
         create::Create,
         delete::Delete,
         enable::Enable,
         expunge::Expunge,
         fetch::Fetch,
+        id::Id,
         list::{LSub, List},
+        metadata::{GetMetadata, SetMetadata},
         login::Login,
         logout::Logout,
         move_::Move,
         namespace::Namespace,
         noop::Noop,
+        notify::Notify,
+        parsers::{literal_size, LiteralSize},
+        quota::{GetQuota, GetQuotaRoot, SetQuota},
         rename::Rename,
         search::Search,
         select::{Examine, Select},
+        sort::Sort,
         status::Status,
         store::Store,
         subscribe::Subscribe,
+        thread::Thread,
         uid::Uid,
         unselect::Unselect,
         unsubscribe::Unsubscribe,
     },
-    servers::state::{Connection, State},
+    servers::state::{Connection, LiteralState, State},
 };
 use erooster_core::{
-    backend::{database::DB, storage::Storage},
+    backend::{database::DB, metadata::MAX_VALUE_SIZE, storage::Storage},
     config::Config,
+    line_codec,
 };
 use {
     color_eyre,
@@ -47,6 +57,7 @@ use {
         error::context,
         multi::many0,
         sequence::terminated,
+        combinator::map,
         Finish, IResult, Parser,
     },
     nom_language::error::{convert_error, VerboseError},
@@ -56,30 +67,39 @@ use {
 #[cfg(test)]
 use std::fmt::Display;
 
+mod acl;
 mod append;
 pub mod auth;
 pub mod capability;
 mod check;
 mod close;
+mod compress;
 mod copy;
 mod create;
 mod delete;
 mod enable;
 mod expunge;
 mod fetch;
+mod id;
+pub mod idle;
 mod list;
 mod login;
+mod metadata;
 mod logout;
 mod move_;
 mod namespace;
 mod noop;
+pub mod notify;
 pub mod parsers;
+mod quota;
 mod rename;
 mod search;
 pub mod select;
+mod sort;
 mod status;
 mod store;
 mod subscribe;
+mod thread;
 mod uid;
 mod unselect;
 mod unsubscribe;
@@ -114,28 +134,43 @@ pub enum Commands {
     Capability,
     Check,
     Close,
+    Compress,
     Copy,
     Create,
     Delete,
+    DeleteAcl,
     Enable,
     Examine,
     Expunge,
     Fetch,
+    GetAcl,
+    GetMetadata,
+    GetQuota,
+    GetQuotaRoot,
+    Id,
     Idle,
     List,
+    ListRights,
     Login,
     Logout,
     LSub,
     Move,
+    MyRights,
     Namespace,
     Noop,
+    Notify,
     Rename,
     Search,
     Select,
+    SetAcl,
+    SetMetadata,
+    SetQuota,
+    Sort,
     Status,
     Starttls,
     Store,
     Subscribe,
+    Thread,
     Uid,
     Unselect,
     Unsubscribe,
@@ -148,6 +183,7 @@ impl TryFrom<&str> for Commands {
     fn try_from(i: &str) -> Result<Self, Self::Error> {
         match i.to_lowercase().as_str() {
             "capability" => Ok(Commands::Capability),
+            "compress" => Ok(Commands::Compress),
             "copy" => Ok(Commands::Copy),
             "login" => Ok(Commands::Login),
             "move" => Ok(Commands::Move),
@@ -158,6 +194,7 @@ impl TryFrom<&str> for Commands {
             "select" => Ok(Commands::Select),
             "examine" => Ok(Commands::Examine),
             "noop" => Ok(Commands::Noop),
+            "notify" => Ok(Commands::Notify),
             "check" => Ok(Commands::Check),
             "create" => Ok(Commands::Create),
             "delete" => Ok(Commands::Delete),
@@ -170,13 +207,26 @@ impl TryFrom<&str> for Commands {
             "rename" => Ok(Commands::Rename),
             "uid" => Ok(Commands::Uid),
             "fetch" => Ok(Commands::Fetch),
+            "id" => Ok(Commands::Id),
             "idle" => Ok(Commands::Idle),
             "store" => Ok(Commands::Store),
             "append" => Ok(Commands::Append),
             "enable" => Ok(Commands::Enable),
             "status" => Ok(Commands::Status),
             "search" => Ok(Commands::Search),
+            "sort" => Ok(Commands::Sort),
+            "thread" => Ok(Commands::Thread),
             "starttls" => Ok(Commands::Starttls),
+            "getquota" => Ok(Commands::GetQuota),
+            "getquotaroot" => Ok(Commands::GetQuotaRoot),
+            "setquota" => Ok(Commands::SetQuota),
+            "setacl" => Ok(Commands::SetAcl),
+            "deleteacl" => Ok(Commands::DeleteAcl),
+            "getacl" => Ok(Commands::GetAcl),
+            "listrights" => Ok(Commands::ListRights),
+            "myrights" => Ok(Commands::MyRights),
+            "getmetadata" => Ok(Commands::GetMetadata),
+            "setmetadata" => Ok(Commands::SetMetadata),
             _ => {
                 warn!("[IMAP] Got unknown command: {}", i);
                 Err(String::from("no other commands supported"))
@@ -221,19 +271,62 @@ fn command(input: &str) -> Res<'_, Result<Commands, String>> {
 }
 
 /// Gets the input minus the tag and minus the command
+///
+/// Consecutive spaces result in empty arguments so that joining the
+/// arguments restores quoted strings as they were sent.
 #[instrument(skip(input))]
 fn arguments(input: &str) -> Res<'_, Vec<&str>> {
     debug!("parsing arguments");
     context(
         "arguments",
         many0(alt((
-            terminated(take_while1(|c: char| !c.is_whitespace()), tag(" ")),
+            terminated(take_while1(|c: char| c != ' '), tag(" ")),
             take_while1(|c: char| c != ' '),
+            map(tag(" "), |_| ""),
         ))),
     )
     .parse(input)
 }
 
+/// Literals of commands other than APPEND are kept in memory until the
+/// command is complete. The largest ones expected are metadata values.
+const MAX_LITERAL_SIZE: usize = MAX_VALUE_SIZE;
+
+/// Splits a literal announcement like `{42}` or `{42+}` off the end of a
+/// command line
+fn literal_announcement(line: &str) -> Option<(&str, LiteralSize)> {
+    let start = line.rfind('{')?;
+    match literal_size(&line[start..]).finish() {
+        Ok(("", literal)) => Some((&line[..start], literal)),
+        _ => None,
+    }
+}
+
+/// APPEND receives the literals of its messages itself, only a mailbox name
+/// sent as literal is collected by [`Data::collect_literals`].
+fn is_append_message(line: &str) -> bool {
+    match (imaptag, command).parse(line) {
+        Ok((arguments, (_, Ok(Commands::Append)))) => !arguments.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// The tag of a command line for responses outside of the command parsers
+fn command_tag(line: &str) -> &str {
+    imaptag(line).map_or("*", |(_, tag)| tag)
+}
+
+/// Turns the octets of a literal into a quoted string so the command parsers
+/// don't need to know about literals. Returns `None` if the literal isn't
+/// UTF-8 text.
+fn quote_literal(literal: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(literal).ok()?;
+    Some(format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum Response {
@@ -241,11 +334,14 @@ pub enum Response {
     Continue,
     STARTTLS(String),
     /// Client sent IDLE; server already sent `+ idling`.  The server loop
-    /// must now watch the selected mailbox and send unsolicited EXISTS updates
-    /// until the client sends `DONE`.
+    /// must now hand the connection to [`idle::Idle`] which sends unsolicited
+    /// updates for the selected mailbox until the client sends `DONE`.
     Idle {
         tag: String,
     },
+    /// COMPRESS succeeded and the tagged OK was sent. The server loop must
+    /// switch the transport to deflate before reading the next command.
+    Compress,
 }
 
 impl Data {
@@ -272,7 +368,7 @@ impl Data {
 
         let state = { self.con_state.state.clone() };
         let secure = { self.con_state.secure };
-        if let State::Authenticating(AuthenticationMethod::Plain, tag) = state {
+        if let State::Authenticating(exchange, tag) = state {
             debug!("Second auth stage");
             let command_data = CommandData {
                 tag: &tag,
@@ -280,21 +376,28 @@ impl Data {
                 command: Commands::Authenticate,
                 arguments: &[],
             };
+            // Responses which aren't text can't be base64 and fail the exchange
+            let auth_data = String::from_utf8_lossy(&line_codec::octets(&line))
+                .trim_end()
+                .to_string();
             Authenticate {
                 data: self,
-                auth_data: &line,
+                auth_data: &auth_data,
             }
-            .plain(lines, database, &command_data)
+            .respond(lines, config, database, &command_data, exchange)
             .await?;
             // We are done here
             return Ok(Response::Continue);
         } else if let State::Appending(state) = state {
             Append { data: self }
-                .append(lines, storage, &line, config, state.tag)
+                .append(lines, config, storage, &line, state.tag)
                 .await?;
             // We are done here
             return Ok(Response::Continue);
         }
+        let Some(line) = self.collect_literals(lines, line).await? else {
+            return Ok(Response::Continue);
+        };
         debug!("Starting to parse");
         let line_borrow: &str = &line;
         match Data::parse_internal(line_borrow).finish() {
@@ -333,14 +436,26 @@ impl Data {
                             tag: tag.to_string(),
                         });
                     }
+                    Commands::Compress => {
+                        if (Compress { data: self }).exec(lines, &command_data).await? {
+                            return Ok(Response::Compress);
+                        }
+                    }
                     Commands::Enable => {
                         Enable { data: self }.exec(lines, &command_data).await?;
                     }
+                    Commands::Id => {
+                        Id { data: self }.exec(lines, &command_data).await?;
+                    }
                     Commands::Capability => {
-                        Capability.exec(lines, &command_data, secure).await?;
+                        Capability
+                            .exec(lines, config, &command_data, secure)
+                            .await?;
                     }
                     Commands::Login => {
-                        Login.exec(lines, &command_data).await?;
+                        Login { data: self }
+                            .exec(lines, config, database, &command_data)
+                            .await?;
                     }
                     Commands::Logout => {
                         Logout.exec(lines, &command_data).await?;
@@ -353,7 +468,7 @@ impl Data {
                             data: self,
                             auth_data,
                         }
-                        .exec(lines, database, &command_data)
+                        .exec(lines, config, database, &command_data)
                         .await?;
                     }
                     Commands::List => {
@@ -433,7 +548,7 @@ impl Data {
                     }
                     Commands::Append => {
                         Append { data: self }
-                            .exec(lines, storage, &command_data)
+                            .exec(lines, config, storage, &command_data)
                             .await?;
                     }
                     Commands::Status => {
@@ -446,14 +561,79 @@ impl Data {
                             .exec(lines, storage, &command_data, false)
                             .await?;
                     }
+                    Commands::Sort => {
+                        Sort { data: self }
+                            .exec(lines, storage, &command_data, false)
+                            .await?;
+                    }
+                    Commands::Thread => {
+                        Thread { data: self }
+                            .exec(lines, storage, &command_data, false)
+                            .await?;
+                    }
                     Commands::Expunge => {
                         Expunge { data: self }
                             .exec(lines, storage, &command_data)
                             .await?;
                     }
+                    Commands::Notify => {
+                        Notify { data: self }
+                            .exec(lines, config, storage, &command_data)
+                            .await?;
+                    }
                     Commands::Namespace => {
                         Namespace.exec(lines, &command_data).await?;
                     }
+                    Commands::GetQuota => {
+                        GetQuota { data: self }
+                            .exec(lines, config, database, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::GetQuotaRoot => {
+                        GetQuotaRoot { data: self }
+                            .exec(lines, database, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::SetQuota => {
+                        SetQuota { data: self }
+                            .exec(lines, config, database, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::GetMetadata => {
+                        GetMetadata { data: self }
+                            .exec(lines, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::SetMetadata => {
+                        SetMetadata { data: self }
+                            .exec(lines, config, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::SetAcl => {
+                        SetAcl { data: self }
+                            .exec(lines, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::DeleteAcl => {
+                        DeleteAcl { data: self }
+                            .exec(lines, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::GetAcl => {
+                        GetAcl { data: self }
+                            .exec(lines, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::ListRights => {
+                        ListRights { data: self }
+                            .exec(lines, storage, &command_data)
+                            .await?;
+                    }
+                    Commands::MyRights => {
+                        MyRights { data: self }
+                            .exec(lines, storage, &command_data)
+                            .await?;
+                    }
                     Commands::Unselect => {
                         Unselect { data: self }.exec(lines, &command_data).await?;
                     }
@@ -487,6 +667,128 @@ impl Data {
 
         Ok(Response::Continue)
     }
+
+    /// Collects a command line which is interrupted by literals (RFC 9051
+    /// §4.3, RFC 7888).
+    ///
+    /// Returns the complete command once its last literal was received. The
+    /// literals are turned into quoted strings.
+    #[instrument(skip(self, lines, line))]
+    async fn collect_literals<S, E>(
+        &mut self,
+        lines: &mut S,
+        line: String,
+    ) -> color_eyre::eyre::Result<Option<String>>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let (mut command, mut too_big, not_text) =
+            if let State::ReceivingLiteral(state) = &mut self.con_state.state {
+                let rest = if let Some(remaining) = state.remaining {
+                    let mut received = line_codec::octets(&line);
+                    let rest = received.split_off(remaining.min(received.len()));
+                    debug!("Literal octets still expected: {}", remaining);
+                    if !state.too_big {
+                        state.literal.extend(&received);
+                    }
+                    if remaining > received.len() {
+                        state.remaining = Some(remaining - received.len());
+                        return Ok(None);
+                    }
+                    let literal = std::mem::take(&mut state.literal);
+                    if let Some(quoted) = quote_literal(&literal) {
+                        state.line.push_str(&quoted);
+                    } else {
+                        state.not_text = true;
+                        state.line.push_str("\"\"");
+                    }
+                    state.remaining = None;
+                    // A literal ending with a line break is followed by the
+                    // rest of the command on the next line.
+                    if rest.is_empty() {
+                        return Ok(None);
+                    }
+                    rest
+                } else {
+                    line_codec::octets(&line)
+                };
+                // The literal may have consumed the CR of the line break,
+                // leaving only the LF behind.
+                let rest = rest
+                    .strip_suffix(b"\r\n")
+                    .or_else(|| rest.strip_suffix(b"\n"))
+                    .unwrap_or(&rest);
+                // The rest is still searched for literals, so that their
+                // octets aren't taken for commands
+                let rest = String::from_utf8(rest.to_vec()).unwrap_or_else(|e| {
+                    state.not_text = true;
+                    String::from_utf8_lossy(e.as_bytes()).to_string()
+                });
+                let command = format!("{}{rest}", state.line);
+                let (too_big, not_text) = (state.too_big, state.not_text);
+                self.con_state.state = *state.previous_state.clone();
+                (command, too_big, not_text)
+            } else {
+                let Some(command) = line_codec::text(line) else {
+                    lines
+                        .send(String::from("* BAD Command is not UTF-8 text"))
+                        .await?;
+                    return Ok(None);
+                };
+                (command, false, false)
+            };
+
+        let announcement = literal_announcement(&command)
+            .filter(|(start, _)| !is_append_message(start))
+            .map(|(start, literal)| (start.len(), literal));
+        if let Some((start, literal)) = announcement {
+            too_big |= literal.length > MAX_LITERAL_SIZE;
+            // With a synchronizing literal we can refuse the command before
+            // the client sends it. Other literals have to be read nonetheless.
+            if too_big && !literal.continuation {
+                lines
+                    .send(format!(
+                        "{} BAD [TOOBIG] Literal exceeds {MAX_LITERAL_SIZE} octets",
+                        command_tag(&command)
+                    ))
+                    .await?;
+                return Ok(None);
+            }
+            command.truncate(start);
+            self.con_state.state = State::ReceivingLiteral(LiteralState {
+                line: command,
+                literal: Vec::new(),
+                remaining: Some(literal.length),
+                too_big,
+                not_text,
+                previous_state: Box::new(self.con_state.state.clone()),
+            });
+            if !literal.continuation {
+                lines.send(String::from("+ Ready for literal data")).await?;
+            }
+            return Ok(None);
+        }
+        if too_big {
+            lines
+                .send(format!(
+                    "{} BAD [TOOBIG] Literal exceeds {MAX_LITERAL_SIZE} octets",
+                    command_tag(&command)
+                ))
+                .await?;
+            return Ok(None);
+        }
+        if not_text {
+            lines
+                .send(format!(
+                    "{} BAD Command is not UTF-8 text",
+                    command_tag(&command)
+                ))
+                .await?;
+            return Ok(None);
+        }
+        Ok(Some(command))
+    }
 }
 
 #[cfg(test)]
@@ -494,6 +796,7 @@ impl Data {
 mod tests {
     use super::*;
     use enum_iterator::all;
+    use std::collections::BTreeMap;
 
     fn alternating_case(s: &str) -> String {
         s.chars()
@@ -544,6 +847,156 @@ mod tests {
     fn test_parsing_arguments() {
         assert_eq!(arguments("PLAIN abd=="), Ok(("", vec!["PLAIN", "abd=="])));
         assert_eq!(arguments("PLAIN"), Ok(("", vec!["PLAIN"])));
+        assert_eq!(
+            arguments("\"a  b\r\nc\" d"),
+            Ok(("", vec!["\"a", "", "b\r\nc\"", "d"]))
+        );
+    }
+
+    #[test]
+    fn test_literal_announcement() {
+        let (start, literal) = literal_announcement("a1 SEARCH SUBJECT {12}").unwrap();
+        assert_eq!(start, "a1 SEARCH SUBJECT ");
+        assert_eq!(literal.length, 12);
+        assert!(!literal.continuation);
+        let (_, literal) = literal_announcement("a1 SEARCH SUBJECT {12+}").unwrap();
+        assert!(literal.continuation);
+        assert!(literal_announcement("a1 SEARCH SUBJECT \"{12}\"").is_none());
+        assert!(is_append_message("a1 APPEND INBOX (\\Seen) "));
+        assert!(!is_append_message("a1 APPEND "));
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_literals_in_commands() {
+        use crate::servers::state::Connection;
+        use futures::{channel::mpsc, StreamExt};
+
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let database = erooster_core::backend::database::get_database(&config)
+            .await
+            .unwrap();
+
+        let mut data = Data {
+            con_state: Connection::new(true, String::new()),
+        };
+        let (mut tx, mut rx) = mpsc::unbounded::<String>();
+        for line in [
+            "a1 ID (\"name\" {11}",
+            "Thunderbird \"os\" {8+}",
+            "Linux",
+            "x \"version\" {0}",
+            ")",
+        ] {
+            let response = data
+                .parse(&mut tx, &config, &database, &storage, line.to_string())
+                .await;
+            assert_eq!(response.unwrap(), Response::Continue);
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        rx.next().await.unwrap();
+        assert_eq!(rx.next().await.unwrap(), "a1 OK ID completed");
+        assert_eq!(data.con_state.state, State::NotAuthenticated);
+        assert_eq!(
+            data.con_state.client_id,
+            BTreeMap::from([
+                (String::from("name"), String::from("Thunderbird")),
+                (String::from("os"), String::from("Linux\r\nx")),
+                (String::from("version"), String::new()),
+            ])
+        );
+
+        data.parse(
+            &mut tx,
+            &config,
+            &database,
+            &storage,
+            String::from("a2 ID ({100000}"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            format!("a2 BAD [TOOBIG] Literal exceeds {MAX_LITERAL_SIZE} octets")
+        );
+        assert_eq!(data.con_state.state, State::NotAuthenticated);
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_literal_ending_inside_line_break() {
+        use crate::servers::state::Connection;
+        use futures::{channel::mpsc, StreamExt};
+
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let database = erooster_core::backend::database::get_database(&config)
+            .await
+            .unwrap();
+
+        let mut data = Data {
+            con_state: Connection::new(true, String::new()),
+        };
+        let (mut tx, mut rx) = mpsc::unbounded::<String>();
+        // The literal takes the CR of the line break, only the LF is left.
+        for line in ["a1 LOGIN {6}", "abcde"] {
+            let response = data
+                .parse(&mut tx, &config, &database, &storage, line.to_string())
+                .await;
+            assert_eq!(response.unwrap(), Response::Continue);
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        // Only the user name was sent
+        assert_eq!(rx.next().await.unwrap(), "a1 BAD Invalid arguments");
+        assert_eq!(data.con_state.state, State::NotAuthenticated);
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_login_with_literals() {
+        use crate::servers::state::Connection;
+        use erooster_core::backend::database::Database;
+        use futures::{channel::mpsc, StreamExt};
+        use secrecy::SecretString;
+
+        let (config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        database.add_user("fred@localhost").await.unwrap();
+        database
+            .change_password("fred@localhost", SecretString::new(Box::from("sec\"ret")))
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = mpsc::unbounded::<String>();
+
+        // A literal which isn't UTF-8 text refuses the command
+        let mut data = Data {
+            con_state: Connection::new(true, String::new()),
+        };
+        let binary = erooster_core::line_codec::raw(b"\xff\xfe\r\n");
+        for line in ["a1 LOGIN fred@localhost {2}", &binary] {
+            data.parse(&mut tx, &config, &database, &storage, line.to_string())
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        assert_eq!(rx.next().await.unwrap(), "a1 BAD Command is not UTF-8 text");
+        assert_eq!(data.con_state.state, State::NotAuthenticated);
+
+        // Both arguments as literals reach the authentication
+        for line in ["a2 LOGIN {14}", "fred@localhost {7+}", "sec\"ret"] {
+            data.parse(&mut tx, &config, &database, &storage, line.to_string())
+                .await
+                .unwrap();
+        }
+        assert_eq!(rx.next().await.unwrap(), "+ Ready for literal data");
+        assert_eq!(rx.next().await.unwrap(), "a2 OK Success (tls protection)");
+        assert_eq!(data.con_state.state, State::Authenticated);
+        assert_eq!(data.con_state.username.as_deref(), Some("fred@localhost"));
     }
 
     #[test]
@@ -620,7 +1073,7 @@ mod tests {
             .unwrap();
 
         let mut data = Data {
-            con_state: Connection::new(true),
+            con_state: Connection::new(true, String::new()),
         };
         let (mut tx, mut rx) = mpsc::unbounded::<String>();
         let response = data
@@ -653,6 +1106,11 @@ mod tests {
                 secure: true,
                 username: Some("testuser".to_string()),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let (mut tx, mut rx) = mpsc::unbounded::<String>();
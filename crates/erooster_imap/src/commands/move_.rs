@@ -11,14 +11,19 @@
 
 use crate::{
     commands::{
+        acl::require_rights,
         copy::{maildir_flags_to_imap, uid_set_string},
         parsers::parse_selected_range,
+        search::resolve_saved_result,
         select::get_or_create_uidvalidity,
         CommandData, Data,
     },
     servers::state::{Access, State},
 };
-use erooster_core::backend::storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName, Rights},
+    storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -83,15 +88,34 @@ impl Move<'_> {
             .clone()
             .context("Username missing in internal State")?;
 
+        // Moving out of a mailbox removes the messages there
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &MailboxName::resolve(folder, &username),
+            Rights::DELETE_MESSAGES.union(Rights::EXPUNGE),
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
+
         let src_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
 
         let mut mails = storage
-            .list_all(format!("{username}/{folder}"), &src_path)
+            .list_all(mailbox_id(folder, &username), &src_path)
             .await;
         mails.sort_by_key(MaildirMailEntry::uid);
 
-        let seq_arg = command_data.arguments[offset];
-        let Ok((_, ranges)) = parse_selected_range(seq_arg).finish() else {
+        let seq_arg = resolve_saved_result(
+            command_data.arguments[offset],
+            &self.data.con_state.saved_result,
+            &mails,
+            is_uid,
+        );
+        let Ok((_, ranges)) = parse_selected_range(&seq_arg).finish() else {
             lines
                 .send(format!(
                     "{} BAD [PARSE] Invalid sequence set: {seq_arg}",
@@ -102,6 +126,19 @@ impl Move<'_> {
         };
 
         let dest_raw = command_data.arguments[1 + offset].replace('"', "");
+        let destination = MailboxName::resolve(&dest_raw, &username);
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &destination,
+            Rights::INSERT,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
         let dest_path = storage.to_ondisk_path(dest_raw.clone(), username.clone())?;
 
         if !dest_path.exists() {
@@ -114,13 +151,13 @@ impl Move<'_> {
             return Ok(());
         }
 
-        let dest_db_name = format!("{username}/{dest_raw}");
+        let dest_db_name = destination.id();
 
         // Identify matching messages (collect paths for later deletion).
         let mut src_uids: Vec<u32> = Vec::new();
         let mut dest_uids: Vec<u32> = Vec::new();
         let mut src_indices: Vec<u32> = Vec::new(); // 1-based sequence numbers
-        let mut paths_to_remove = Vec::new();
+        let mut ids_to_remove = Vec::new();
 
         for (index, mail) in mails.iter_mut().enumerate() {
             #[allow(clippy::cast_possible_truncation)]
@@ -137,15 +174,17 @@ impl Move<'_> {
             }
 
             let bytes = fs::read(mail.path()).await?;
-            let imap_flags = maildir_flags_to_imap(mail.flags());
-            storage
+            let mut imap_flags = maildir_flags_to_imap(mail.flags());
+            imap_flags.extend_from_slice(mail.keywords());
+            let copy_id = storage
                 .store_cur_with_flags(dest_db_name.clone(), &dest_path, &bytes, imap_flags)
                 .await?;
+            storage.inherit_object_ids(mail.id(), &copy_id).await?;
 
             src_uids.push(mail.uid());
             dest_uids.push(storage.get_uid_for_folder(&dest_db_name).await?);
             src_indices.push(seq);
-            paths_to_remove.push(mail.path().clone());
+            ids_to_remove.push(mail.id().to_string());
         }
 
         if src_uids.is_empty() {
@@ -171,11 +210,18 @@ impl Move<'_> {
 
         // Delete source messages and send EXPUNGE.  Sequence numbers shift
         // down after each removal, so we use the enumerate index as the offset.
-        for (offset, (path, seq)) in paths_to_remove.iter().zip(src_indices.iter()).enumerate() {
-            fs::remove_file(path).await?;
-            #[allow(clippy::cast_possible_truncation)]
-            let adjusted_seq = seq - offset as u32;
-            lines.feed(format!("* {adjusted_seq} EXPUNGE")).await?;
+        let qresync = self.data.con_state.qresync_enabled();
+        for (offset, (id, seq)) in ids_to_remove.iter().zip(src_indices.iter()).enumerate() {
+            storage.expunge(&src_path, id).await?;
+            if !qresync {
+                #[allow(clippy::cast_possible_truncation)]
+                let adjusted_seq = seq - offset as u32;
+                lines.feed(format!("* {adjusted_seq} EXPUNGE")).await?;
+            }
+        }
+        // RFC 7162 §3.2.10: QRESYNC clients get VANISHED instead of EXPUNGE.
+        if qresync {
+            lines.feed(format!("* VANISHED {src_uid_str}")).await?;
         }
 
         lines.flush().await?;
@@ -193,6 +239,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Connection, State};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[cfg_attr(coverage_nightly, coverage(off))]
@@ -204,6 +251,11 @@ mod tests {
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -236,6 +288,11 @@ mod tests {
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
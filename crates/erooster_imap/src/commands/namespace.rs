@@ -5,6 +5,7 @@
 //! NAMESPACE command (RFC 2342 / RFC 9051 §6.3.10) — mandatory in `IMAP4rev2`.
 
 use crate::commands::CommandData;
+use erooster_core::backend::acl::{OTHER_USERS_NAMESPACE, SHARED_NAMESPACE};
 use {
     color_eyre,
     futures::{Sink, SinkExt},
@@ -24,10 +25,13 @@ impl Namespace {
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
-        // Personal namespace with empty prefix and "/" delimiter.
-        // No other-users or shared namespaces for a single-server setup.
+        // Personal namespace with empty prefix, followed by the mailboxes of
+        // other users and the shared mailboxes (RFC 4314 ACLs), all with "/"
+        // as delimiter.
         lines
-            .feed(String::from("* NAMESPACE ((\"\" \"/\")) NIL NIL"))
+            .feed(format!(
+                "* NAMESPACE ((\"\" \"/\")) ((\"{OTHER_USERS_NAMESPACE}\" \"/\")) ((\"{SHARED_NAMESPACE}\" \"/\"))"
+            ))
             .await?;
         lines
             .feed(format!("{} OK NAMESPACE completed", command_data.tag))
@@ -58,7 +62,9 @@ mod tests {
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
             rx.next().await,
-            Some(String::from("* NAMESPACE ((\"\" \"/\")) NIL NIL"))
+            Some(String::from(
+                "* NAMESPACE ((\"\" \"/\")) ((\"Users/\" \"/\")) ((\"Shared/\" \"/\"))"
+            ))
         );
         assert_eq!(
             rx.next().await,
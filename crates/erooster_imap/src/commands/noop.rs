@@ -30,7 +30,6 @@ impl Noop<'_> {
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
         if let State::Selected(folder, _) = &self.data.con_state.state {
-            let folder = folder.replace('/', ".");
             let mailbox_path = storage.to_ondisk_path(
                 folder.clone(),
                 self.data
@@ -55,6 +54,7 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Access, Connection, State};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[allow(clippy::unwrap_used)]
@@ -67,6 +67,11 @@ mod tests {
                 secure: true,
                 username: None,
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let caps = Noop { data: state };
@@ -94,6 +99,11 @@ mod tests {
                 secure: true,
                 username: Some("MTRNord".to_string()),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let caps = Noop { data: state };
@@ -0,0 +1,572 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    commands::{
+        idle::{fetch_flags_response, mailbox_uids},
+        list::personal_mailboxes,
+        parsers::{
+            notify_parameters, NotifyEvent, NotifyEventGroup, NotifyFilter, NotifyParameters,
+        },
+        status::status_items,
+        CommandData, Data,
+    },
+    servers::state::State,
+};
+use erooster_core::{
+    backend::{
+        acl::mailbox_id,
+        events::MailboxEvent,
+        storage::{MailStorage, Storage},
+    },
+    config::Config,
+};
+use {
+    color_eyre::{self, eyre::ContextCompat},
+    futures::{Sink, SinkExt},
+    nom::Finish,
+    tokio::sync::broadcast::{
+        error::{RecvError, TryRecvError},
+        Receiver,
+    },
+    tracing::{instrument, warn},
+};
+
+/// The events we are able to deliver, as advertised in `BADEVENT`
+const SUPPORTED_EVENTS: &str = "MessageNew MessageExpunge FlagChange MailboxName";
+
+/// The NOTIFY command (RFC 5465)
+pub struct Notify<'a> {
+    pub data: &'a mut Data,
+}
+
+impl Notify<'_> {
+    #[instrument(skip(self, lines, config, storage, command_data))]
+    pub async fn exec<S, E>(
+        &mut self,
+        lines: &mut S,
+        config: &Config,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        if !matches!(
+            self.data.con_state.state,
+            State::Authenticated | State::Selected(..)
+        ) {
+            lines
+                .send(format!("{} BAD Not Authenticated", command_data.tag))
+                .await?;
+            return Ok(());
+        }
+
+        let arguments = command_data.arguments.join(" ");
+        let Ok(("", parameters)) = notify_parameters(&arguments).finish() else {
+            lines
+                .send(format!(
+                    "{} BAD [CLIENTBUG] Unable to parse NOTIFY arguments",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+
+        let NotifyParameters::Set { status, groups } = parameters else {
+            self.data.con_state.notify = None;
+            lines
+                .send(format!("{} OK NOTIFY completed", command_data.tag))
+                .await?;
+            return Ok(());
+        };
+
+        if groups
+            .iter()
+            .flat_map(|group| &group.events)
+            .any(|event| !is_supported(event))
+        {
+            lines
+                .send(format!(
+                    "{} NO [BADEVENT ({SUPPORTED_EVENTS})] Unsupported NOTIFY event",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        // RFC 5465 §5: MessageNew and MessageExpunge only come as a pair and
+        // FlagChange requires both of them.
+        if groups.iter().any(|group| {
+            let new = group.events.contains(&NotifyEvent::MessageNew);
+            let expunge = group.events.contains(&NotifyEvent::MessageExpunge);
+            let flags = group.events.contains(&NotifyEvent::FlagChange);
+            new != expunge || (flags && !new)
+        }) {
+            lines
+                .send(format!(
+                    "{} BAD [CLIENTBUG] MessageNew, MessageExpunge and FlagChange must be requested together",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+
+        if status {
+            let username = self
+                .data
+                .con_state
+                .username
+                .clone()
+                .context("Username missing in internal State")?;
+            let selected = selected_folder(self.data);
+            for name in personal_mailboxes(config, storage, &username) {
+                if selected.as_deref() == Some(name.as_str()) {
+                    continue;
+                }
+                if wanted(&groups, storage, &username, &name, &NotifyEvent::MessageNew).await {
+                    let items = status_items(
+                        storage,
+                        &username,
+                        &name,
+                        &["MESSAGES", "UIDNEXT", "UIDVALIDITY", "UNSEEN"],
+                    )
+                    .await?;
+                    lines
+                        .feed(format!("* STATUS \"{name}\" ({})", items.join(" ")))
+                        .await?;
+                }
+            }
+        }
+
+        self.data.con_state.notify = Some(groups);
+        lines
+            .feed(format!("{} OK NOTIFY completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+/// Delivers mailbox events to a connection that enabled NOTIFY while it is
+/// not running a command.
+#[derive(Debug, Default)]
+pub struct Notifications {
+    receiver: Option<Receiver<MailboxEvent>>,
+}
+
+impl Notifications {
+    /// Follows NOTIFY SET and NOTIFY NONE of the connection
+    pub fn update(&mut self, data: &Data, storage: &Storage) {
+        if data.con_state.notify.is_none() {
+            self.receiver = None;
+        } else if self.receiver.is_none() {
+            self.receiver = Some(storage.events().subscribe());
+        }
+    }
+
+    /// Waits for the next event, forever if NOTIFY is not enabled
+    pub async fn next(&mut self) -> MailboxEvent {
+        if let Some(receiver) = &mut self.receiver {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("[IMAP] NOTIFY: missed {skipped} mailbox events");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+            self.receiver = None;
+        }
+        std::future::pending().await
+    }
+
+    /// Sends the events that were published while a command was running.
+    ///
+    /// Expunges in the selected mailbox are skipped as the command already
+    /// reported those itself and a second EXPUNGE would shift the sequence
+    /// numbers of the client.
+    pub async fn flush_pending<S, E>(
+        &mut self,
+        data: &Data,
+        lines: &mut S,
+        storage: &Storage,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let Some(receiver) = &mut self.receiver else {
+            return Ok(());
+        };
+        let selected = selected_mailbox_id(data);
+        loop {
+            match receiver.try_recv() {
+                Ok(MailboxEvent::Expunged { ref mailbox, .. })
+                    if selected.as_deref() == Some(mailbox.as_str()) => {}
+                Ok(event) => send_event(data, lines, storage, event).await?,
+                Err(TryRecvError::Lagged(skipped)) => {
+                    warn!("[IMAP] NOTIFY: missed {skipped} mailbox events");
+                }
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends the untagged response for an event if the client asked for it with NOTIFY
+#[instrument(skip(data, lines, storage))]
+pub async fn send_event<S, E>(
+    data: &Data,
+    lines: &mut S,
+    storage: &Storage,
+    event: MailboxEvent,
+) -> color_eyre::eyre::Result<()>
+where
+    E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+    S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+{
+    let (Some(groups), Some(username)) = (&data.con_state.notify, &data.con_state.username)
+    else {
+        return Ok(());
+    };
+    let kind = event_kind(&event);
+    let selected = kind != NotifyEvent::MailboxName
+        && selected_mailbox_id(data).as_deref() == Some(event.mailbox());
+    // Besides the selected one only personal mailboxes can be watched
+    let prefix = format!("{username}/");
+    let name = match event.mailbox().strip_prefix(&prefix) {
+        Some(name) => name.to_string(),
+        None if selected => String::new(),
+        None => return Ok(()),
+    };
+
+    let response = if selected {
+        let delayed_expunge = kind == NotifyEvent::MessageExpunge;
+        if !groups.iter().any(|group| {
+            group.events.contains(&kind)
+                && (group.filter == NotifyFilter::Selected
+                    || (group.filter == NotifyFilter::SelectedDelayed && !delayed_expunge))
+        }) {
+            return Ok(());
+        }
+        selected_response(data, storage, username, event).await?
+    } else {
+        if !wanted(groups, storage, username, &name, &kind).await {
+            return Ok(());
+        }
+        match event {
+            MailboxEvent::Exists { .. } | MailboxEvent::Expunged { .. } => {
+                let items = status_items(
+                    storage,
+                    username,
+                    &name,
+                    &["MESSAGES", "UIDNEXT", "UIDVALIDITY", "UNSEEN"],
+                )
+                .await?;
+                Some(format!("* STATUS \"{name}\" ({})", items.join(" ")))
+            }
+            MailboxEvent::FlagsChanged { .. } => {
+                let requested: &[&str] = if data.con_state.condstore_enabled() {
+                    &["UIDVALIDITY", "UNSEEN", "HIGHESTMODSEQ"]
+                } else {
+                    &["UIDVALIDITY", "UNSEEN"]
+                };
+                let items = status_items(storage, username, &name, requested).await?;
+                Some(format!("* STATUS \"{name}\" ({})", items.join(" ")))
+            }
+            MailboxEvent::Created { .. } => Some(format!("* LIST () \".\" \"{name}\"")),
+            MailboxEvent::Deleted { .. } => {
+                Some(format!("* LIST (\\NonExistent) \".\" \"{name}\""))
+            }
+            MailboxEvent::Renamed { ref old, .. } => {
+                let old = old.strip_prefix(&prefix).unwrap_or(old);
+                Some(format!(
+                    "* LIST () \".\" \"{name}\" (\"OLDNAME\" (\"{old}\"))"
+                ))
+            }
+        }
+    };
+
+    if let Some(response) = response {
+        lines.send(response).await?;
+    }
+    Ok(())
+}
+
+/// Builds the response for a change in the selected mailbox outside of IDLE.
+///
+/// Sequence numbers are derived from the current mailbox content as we do not
+/// track the view of the client between commands.
+async fn selected_response(
+    data: &Data,
+    storage: &Storage,
+    username: &str,
+    event: MailboxEvent,
+) -> color_eyre::eyre::Result<Option<String>> {
+    let State::Selected(folder, _) = &data.con_state.state else {
+        return Ok(None);
+    };
+    let path = storage.to_ondisk_path(folder.clone(), username.to_string())?;
+    let uids = mailbox_uids(storage, event.mailbox(), &path).await;
+    Ok(match event {
+        MailboxEvent::Exists { .. } => Some(format!("* {} EXISTS", uids.len())),
+        MailboxEvent::Expunged { uid, .. } => {
+            if data.con_state.qresync_enabled() {
+                Some(format!("* VANISHED {uid}"))
+            } else {
+                let sequence = uids.iter().filter(|known| **known < uid).count() + 1;
+                Some(format!("* {sequence} EXPUNGE"))
+            }
+        }
+        MailboxEvent::FlagsChanged {
+            uid, modseq, flags, ..
+        } => uids
+            .iter()
+            .position(|known| *known == uid)
+            .map(|index| fetch_flags_response(data, index + 1, uid, modseq, &flags)),
+        MailboxEvent::Created { .. }
+        | MailboxEvent::Deleted { .. }
+        | MailboxEvent::Renamed { .. } => None,
+    })
+}
+
+const fn is_supported(event: &NotifyEvent) -> bool {
+    matches!(
+        event,
+        NotifyEvent::MessageNew
+            | NotifyEvent::MessageExpunge
+            | NotifyEvent::FlagChange
+            | NotifyEvent::MailboxName
+    )
+}
+
+const fn event_kind(event: &MailboxEvent) -> NotifyEvent {
+    match event {
+        MailboxEvent::Exists { .. } => NotifyEvent::MessageNew,
+        MailboxEvent::Expunged { .. } => NotifyEvent::MessageExpunge,
+        MailboxEvent::FlagsChanged { .. } => NotifyEvent::FlagChange,
+        MailboxEvent::Created { .. }
+        | MailboxEvent::Deleted { .. }
+        | MailboxEvent::Renamed { .. } => NotifyEvent::MailboxName,
+    }
+}
+
+/// The selected folder in the form used for the mailbox key
+fn selected_folder(data: &Data) -> Option<String> {
+    if let State::Selected(folder, _) = &data.con_state.state {
+        Some(folder.replace('/', "."))
+    } else {
+        None
+    }
+}
+
+fn selected_mailbox_id(data: &Data) -> Option<String> {
+    let username = data.con_state.username.as_ref()?;
+    if let State::Selected(folder, _) = &data.con_state.state {
+        Some(mailbox_id(folder, username))
+    } else {
+        None
+    }
+}
+
+/// Whether any non-selected event group asks for `kind` in the mailbox `name`
+async fn wanted(
+    groups: &[NotifyEventGroup],
+    storage: &Storage,
+    username: &str,
+    name: &str,
+    kind: &NotifyEvent,
+) -> bool {
+    for group in groups {
+        if group.events.contains(kind) && filter_matches(&group.filter, storage, username, name).await
+        {
+            return true;
+        }
+    }
+    false
+}
+
+async fn filter_matches(
+    filter: &NotifyFilter,
+    storage: &Storage,
+    username: &str,
+    name: &str,
+) -> bool {
+    match filter {
+        NotifyFilter::Selected | NotifyFilter::SelectedDelayed => false,
+        // We don't deliver into any other mailbox than INBOX
+        NotifyFilter::Inboxes => name == "INBOX",
+        NotifyFilter::Personal => true,
+        NotifyFilter::Subscribed => {
+            let Ok(path) = storage.to_ondisk_path(name.to_string(), username.to_string()) else {
+                return false;
+            };
+            storage
+                .get_flags(&path)
+                .await
+                .is_ok_and(|flags| flags.iter().any(|flag| flag == "\\Subscribed"))
+        }
+        NotifyFilter::Subtree(roots) => roots.iter().any(|root| {
+            let root = root.replace('/', ".");
+            name == root || name.starts_with(&format!("{root}."))
+        }),
+        NotifyFilter::Mailboxes(names) => names.iter().any(|n| n.replace('/', ".") == name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Commands;
+    use crate::servers::state::{Access, Connection};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    const MSG: &[u8] = b"From: a@localhost\r\nTo: b@localhost\r\nSubject: test\r\n\r\nHello\r\n";
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_notify_set_and_status_updates() {
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Selected("INBOX".to_string(), Access::ReadWrite),
+                secure: true,
+                username: Some("test_notify_user".to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let sent_path = storage
+            .to_ondisk_path("Sent".to_string(), "test_notify_user".to_string())
+            .unwrap();
+        storage.create_dirs(&sent_path).unwrap();
+
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Notify,
+            arguments: &[
+                "SET",
+                "(selected",
+                "(MessageNew",
+                "MessageExpunge))",
+                "(personal",
+                "(MessageNew",
+                "MessageExpunge",
+                "MailboxName))",
+            ],
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = Notify { data: &mut data }
+            .exec(&mut tx, &config, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(rx.next().await, Some(String::from("a1 OK NOTIFY completed")));
+        assert_eq!(data.con_state.notify.as_ref().map(Vec::len), Some(2));
+
+        let mut notifications = Notifications::default();
+        notifications.update(&data, &storage);
+        storage
+            .store_new("test_notify_user/Sent".to_string(), &sent_path, MSG, None)
+            .await
+            .unwrap();
+        let event = notifications.next().await;
+        send_event(&data, &mut tx, &storage, event).await.unwrap();
+        let status = rx.next().await.unwrap();
+        assert!(
+            status.starts_with("* STATUS \"Sent\" (MESSAGES 1 UNSEEN 1 UIDNEXT 2 UIDVALIDITY "),
+            "{status}"
+        );
+
+        storage.events().publish(MailboxEvent::Created {
+            mailbox: String::from("test_notify_user/Lists"),
+        });
+        storage.events().publish(MailboxEvent::Created {
+            mailbox: String::from("someone_else/Lists"),
+        });
+        notifications.flush_pending(&data, &mut tx, &storage).await.unwrap();
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("* LIST () \".\" \"Lists\""))
+        );
+
+        let inbox_path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_notify_user".to_string())
+            .unwrap();
+        storage.create_dirs(&inbox_path).unwrap();
+        storage
+            .store_new("test_notify_user/INBOX".to_string(), &inbox_path, MSG, None)
+            .await
+            .unwrap();
+        let event = notifications.next().await;
+        send_event(&data, &mut tx, &storage, event).await.unwrap();
+        assert_eq!(rx.next().await, Some(String::from("* 1 EXISTS")));
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_notify_rejects_unsupported_events() {
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some("test_notify_bad_user".to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let (mut tx, mut rx) = mpsc::unbounded();
+
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::Notify,
+            arguments: &["SET", "(personal", "(AnnotationChange))"],
+        };
+        let res = Notify { data: &mut data }
+            .exec(&mut tx, &config, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "a1 NO [BADEVENT (MessageNew MessageExpunge FlagChange MailboxName)] Unsupported NOTIFY event"
+            ))
+        );
+
+        let cmd_data = CommandData {
+            tag: "a2",
+            command: Commands::Notify,
+            arguments: &["SET", "(personal", "(MessageNew))"],
+        };
+        let res = Notify { data: &mut data }
+            .exec(&mut tx, &config, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "a2 BAD [CLIENTBUG] MessageNew, MessageExpunge and FlagChange must be requested together"
+            ))
+        );
+        assert!(data.con_state.notify.is_none());
+    }
+}
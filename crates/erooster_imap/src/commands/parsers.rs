@@ -7,9 +7,9 @@ use std::ops::Bound;
 use {
     nom::{
         branch::alt,
-        bytes::complete::{tag_no_case, take_while1},
-        character::complete::{char, digit1, space1},
-        combinator::{map, opt},
+        bytes::complete::{tag_no_case, take_while, take_while1, take_while_m_n},
+        character::complete::{char, digit1, none_of, one_of, space1},
+        combinator::{map, map_res, opt},
         error::context,
         multi::{many0, separated_list0, separated_list1},
         sequence::{delimited, pair, preceded, separated_pair, terminated},
@@ -74,6 +74,21 @@ fn section(input: &str) -> Res<'_, Option<SectionText>> {
     .parse(input)
 }
 
+/// The part number of a BINARY section like `[1.2]`, empty for the whole
+/// message (RFC 3516 §4.2)
+#[instrument(skip(input))]
+fn section_binary(input: &str) -> Res<'_, Vec<u32>> {
+    context(
+        "section_binary",
+        delimited(
+            char('['),
+            separated_list0(char('.'), map_res(digit1, str::parse::<u32>)),
+            char(']'),
+        ),
+    )
+    .parse(input)
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub enum FetchAttributes {
@@ -87,9 +102,18 @@ pub enum FetchAttributes {
     BodyStructure,
     BodySection(Option<SectionText>, Option<(u64, u64)>),
     BodyPeek(Option<SectionText>, Option<(u64, u64)>),
-    Binary(Option<SectionText>, Option<(u64, u64)>),
-    BinaryPeek(Option<SectionText>, Option<(u64, u64)>),
-    BinarySize(Option<SectionText>),
+    /// RFC 3516 §4.2
+    Binary(Vec<u32>, Option<(u64, u64)>),
+    BinaryPeek(Vec<u32>, Option<(u64, u64)>),
+    BinarySize(Vec<u32>),
+    /// RFC 7162 §3.1.4.1
+    Modseq,
+    /// RFC 8474 §5.1
+    EmailId,
+    /// RFC 8474 §5.2
+    ThreadId,
+    /// RFC 8514 §4.2
+    SaveDate,
 }
 
 #[allow(clippy::too_many_lines)]
@@ -108,6 +132,10 @@ fn fetch_attributes(input: &str) -> Res<'_, FetchAttributes> {
                 FetchAttributes::RFC822Header
             }),
             map(tag_no_case("UID"), |_| FetchAttributes::Uid),
+            map(tag_no_case("MODSEQ"), |_| FetchAttributes::Modseq),
+            map(tag_no_case("EMAILID"), |_| FetchAttributes::EmailId),
+            map(tag_no_case("THREADID"), |_| FetchAttributes::ThreadId),
+            map(tag_no_case("SAVEDATE"), |_| FetchAttributes::SaveDate),
             map(
                 (
                     tag_no_case("BODY.PEEK"),
@@ -165,7 +193,7 @@ fn fetch_attributes(input: &str) -> Res<'_, FetchAttributes> {
             map(
                 (
                     tag_no_case("BINARY.PEEK"),
-                    section,
+                    section_binary,
                     opt(space1),
                     opt(delimited(
                         char('<'),
@@ -191,7 +219,7 @@ fn fetch_attributes(input: &str) -> Res<'_, FetchAttributes> {
                     )
                 },
             ),
-            map((tag_no_case("BINARY.SIZE"), section), |(_, x)| {
+            map((tag_no_case("BINARY.SIZE"), section_binary), |(_, x)| {
                 FetchAttributes::BinarySize(x)
             }),
             map(
@@ -224,7 +252,7 @@ fn fetch_attributes(input: &str) -> Res<'_, FetchAttributes> {
             map(
                 (
                     tag_no_case("BINARY"),
-                    section,
+                    section_binary,
                     opt(space1),
                     opt(delimited(
                         char('<'),
@@ -253,10 +281,10 @@ fn fetch_attributes(input: &str) -> Res<'_, FetchAttributes> {
                 |_| FetchAttributes::BodyStructure,
             ),
             map(tag_no_case("BODY.PEEK"), |_| {
-                FetchAttributes::BinaryPeek(None, None)
+                FetchAttributes::BodyPeek(None, None)
             }),
             map(tag_no_case("RFC822.PEEK"), |_| {
-                FetchAttributes::BinaryPeek(None, None)
+                FetchAttributes::BodyPeek(None, None)
             }),
             map(tag_no_case("BODY"), |_| {
                 FetchAttributes::BodySection(None, None)
@@ -306,6 +334,766 @@ pub fn fetch_arguments(input: &str) -> Res<'_, FetchArguments> {
     context("fetch_arguments", inner_fetch_arguments).parse(input)
 }
 
+/// FETCH modifiers from RFC 7162 §3.1.4 (CHANGEDSINCE) and §3.2.6 (VANISHED)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchModifiers {
+    pub changed_since: Option<u64>,
+    pub vanished: bool,
+}
+
+enum FetchModifier {
+    ChangedSince(u64),
+    Vanished,
+}
+
+#[instrument(skip(input))]
+pub fn fetch_modifiers(input: &str) -> Res<'_, FetchModifiers> {
+    context(
+        "fetch_modifiers",
+        map(
+            delimited(
+                char('('),
+                separated_list1(
+                    space1,
+                    alt((
+                        map(
+                            separated_pair(tag_no_case("CHANGEDSINCE"), space1, mod_sequence),
+                            |(_, modseq)| FetchModifier::ChangedSince(modseq),
+                        ),
+                        map(tag_no_case("VANISHED"), |_| FetchModifier::Vanished),
+                    )),
+                ),
+                char(')'),
+            ),
+            |modifiers| {
+                let mut result = FetchModifiers::default();
+                for modifier in modifiers {
+                    match modifier {
+                        FetchModifier::ChangedSince(modseq) => result.changed_since = Some(modseq),
+                        FetchModifier::Vanished => result.vanished = true,
+                    }
+                }
+                result
+            },
+        ),
+    )
+    .parse(input)
+}
+
+/// Parses the `(UNCHANGEDSINCE n)` STORE modifier from RFC 7162 §3.1.3
+#[instrument(skip(input))]
+pub fn store_modifiers(input: &str) -> Res<'_, u64> {
+    context(
+        "store_modifiers",
+        delimited(
+            char('('),
+            preceded(pair(tag_no_case("UNCHANGEDSINCE"), space1), mod_sequence),
+            char(')'),
+        ),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn mod_sequence(input: &str) -> Res<'_, u64> {
+    context(
+        "mod_sequence",
+        map(digit1, |x: &str| {
+            x.parse::<u64>().expect("mod-sequence is a number")
+        }),
+    )
+    .parse(input)
+}
+
+/// QRESYNC parameters of SELECT/EXAMINE (RFC 7162 §3.2.5)
+#[derive(Debug, Clone)]
+pub struct QResyncParameters {
+    pub uidvalidity: u32,
+    pub modseq: u64,
+    pub known_uids: Option<Vec<Range>>,
+}
+
+/// Optional SELECT/EXAMINE parameters
+#[derive(Debug, Clone, Default)]
+pub struct SelectParameters {
+    pub condstore: bool,
+    pub qresync: Option<QResyncParameters>,
+}
+
+enum SelectParameter {
+    Condstore,
+    QResync(QResyncParameters),
+}
+
+#[instrument(skip(input))]
+fn qresync_parameters(input: &str) -> Res<'_, QResyncParameters> {
+    context(
+        "qresync_parameters",
+        map(
+            preceded(
+                pair(tag_no_case("QRESYNC"), space1),
+                delimited(
+                    char('('),
+                    (
+                        map(digit1, |x: &str| {
+                            x.parse::<u32>().expect("uidvalidity is a number")
+                        }),
+                        preceded(space1, mod_sequence),
+                        opt(preceded(space1, parse_selected_range)),
+                        // The sequence match data is only an optimisation hint; we
+                        // always resync the full known-uids set instead.
+                        opt(preceded(
+                            space1,
+                            delimited(char('('), take_while1(|c: char| c != ')'), char(')')),
+                        )),
+                    ),
+                    char(')'),
+                ),
+            ),
+            |(uidvalidity, modseq, known_uids, _)| QResyncParameters {
+                uidvalidity,
+                modseq,
+                known_uids,
+            },
+        ),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+pub fn select_parameters(input: &str) -> Res<'_, SelectParameters> {
+    context(
+        "select_parameters",
+        map(
+            delimited(
+                char('('),
+                separated_list0(
+                    space1,
+                    alt((
+                        map(tag_no_case("CONDSTORE"), |_| SelectParameter::Condstore),
+                        map(qresync_parameters, SelectParameter::QResync),
+                    )),
+                ),
+                char(')'),
+            ),
+            |parameters| {
+                let mut result = SelectParameters::default();
+                for parameter in parameters {
+                    match parameter {
+                        SelectParameter::Condstore => result.condstore = true,
+                        SelectParameter::QResync(qresync) => result.qresync = Some(qresync),
+                    }
+                }
+                result
+            },
+        ),
+    )
+    .parse(input)
+}
+
+/// Mailbox filters of NOTIFY (RFC 5465 §6)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyFilter {
+    Selected,
+    SelectedDelayed,
+    Inboxes,
+    Personal,
+    Subscribed,
+    Subtree(Vec<String>),
+    Mailboxes(Vec<String>),
+}
+
+/// Events of NOTIFY (RFC 5465 §5)
+///
+/// Unknown events are kept so the command can answer with `BADEVENT` instead
+/// of a parse error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyEvent {
+    MessageNew,
+    MessageExpunge,
+    FlagChange,
+    AnnotationChange,
+    MailboxName,
+    SubscriptionChange,
+    MailboxMetadataChange,
+    ServerMetadataChange,
+    Other(String),
+}
+
+/// A filter together with the events the client wants for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyEventGroup {
+    pub filter: NotifyFilter,
+    pub events: Vec<NotifyEvent>,
+}
+
+/// Arguments of the NOTIFY command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifyParameters {
+    None,
+    Set {
+        status: bool,
+        groups: Vec<NotifyEventGroup>,
+    },
+}
+
+#[instrument(skip(input))]
+fn mailbox_name(input: &str) -> Res<'_, String> {
+    context(
+        "mailbox_name",
+        map(
+            alt((
+                delimited(char('"'), take_while1(|c: char| c != '"'), char('"')),
+                take_while1(|c: char| c != '(' && c != ')' && !c.is_whitespace()),
+            )),
+            |name: &str| {
+                if name.eq_ignore_ascii_case("INBOX") {
+                    String::from("INBOX")
+                } else {
+                    name.to_string()
+                }
+            },
+        ),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn one_or_more_mailbox(input: &str) -> Res<'_, Vec<String>> {
+    context(
+        "one_or_more_mailbox",
+        alt((
+            delimited(
+                char('('),
+                separated_list1(space1, mailbox_name),
+                char(')'),
+            ),
+            map(mailbox_name, |name| vec![name]),
+        )),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn notify_filter(input: &str) -> Res<'_, NotifyFilter> {
+    context(
+        "notify_filter",
+        alt((
+            map(tag_no_case("SELECTED-DELAYED"), |_| {
+                NotifyFilter::SelectedDelayed
+            }),
+            map(tag_no_case("SELECTED"), |_| NotifyFilter::Selected),
+            map(tag_no_case("INBOXES"), |_| NotifyFilter::Inboxes),
+            map(tag_no_case("PERSONAL"), |_| NotifyFilter::Personal),
+            map(tag_no_case("SUBSCRIBED"), |_| NotifyFilter::Subscribed),
+            map(
+                preceded(pair(tag_no_case("SUBTREE"), space1), one_or_more_mailbox),
+                NotifyFilter::Subtree,
+            ),
+            map(
+                preceded(pair(tag_no_case("MAILBOXES"), space1), one_or_more_mailbox),
+                NotifyFilter::Mailboxes,
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn notify_event(input: &str) -> Res<'_, NotifyEvent> {
+    context(
+        "notify_event",
+        alt((
+            // We only announce new messages with EXISTS, so the requested
+            // fetch attributes are accepted but not sent along.
+            map(
+                pair(
+                    tag_no_case("MessageNew"),
+                    opt(preceded(
+                        space1,
+                        delimited(
+                            char('('),
+                            separated_list1(space1, fetch_attributes),
+                            char(')'),
+                        ),
+                    )),
+                ),
+                |_| NotifyEvent::MessageNew,
+            ),
+            map(tag_no_case("MessageExpunge"), |_| {
+                NotifyEvent::MessageExpunge
+            }),
+            map(tag_no_case("FlagChange"), |_| NotifyEvent::FlagChange),
+            map(tag_no_case("AnnotationChange"), |_| {
+                NotifyEvent::AnnotationChange
+            }),
+            map(tag_no_case("MailboxName"), |_| NotifyEvent::MailboxName),
+            map(tag_no_case("SubscriptionChange"), |_| {
+                NotifyEvent::SubscriptionChange
+            }),
+            map(tag_no_case("MailboxMetadataChange"), |_| {
+                NotifyEvent::MailboxMetadataChange
+            }),
+            map(tag_no_case("ServerMetadataChange"), |_| {
+                NotifyEvent::ServerMetadataChange
+            }),
+            map(
+                take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.'),
+                |event: &str| NotifyEvent::Other(event.to_string()),
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn notify_event_group(input: &str) -> Res<'_, NotifyEventGroup> {
+    context(
+        "notify_event_group",
+        map(
+            delimited(
+                char('('),
+                separated_pair(
+                    notify_filter,
+                    space1,
+                    alt((
+                        map(tag_no_case("NONE"), |_| Vec::new()),
+                        delimited(
+                            char('('),
+                            separated_list1(space1, notify_event),
+                            char(')'),
+                        ),
+                    )),
+                ),
+                char(')'),
+            ),
+            |(filter, events)| NotifyEventGroup { filter, events },
+        ),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+pub fn notify_parameters(input: &str) -> Res<'_, NotifyParameters> {
+    context(
+        "notify_parameters",
+        alt((
+            map(tag_no_case("NONE"), |_| NotifyParameters::None),
+            map(
+                preceded(
+                    tag_no_case("SET"),
+                    pair(
+                        opt(preceded(space1, tag_no_case("(STATUS)"))),
+                        many0(preceded(space1, notify_event_group)),
+                    ),
+                ),
+                |(status, groups)| NotifyParameters::Set {
+                    status: status.is_some(),
+                    groups,
+                },
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// A quota root name (RFC 9208), which may be the empty string
+#[instrument(skip(input))]
+pub fn quota_root(input: &str) -> Res<'_, String> {
+    context(
+        "quota_root",
+        map(
+            alt((
+                delimited(char('"'), take_while(|c: char| c != '"'), char('"')),
+                take_while1(|c: char| c != '(' && c != ')' && !c.is_whitespace()),
+            )),
+            ToString::to_string,
+        ),
+    )
+    .parse(input)
+}
+
+/// Arguments of SETQUOTA (RFC 9208 §4.1.3)
+///
+/// Returns the quota root and the new limit of each resource, with the
+/// resource names in upper case.
+#[instrument(skip(input))]
+pub fn setquota_arguments(input: &str) -> Res<'_, (String, Vec<(String, u64)>)> {
+    context(
+        "setquota_arguments",
+        separated_pair(
+            quota_root,
+            space1,
+            delimited(
+                char('('),
+                separated_list0(
+                    space1,
+                    separated_pair(
+                        map(
+                            take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-'),
+                            str::to_uppercase,
+                        ),
+                        space1,
+                        map_res(digit1, str::parse::<u64>),
+                    ),
+                ),
+                char(')'),
+            ),
+        ),
+    )
+    .parse(input)
+}
+
+/// A quoted string (RFC 9051 §4.3) with its `\"` and `\\` escapes resolved
+#[instrument(skip(input))]
+fn quoted(input: &str) -> Res<'_, String> {
+    context(
+        "quoted",
+        delimited(
+            char('"'),
+            map(
+                many0(alt((preceded(char('\\'), one_of("\"\\")), none_of("\"\\")))),
+                |chars| chars.into_iter().collect(),
+            ),
+            char('"'),
+        ),
+    )
+    .parse(input)
+}
+
+/// An atom or a quoted string
+#[instrument(skip(input))]
+fn astring(input: &str) -> Res<'_, String> {
+    context(
+        "astring",
+        alt((
+            quoted,
+            map(
+                take_while1(|c: char| {
+                    !c.is_whitespace() && !matches!(c, '(' | ')' | '{' | '"' | '\\')
+                }),
+                ToString::to_string,
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// Options of GETMETADATA (RFC 5464 §4.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GetMetadataArguments {
+    /// Entries with a longer value are left out of the response
+    pub max_size: Option<usize>,
+    /// How many levels below the requested entries are returned, `None` for infinity
+    pub depth: Option<usize>,
+    /// The mailbox, or an empty string for server annotations
+    pub mailbox: String,
+    pub entries: Vec<String>,
+}
+
+enum GetMetadataOption {
+    MaxSize(usize),
+    Depth(Option<usize>),
+}
+
+#[instrument(skip(input))]
+fn getmetadata_option(input: &str) -> Res<'_, GetMetadataOption> {
+    context(
+        "getmetadata_option",
+        alt((
+            map(
+                preceded(
+                    (tag_no_case("MAXSIZE"), space1),
+                    map_res(digit1, str::parse::<usize>),
+                ),
+                GetMetadataOption::MaxSize,
+            ),
+            map(
+                preceded(
+                    (tag_no_case("DEPTH"), space1),
+                    alt((
+                        map(char('0'), |_| Some(0)),
+                        map(char('1'), |_| Some(1)),
+                        map(tag_no_case("infinity"), |_| None),
+                    )),
+                ),
+                GetMetadataOption::Depth,
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// Arguments of GETMETADATA: `[(options)] mailbox entries`
+#[instrument(skip(input))]
+pub fn getmetadata_arguments(input: &str) -> Res<'_, GetMetadataArguments> {
+    context(
+        "getmetadata_arguments",
+        map(
+            (
+                opt(terminated(
+                    delimited(
+                        char('('),
+                        separated_list1(space1, getmetadata_option),
+                        char(')'),
+                    ),
+                    space1,
+                )),
+                astring,
+                space1,
+                alt((
+                    delimited(char('('), separated_list1(space1, astring), char(')')),
+                    map(astring, |entry| vec![entry]),
+                )),
+            ),
+            |(options, mailbox, _, entries)| {
+                let mut arguments = GetMetadataArguments {
+                    max_size: None,
+                    depth: Some(0),
+                    mailbox,
+                    entries,
+                };
+                for option in options.unwrap_or_default() {
+                    match option {
+                        GetMetadataOption::MaxSize(size) => arguments.max_size = Some(size),
+                        GetMetadataOption::Depth(depth) => arguments.depth = depth,
+                    }
+                }
+                arguments
+            },
+        ),
+    )
+    .parse(input)
+}
+
+/// An entry name and its new value, `None` to remove the entry
+pub type MetadataChange = (String, Option<String>);
+
+/// Arguments of SETMETADATA: `mailbox (entry value ...)`
+///
+/// A value of `NIL` removes the entry.
+#[instrument(skip(input))]
+pub fn setmetadata_arguments(input: &str) -> Res<'_, (String, Vec<MetadataChange>)> {
+    context(
+        "setmetadata_arguments",
+        separated_pair(
+            astring,
+            space1,
+            delimited(
+                char('('),
+                separated_list1(
+                    space1,
+                    separated_pair(
+                        astring,
+                        space1,
+                        alt((map(quoted, Some), map(tag_no_case("NIL"), |_| None))),
+                    ),
+                ),
+                char(')'),
+            ),
+        ),
+    )
+    .parse(input)
+}
+
+/// Arguments of LOGIN: the user name and the password (RFC 9051 §6.2.3)
+#[instrument(skip(input))]
+pub fn login_arguments(input: &str) -> Res<'_, (String, String)> {
+    context("login_arguments", separated_pair(astring, space1, astring)).parse(input)
+}
+
+/// Arguments of ID: `NIL` or `(field value ...)` (RFC 2971 §3.1)
+///
+/// A value of `NIL` is returned as `None`.
+#[instrument(skip(input))]
+pub fn id_arguments(input: &str) -> Res<'_, Vec<(String, Option<String>)>> {
+    context(
+        "id_arguments",
+        alt((
+            map(tag_no_case("NIL"), |_| Vec::new()),
+            delimited(
+                char('('),
+                separated_list0(
+                    space1,
+                    separated_pair(
+                        quoted,
+                        space1,
+                        alt((map(quoted, Some), map(tag_no_case("NIL"), |_| None))),
+                    ),
+                ),
+                char(')'),
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// Arguments of CREATE: `mailbox [(USE (attributes))]` (RFC 6154 §3)
+#[instrument(skip(input))]
+pub fn create_arguments(input: &str) -> Res<'_, (String, Vec<String>)> {
+    context(
+        "create_arguments",
+        pair(
+            astring,
+            map(
+                opt(preceded(
+                    (space1, char('('), tag_no_case("USE"), space1),
+                    terminated(
+                        delimited(
+                            char('('),
+                            separated_list1(
+                                space1,
+                                map(preceded(char('\\'), flag_keyword), |attr| {
+                                    format!("\\{attr}")
+                                }),
+                            ),
+                            char(')'),
+                        ),
+                        char(')'),
+                    ),
+                )),
+                Option::unwrap_or_default,
+            ),
+        ),
+    )
+    .parse(input)
+}
+
+/// Selection options of the extended LIST (RFC 5258 §3.1, RFC 6154 §3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSelectOption {
+    Subscribed,
+    Remote,
+    RecursiveMatch,
+    SpecialUse,
+}
+
+/// Return options of the extended LIST (RFC 5258 §3.2, RFC 5819, RFC 6154 §3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListReturnOption {
+    Subscribed,
+    Children,
+    SpecialUse,
+    /// The upper case STATUS data items to return for each mailbox
+    Status(Vec<String>),
+}
+
+/// Arguments of the extended LIST:
+/// `[(selection)] reference (patterns) [RETURN (options)]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListArguments {
+    pub selection: Vec<ListSelectOption>,
+    pub reference: String,
+    pub patterns: Vec<String>,
+    pub return_options: Vec<ListReturnOption>,
+}
+
+/// A mailbox name which may contain the `*` and `%` wildcards
+#[instrument(skip(input))]
+fn list_mailbox(input: &str) -> Res<'_, String> {
+    context(
+        "list_mailbox",
+        alt((
+            quoted,
+            map(
+                take_while1(|c: char| {
+                    !c.is_whitespace() && !matches!(c, '(' | ')' | '{' | '"' | '\\')
+                }),
+                ToString::to_string,
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn list_select_option(input: &str) -> Res<'_, ListSelectOption> {
+    context(
+        "list_select_option",
+        alt((
+            map(tag_no_case("SUBSCRIBED"), |_| ListSelectOption::Subscribed),
+            map(tag_no_case("REMOTE"), |_| ListSelectOption::Remote),
+            map(tag_no_case("RECURSIVEMATCH"), |_| {
+                ListSelectOption::RecursiveMatch
+            }),
+            map(tag_no_case("SPECIAL-USE"), |_| ListSelectOption::SpecialUse),
+        )),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn list_return_option(input: &str) -> Res<'_, ListReturnOption> {
+    context(
+        "list_return_option",
+        alt((
+            map(tag_no_case("SUBSCRIBED"), |_| ListReturnOption::Subscribed),
+            map(tag_no_case("CHILDREN"), |_| ListReturnOption::Children),
+            map(tag_no_case("SPECIAL-USE"), |_| ListReturnOption::SpecialUse),
+            map(
+                preceded(
+                    (tag_no_case("STATUS"), space1),
+                    delimited(
+                        char('('),
+                        separated_list1(
+                            space1,
+                            take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-'),
+                        ),
+                        char(')'),
+                    ),
+                ),
+                |items: Vec<&str>| {
+                    ListReturnOption::Status(
+                        items.iter().map(|item| item.to_ascii_uppercase()).collect(),
+                    )
+                },
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// Arguments of LIST in the extended form of RFC 5258 §3
+#[instrument(skip(input))]
+pub fn list_arguments(input: &str) -> Res<'_, ListArguments> {
+    context(
+        "list_arguments",
+        map(
+            (
+                opt(terminated(
+                    delimited(
+                        char('('),
+                        separated_list0(space1, list_select_option),
+                        char(')'),
+                    ),
+                    space1,
+                )),
+                list_mailbox,
+                space1,
+                alt((
+                    delimited(char('('), separated_list1(space1, list_mailbox), char(')')),
+                    map(list_mailbox, |pattern| vec![pattern]),
+                )),
+                opt(preceded(
+                    (space1, tag_no_case("RETURN"), space1),
+                    delimited(
+                        char('('),
+                        separated_list0(space1, list_return_option),
+                        char(')'),
+                    ),
+                )),
+            ),
+            |(selection, reference, _, patterns, return_options)| ListArguments {
+                selection: selection.unwrap_or_default(),
+                reference,
+                patterns,
+                return_options: return_options.unwrap_or_default(),
+            },
+        ),
+    )
+    .parse(input)
+}
+
 #[derive(Debug, Clone)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum SearchReturnOption {
@@ -314,9 +1102,67 @@ pub enum SearchReturnOption {
     ALL,
     COUNT,
     SAVE,
+    /// A window of the results (RFC 9394)
+    PARTIAL(PartialRange),
+    /// The scores of a FUZZY search (RFC 6203)
+    RELEVANCY,
     Multiple(Vec<SearchReturnOption>),
 }
 
+impl SearchReturnOption {
+    /// The requested options as a list, unwrapping [`SearchReturnOption::Multiple`]
+    #[must_use]
+    pub fn options(&self) -> &[SearchReturnOption] {
+        match self {
+            SearchReturnOption::Multiple(options) => options,
+            option => std::slice::from_ref(option),
+        }
+    }
+}
+
+/// The positions of the results returned by `RETURN (PARTIAL ...)`.
+///
+/// Positions start at 1. `1:50` are the first 50 results, `-1:-50` the
+/// last 50 results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialRange {
+    /// The lower of both positions
+    pub start: u32,
+    /// The higher of both positions
+    pub end: u32,
+    /// Whether the positions are counted from the last result backwards
+    pub from_end: bool,
+}
+
+impl PartialRange {
+    /// The results within the range, in the order of `results`
+    #[must_use]
+    pub fn window<'a>(&self, results: &'a [u32]) -> &'a [u32] {
+        let len = results.len();
+        let start = usize::try_from(self.start).unwrap_or(usize::MAX);
+        let end = usize::try_from(self.end).unwrap_or(usize::MAX);
+        let (from, to) = if self.from_end {
+            (
+                len.saturating_sub(end),
+                len.saturating_sub(start.saturating_sub(1)),
+            )
+        } else {
+            (start.saturating_sub(1).min(len), end.min(len))
+        };
+        &results[from..to]
+    }
+}
+
+impl std::fmt::Display for PartialRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.from_end {
+            write!(f, "-{}:-{}", self.start, self.end)
+        } else {
+            write!(f, "{}:{}", self.start, self.end)
+        }
+    }
+}
+
 pub type EmailDate = String;
 pub type EmailHeader = String;
 
@@ -355,6 +1201,25 @@ pub enum SearchProgram {
     SENTSINCE(EmailDate),
     SMALLER(u64),
     UID(Range),
+    /// RFC 7162 §3.1.5; the optional entry name and type are ignored
+    MODSEQ(u64),
+    /// Matches approximately (RFC 6203)
+    FUZZY(Box<SearchProgram>),
+    /// The `$` reference to the saved search result (RFC 5182). It is parsed
+    /// empty, the UIDs are filled in when the command runs.
+    SavedResult(Vec<u32>),
+    /// RFC 8474 §6
+    EMAILID(String),
+    /// RFC 8474 §6
+    THREADID(String),
+    /// RFC 8514 §4.3
+    SAVEDBEFORE(EmailDate),
+    /// RFC 8514 §4.3
+    SAVEDON(EmailDate),
+    /// RFC 8514 §4.3
+    SAVEDSINCE(EmailDate),
+    /// RFC 8514 §4.3
+    SAVEDATESUPPORTED,
     // These are actually untagged in the ABNF but since we are an enum we need a tag here
     Range(Range),
     AND(Vec<SearchProgram>),
@@ -469,6 +1334,18 @@ fn inner_search_arguments(input: &str) -> Res<'_, SearchArguments> {
     .parse(input)
 }
 
+/// A user-defined flag keyword like `$Junk` (an atom in RFC 9051)
+#[instrument(skip(input))]
+fn flag_keyword(input: &str) -> Res<'_, &str> {
+    context(
+        "flag_keyword",
+        take_while1(|c: char| {
+            c.is_ascii_graphic() && !matches!(c, '(' | ')' | '{' | '%' | '*' | '"' | '\\' | ']')
+        }),
+    )
+    .parse(input)
+}
+
 #[instrument(skip(input))]
 fn search_program(input: &str) -> Res<'_, SearchProgram> {
     context(
@@ -520,12 +1397,8 @@ fn search_key(input: &str) -> Res<'_, SearchProgram> {
                 }),
                 // 13
                 map(
-                    separated_pair(
-                        tag_no_case("BCC"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::BCC(query.to_string()),
+                    separated_pair(tag_no_case("BCC"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::BCC(query),
                 ),
                 // 14
                 map(
@@ -538,38 +1411,22 @@ fn search_key(input: &str) -> Res<'_, SearchProgram> {
                 ),
                 // 15
                 map(
-                    separated_pair(
-                        tag_no_case("BODY"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::BODY(query.to_string()),
+                    separated_pair(tag_no_case("BODY"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::BODY(query),
                 ),
                 // 16
                 map(
-                    separated_pair(
-                        tag_no_case("CC"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::CC(query.to_string()),
+                    separated_pair(tag_no_case("CC"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::CC(query),
                 ),
                 // 17
                 map(
-                    separated_pair(
-                        tag_no_case("FROM"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::FROM(query.to_string()),
+                    separated_pair(tag_no_case("FROM"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::FROM(query),
                 ),
                 // 18
                 map(
-                    separated_pair(
-                        tag_no_case("KEYWORD"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
+                    separated_pair(tag_no_case("KEYWORD"), space1, flag_keyword),
                     |(_, query): (&str, &str)| SearchProgram::KEYWORD(query.to_string()),
                 ),
                 // 19
@@ -594,38 +1451,22 @@ fn search_key(input: &str) -> Res<'_, SearchProgram> {
             alt((
                 // 21
                 map(
-                    separated_pair(
-                        tag_no_case("SUBJECT"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::SUBJECT(query.to_string()),
+                    separated_pair(tag_no_case("SUBJECT"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::SUBJECT(query),
                 ),
                 // 22
                 map(
-                    separated_pair(
-                        tag_no_case("TEXT"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::TEXT(query.to_string()),
+                    separated_pair(tag_no_case("TEXT"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::TEXT(query),
                 ),
                 // 23
                 map(
-                    separated_pair(
-                        tag_no_case("TO"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
-                    |(_, query): (&str, &str)| SearchProgram::TO(query.to_string()),
+                    separated_pair(tag_no_case("TO"), space1, astring),
+                    |(_, query): (&str, String)| SearchProgram::TO(query),
                 ),
                 // 24
                 map(
-                    separated_pair(
-                        tag_no_case("UNKEYWORD"),
-                        space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
-                    ),
+                    separated_pair(tag_no_case("UNKEYWORD"), space1, flag_keyword),
                     |(_, query): (&str, &str)| SearchProgram::UNKEYWORD(query.to_string()),
                 ),
                 // 25
@@ -633,14 +1474,10 @@ fn search_key(input: &str) -> Res<'_, SearchProgram> {
                     separated_pair(
                         tag_no_case("HEADER"),
                         space1,
-                        separated_pair(
-                            take_while1(|x: char| x.is_ascii_alphanumeric()),
-                            space1,
-                            take_while1(|x: char| x.is_ascii_alphanumeric()),
-                        ),
+                        separated_pair(astring, space1, astring),
                     ),
-                    |(_, (header_name, header_value)): (&str, (&str, &str))| {
-                        SearchProgram::HEADER(header_name.to_string(), header_value.to_string())
+                    |(_, (header_name, header_value)): (&str, (String, String))| {
+                        SearchProgram::HEADER(header_name, header_value)
                     },
                 ),
                 // 26
@@ -699,39 +1536,124 @@ fn search_key(input: &str) -> Res<'_, SearchProgram> {
                     ),
                     |(_, query): (&str, &str)| SearchProgram::SENTSINCE(query.to_string()),
                 ),
-                // 32
+                // 32
+                map(
+                    separated_pair(
+                        tag_no_case("SMALLER"),
+                        space1,
+                        take_while1(|x: char| x.is_ascii_alphanumeric()),
+                    ),
+                    |(_, query): (&str, &str)| {
+                        SearchProgram::SMALLER(
+                            query.parse::<u64>().expect("LARGER query is not u64"),
+                        )
+                    },
+                ),
+                // 33
+                map(
+                    separated_pair(tag_no_case("UID"), space1, parse_selected_range_inner),
+                    |(_, query): (&str, Range)| SearchProgram::UID(query),
+                ),
+                // 34
+                map(parse_selected_range_inner, |query: Range| {
+                    SearchProgram::Range(query)
+                }),
+                // 35
+                map(
+                    delimited(char('('), separated_list1(space1, search_key), char(')')),
+                    SearchProgram::AND,
+                ),
+                // 36
+                map(
+                    preceded(
+                        pair(tag_no_case("MODSEQ"), space1),
+                        preceded(
+                            opt((
+                                delimited(char('"'), take_while1(|c: char| c != '"'), char('"')),
+                                space1,
+                                alt((
+                                    tag_no_case("priv"),
+                                    tag_no_case("shared"),
+                                    tag_no_case("all"),
+                                )),
+                                space1,
+                            )),
+                            mod_sequence,
+                        ),
+                    ),
+                    SearchProgram::MODSEQ,
+                ),
+                // 37
+                map(
+                    separated_pair(tag_no_case("FUZZY"), space1, search_key),
+                    |(_, query): (&str, SearchProgram)| SearchProgram::FUZZY(Box::new(query)),
+                ),
+                // 38
+                map(
+                    preceded(opt(pair(tag_no_case("UID"), space1)), char('$')),
+                    |_| SearchProgram::SavedResult(Vec::new()),
+                ),
+            )),
+            alt((
+                // 39
+                map(
+                    separated_pair(tag_no_case("EMAILID"), space1, object_id),
+                    |(_, query): (&str, &str)| SearchProgram::EMAILID(query.to_string()),
+                ),
+                // 40
+                map(
+                    separated_pair(tag_no_case("THREADID"), space1, object_id),
+                    |(_, query): (&str, &str)| SearchProgram::THREADID(query.to_string()),
+                ),
+                // 41
+                map(
+                    separated_pair(
+                        tag_no_case("SAVEDBEFORE"),
+                        space1,
+                        take_while1(|x: char| x.is_ascii_alphanumeric() || x == '-'),
+                    ),
+                    |(_, query): (&str, &str)| SearchProgram::SAVEDBEFORE(query.to_string()),
+                ),
+                // 42
+                map(
+                    separated_pair(
+                        tag_no_case("SAVEDON"),
+                        space1,
+                        take_while1(|x: char| x.is_ascii_alphanumeric() || x == '-'),
+                    ),
+                    |(_, query): (&str, &str)| SearchProgram::SAVEDON(query.to_string()),
+                ),
+                // 43
                 map(
                     separated_pair(
-                        tag_no_case("SMALLER"),
+                        tag_no_case("SAVEDSINCE"),
                         space1,
-                        take_while1(|x: char| x.is_ascii_alphanumeric()),
+                        take_while1(|x: char| x.is_ascii_alphanumeric() || x == '-'),
                     ),
-                    |(_, query): (&str, &str)| {
-                        SearchProgram::SMALLER(
-                            query.parse::<u64>().expect("LARGER query is not u64"),
-                        )
-                    },
-                ),
-                // 33
-                map(
-                    separated_pair(tag_no_case("UID"), space1, parse_selected_range_inner),
-                    |(_, query): (&str, Range)| SearchProgram::UID(query),
+                    |(_, query): (&str, &str)| SearchProgram::SAVEDSINCE(query.to_string()),
                 ),
-                // 34
-                map(parse_selected_range_inner, |query: Range| {
-                    SearchProgram::Range(query)
+                // 44
+                map(tag_no_case("SAVEDATESUPPORTED"), |_| {
+                    SearchProgram::SAVEDATESUPPORTED
                 }),
-                // 35
-                map(
-                    delimited(char('('), separated_list1(space1, search_key), char(')')),
-                    SearchProgram::AND,
-                ),
             )),
         )),
     )
     .parse(input)
 }
 
+/// An RFC 8474 `objectid`
+#[instrument(skip(input))]
+fn object_id(input: &str) -> Res<'_, &str> {
+    context(
+        "object_id",
+        take_while_m_n(1, 255, |x: char| {
+            x.is_ascii_alphanumeric() || x == '_' || x == '-'
+        }),
+    )
+    .parse(input)
+}
+
 #[instrument(skip(input))]
 fn search_return_opts(input: &str) -> Res<'_, SearchReturnOption> {
     context(
@@ -750,6 +1672,11 @@ fn search_return_opts(input: &str) -> Res<'_, SearchReturnOption> {
                             map(tag_no_case("ALL"), |_| SearchReturnOption::ALL),
                             map(tag_no_case("COUNT"), |_| SearchReturnOption::COUNT),
                             map(tag_no_case("SAVE"), |_| SearchReturnOption::SAVE),
+                            map(tag_no_case("RELEVANCY"), |_| SearchReturnOption::RELEVANCY),
+                            map(
+                                preceded((tag_no_case("PARTIAL"), space1), partial_range),
+                                SearchReturnOption::PARTIAL,
+                            ),
                         )),
                     )),
                     char(')'),
@@ -769,6 +1696,192 @@ fn search_return_opts(input: &str) -> Res<'_, SearchReturnOption> {
     .parse(input)
 }
 
+/// A non-zero number of a partial range
+fn partial_position(input: &str) -> Res<'_, u32> {
+    context(
+        "partial_position",
+        map_res(digit1, |x: &str| {
+            x.parse::<u32>().ok().filter(|x| *x != 0).ok_or(())
+        }),
+    )
+    .parse(input)
+}
+
+/// `1:50` or `-1:-50` (RFC 9394 §3.1)
+#[instrument(skip(input))]
+fn partial_range(input: &str) -> Res<'_, PartialRange> {
+    context(
+        "partial_range",
+        alt((
+            map(
+                separated_pair(
+                    preceded(char('-'), partial_position),
+                    char(':'),
+                    preceded(char('-'), partial_position),
+                ),
+                |(a, b)| PartialRange {
+                    start: a.min(b),
+                    end: a.max(b),
+                    from_end: true,
+                },
+            ),
+            map(
+                separated_pair(partial_position, char(':'), partial_position),
+                |(a, b)| PartialRange {
+                    start: a.min(b),
+                    end: a.max(b),
+                    from_end: false,
+                },
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// Sort keys of SORT (RFC 5256 §3) and SORT=DISPLAY (RFC 5957)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Arrival,
+    Cc,
+    Date,
+    From,
+    Size,
+    Subject,
+    To,
+    DisplayFrom,
+    DisplayTo,
+}
+
+/// A sort key, optionally in reverse order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortCriterion {
+    pub reverse: bool,
+    pub key: SortKey,
+}
+
+#[derive(Debug, Clone)]
+pub struct SortArguments {
+    /// The options of an ESORT (RFC 5267) when `RETURN (...)` was given
+    pub return_opts: Option<SearchReturnOption>,
+    pub criteria: Vec<SortCriterion>,
+    pub charset: String,
+    pub program: SearchProgram,
+}
+
+/// Threading algorithms of THREAD (RFC 5256 §4)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadAlgorithm {
+    OrderedSubject,
+    References,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThreadArguments {
+    pub algorithm: ThreadAlgorithm,
+    pub charset: String,
+    pub program: SearchProgram,
+}
+
+#[instrument(skip(input))]
+fn charset(input: &str) -> Res<'_, String> {
+    context(
+        "charset",
+        map(
+            alt((
+                delimited(char('"'), take_while1(|c: char| c != '"'), char('"')),
+                take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            )),
+            ToString::to_string,
+        ),
+    )
+    .parse(input)
+}
+
+#[instrument(skip(input))]
+fn sort_criterion(input: &str) -> Res<'_, SortCriterion> {
+    context(
+        "sort_criterion",
+        map(
+            pair(
+                opt(terminated(tag_no_case("REVERSE"), space1)),
+                alt((
+                    map(tag_no_case("ARRIVAL"), |_| SortKey::Arrival),
+                    map(tag_no_case("CC"), |_| SortKey::Cc),
+                    map(tag_no_case("DATE"), |_| SortKey::Date),
+                    map(tag_no_case("DISPLAYFROM"), |_| SortKey::DisplayFrom),
+                    map(tag_no_case("DISPLAYTO"), |_| SortKey::DisplayTo),
+                    map(tag_no_case("FROM"), |_| SortKey::From),
+                    map(tag_no_case("SIZE"), |_| SortKey::Size),
+                    map(tag_no_case("SUBJECT"), |_| SortKey::Subject),
+                    map(tag_no_case("TO"), |_| SortKey::To),
+                )),
+            ),
+            |(reverse, key)| SortCriterion {
+                reverse: reverse.is_some(),
+                key,
+            },
+        ),
+    )
+    .parse(input)
+}
+
+/// Arguments of SORT: `[RETURN (options)] (criteria) charset search-program`
+#[instrument(skip(input))]
+pub fn sort_arguments(input: &str) -> Res<'_, SortArguments> {
+    context(
+        "sort_arguments",
+        map(
+            (
+                opt(terminated(search_return_opts, space1)),
+                delimited(
+                    char('('),
+                    separated_list1(space1, sort_criterion),
+                    char(')'),
+                ),
+                space1,
+                charset,
+                space1,
+                search_program,
+            ),
+            |(return_opts, criteria, _, charset, _, program)| SortArguments {
+                return_opts,
+                criteria,
+                charset,
+                program,
+            },
+        ),
+    )
+    .parse(input)
+}
+
+/// Arguments of THREAD: `algorithm charset search-program`
+#[instrument(skip(input))]
+pub fn thread_arguments(input: &str) -> Res<'_, ThreadArguments> {
+    context(
+        "thread_arguments",
+        map(
+            (
+                alt((
+                    map(tag_no_case("ORDEREDSUBJECT"), |_| {
+                        ThreadAlgorithm::OrderedSubject
+                    }),
+                    map(tag_no_case("REFERENCES"), |_| ThreadAlgorithm::References),
+                )),
+                space1,
+                charset,
+                space1,
+                search_program,
+            ),
+            |(algorithm, _, charset, _, program)| ThreadArguments {
+                algorithm,
+                charset,
+                program,
+            },
+        ),
+    )
+    .parse(input)
+}
+
 #[derive(Debug, Clone)]
 pub enum RangeEnd {
     End(u32),
@@ -951,8 +2064,44 @@ pub struct LiteralSize {
     pub continuation: bool,
 }
 
-pub type AppendArgs<'a> = (Option<Vec<&'a str>>, Option<DateTime>, LiteralSize);
+/// A literal announcement like `{42}` or the non-synchronizing `{42+}`
+///
+/// The binary literals `~{42}` of RFC 3516 §4.4 are accepted as well.
+#[instrument(skip(input))]
+pub fn literal_size(input: &str) -> Res<'_, LiteralSize> {
+    context(
+        "literal_size",
+        delimited(
+            (opt(char('~')), char('{')),
+            map(
+                pair(
+                    map_res(digit1, str::parse::<usize>),
+                    map(opt(char('+')), |x| x.is_some()),
+                ),
+                |(length, continuation)| LiteralSize {
+                    length,
+                    continuation,
+                },
+            ),
+            char('}'),
+        ),
+    )
+    .parse(input)
+}
+
+/// How the content of a message to APPEND is transmitted
+pub enum AppendData {
+    /// The whole message follows as a literal
+    Literal(LiteralSize),
+    /// The message is built from the [`CatenatePart`]s which follow (RFC 4469)
+    Catenate,
+}
+
+pub type AppendArgs<'a> = (Option<Vec<&'a str>>, Option<DateTime>, AppendData);
 
+/// The flags, date and data of a single message to APPEND.
+///
+/// With MULTIAPPEND (RFC 3502) these repeat after each message.
 #[instrument(skip(input))]
 pub fn append_arguments(input: &str) -> Res<'_, AppendArgs<'_>> {
     context(
@@ -970,31 +2119,59 @@ pub fn append_arguments(input: &str) -> Res<'_, AppendArgs<'_>> {
                 opt(space1),
                 opt(date_time),
                 opt(space1),
-                opt(tag_no_case("UTF8")),
-                opt(space1),
-                opt(tag_no_case("(")),
-                opt(tag_no_case("~")),
-                tag_no_case("{"),
-                map(
-                    pair(
-                        map(take_while1(|c: char| c.is_ascii_digit()), |s: &str| {
-                            s.parse::<usize>().expect("literal size is a number")
-                        }),
-                        map(opt(tag_no_case("+")), |x| x.is_some()),
+                alt((
+                    map((tag_no_case("CATENATE"), space1, char('(')), |_| {
+                        AppendData::Catenate
+                    }),
+                    map(
+                        preceded(
+                            (
+                                opt(tag_no_case("UTF8")),
+                                opt(space1),
+                                opt(char('(')),
+                                opt(char('~')),
+                            ),
+                            literal_size,
+                        ),
+                        AppendData::Literal,
                     ),
-                    |(length, continuation)| LiteralSize {
-                        length,
-                        continuation,
-                    },
-                ),
-                tag_no_case("}"),
+                )),
             ),
-            |(flags, _, datetime, _, _, _, _, _, _, literal, _)| (flags, datetime, literal),
+            |(flags, _, datetime, _, data)| (flags, datetime, data),
         ),
     )
     .parse(input)
 }
 
+/// A part of the CATENATE list (RFC 4469 §5)
+pub enum CatenatePart {
+    /// An IMAP URL of an existing message or message part
+    Url(String),
+    /// A literal with new text
+    Text(LiteralSize),
+    /// The closing parenthesis of the list
+    End,
+}
+
+#[instrument(skip(input))]
+pub fn catenate_part(input: &str) -> Res<'_, CatenatePart> {
+    context(
+        "catenate_part",
+        alt((
+            map(
+                preceded((tag_no_case("URL"), space1), astring),
+                CatenatePart::Url,
+            ),
+            map(
+                preceded((tag_no_case("TEXT"), space1), literal_size),
+                CatenatePart::Text,
+            ),
+            map(char(')'), |_| CatenatePart::End),
+        )),
+    )
+    .parse(input)
+}
+
 // Parses this ABNF:
 //
 // date            = date-text / DQUOTE date-text DQUOTE
@@ -1120,4 +2297,353 @@ mod tests {
         let (unparsed, _) = args.unwrap();
         assert_eq!(unparsed, "");
     }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_fetch_modifiers() {
+        let (unparsed, modifiers) = fetch_modifiers("(CHANGEDSINCE 12345 VANISHED)").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(modifiers.changed_since, Some(12345));
+        assert!(modifiers.vanished);
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_store_modifiers() {
+        let (unparsed, modseq) = store_modifiers("(UNCHANGEDSINCE 320162338)").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(modseq, 320_162_338);
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_select_parameters_qresync() {
+        let (unparsed, params) =
+            select_parameters("(QRESYNC (67890007 20050715194045000 41,43:211,214:541))")
+                .unwrap();
+        assert_eq!(unparsed, "");
+        let qresync = params.qresync.unwrap();
+        assert_eq!(qresync.uidvalidity, 67_890_007);
+        assert_eq!(qresync.modseq, 20_050_715_194_045_000);
+        assert_eq!(qresync.known_uids.unwrap().len(), 3);
+
+        let (_, params) = select_parameters("(CONDSTORE)").unwrap();
+        assert!(params.condstore);
+        assert!(params.qresync.is_none());
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_search_modseq() {
+        let (_, program) = search_program("MODSEQ 620162338").unwrap();
+        assert!(matches!(program, SearchProgram::MODSEQ(620_162_338)));
+        let (_, program) = search_program("MODSEQ \"/flags/\\\\draft\" all 620162338").unwrap();
+        assert!(matches!(program, SearchProgram::MODSEQ(620_162_338)));
+    }
+
+    #[test]
+    fn test_binary_sections() {
+        let (_, args) =
+            fetch_arguments("(BINARY.PEEK[1.2]<0.10> BINARY.SIZE[] BINARY[3])").unwrap();
+        let FetchArguments::List(args) = args else {
+            panic!("Expected a list of attributes");
+        };
+        assert!(
+            matches!(&args[0], FetchAttributes::BinaryPeek(part, Some((0, 10))) if part == &[1, 2])
+        );
+        assert!(matches!(&args[1], FetchAttributes::BinarySize(part) if part.is_empty()));
+        assert!(matches!(&args[2], FetchAttributes::Binary(part, None) if part == &[3]));
+    }
+
+    #[test]
+    fn test_search_strings() {
+        let (_, program) = search_program("SUBJECT \"Grüße aus Köln\"").unwrap();
+        assert!(matches!(program, SearchProgram::SUBJECT(query) if query == "Grüße aus Köln"));
+        let (_, program) = search_program("FROM alice@example.com").unwrap();
+        assert!(matches!(program, SearchProgram::FROM(query) if query == "alice@example.com"));
+        let (_, program) = search_program("HEADER List-Id \"\"").unwrap();
+        assert!(
+            matches!(program, SearchProgram::HEADER(name, value) if name == "List-Id" && value.is_empty())
+        );
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_notify_parameters() {
+        let (unparsed, params) = notify_parameters(
+            "SET (STATUS) (selected (MessageNew (UID FLAGS) MessageExpunge FlagChange)) (subtree (\"Lists\" Work) (MessageNew MessageExpunge MailboxName)) (mailboxes INBOX NONE)",
+        )
+        .unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(
+            params,
+            NotifyParameters::Set {
+                status: true,
+                groups: vec![
+                    NotifyEventGroup {
+                        filter: NotifyFilter::Selected,
+                        events: vec![
+                            NotifyEvent::MessageNew,
+                            NotifyEvent::MessageExpunge,
+                            NotifyEvent::FlagChange,
+                        ],
+                    },
+                    NotifyEventGroup {
+                        filter: NotifyFilter::Subtree(vec![
+                            String::from("Lists"),
+                            String::from("Work"),
+                        ]),
+                        events: vec![
+                            NotifyEvent::MessageNew,
+                            NotifyEvent::MessageExpunge,
+                            NotifyEvent::MailboxName,
+                        ],
+                    },
+                    NotifyEventGroup {
+                        filter: NotifyFilter::Mailboxes(vec![String::from("INBOX")]),
+                        events: vec![],
+                    },
+                ],
+            }
+        );
+
+        let (_, params) = notify_parameters("NONE").unwrap();
+        assert_eq!(params, NotifyParameters::None);
+
+        let (_, params) = notify_parameters("SET (personal (FooChange))").unwrap();
+        assert_eq!(
+            params,
+            NotifyParameters::Set {
+                status: false,
+                groups: vec![NotifyEventGroup {
+                    filter: NotifyFilter::Personal,
+                    events: vec![NotifyEvent::Other(String::from("FooChange"))],
+                }],
+            }
+        );
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_setquota_arguments() {
+        let (unparsed, (root, limits)) =
+            setquota_arguments("\"alice@localhost\" (STORAGE 512 message 10)").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(root, "alice@localhost");
+        assert_eq!(
+            limits,
+            vec![
+                (String::from("STORAGE"), 512),
+                (String::from("MESSAGE"), 10)
+            ]
+        );
+
+        let (_, (root, limits)) = setquota_arguments("\"\" ()").unwrap();
+        assert_eq!(root, "");
+        assert!(limits.is_empty());
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_metadata_arguments() {
+        let (unparsed, args) =
+            getmetadata_arguments("(MAXSIZE 1024 DEPTH infinity) \"\" (/shared/comment /private)")
+                .unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(
+            args,
+            GetMetadataArguments {
+                max_size: Some(1024),
+                depth: None,
+                mailbox: String::new(),
+                entries: vec![String::from("/shared/comment"), String::from("/private")],
+            }
+        );
+        let (_, args) = getmetadata_arguments("INBOX /private/comment").unwrap();
+        assert_eq!(args.depth, Some(0));
+        assert_eq!(args.mailbox, "INBOX");
+
+        let (unparsed, (mailbox, entries)) = setmetadata_arguments(
+            "\"Shared/support\" (/shared/comment \"Say \\\"hi\\\"\" /private/comment NIL)",
+        )
+        .unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(mailbox, "Shared/support");
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    String::from("/shared/comment"),
+                    Some(String::from("Say \"hi\""))
+                ),
+                (String::from("/private/comment"), None),
+            ]
+        );
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_create_arguments() {
+        let (unparsed, args) = create_arguments("\"Sent Mail\" (USE (\\Sent \\Archive))").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(
+            args,
+            (
+                String::from("Sent Mail"),
+                vec![String::from("\\Sent"), String::from("\\Archive")]
+            )
+        );
+        let (unparsed, args) = create_arguments("Projects").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(args, (String::from("Projects"), vec![]));
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_login_arguments() {
+        let (unparsed, args) = login_arguments("alice@localhost \"pass \\\"word\"").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(
+            args,
+            (String::from("alice@localhost"), String::from("pass \"word"))
+        );
+        assert!(login_arguments("alice@localhost").is_err());
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_append_and_catenate_arguments() {
+        let (unparsed, (flags, _, data)) = append_arguments("(\\Seen) {310}").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(flags, Some(vec!["\\Seen"]));
+        assert!(matches!(
+            data,
+            AppendData::Literal(LiteralSize {
+                length: 310,
+                continuation: false
+            })
+        ));
+        let (unparsed, (_, _, data)) = append_arguments("~{12+}").unwrap();
+        assert_eq!(unparsed, "");
+        assert!(matches!(
+            data,
+            AppendData::Literal(LiteralSize {
+                length: 12,
+                continuation: true
+            })
+        ));
+        let (unparsed, (flags, _, data)) =
+            append_arguments("CATENATE (URL \"/INBOX/;UID=20\" TEXT {42+}").unwrap();
+        assert_eq!(unparsed, "URL \"/INBOX/;UID=20\" TEXT {42+}");
+        assert_eq!(flags, None);
+        assert!(matches!(data, AppendData::Catenate));
+
+        let (unparsed, part) = catenate_part(unparsed).unwrap();
+        assert!(matches!(part, CatenatePart::Url(url) if url == "/INBOX/;UID=20"));
+        let (unparsed, part) = catenate_part(unparsed.trim_start()).unwrap();
+        assert_eq!(unparsed, "");
+        assert!(matches!(
+            part,
+            CatenatePart::Text(LiteralSize {
+                length: 42,
+                continuation: true
+            })
+        ));
+        assert!(matches!(catenate_part(")"), Ok(("", CatenatePart::End))));
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_list_arguments() {
+        let (unparsed, args) = list_arguments(
+            "(SUBSCRIBED RECURSIVEMATCH) \"\" (INBOX \"Archive.%\") RETURN (CHILDREN STATUS (messages UIDNEXT))",
+        )
+        .unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(
+            args,
+            ListArguments {
+                selection: vec![
+                    ListSelectOption::Subscribed,
+                    ListSelectOption::RecursiveMatch
+                ],
+                reference: String::new(),
+                patterns: vec![String::from("INBOX"), String::from("Archive.%")],
+                return_options: vec![
+                    ListReturnOption::Children,
+                    ListReturnOption::Status(vec![
+                        String::from("MESSAGES"),
+                        String::from("UIDNEXT")
+                    ]),
+                ],
+            }
+        );
+        let (unparsed, args) = list_arguments("\"\" * RETURN (SPECIAL-USE)").unwrap();
+        assert_eq!(unparsed, "");
+        assert!(args.selection.is_empty());
+        assert_eq!(args.patterns, vec![String::from("*")]);
+        assert_eq!(args.return_options, vec![ListReturnOption::SpecialUse]);
+        assert!(list_arguments("(BOGUS) \"\" *").is_err());
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_sort_and_thread_arguments() {
+        let (unparsed, args) = sort_arguments("(REVERSE DATE subject) UTF-8 SEEN").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(
+            args.criteria,
+            vec![
+                SortCriterion {
+                    reverse: true,
+                    key: SortKey::Date
+                },
+                SortCriterion {
+                    reverse: false,
+                    key: SortKey::Subject
+                }
+            ]
+        );
+        assert_eq!(args.charset, "UTF-8");
+        assert!(matches!(args.program, SearchProgram::SEEN));
+        assert!(sort_arguments("() UTF-8 ALL").is_err());
+
+        let (unparsed, args) = thread_arguments("REFERENCES \"US-ASCII\" ALL").unwrap();
+        assert_eq!(unparsed, "");
+        assert_eq!(args.algorithm, ThreadAlgorithm::References);
+        assert_eq!(args.charset, "US-ASCII");
+    }
+
+    #[test]
+    fn test_partial_and_esort_arguments() {
+        let (_, args) = search_arguments("RETURN (COUNT PARTIAL -1:-50) ALL").unwrap();
+        let SearchReturnOption::Multiple(options) = args.return_opts else {
+            panic!("Expected multiple return options");
+        };
+        assert!(matches!(options[0], SearchReturnOption::COUNT));
+        let SearchReturnOption::PARTIAL(range) = options[1] else {
+            panic!("Expected a partial range");
+        };
+        assert_eq!(range.to_string(), "-1:-50");
+        let results: Vec<u32> = (1..=100).collect();
+        assert_eq!(range.window(&results), &results[50..]);
+
+        let (_, args) = search_arguments("RETURN (PARTIAL 60:51) ALL").unwrap();
+        let SearchReturnOption::PARTIAL(range) = args.return_opts else {
+            panic!("Expected a partial range");
+        };
+        assert_eq!(range.to_string(), "51:60");
+        assert_eq!(range.window(&[1, 2, 3]), &[] as &[u32]);
+        assert!(search_arguments("RETURN (PARTIAL 0:10) ALL").is_err());
+        assert!(search_arguments("RETURN (PARTIAL 1:-10) ALL").is_err());
+
+        let (unparsed, args) = sort_arguments("RETURN (MIN COUNT) (DATE) UTF-8 ALL").unwrap();
+        assert_eq!(unparsed, "");
+        assert!(matches!(
+            args.return_opts,
+            Some(SearchReturnOption::Multiple(_))
+        ));
+        let (_, args) = sort_arguments("(DATE) UTF-8 ALL").unwrap();
+        assert!(args.return_opts.is_none());
+    }
 }
@@ -0,0 +1,414 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! QUOTA extension (RFC 9208).
+//!
+//! Every user has a single quota root named after the account which covers
+//! all of their mailboxes. Storage is reported in units of 1024 octets as
+//! required by the `STORAGE` resource. Only admins listed in the config may
+//! change quotas or look at the quota of other users.
+
+use crate::{
+    commands::{
+        parsers::{quota_root, setquota_arguments},
+        CommandData, Data,
+    },
+    servers::state::State,
+};
+use erooster_core::{
+    backend::{
+        acl::MailboxName,
+        database::{Database, DB},
+        quota::Quota,
+        storage::{MailStorage, Storage},
+    },
+    config::Config,
+};
+use {
+    futures::{Sink, SinkExt},
+    nom::Finish,
+    tracing::instrument,
+};
+
+pub struct GetQuota<'a> {
+    pub data: &'a Data,
+}
+
+impl GetQuota<'_> {
+    #[instrument(skip(self, lines, config, database, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        config: &Config,
+        database: &DB,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let Some(username) = authenticated_user(self.data) else {
+            lines
+                .send(format!("{} NO invalid state", command_data.tag))
+                .await?;
+            return Ok(());
+        };
+        let arguments = command_data.arguments.join(" ");
+        let Ok((_, root)) = quota_root(&arguments).finish() else {
+            lines
+                .send(format!(
+                    "{} BAD failed to parse arguments",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+        if root != username && !is_admin(config, username) {
+            lines
+                .send(format!(
+                    "{} NO [NOPERM] Not allowed to access this quota root",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        if !database.user_exists(&root).await {
+            lines
+                .send(format!("{} NO No such quota root", command_data.tag))
+                .await?;
+            return Ok(());
+        }
+
+        lines
+            .feed(quota_response(database, storage, &root).await?)
+            .await?;
+        lines
+            .feed(format!("{} OK GETQUOTA completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct GetQuotaRoot<'a> {
+    pub data: &'a Data,
+}
+
+impl GetQuotaRoot<'_> {
+    #[instrument(skip(self, lines, database, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        database: &DB,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let Some(username) = authenticated_user(self.data) else {
+            lines
+                .send(format!("{} NO invalid state", command_data.tag))
+                .await?;
+            return Ok(());
+        };
+        let Some(mailbox) = command_data.arguments.first() else {
+            lines
+                .send(format!("{} BAD missing mailbox name", command_data.tag))
+                .await?;
+            return Ok(());
+        };
+        let mailbox_path =
+            storage.to_ondisk_path(mailbox.replace('"', ""), username.to_string())?;
+        if !mailbox_path.exists() {
+            lines
+                .send(format!(
+                    "{} NO [NONEXISTENT] No such mailbox",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        // Only the user's own quota root is visible to them
+        if !MailboxName::resolve(mailbox, username).is_owned_by(username) {
+            lines.feed(format!("* QUOTAROOT {mailbox}")).await?;
+            lines
+                .feed(format!("{} OK GETQUOTAROOT completed", command_data.tag))
+                .await?;
+            lines.flush().await?;
+            return Ok(());
+        }
+
+        lines
+            .feed(format!("* QUOTAROOT {mailbox} \"{username}\""))
+            .await?;
+        lines
+            .feed(quota_response(database, storage, username).await?)
+            .await?;
+        lines
+            .feed(format!("{} OK GETQUOTAROOT completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+pub struct SetQuota<'a> {
+    pub data: &'a Data,
+}
+
+impl SetQuota<'_> {
+    #[instrument(skip(self, lines, config, database, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        config: &Config,
+        database: &DB,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let Some(username) = authenticated_user(self.data) else {
+            lines
+                .send(format!("{} NO invalid state", command_data.tag))
+                .await?;
+            return Ok(());
+        };
+        let arguments = command_data.arguments.join(" ");
+        let Ok((_, (root, limits))) = setquota_arguments(&arguments).finish() else {
+            lines
+                .send(format!(
+                    "{} BAD failed to parse arguments",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+        if !is_admin(config, username) {
+            lines
+                .send(format!(
+                    "{} NO [NOPERM] Only admins may change quotas",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        if !database.user_exists(&root).await {
+            lines
+                .send(format!("{} NO No such quota root", command_data.tag))
+                .await?;
+            return Ok(());
+        }
+
+        let mut quota = Quota::default();
+        for (resource, limit) in limits {
+            match resource.as_str() {
+                "STORAGE" => quota.storage = Some(limit.saturating_mul(1024)),
+                "MESSAGE" => quota.messages = Some(limit),
+                _ => {
+                    lines
+                        .send(format!(
+                            "{} NO Unsupported resource {resource}",
+                            command_data.tag
+                        ))
+                        .await?;
+                    return Ok(());
+                }
+            }
+        }
+        database.set_user_quota(&root, quota).await?;
+
+        lines
+            .feed(quota_response(database, storage, &root).await?)
+            .await?;
+        lines
+            .feed(format!("{} OK SETQUOTA completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+/// The username if the client is logged in
+pub fn authenticated_user(data: &Data) -> Option<&str> {
+    if matches!(
+        data.con_state.state,
+        State::Authenticated | State::Selected(..)
+    ) {
+        data.con_state.username.as_deref()
+    } else {
+        None
+    }
+}
+
+/// Whether the user is listed as admin in the config
+pub fn is_admin(config: &Config, username: &str) -> bool {
+    config
+        .mail
+        .admins
+        .iter()
+        .any(|admin| admin.eq_ignore_ascii_case(username))
+}
+
+/// The untagged `QUOTA` response with usage and limits of a quota root
+///
+/// Resources without a limit are left out.
+#[instrument(skip(database, storage))]
+pub async fn quota_response(
+    database: &DB,
+    storage: &Storage,
+    root: &str,
+) -> color_eyre::eyre::Result<String> {
+    let quota = database.get_quota(root).await?;
+    let usage = storage.get_quota_usage(root).await?;
+
+    let mut resources = Vec::new();
+    if let Some(limit) = quota.storage {
+        resources.push(format!(
+            "STORAGE {} {}",
+            usage.storage.div_ceil(1024),
+            limit / 1024
+        ));
+    }
+    if let Some(limit) = quota.messages {
+        resources.push(format!("MESSAGE {} {limit}", usage.messages));
+    }
+    Ok(format!("* QUOTA \"{root}\" ({})", resources.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Commands;
+    use crate::servers::state::{Access, Connection};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    fn logged_in(username: &str) -> Data {
+        Data {
+            con_state: Connection {
+                state: State::Selected(String::from("INBOX"), Access::ReadWrite),
+                secure: true,
+                username: Some(username.to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        }
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_getquotaroot_reports_usage_and_limits() {
+        let (_config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        let username = "quota_user@localhost";
+        database.add_user(username).await.unwrap();
+        database
+            .set_user_quota(
+                username,
+                Quota {
+                    storage: Some(10 * 1024),
+                    messages: Some(5),
+                },
+            )
+            .await
+            .unwrap();
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), username.to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        storage
+            .store_cur_with_flags(
+                format!("{username}/INBOX"),
+                &path,
+                b"Subject: a\r\n\r\nb\r\n",
+                vec![],
+            )
+            .await
+            .unwrap();
+
+        let data = logged_in(username);
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::GetQuotaRoot,
+            arguments: &["INBOX"],
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = GetQuotaRoot { data: &data }
+            .exec(&mut tx, &database, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(format!("* QUOTAROOT INBOX \"{username}\""))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(format!("* QUOTA \"{username}\" (STORAGE 1 10 MESSAGE 1 5)"))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 OK GETQUOTAROOT completed"))
+        );
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_setquota_requires_admin() {
+        let (mut config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        let admin = "quota_admin@localhost";
+        let username = "quota_other@localhost";
+        database.add_user(username).await.unwrap();
+        config.mail.admins = vec![admin.to_string()];
+        let cmd_data = CommandData {
+            tag: "a1",
+            command: Commands::SetQuota,
+            arguments: &["\"quota_other@localhost\"", "(STORAGE", "512)"],
+        };
+
+        let data = logged_in(username);
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = SetQuota { data: &data }
+            .exec(&mut tx, &config, &database, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 NO [NOPERM] Only admins may change quotas"))
+        );
+
+        let data = logged_in(admin);
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = SetQuota { data: &data }
+            .exec(&mut tx, &config, &database, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(format!("* QUOTA \"{username}\" (STORAGE 0 512)"))
+        );
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a1 OK SETQUOTA completed"))
+        );
+        assert_eq!(
+            database.get_quota(username).await.unwrap().storage,
+            Some(512 * 1024)
+        );
+    }
+}
@@ -2,8 +2,12 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::commands::{CommandData, Data};
-use erooster_core::backend::storage::{MailStorage, Storage};
+use crate::commands::{acl::require_rights, CommandData, Data};
+use erooster_core::backend::{
+    acl::{MailboxName, Rights},
+    events::MailboxEvent,
+    storage::{MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -29,25 +33,46 @@ impl Rename<'_> {
     {
         let args = &command_data.arguments;
         assert!(args.len() == 2);
-        let old_folder = args[0].replace('/', ".");
-        let old_mailbox_path = storage.to_ondisk_path(
-            old_folder.clone(),
-            self.data
-                .con_state
-                .username
-                .clone()
-                .context("Username missing in internal State")?,
-        )?;
-        let new_folder = args[1].replace('/', ".");
-        let new_mailbox_path = storage.to_ondisk_path(
-            new_folder.clone(),
-            self.data
-                .con_state
-                .username
-                .clone()
-                .context("Username missing in internal State")?,
-        )?;
+        let username = self
+            .data
+            .con_state
+            .username
+            .clone()
+            .context("Username missing in internal State")?;
+        let old = MailboxName::resolve(args[0], &username);
+        let new = MailboxName::resolve(args[1], &username);
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &old,
+            Rights::DELETE_MAILBOX,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
+        // The mailbox stays in the directory of its owner
+        if old.owner != new.owner {
+            lines
+                .send(format!(
+                    "{} NO [CANNOT] Mailboxes can't be moved to another owner",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        let old_mailbox_path = storage.to_ondisk_path(args[0].to_string(), username.clone())?;
+        let new_mailbox_path = storage.to_ondisk_path(args[1].to_string(), username.clone())?;
         fs::rename(old_mailbox_path, new_mailbox_path).await?;
+        storage.move_acl(&old, Some(&new)).await?;
+        storage.move_metadata(&old, Some(&new)).await?;
+        storage.move_mailbox(&old, Some(&new)).await?;
+        storage.events().publish(MailboxEvent::Renamed {
+            mailbox: new.id(),
+            old: old.id(),
+        });
         lines
             .send(format!("{} OK RENAME completed", command_data.tag))
             .await?;
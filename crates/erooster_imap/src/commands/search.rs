@@ -6,7 +6,12 @@ use crate::{
     commands::{parsers::search_arguments, CommandData, Data},
     servers::state::{Capabilities, State},
 };
-use erooster_core::backend::storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::mailbox_id,
+    fulltext::{MessageText, TextField},
+    storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage},
+};
+use std::{borrow::Cow, collections::HashSet, path::Path};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -17,15 +22,18 @@ use {
 
 use super::parsers::{parse_search_date, SearchProgram, SearchReturnOption};
 
+/// The score from which on a FUZZY search key matches
+const FUZZY_MATCH: u8 = 75;
+
 pub struct Search<'a> {
-    pub data: &'a Data,
+    pub data: &'a mut Data,
 }
 
 impl Search<'_> {
     #[allow(clippy::too_many_lines, clippy::cognitive_complexity)]
     #[instrument(skip(self, lines, storage, command_data))]
     pub async fn exec<S, E>(
-        &self,
+        &mut self,
         lines: &mut S,
         storage: &Storage,
         command_data: &CommandData<'_>,
@@ -36,7 +44,7 @@ impl Search<'_> {
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
         if let State::Selected(folder, _) = &self.data.con_state.state {
-            let folder = folder.replace('/', ".");
+            let folder = folder.clone();
             let username = self
                 .data
                 .con_state
@@ -52,21 +60,16 @@ impl Search<'_> {
             debug!("Search arguments: {:?}", search_args);
 
             match search_arguments(search_args).finish() {
-                Ok((_, args)) => {
+                Ok((_, mut args)) => {
                     debug!(
                         "Resulting parsed arguments of the search query: {:#?}",
                         args
                     );
+                    fill_saved_result(&mut args.program, &self.data.con_state.saved_result);
 
-                    let mut mails = storage
-                        .list_all(format!("{username}/{folder}"), &mailbox_path)
-                        .await;
-                    // Assign sequence numbers (1-based, sorted by UID) before searching
-                    mails.sort_by_key(MaildirMailEntry::uid);
-                    for (idx, mail) in mails.iter_mut().enumerate() {
-                        mail.sequence_number =
-                            Some(u32::try_from(idx).expect("mail index fits u32") + 1);
-                    }
+                    let mailbox_id = mailbox_id(&folder, &username);
+                    let mut mails = selected_mails(storage, &mailbox_id, &mailbox_path).await;
+                    narrow_by_index(storage, &mailbox_id, &args.program, &mut mails);
 
                     let is_rev2 = self
                         .data
@@ -76,13 +79,35 @@ impl Search<'_> {
                     // Use ESEARCH when: client is in rev2 mode OR explicitly sent RETURN (...)
                     let use_esearch = is_rev2 || args.explicit_return;
 
-                    let mut results = parse_search_program(mails, &args.program, is_uid);
+                    let results = parse_search_program(&mut mails, &args.program, is_uid);
+
+                    // RFC 7162 §3.1.5: a search using MODSEQ reports the highest
+                    // mod-sequence of all matched messages.
+                    let highest_modseq = if uses_modseq(&args.program) {
+                        mails
+                            .iter()
+                            .filter(|mail| {
+                                let id = if is_uid {
+                                    Some(mail.uid())
+                                } else {
+                                    mail.sequence_number()
+                                };
+                                id.is_some_and(|id| results.contains(&id))
+                            })
+                            .map(MailEntry::modseq)
+                            .max()
+                    } else {
+                        None
+                    };
 
                     if !use_esearch {
                         // IMAP4rev1 compat: send legacy * SEARCH response
+                        let modseq = highest_modseq
+                            .map(|modseq| format!(" (MODSEQ {modseq})"))
+                            .unwrap_or_default();
                         lines
                             .feed(format!(
-                                "* SEARCH {}",
+                                "* SEARCH {}{modseq}",
                                 results
                                     .iter()
                                     .map(ToString::to_string)
@@ -97,151 +122,45 @@ impl Search<'_> {
                         return Ok(());
                     }
 
-                    let esearch_return_string = if results.is_empty() {
-                        if is_uid {
-                            format!("* ESEARCH (TAG \"{}\") UID", command_data.tag)
-                        } else {
-                            format!("* ESEARCH (TAG \"{}\")", command_data.tag)
+                    if let Err(reason) = check_return_options(&args.return_opts, &args.program) {
+                        // RFC 5182 §2.1: a failed SEARCH with SAVE empties the saved result
+                        if saved_results(&args.return_opts, &[]).is_some() {
+                            self.data.con_state.saved_result.clear();
                         }
-                    } else {
-                        match args.return_opts {
-                            SearchReturnOption::SAVE => {
-                                // Not implemented
-                                lines
-                                    .send(format!("{} BAD Not implemented", command_data.tag))
-                                    .await?;
-                                return Ok(());
-                            }
-                            SearchReturnOption::ALL => {
-                                if is_uid {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") UID ALL {}",
-                                        command_data.tag,
-                                        generate_ranges(&mut results)
-                                    )
-                                } else {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") ALL {}",
-                                        command_data.tag,
-                                        generate_ranges(&mut results)
-                                    )
-                                }
-                            }
-                            SearchReturnOption::MIN => {
-                                if is_uid {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") UID MIN {}",
-                                        command_data.tag,
-                                        results.iter().min().expect("No minimum value found")
-                                    )
-                                } else {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") MIN {}",
-                                        command_data.tag,
-                                        results.iter().min().expect("No minimum value found")
-                                    )
-                                }
-                            }
-                            SearchReturnOption::MAX => {
-                                if is_uid {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") UID MAX {}",
-                                        command_data.tag,
-                                        results.iter().max().expect("No maximum value found")
-                                    )
-                                } else {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") MAX {}",
-                                        command_data.tag,
-                                        results.iter().max().expect("No maximum value found")
-                                    )
-                                }
-                            }
-                            SearchReturnOption::COUNT => {
-                                if is_uid {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") UID COUNT {}",
-                                        command_data.tag,
-                                        results.len()
-                                    )
-                                } else {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") COUNT {}",
-                                        command_data.tag,
-                                        results.len()
-                                    )
-                                }
-                            }
-                            SearchReturnOption::Multiple(result_options) => {
-                                let mut result_options_string = String::new();
-                                for option in result_options {
-                                    match option {
-                                        SearchReturnOption::ALL => {
-                                            result_options_string.push_str(
-                                                format!("ALL {}", generate_ranges(&mut results))
-                                                    .as_str(),
-                                            );
-                                        }
-                                        SearchReturnOption::MIN => {
-                                            result_options_string.push_str(
-                                                format!(
-                                                    "MIN {}",
-                                                    results
-                                                        .iter()
-                                                        .min()
-                                                        .expect("No minimum value found")
-                                                )
-                                                .as_str(),
-                                            );
-                                        }
-                                        SearchReturnOption::MAX => {
-                                            result_options_string.push_str(
-                                                format!(
-                                                    "MAX {}",
-                                                    results
-                                                        .iter()
-                                                        .max()
-                                                        .expect("No maximum value found")
-                                                )
-                                                .as_str(),
-                                            );
-                                        }
-                                        SearchReturnOption::COUNT => {
-                                            result_options_string.push_str(
-                                                format!("COUNT {}", results.len()).as_str(),
-                                            );
-                                        }
-                                        SearchReturnOption::Multiple(_) => {
-                                            unreachable!("Multiple options cannot be nested. This type only exists for internal data structure and is unreachable.");
-                                        }
-                                        SearchReturnOption::SAVE => {
-                                            // Not yet implemented
-                                            lines
-                                                .send(format!(
-                                                    "{} BAD SAVE is not yet implemented",
-                                                    command_data.tag
-                                                ))
-                                                .await?;
-                                        }
-                                    }
-                                }
-                                if is_uid {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") UID {}",
-                                        command_data.tag, result_options_string
-                                    )
-                                } else {
-                                    format!(
-                                        "* ESEARCH (TAG \"{}\") {}",
-                                        command_data.tag, result_options_string
-                                    )
-                                }
-                            }
+                        lines
+                            .send(format!("{} BAD {reason}", command_data.tag))
+                            .await?;
+                        return Ok(());
+                    }
+                    if let Some(saved) = saved_results(&args.return_opts, &results) {
+                        self.data.con_state.saved_result = saved_uids(&saved, &mails, is_uid);
+                    }
+                    // RFC 5182 §2.4: SAVE on its own suppresses the ESEARCH response
+                    if !is_save_only(&args.return_opts) {
+                        let mut esearch_return_string =
+                            esearch_response(command_data.tag, is_uid, &args.return_opts, &results);
+                        if let Some(modseq) = highest_modseq {
+                            esearch_return_string =
+                                format!("{esearch_return_string} MODSEQ {modseq}");
                         }
-                    };
-
-                    debug!("esearch return_string: {:#?}", esearch_return_string);
-                    lines.feed(esearch_return_string).await?;
+                        if wants_relevancy(&args.return_opts) {
+                            let scores: Vec<u8> = mails
+                                .iter_mut()
+                                .filter(|mail| {
+                                    let id = if is_uid {
+                                        Some(mail.uid())
+                                    } else {
+                                        mail.sequence_number()
+                                    };
+                                    id.is_some_and(|id| results.contains(&id))
+                                })
+                                .map(|mail| relevancy(&args.program, mail, is_uid))
+                                .collect();
+                            esearch_return_string.push_str(&relevancy_data(&scores));
+                        }
+                        debug!("esearch return_string: {:#?}", esearch_return_string);
+                        lines.feed(esearch_return_string).await?;
+                    }
                     lines
                         .feed(format!("{} OK SEARCH completed", command_data.tag))
                         .await?;
@@ -274,8 +193,86 @@ impl Search<'_> {
     }
 }
 
+/// All messages of the mailbox ordered by UID, with their sequence numbers assigned
+pub async fn selected_mails(
+    storage: &Storage,
+    mailbox_id: &str,
+    mailbox_path: &Path,
+) -> Vec<MaildirMailEntry> {
+    let mut mails = storage.list_all(mailbox_id.to_string(), mailbox_path).await;
+    mails.sort_by_key(MaildirMailEntry::uid);
+    for (idx, mail) in mails.iter_mut().enumerate() {
+        mail.sequence_number = Some(u32::try_from(idx).expect("mail index fits u32") + 1);
+    }
+    mails
+}
+
+/// Drops the messages the full-text index rules out for the search program
+///
+/// Messages which are not indexed yet are kept. The remaining messages still
+/// have to be checked with [`check_search_condition`].
+pub fn narrow_by_index(
+    storage: &Storage,
+    mailbox_id: &str,
+    program: &SearchProgram,
+    mails: &mut Vec<MaildirMailEntry>,
+) {
+    // Read before the candidates, so every message counted as indexed is
+    // also in the index the candidates come from
+    let indexed = match storage.indexed_messages(mailbox_id) {
+        Ok(indexed) => indexed,
+        Err(e) => {
+            error!("Failed to query the search index: {e}");
+            return;
+        }
+    };
+    if indexed.is_empty() {
+        return;
+    }
+    if let Some(candidates) = index_candidates(storage, mailbox_id, program) {
+        mails.retain(|mail| !indexed.contains(mail.id()) || candidates.contains(mail.id()));
+    }
+}
+
+/// The ids of the only messages that can match the search program, if the
+/// full-text index knows them
+fn index_candidates(
+    storage: &Storage,
+    mailbox_id: &str,
+    program: &SearchProgram,
+) -> Option<HashSet<String>> {
+    let (field, query) = match program {
+        SearchProgram::TEXT(query) => (TextField::Text, query),
+        SearchProgram::BODY(query) => (TextField::Body, query),
+        SearchProgram::SUBJECT(query) => (TextField::Subject, query),
+        SearchProgram::FROM(query) => (TextField::From, query),
+        SearchProgram::TO(query) => (TextField::To, query),
+        SearchProgram::CC(query) => (TextField::Cc, query),
+        SearchProgram::BCC(query) => (TextField::Bcc, query),
+        SearchProgram::AND(programs) => {
+            return programs
+                .iter()
+                .filter_map(|program| index_candidates(storage, mailbox_id, program))
+                .reduce(|a, b| a.intersection(&b).cloned().collect());
+        }
+        SearchProgram::OR(a, b) => {
+            let a = index_candidates(storage, mailbox_id, a)?;
+            let b = index_candidates(storage, mailbox_id, b)?;
+            return Some(a.union(&b).cloned().collect());
+        }
+        _ => return None,
+    };
+    match storage.index_candidates(mailbox_id, field, query) {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            error!("Failed to query the search index: {e}");
+            None
+        }
+    }
+}
+
 fn parse_search_program(
-    mut mails: Vec<MaildirMailEntry>,
+    mails: &mut [MaildirMailEntry],
     program: &SearchProgram,
     is_uid: bool,
 ) -> Vec<u32> {
@@ -295,17 +292,362 @@ fn parse_search_program(
         .collect()
 }
 
+/// Whether the search program contains a MODSEQ search key
+fn uses_modseq(program: &SearchProgram) -> bool {
+    match program {
+        SearchProgram::MODSEQ(_) => true,
+        SearchProgram::NOT(program) => uses_modseq(program),
+        SearchProgram::OR(a, b) => uses_modseq(a) || uses_modseq(b),
+        SearchProgram::AND(programs) => programs.iter().any(uses_modseq),
+        _ => false,
+    }
+}
+
+/// Whether the search program contains a FUZZY search key
+fn uses_fuzzy(program: &SearchProgram) -> bool {
+    match program {
+        SearchProgram::FUZZY(_) => true,
+        SearchProgram::NOT(program) => uses_fuzzy(program),
+        SearchProgram::OR(a, b) => uses_fuzzy(a) || uses_fuzzy(b),
+        SearchProgram::AND(programs) => programs.iter().any(uses_fuzzy),
+        _ => false,
+    }
+}
+
+/// Whether the RELEVANCY return option was requested (RFC 6203)
+pub fn wants_relevancy(options: &SearchReturnOption) -> bool {
+    options
+        .options()
+        .iter()
+        .any(|option| matches!(option, SearchReturnOption::RELEVANCY))
+}
+
+/// Whether SAVE is the only return option, which leaves out the ESEARCH
+/// response (RFC 5182 §2.4)
+fn is_save_only(options: &SearchReturnOption) -> bool {
+    options
+        .options()
+        .iter()
+        .all(|option| matches!(option, SearchReturnOption::SAVE))
+}
+
+/// The results `RETURN (SAVE)` stores, if it was requested (RFC 5182 §2.4).
+///
+/// With MIN, MAX or PARTIAL but neither ALL nor COUNT only the reported
+/// results are saved.
+pub fn saved_results(options: &SearchReturnOption, results: &[u32]) -> Option<Vec<u32>> {
+    let options = options.options();
+    if !options
+        .iter()
+        .any(|option| matches!(option, SearchReturnOption::SAVE))
+    {
+        return None;
+    }
+    let everything = options.iter().all(|option| {
+        matches!(
+            option,
+            SearchReturnOption::SAVE | SearchReturnOption::RELEVANCY
+        )
+    }) || options
+        .iter()
+        .any(|option| matches!(option, SearchReturnOption::ALL | SearchReturnOption::COUNT));
+    if everything {
+        return Some(results.to_vec());
+    }
+    let mut saved = Vec::new();
+    for option in options {
+        match option {
+            SearchReturnOption::MIN => saved.extend(results.first()),
+            SearchReturnOption::MAX => saved.extend(results.last()),
+            SearchReturnOption::PARTIAL(range) => saved.extend_from_slice(range.window(results)),
+            _ => {}
+        }
+    }
+    Some(saved)
+}
+
+/// The UIDs of the results, which are sequence numbers unless `is_uid`
+pub fn saved_uids(results: &[u32], mails: &[MaildirMailEntry], is_uid: bool) -> Vec<u32> {
+    if is_uid {
+        return results.to_vec();
+    }
+    mails
+        .iter()
+        .filter(|mail| {
+            mail.sequence_number()
+                .is_some_and(|sequence| results.contains(&sequence))
+        })
+        .map(MailEntry::uid)
+        .collect()
+}
+
+/// Fills the `$` references of the search program with the saved UIDs
+pub fn fill_saved_result(program: &mut SearchProgram, saved: &[u32]) {
+    match program {
+        SearchProgram::SavedResult(uids) => *uids = saved.to_vec(),
+        SearchProgram::NOT(program) | SearchProgram::FUZZY(program) => {
+            fill_saved_result(program, saved);
+        }
+        SearchProgram::OR(a, b) => {
+            fill_saved_result(a, saved);
+            fill_saved_result(b, saved);
+        }
+        SearchProgram::AND(programs) => {
+            for program in programs {
+                fill_saved_result(program, saved);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The sequence set argument with the `$` reference (RFC 5182) replaced by
+/// the saved search result.
+///
+/// `mails` have to be ordered by UID. Messages which were expunged since the
+/// result was saved are left out.
+pub fn resolve_saved_result<'a>(
+    set: &'a str,
+    saved: &[u32],
+    mails: &[MaildirMailEntry],
+    is_uid: bool,
+) -> Cow<'a, str> {
+    if set != "$" {
+        return Cow::Borrowed(set);
+    }
+    let numbers: Vec<u32> = mails
+        .iter()
+        .zip(1u32..)
+        .filter(|(mail, _)| saved.contains(&mail.uid()))
+        .map(|(mail, sequence)| if is_uid { mail.uid() } else { sequence })
+        .collect();
+    if numbers.is_empty() {
+        Cow::Borrowed("")
+    } else {
+        Cow::Owned(generate_ranges(&numbers))
+    }
+}
+
+/// The RELEVANCY return data (RFC 6203 §4) for the scores of the results
+pub fn relevancy_data(scores: &[u8]) -> String {
+    format!(
+        " RELEVANCY ({})",
+        scores
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    )
+}
+
+/// The relevancy score of a matching message from 1 to 100.
+///
+/// It is the average score of the FUZZY keys the message matched by, the
+/// better branch counts for OR.
+pub fn relevancy(program: &SearchProgram, entry: &mut impl MailEntry, is_uid: bool) -> u8 {
+    fuzzy_relevancy(program, entry, is_uid)
+        .unwrap_or(100)
+        .max(1)
+}
+
+fn fuzzy_relevancy(
+    program: &SearchProgram,
+    entry: &mut impl MailEntry,
+    is_uid: bool,
+) -> Option<u8> {
+    match program {
+        SearchProgram::FUZZY(program) => Some(fuzzy_score(program, entry, is_uid)),
+        SearchProgram::OR(a, b) => {
+            fuzzy_relevancy(a, entry, is_uid).max(fuzzy_relevancy(b, entry, is_uid))
+        }
+        SearchProgram::AND(programs) => {
+            let scores: Vec<usize> = programs
+                .iter()
+                .filter_map(|program| fuzzy_relevancy(program, entry, is_uid))
+                .map(usize::from)
+                .collect();
+            (!scores.is_empty())
+                .then(|| u8::try_from(scores.iter().sum::<usize>() / scores.len()).unwrap_or(100))
+        }
+        _ => None,
+    }
+}
+
+/// How well the message matches the search key from 0 to 100.
+///
+/// Text keys compare the words of the MIME decoded text, all other keys score
+/// 100 if they match and 0 otherwise.
+fn fuzzy_score(program: &SearchProgram, entry: &mut impl MailEntry, is_uid: bool) -> u8 {
+    let (field, query) = match program {
+        SearchProgram::SUBJECT(query) => ("subject", query),
+        SearchProgram::FROM(query) => ("from", query),
+        SearchProgram::TO(query) => ("to", query),
+        SearchProgram::CC(query) => ("cc", query),
+        SearchProgram::BCC(query) => ("bcc", query),
+        SearchProgram::HEADER(name, query) => (name.as_str(), query),
+        SearchProgram::BODY(query) | SearchProgram::TEXT(query) => ("", query),
+        program => {
+            return if check_search_condition(program, entry, is_uid) {
+                100
+            } else {
+                0
+            };
+        }
+    };
+    let Ok(mail) = entry.parsed() else {
+        return 0;
+    };
+    let text = MessageText::from_parsed(&mail);
+    match program {
+        SearchProgram::BODY(_) => similarity(query, &text.body),
+        SearchProgram::TEXT(_) => {
+            similarity(query, &format!("{}\n{}", text.header_lines(), text.body))
+        }
+        _ => similarity(query, &text.header(field)),
+    }
+}
+
+/// How well `text` matches `query` from 0 to 100.
+///
+/// Case is ignored. Without the query as a whole in the text, every word of
+/// the query scores by the closest word of the text.
+fn similarity(query: &str, text: &str) -> u8 {
+    let query = query.to_lowercase();
+    let text = text.to_lowercase();
+    if text.contains(&query) {
+        return 100;
+    }
+    let words: Vec<Vec<char>> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().collect())
+        .collect();
+    let query_words: Vec<Vec<char>> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().collect())
+        .collect();
+    if query_words.is_empty() {
+        return 0;
+    }
+    let total: usize = query_words
+        .iter()
+        .map(|query_word| {
+            words
+                .iter()
+                .map(|word| {
+                    let longest = query_word.len().max(word.len());
+                    100 * longest.saturating_sub(edit_distance(query_word, word)) / longest
+                })
+                .max()
+                .unwrap_or(0)
+        })
+        .sum();
+    u8::try_from(total / query_words.len()).unwrap_or(100)
+}
+
+/// The optimal string alignment distance, counting insertions, deletions,
+/// substitutions and swaps of neighbouring characters
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Rejects return options which can not be answered for the search program
+pub fn check_return_options(
+    options: &SearchReturnOption,
+    program: &SearchProgram,
+) -> Result<(), &'static str> {
+    // RFC 6203 §4: scores only exist for FUZZY searches
+    if wants_relevancy(options) && !uses_fuzzy(program) {
+        return Err("RELEVANCY requires a FUZZY search key");
+    }
+    let options = options.options();
+    // RFC 9394 §3.1: PARTIAL and ALL are mutually exclusive
+    let partial = options
+        .iter()
+        .any(|option| matches!(option, SearchReturnOption::PARTIAL(_)));
+    let all = options
+        .iter()
+        .any(|option| matches!(option, SearchReturnOption::ALL));
+    if partial && all {
+        return Err("PARTIAL can not be combined with ALL");
+    }
+    Ok(())
+}
+
+/// The untagged ESEARCH response (RFC 4731) for the requested return options.
+///
+/// `results` are in the order they are reported in: ascending for SEARCH and
+/// in sort order for ESORT (RFC 5267), so MIN and MAX are the first and the
+/// last result. Options without a value are left out when nothing matched,
+/// except for COUNT and PARTIAL.
+pub fn esearch_response(
+    tag: &str,
+    is_uid: bool,
+    options: &SearchReturnOption,
+    results: &[u32],
+) -> String {
+    let mut response = format!("* ESEARCH (TAG \"{tag}\")");
+    if is_uid {
+        response.push_str(" UID");
+    }
+    for option in options.options() {
+        let data = match option {
+            SearchReturnOption::MIN => results.first().map(|min| format!("MIN {min}")),
+            SearchReturnOption::MAX => results.last().map(|max| format!("MAX {max}")),
+            SearchReturnOption::ALL => {
+                (!results.is_empty()).then(|| format!("ALL {}", generate_ranges(results)))
+            }
+            SearchReturnOption::COUNT => Some(format!("COUNT {}", results.len())),
+            SearchReturnOption::PARTIAL(range) => {
+                let window = range.window(results);
+                if window.is_empty() {
+                    Some(format!("PARTIAL ({range} NIL)"))
+                } else {
+                    Some(format!("PARTIAL ({range} {})", generate_ranges(window)))
+                }
+            }
+            SearchReturnOption::SAVE
+            | SearchReturnOption::RELEVANCY
+            | SearchReturnOption::Multiple(_) => None,
+        };
+        if let Some(data) = data {
+            response.push(' ');
+            response.push_str(&data);
+        }
+    }
+    response
+}
+
 /// Generates a string where continuous numbers are represented in a string as `<start>:<end>`.
 /// Singular numbers are represented as `<number>`.
 /// If there are gaps then there should be a "," between the ranges.
-/// Order is not required.
+/// The order of the results is kept, only ascending runs are merged.
 /// There MUST be no spaces before or after the returned string.
 ///
 /// # Example
 ///
 /// `1:3,5,7:9,11,13:15`
-fn generate_ranges(results: &mut Vec<u32>) -> String {
-    results.sort_unstable();
+fn generate_ranges(results: &[u32]) -> String {
     let mut ranges = Vec::new();
     let mut current_range = (0, 0);
     for result in results {
@@ -334,13 +676,14 @@ fn generate_ranges(results: &mut Vec<u32>) -> String {
 }
 
 #[allow(clippy::too_many_lines)]
-fn check_search_condition(
+pub fn check_search_condition(
     program: &SearchProgram,
     entry: &mut impl MailEntry,
     is_uid: bool,
 ) -> bool {
     match program {
-        SearchProgram::ALL => true,
+        // Every mailbox records save dates (RFC 8514 §4.3)
+        SearchProgram::ALL | SearchProgram::SAVEDATESUPPORTED => true,
         SearchProgram::ANSWERED => entry.is_replied(),
         SearchProgram::BCC(ref bcc) => {
             entry
@@ -389,7 +732,10 @@ fn check_search_condition(
                 header.get_key_ref().to_lowercase() == header_query.to_lowercase()
                     && header.get_value().contains(value)
             }),
-        SearchProgram::KEYWORD(_keyword) => false, // keyword flags not yet implemented
+        SearchProgram::KEYWORD(ref keyword) => entry
+            .keywords()
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword)),
         SearchProgram::LARGER(ref size) => entry.body_size() > *size,
         SearchProgram::NOT(program) => !check_search_condition(program, entry, is_uid),
         SearchProgram::ON(ref date) => {
@@ -463,11 +809,45 @@ fn check_search_condition(
                 })
         }
         SearchProgram::UID(ref uid) => uid.contains(&entry.uid()),
+        SearchProgram::SavedResult(ref uids) => uids.contains(&entry.uid()),
+        SearchProgram::FUZZY(program) => fuzzy_score(program, entry, is_uid) >= FUZZY_MATCH,
+        SearchProgram::MODSEQ(modseq) => entry.modseq() >= *modseq,
+        SearchProgram::EMAILID(ref email_id) => entry.email_id() == Some(email_id.as_str()),
+        SearchProgram::THREADID(ref thread_id) => entry.thread_id() == Some(thread_id.as_str()),
+        // Messages saved before save dates were recorded never match
+        SearchProgram::SAVEDBEFORE(ref date) => {
+            match (parse_search_date(date).finish(), entry.save_date()) {
+                (Ok((_, date)), Some(saved)) => {
+                    saved < date.midnight().assume_utc().unix_timestamp()
+                }
+                _ => false,
+            }
+        }
+        SearchProgram::SAVEDON(ref date) => {
+            match (parse_search_date(date).finish(), entry.save_date()) {
+                (Ok((_, date)), Some(saved)) => {
+                    let day = date.midnight().assume_utc().unix_timestamp();
+                    (day..day + 86400).contains(&saved)
+                }
+                _ => false,
+            }
+        }
+        SearchProgram::SAVEDSINCE(ref date) => {
+            match (parse_search_date(date).finish(), entry.save_date()) {
+                (Ok((_, date)), Some(saved)) => {
+                    saved >= date.midnight().assume_utc().unix_timestamp()
+                }
+                _ => false,
+            }
+        }
         SearchProgram::UNANSWERED => !entry.is_replied(),
         SearchProgram::UNDELETED => !entry.is_trashed(),
         SearchProgram::UNDRAFT => !entry.is_draft(),
         SearchProgram::UNFLAGGED => !entry.is_flagged(),
-        SearchProgram::UNKEYWORD(_keyword) => true, // keyword flags not yet implemented
+        SearchProgram::UNKEYWORD(ref keyword) => !entry
+            .keywords()
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword)),
         SearchProgram::UNSEEN => !entry.is_seen(),
         SearchProgram::OR(a, b) => {
             check_search_condition(a, entry, is_uid) || check_search_condition(b, entry, is_uid)
@@ -493,28 +873,83 @@ fn check_search_condition(
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
-    use crate::commands::parsers::search_arguments;
+    use crate::commands::{parsers::search_arguments, Commands};
+    use crate::servers::state::{Access, Connection};
+    use futures::{channel::mpsc, StreamExt};
     use nom::Finish;
     use nom_language::error::VerboseError;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_generate_ranges_single() {
-        assert_eq!(generate_ranges(&mut vec![5]), "5");
+        assert_eq!(generate_ranges(&[5]), "5");
     }
 
     #[test]
     fn test_generate_ranges_contiguous() {
-        assert_eq!(generate_ranges(&mut vec![1, 2, 3]), "1:3");
+        assert_eq!(generate_ranges(&[1, 2, 3]), "1:3");
     }
 
     #[test]
     fn test_generate_ranges_gap() {
-        assert_eq!(generate_ranges(&mut vec![1, 3, 5]), "1,3,5");
+        assert_eq!(generate_ranges(&[1, 3, 5]), "1,3,5");
     }
 
     #[test]
     fn test_generate_ranges_mixed() {
-        assert_eq!(generate_ranges(&mut vec![1, 2, 3, 5, 7, 8, 9]), "1:3,5,7:9");
+        assert_eq!(generate_ranges(&[1, 2, 3, 5, 7, 8, 9]), "1:3,5,7:9");
+    }
+
+    #[test]
+    fn test_esearch_response_partial() {
+        let (_, args) = search_arguments("RETURN (MIN COUNT PARTIAL -1:-2) ALL")
+            .finish()
+            .unwrap();
+        assert_eq!(
+            esearch_response("a1", true, &args.return_opts, &[3, 4, 7, 8]),
+            "* ESEARCH (TAG \"a1\") UID MIN 3 COUNT 4 PARTIAL (-1:-2 7:8)"
+        );
+        assert_eq!(
+            esearch_response("a1", false, &args.return_opts, &[]),
+            "* ESEARCH (TAG \"a1\") COUNT 0 PARTIAL (-1:-2 NIL)"
+        );
+        let (_, args) = search_arguments("RETURN (ALL PARTIAL 1:5) ALL")
+            .finish()
+            .unwrap();
+        assert!(check_return_options(&args.return_opts, &args.program).is_err());
+    }
+
+    #[test]
+    fn test_fuzzy_similarity() {
+        assert_eq!(similarity("Meeting", "Re: the meeting notes"), 100);
+        assert!(similarity("recieve", "we receive mail") >= FUZZY_MATCH);
+        assert!(similarity("helo wrld", "hello world") >= FUZZY_MATCH);
+        assert!(similarity("car", "a cat") < FUZZY_MATCH);
+        assert_eq!(similarity("anything", ""), 0);
+    }
+
+    #[test]
+    fn test_saved_results() {
+        let results = [2, 4, 6, 8];
+        let saved = |options: &str| {
+            let (_, args) = search_arguments(&format!("RETURN ({options}) ALL"))
+                .finish()
+                .unwrap();
+            saved_results(&args.return_opts, &results)
+        };
+        assert_eq!(saved("MIN"), None);
+        assert_eq!(saved("SAVE"), Some(vec![2, 4, 6, 8]));
+        assert_eq!(saved("SAVE COUNT MIN"), Some(vec![2, 4, 6, 8]));
+        assert_eq!(saved("SAVE MIN MAX"), Some(vec![2, 8]));
+        assert_eq!(saved("SAVE PARTIAL 2:3"), Some(vec![4, 6]));
+    }
+
+    #[test]
+    fn test_uses_modseq_nested() {
+        let (_, args) = search_arguments("NOT (SEEN MODSEQ 5)").finish().unwrap();
+        assert!(uses_modseq(&args.program));
+        let (_, args) = search_arguments("SEEN").finish().unwrap();
+        assert!(!uses_modseq(&args.program));
     }
 
     #[test]
@@ -534,4 +969,289 @@ mod tests {
         let (_, args) = search_arguments("RETURN (MIN) ALL").finish().unwrap();
         assert!(args.explicit_return);
     }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_keyword_search_matches_stored_keywords() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let mailbox_id = "test_keyword_search/INBOX";
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_keyword_search".to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        storage
+            .store_cur_with_flags(
+                mailbox_id.to_string(),
+                &path,
+                b"Subject: a\r\n\r\nb\r\n",
+                vec![String::from("$Junk")],
+            )
+            .await
+            .unwrap();
+        let mut mails = storage.list_all(mailbox_id.to_string(), &path).await;
+
+        let (_, args) = search_arguments("KEYWORD $junk").finish().unwrap();
+        assert!(check_search_condition(&args.program, &mut mails[0], false));
+        let (_, args) = search_arguments("UNKEYWORD $Label1").finish().unwrap();
+        assert!(check_search_condition(&args.program, &mut mails[0], false));
+        let (_, args) = search_arguments("UNKEYWORD $Junk").finish().unwrap();
+        assert!(!check_search_condition(&args.program, &mut mails[0], false));
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_object_id_and_save_date_search() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let mailbox_id = "test_objectid_search/INBOX";
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_objectid_search".to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        for message in [
+            "Message-ID: <a@localhost>\r\nSubject: a\r\n\r\nb\r\n",
+            "Message-ID: <b@localhost>\r\nReferences: <a@localhost>\r\n\r\nc\r\n",
+            "Subject: other\r\n\r\nd\r\n",
+        ] {
+            storage
+                .store_cur_with_flags(mailbox_id.to_string(), &path, message.as_bytes(), vec![])
+                .await
+                .unwrap();
+        }
+        let mut mails = selected_mails(&storage, mailbox_id, &path).await;
+        let email_id = mails[0].email_id().unwrap().to_string();
+        let thread_id = mails[0].thread_id().unwrap().to_string();
+
+        let matches = |query: &str, mails: &mut [MaildirMailEntry]| {
+            let (_, args) = search_arguments(query).finish().unwrap();
+            mails
+                .iter_mut()
+                .filter_map(|mail| check_search_condition(&args.program, mail, false).then_some(()))
+                .count()
+        };
+        assert_eq!(matches(&format!("EMAILID {email_id}"), &mut mails), 1);
+        assert_eq!(matches(&format!("THREADID {thread_id}"), &mut mails), 2);
+        assert_eq!(matches("SAVEDSINCE 01-Jan-2000", &mut mails), 3);
+        assert_eq!(matches("SAVEDBEFORE 01-Jan-2000", &mut mails), 0);
+        assert_eq!(matches("SAVEDON 01-Jan-2000", &mut mails), 0);
+        assert_eq!(matches("SAVEDATESUPPORTED", &mut mails), 3);
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_body_search_uses_index_and_decoded_text() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let mailbox_id = "test_index_search/INBOX";
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_index_search".to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        for message in [
+            concat!(
+                "Subject: report\r\n",
+                "Content-Type: multipart/mixed; boundary=b\r\n",
+                "\r\n",
+                "--b\r\n",
+                "Content-Type: text/plain\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "SGVsbG8gd29ybGQ=\r\n",
+                "--b\r\n",
+                "Content-Type: application/pdf; name=\"invoice.pdf\"\r\n",
+                "\r\n",
+                "%PDF\r\n",
+                "--b--\r\n",
+            ),
+            "Subject: other\r\n\r\nnothing to see\r\n",
+        ] {
+            storage
+                .store_cur_with_flags(mailbox_id.to_string(), &path, message.as_bytes(), vec![])
+                .await
+                .unwrap();
+        }
+        // Waits until both messages are in the index
+        assert_eq!(storage.reindex("test_index_search").await.unwrap(), 2);
+
+        for (query, expected) in [
+            ("BODY world", vec![1]),
+            ("BODY invoice.pdf", vec![1]),
+            ("OR BODY nothing SUBJECT report", vec![1, 2]),
+            ("NOT BODY world", vec![2]),
+            ("BODY SGVsbG8", vec![]),
+        ] {
+            let (_, args) = search_arguments(query).finish().unwrap();
+            let mut mails = selected_mails(&storage, mailbox_id, &path).await;
+            narrow_by_index(&storage, mailbox_id, &args.program, &mut mails);
+            assert_eq!(
+                parse_search_program(&mut mails, &args.program, true),
+                expected,
+                "{query}"
+            );
+        }
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_index_search_checks_unindexed_messages() {
+        let (mut config, database, storage) = erooster_core::test_helpers::setup_test_database()
+            .await
+            .unwrap();
+        let mailbox_id = "test_unindexed_search/INBOX";
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), "test_unindexed_search".to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        storage
+            .store_cur_with_flags(
+                mailbox_id.to_string(),
+                &path,
+                b"Subject: one\r\n\r\nhello world\r\n",
+                vec![],
+            )
+            .await
+            .unwrap();
+        assert_eq!(storage.reindex("test_unindexed_search").await.unwrap(), 1);
+        // A message the index has not seen yet
+        config.search_index = None;
+        erooster_core::backend::storage::get_storage(database, config)
+            .store_cur_with_flags(
+                mailbox_id.to_string(),
+                &path,
+                b"Subject: two\r\n\r\nhello again world\r\n",
+                vec![],
+            )
+            .await
+            .unwrap();
+
+        for (query, expected) in [
+            ("BODY world", vec![1, 2]),
+            ("BODY again", vec![2]),
+            ("SUBJECT one", vec![1]),
+        ] {
+            let (_, args) = search_arguments(query).finish().unwrap();
+            let mut mails = selected_mails(&storage, mailbox_id, &path).await;
+            narrow_by_index(&storage, mailbox_id, &args.program, &mut mails);
+            assert_eq!(
+                parse_search_program(&mut mails, &args.program, true),
+                expected,
+                "{query}"
+            );
+        }
+    }
+
+    #[allow(clippy::unwrap_used)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_fuzzy_relevancy_and_saved_result() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let username = "test_searchres_user";
+        let mailbox_id = format!("{username}/INBOX");
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), username.to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        for message in [
+            "Subject: Quarterly report\r\n\r\nnumbers\r\n",
+            "Subject: lunch\r\n\r\nnothing\r\n",
+            "Subject: Quartely reprot\r\n\r\ntypos\r\n",
+        ] {
+            storage
+                .store_cur_with_flags(mailbox_id.clone(), &path, message.as_bytes(), vec![])
+                .await
+                .unwrap();
+        }
+        let uids: Vec<u32> = selected_mails(&storage, &mailbox_id, &path)
+            .await
+            .iter()
+            .map(MailEntry::uid)
+            .collect();
+
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Selected(String::from("INBOX"), Access::ReadWrite),
+                secure: true,
+                username: Some(username.to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let cases: [(&[&str], Option<&str>); 5] = [
+            (&["RETURN", "(RELEVANCY)", "SUBJECT", "report"], None),
+            (
+                &[
+                    "RETURN",
+                    "(ALL",
+                    "RELEVANCY)",
+                    "FUZZY",
+                    "SUBJECT",
+                    "quarterly",
+                ],
+                Some("* ESEARCH (TAG \"a1\") ALL 1,3 RELEVANCY (100 88)"),
+            ),
+            (
+                &["RETURN", "(SAVE)", "FUZZY", "SUBJECT", "quarterly"],
+                Some(""),
+            ),
+            (
+                &["RETURN", "(COUNT)", "NOT", "$"],
+                Some("* ESEARCH (TAG \"a1\") COUNT 1"),
+            ),
+            (
+                &["RETURN", "(SAVE", "MIN)", "$"],
+                Some("* ESEARCH (TAG \"a1\") MIN 1"),
+            ),
+        ];
+        for (arguments, expected) in cases {
+            let cmd_data = CommandData {
+                tag: "a1",
+                command: Commands::Search,
+                arguments,
+            };
+            let (mut tx, mut rx) = mpsc::unbounded();
+            let res = Search { data: &mut data }
+                .exec(&mut tx, &storage, &cmd_data, false)
+                .await;
+            assert!(res.is_ok(), "{res:?}");
+            let Some(expected) = expected else {
+                assert_eq!(
+                    rx.next().await,
+                    Some(String::from("a1 BAD RELEVANCY requires a FUZZY search key"))
+                );
+                continue;
+            };
+            if !expected.is_empty() {
+                assert_eq!(rx.next().await, Some(String::from(expected)));
+            }
+            assert_eq!(
+                rx.next().await,
+                Some(String::from("a1 OK SEARCH completed"))
+            );
+        }
+        assert_eq!(data.con_state.saved_result, vec![uids[0]]);
+
+        let mails = selected_mails(&storage, &mailbox_id, &path).await;
+        let saved = &data.con_state.saved_result;
+        assert_eq!(resolve_saved_result("$", saved, &mails, false), "1");
+        assert_eq!(
+            resolve_saved_result("$", saved, &mails, true),
+            uids[0].to_string()
+        );
+        assert_eq!(resolve_saved_result("$", &[], &mails, true), "");
+        assert_eq!(resolve_saved_result("2:*", saved, &mails, true), "2:*");
+    }
 }
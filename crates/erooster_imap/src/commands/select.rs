@@ -3,17 +3,30 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{CommandData, Data},
+    commands::{
+        acl::require_rights,
+        copy::uid_set_string,
+        fetch::generate_response,
+        parsers::{
+            select_parameters, FetchArguments, FetchAttributes, QResyncParameters,
+            SelectParameters,
+        },
+        CommandData, Data,
+    },
     servers::state::{Access, State},
 };
-use erooster_core::backend::storage::{MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{MailboxName, Rights},
+    storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage},
+};
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
+    nom::Finish,
     tokio::fs,
     tracing::instrument,
 };
@@ -71,6 +84,62 @@ where
         return Ok(());
     };
     let folder = folder_arg.replace('"', "");
+
+    // RFC 7162 §3.1.8 / §3.2.5: optional (CONDSTORE) or (QRESYNC (...)) parameters.
+    let parameters = if args.len() > 1 {
+        let parameters = args[1..].join(" ");
+        if let Ok((_, parameters)) = select_parameters(&parameters).finish() {
+            parameters
+        } else {
+            lines
+                .send(format!(
+                    "{} BAD [CLIENTBUG] Unable to parse SELECT parameters",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+    } else {
+        SelectParameters::default()
+    };
+    if parameters.qresync.is_some() && !data.con_state.qresync_enabled() {
+        lines
+            .send(format!(
+                "{} BAD [CLIENTBUG] QRESYNC must be enabled first",
+                command_data.tag
+            ))
+            .await?;
+        return Ok(());
+    }
+    if parameters.condstore || parameters.qresync.is_some() {
+        data.con_state.enable_condstore();
+    }
+
+    let username = data
+        .con_state
+        .username
+        .clone()
+        .context("Username missing in internal State")?;
+    let mailbox = MailboxName::resolve(&folder, &username);
+    if !require_rights(
+        lines,
+        storage,
+        &username,
+        &mailbox,
+        Rights::READ,
+        command_data.tag,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+    // RFC 4314 §4: without any right to change the mailbox it is opened read-only
+    let rw = rw
+        && storage
+            .my_rights(&mailbox, &username)
+            .await?
+            .intersects(Rights::MODIFY);
+
     let access = if rw {
         Access::ReadWrite
     } else {
@@ -87,17 +156,14 @@ where
 
     {
         data.con_state.state = State::Selected(folder.clone(), access);
+        // RFC 5182 §2.1: the saved search result starts out empty in every mailbox
+        data.con_state.saved_result.clear();
     };
 
-    let username = data
-        .con_state
-        .username
-        .clone()
-        .context("Username missing in internal State")?;
     let folder_on_disk = folder_arg;
     let mailbox_path = storage.to_ondisk_path((*folder_on_disk).to_string(), username.clone())?;
-    let folder_ondisk_name = storage.to_ondisk_path_name(folder.clone())?;
-    let mailbox_id = format!("{username}/{folder_ondisk_name}");
+    // Same key the other commands use for the mails table.
+    let mailbox_id = mailbox.id();
     // Special INBOX check to make sure we have a mailbox
     if folder == "INBOX" && !mailbox_path.exists() {
         storage.create_dirs(&mailbox_path)?;
@@ -111,13 +177,15 @@ where
         storage,
         mailbox_path,
         rw,
+        parameters.qresync,
         command_data,
     )
     .await?;
     Ok(())
 }
 
-#[instrument(skip(lines, folder, mailbox_id, storage, mailbox_path, rw, command_data))]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(lines, folder, mailbox_id, storage, mailbox_path, rw, qresync, command_data))]
 async fn send_success<S, E>(
     lines: &mut S,
     folder: String,
@@ -125,6 +193,7 @@ async fn send_success<S, E>(
     storage: &Storage,
     mailbox_path: PathBuf,
     rw: bool,
+    qresync: Option<QResyncParameters>,
     command_data: &CommandData<'_>,
 ) -> color_eyre::eyre::Result<()>
 where
@@ -146,14 +215,27 @@ where
             current_uid + 1,
         ))
         .await?;
+    let highest_modseq = storage.get_highest_modseq(&mailbox_id).await?;
     lines
-        .feed(String::from(
-            "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)",
-        ))
+        .feed(format!("* OK [HIGHESTMODSEQ {highest_modseq}] Highest"))
         .await?;
+    if let Some(mailbox) = MailboxName::from_id(&mailbox_id) {
+        let object_id = storage.mailbox_object_id(&mailbox).await?;
+        lines
+            .feed(format!("* OK [MAILBOXID ({object_id})] Ok"))
+            .await?;
+    }
+    // User-defined keywords are listed next to the system flags and clients
+    // may create new ones with STORE or APPEND.
+    let mut flags = vec!["\\Answered", "\\Flagged", "\\Deleted", "\\Seen", "\\Draft"];
+    let keywords = storage.get_keywords(&mailbox_id).await?;
+    flags.extend(keywords.iter().map(String::as_str));
+    lines.feed(format!("* FLAGS ({})", flags.join(" "))).await?;
+    flags.push("\\*");
     lines
-        .feed(String::from(
-            "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\*)] Limited",
+        .feed(format!(
+            "* OK [PERMANENTFLAGS ({})] Permanent flags",
+            flags.join(" ")
         ))
         .await?;
 
@@ -176,6 +258,13 @@ where
             .await?;
     }
 
+    if let Some(qresync) = qresync {
+        // RFC 7162 §3.2.5.1: a stale UIDVALIDITY means the client must do a full resync.
+        if qresync.uidvalidity == uidvalidity {
+            send_qresync_changes(lines, &mailbox_id, storage, &mailbox_path, &qresync).await?;
+        }
+    }
+
     let resp = if rw {
         format!("{} OK [READ-WRITE] SELECT completed", command_data.tag)
     } else {
@@ -186,6 +275,60 @@ where
     Ok(())
 }
 
+/// Sends the VANISHED (EARLIER) and FETCH responses a QRESYNC client needs to
+/// catch up from its cached mod-sequence.
+async fn send_qresync_changes<S, E>(
+    lines: &mut S,
+    mailbox_id: &str,
+    storage: &Storage,
+    mailbox_path: &Path,
+    qresync: &QResyncParameters,
+) -> color_eyre::eyre::Result<()>
+where
+    E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+    S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+{
+    let is_known = |uid: &u32| {
+        qresync
+            .known_uids
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|range| range.contains(uid)))
+    };
+
+    let vanished: Vec<u32> = storage
+        .get_vanished_since(mailbox_id, qresync.modseq)
+        .await?
+        .into_iter()
+        .filter(is_known)
+        .collect();
+    if !vanished.is_empty() {
+        lines
+            .feed(format!("* VANISHED (EARLIER) {}", uid_set_string(&vanished)))
+            .await?;
+    }
+
+    let mut mails = storage
+        .list_all(mailbox_id.to_string(), mailbox_path)
+        .await;
+    mails.sort_by_key(MaildirMailEntry::uid);
+    for (index, mail) in mails.iter_mut().enumerate() {
+        if mail.modseq() <= qresync.modseq || !is_known(&mail.uid()) {
+            continue;
+        }
+        let attributes = FetchArguments::List(vec![
+            FetchAttributes::Uid,
+            FetchAttributes::Flags,
+            FetchAttributes::Modseq,
+        ]);
+        if let Some(resp) = generate_response(attributes, mail)? {
+            lines
+                .feed(format!("* {} FETCH ({resp})", index + 1))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
 impl Select<'_> {
     #[instrument(skip(self, lines, storage, command_data))]
     pub async fn exec<S, E>(
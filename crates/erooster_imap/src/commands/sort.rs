@@ -0,0 +1,470 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! SORT extension (RFC 5256) including the DISPLAYFROM and DISPLAYTO keys
+//! of SORT=DISPLAY (RFC 5957) and the ESEARCH style results of ESORT
+//! (RFC 5267).
+//!
+//! The search program is evaluated with the same code as SEARCH, the matching
+//! messages are then ordered by the requested keys. Messages that compare
+//! equal are ordered by their sequence number.
+
+use crate::{
+    commands::{
+        parsers::{sort_arguments, SortKey},
+        search::{
+            check_return_options, check_search_condition, esearch_response, fill_saved_result,
+            narrow_by_index, relevancy, relevancy_data, saved_results, saved_uids, selected_mails,
+            wants_relevancy,
+        },
+        CommandData, Data,
+    },
+    servers::state::State,
+};
+use erooster_core::backend::{
+    acl::mailbox_id,
+    storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage},
+};
+use std::cmp::Ordering;
+use {
+    color_eyre::{self, eyre::ContextCompat},
+    futures::{Sink, SinkExt},
+    mailparse::{addrparse_header, MailAddr, SingleInfo},
+    nom::Finish,
+    nom_language::error::convert_error,
+    tracing::{debug, error, instrument},
+};
+
+pub struct Sort<'a> {
+    pub data: &'a mut Data,
+}
+
+impl Sort<'_> {
+    #[allow(clippy::too_many_lines)]
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &mut self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+        is_uid: bool,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let State::Selected(folder, _) = &self.data.con_state.state else {
+            lines
+                .send(format!(
+                    "{} NO [TRYCREATE] No mailbox selected",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+        let username = self
+            .data
+            .con_state
+            .username
+            .clone()
+            .context("Username missing in internal State")?;
+        let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
+        let mailbox_id = mailbox_id(folder, &username);
+
+        let offset = usize::from(is_uid);
+        let arguments = command_data.arguments[offset..].join(" ");
+        let mut args = match sort_arguments(&arguments).finish() {
+            Ok((_, args)) => args,
+            Err(e) => {
+                error!(
+                    "Failed to parse sort arguments: {}",
+                    convert_error(arguments.as_str(), e)
+                );
+                lines
+                    .send(format!("{} BAD Unable to parse", command_data.tag))
+                    .await?;
+                return Ok(());
+            }
+        };
+        debug!("Parsed sort arguments: {:#?}", args);
+        if !is_supported_charset(&args.charset) {
+            lines
+                .send(format!(
+                    "{} NO [BADCHARSET (US-ASCII UTF-8)] Unsupported charset",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        if let Some(Err(reason)) = args
+            .return_opts
+            .as_ref()
+            .map(|return_opts| check_return_options(return_opts, &args.program))
+        {
+            if let Some(return_opts) = &args.return_opts {
+                if saved_results(return_opts, &[]).is_some() {
+                    self.data.con_state.saved_result.clear();
+                }
+            }
+            lines
+                .send(format!("{} BAD {reason}", command_data.tag))
+                .await?;
+            return Ok(());
+        }
+        fill_saved_result(&mut args.program, &self.data.con_state.saved_result);
+
+        let mut mails = selected_mails(storage, &mailbox_id, &mailbox_path).await;
+        narrow_by_index(storage, &mailbox_id, &args.program, &mut mails);
+        mails.retain_mut(|mail| check_search_condition(&args.program, mail, is_uid));
+
+        let scores = args.return_opts.as_ref().is_some_and(wants_relevancy);
+        let mut sorted: Vec<(Vec<SortValue>, u32, u32, u8)> = mails
+            .iter_mut()
+            .map(|mail| {
+                let values = args
+                    .criteria
+                    .iter()
+                    .map(|criterion| sort_value(mail, criterion.key))
+                    .collect();
+                let sequence = mail.sequence_number().unwrap_or_default();
+                let id = if is_uid { mail.uid() } else { sequence };
+                let score = if scores {
+                    relevancy(&args.program, mail, is_uid)
+                } else {
+                    0
+                };
+                (values, sequence, id, score)
+            })
+            .collect();
+        sorted.sort_by(|(a, a_sequence, _, _), (b, b_sequence, _, _)| {
+            args.criteria
+                .iter()
+                .zip(a.iter().zip(b))
+                .map(|(criterion, (a, b))| {
+                    if criterion.reverse {
+                        b.cmp(a)
+                    } else {
+                        a.cmp(b)
+                    }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+                .then(a_sequence.cmp(b_sequence))
+        });
+
+        if let Some(return_opts) = &args.return_opts {
+            let ids: Vec<u32> = sorted.iter().map(|(_, _, id, _)| *id).collect();
+            if let Some(saved) = saved_results(return_opts, &ids) {
+                self.data.con_state.saved_result = saved_uids(&saved, &mails, is_uid);
+            }
+            let mut response = esearch_response(command_data.tag, is_uid, return_opts, &ids);
+            if scores {
+                let scores: Vec<u8> = sorted.iter().map(|(_, _, _, score)| *score).collect();
+                response.push_str(&relevancy_data(&scores));
+            }
+            lines.feed(response).await?;
+            lines
+                .feed(format!("{} OK SORT completed", command_data.tag))
+                .await?;
+            lines.flush().await?;
+            return Ok(());
+        }
+
+        let ids: Vec<String> = sorted.iter().map(|(_, _, id, _)| id.to_string()).collect();
+        if ids.is_empty() {
+            lines.feed(String::from("* SORT")).await?;
+        } else {
+            lines.feed(format!("* SORT {}", ids.join(" "))).await?;
+        }
+        lines
+            .feed(format!("{} OK SORT completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+/// Whether SORT and THREAD can handle search strings in the charset
+pub const fn is_supported_charset(charset: &str) -> bool {
+    charset.eq_ignore_ascii_case("UTF-8") || charset.eq_ignore_ascii_case("US-ASCII")
+}
+
+/// The value a message is compared by for a single sort key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(i64),
+    Text(String),
+}
+
+fn sort_value(mail: &mut MaildirMailEntry, key: SortKey) -> SortValue {
+    match key {
+        SortKey::Arrival => SortValue::Number(mail.received().unwrap_or_default()),
+        SortKey::Date => SortValue::Number(sent_date(mail)),
+        SortKey::Size => SortValue::Number(mail.parsed().map_or(0, |parsed| {
+            i64::try_from(parsed.raw_bytes.len()).unwrap_or(i64::MAX)
+        })),
+        SortKey::Subject => SortValue::Text(
+            header_value(mail, "subject")
+                .map(|subject| base_subject(&subject).0)
+                .unwrap_or_default(),
+        ),
+        SortKey::From => SortValue::Text(address_mailbox(mail, "from")),
+        SortKey::To => SortValue::Text(address_mailbox(mail, "to")),
+        SortKey::Cc => SortValue::Text(address_mailbox(mail, "cc")),
+        SortKey::DisplayFrom => SortValue::Text(display_name(mail, "from")),
+        SortKey::DisplayTo => SortValue::Text(display_name(mail, "to")),
+    }
+}
+
+/// The date from the `Date` header, falling back to the internal date
+pub fn sent_date(mail: &mut MaildirMailEntry) -> i64 {
+    mail.sent().or_else(|_| mail.received()).unwrap_or_default()
+}
+
+/// The decoded value of the first header with the given name
+pub fn header_value(mail: &mut MaildirMailEntry, name: &str) -> Option<String> {
+    mail.headers()
+        .ok()?
+        .iter()
+        .find(|header| header.get_key_ref().eq_ignore_ascii_case(name))
+        .map(mailparse::MailHeader::get_value)
+}
+
+fn first_address(mail: &mut MaildirMailEntry, name: &str) -> Option<SingleInfo> {
+    let headers = mail.headers().ok()?;
+    let header = headers
+        .iter()
+        .find(|header| header.get_key_ref().eq_ignore_ascii_case(name))?;
+    addrparse_header(header)
+        .ok()?
+        .iter()
+        .find_map(|address| match address {
+            MailAddr::Single(info) => Some(info.clone()),
+            MailAddr::Group(group) => group.addrs.first().cloned(),
+        })
+}
+
+/// The local part of the first address, compared case-insensitively
+fn address_mailbox(mail: &mut MaildirMailEntry, name: &str) -> String {
+    first_address(mail, name)
+        .map(|info| {
+            info.addr
+                .split('@')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_uppercase()
+        })
+        .unwrap_or_default()
+}
+
+/// The display name of the first address, or the address itself if it has none
+fn display_name(mail: &mut MaildirMailEntry, name: &str) -> String {
+    first_address(mail, name)
+        .map(|info| {
+            info.display_name
+                .filter(|display_name| !display_name.trim().is_empty())
+                .unwrap_or(info.addr)
+                .to_ascii_uppercase()
+        })
+        .unwrap_or_default()
+}
+
+/// Extracts the base subject as described in RFC 5256 §2.1
+///
+/// The result is upper cased so it can be compared directly. The second
+/// value tells whether the subject indicated a reply or a forward.
+pub fn base_subject(subject: &str) -> (String, bool) {
+    let mut subject = subject.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut reply = false;
+    loop {
+        // Trailing "(fwd)"
+        loop {
+            let trimmed = subject.trim_end();
+            let lower = trimmed.to_ascii_lowercase();
+            if lower.ends_with("(fwd)") {
+                subject = trimmed[..trimmed.len() - 5].to_string();
+                reply = true;
+            } else {
+                subject = trimmed.to_string();
+                break;
+            }
+        }
+        // Leading "Re:", "Fwd:" and "[blob]"s
+        loop {
+            let trimmed = subject.trim_start();
+            if let Some(rest) = strip_reply_prefix(trimmed) {
+                subject = rest.to_string();
+                reply = true;
+            } else if let Some(rest) = strip_blob(trimmed).filter(|rest| !rest.is_empty()) {
+                subject = rest.to_string();
+            } else {
+                subject = trimmed.to_string();
+                break;
+            }
+        }
+        // "[fwd: ...]" wrapper
+        let lower = subject.to_ascii_lowercase();
+        if lower.starts_with("[fwd:") && lower.ends_with(']') {
+            subject = subject[5..subject.len() - 1].to_string();
+            reply = true;
+        } else {
+            break;
+        }
+    }
+    (subject.to_ascii_uppercase(), reply)
+}
+
+/// Strips `re`, `fw` or `fwd` with an optional blob and the colon
+fn strip_reply_prefix(subject: &str) -> Option<&str> {
+    let lower = subject.to_ascii_lowercase();
+    let prefix = ["fwd", "fw", "re"]
+        .iter()
+        .find(|prefix| lower.starts_with(*prefix))?;
+    let rest = subject[prefix.len()..].trim_start();
+    let rest = strip_blob(rest).unwrap_or(rest);
+    rest.strip_prefix(':')
+}
+
+/// Strips a leading `[...]` blob like a mailing list tag
+fn strip_blob(subject: &str) -> Option<&str> {
+    let inner = subject.strip_prefix('[')?;
+    let end = inner.find(['[', ']'])?;
+    inner[end..].strip_prefix(']').map(str::trim_start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Commands;
+    use crate::servers::state::{Access, Connection};
+    use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_base_subject() {
+        assert_eq!(base_subject("Hello"), (String::from("HELLO"), false));
+        assert_eq!(
+            base_subject("Re: [list]  Fwd: hello   world (fwd)"),
+            (String::from("HELLO WORLD"), true)
+        );
+        assert_eq!(
+            base_subject("[Fwd: Re[2]: meeting]"),
+            (String::from("MEETING"), true)
+        );
+        assert_eq!(base_subject("[list]"), (String::from("[LIST]"), false));
+        assert_eq!(base_subject("Report"), (String::from("REPORT"), false));
+    }
+
+    #[allow(clippy::unwrap_used, clippy::too_many_lines)]
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_sort_by_subject_and_reverse_size() {
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let username = "test_sort_user";
+        let mailbox_id = format!("{username}/INBOX");
+        let path = storage
+            .to_ondisk_path("INBOX".to_string(), username.to_string())
+            .unwrap();
+        storage.create_dirs(&path).unwrap();
+        for message in [
+            "From: Zoe <zoe@localhost>\r\nSubject: Re: banana\r\n\r\nshort\r\n",
+            "From: adam@localhost\r\nSubject: apple\r\n\r\na longer body\r\n",
+            "From: Bob <bob@localhost>\r\nSubject: Banana\r\n\r\nthe longest body of all\r\n",
+        ] {
+            storage
+                .store_cur_with_flags(mailbox_id.clone(), &path, message.as_bytes(), vec![])
+                .await
+                .unwrap();
+        }
+
+        let mut data = Data {
+            con_state: Connection {
+                state: State::Selected(String::from("INBOX"), Access::ReadWrite),
+                secure: true,
+                username: Some(username.to_string()),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let cases: [(&[&str], &str); 6] = [
+            (&["(SUBJECT REVERSE SIZE)", "UTF-8", "ALL"], "* SORT 2 3 1"),
+            (&["(DISPLAYFROM)", "UTF-8", "ALL"], "* SORT 2 3 1"),
+            (&["(FROM)", "UTF-8", "SUBJECT", "anana"], "* SORT 3 1"),
+            (
+                &[
+                    "RETURN (MIN MAX COUNT)",
+                    "(SUBJECT REVERSE SIZE)",
+                    "UTF-8",
+                    "ALL",
+                ],
+                "* ESEARCH (TAG \"a1\") MIN 2 MAX 1 COUNT 3",
+            ),
+            (
+                &["RETURN ()", "(SUBJECT REVERSE SIZE)", "UTF-8", "ALL"],
+                "* ESEARCH (TAG \"a1\") ALL 2:3,1",
+            ),
+            (
+                &[
+                    "RETURN (PARTIAL -1:-2)",
+                    "(SUBJECT REVERSE SIZE)",
+                    "UTF-8",
+                    "ALL",
+                ],
+                "* ESEARCH (TAG \"a1\") PARTIAL (-1:-2 3,1)",
+            ),
+        ];
+        for (arguments, expected) in cases {
+            let cmd_data = CommandData {
+                tag: "a1",
+                command: Commands::Sort,
+                arguments,
+            };
+            let (mut tx, mut rx) = mpsc::unbounded();
+            let res = Sort { data: &mut data }
+                .exec(&mut tx, &storage, &cmd_data, false)
+                .await;
+            assert!(res.is_ok(), "{res:?}");
+            assert_eq!(rx.next().await, Some(String::from(expected)));
+            assert_eq!(rx.next().await, Some(String::from("a1 OK SORT completed")));
+        }
+
+        let cmd_data = CommandData {
+            tag: "a2",
+            command: Commands::Sort,
+            arguments: &["(DATE)", "KOI8-R", "ALL"],
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = Sort { data: &mut data }
+            .exec(&mut tx, &storage, &cmd_data, false)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(String::from(
+                "a2 NO [BADCHARSET (US-ASCII UTF-8)] Unsupported charset"
+            ))
+        );
+
+        let cmd_data = CommandData {
+            tag: "a3",
+            command: Commands::Sort,
+            arguments: &["RETURN (ALL PARTIAL 1:2)", "(DATE)", "UTF-8", "ALL"],
+        };
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = Sort { data: &mut data }
+            .exec(&mut tx, &storage, &cmd_data, false)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("a3 BAD PARTIAL can not be combined with ALL"))
+        );
+    }
+}
@@ -2,9 +2,13 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::commands::acl::require_rights;
 use crate::commands::select::get_or_create_uidvalidity;
 use crate::commands::{CommandData, Data};
-use erooster_core::backend::storage::{MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName, Rights},
+    storage::{MailEntry, MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -37,57 +41,19 @@ impl Status<'_> {
             .username
             .clone()
             .context("Username missing in internal State")?;
-        let mailbox_path =
-            storage.to_ondisk_path((*folder_on_disk).to_string(), username.clone())?;
-
-        let mut parts: Vec<String> = Vec::new();
-
-        if responses.contains(&"MESSAGES") {
-            let count = storage.count_cur(&mailbox_path) + storage.count_new(&mailbox_path);
-            parts.push(format!("MESSAGES {count}"));
-        }
-        if responses.contains(&"UNSEEN") {
-            let count = storage
-                .list_cur(format!("{username}/{folder_on_disk}"), &mailbox_path)
-                .await
-                .iter()
-                .filter(|mail| !mail.is_seen())
-                .count()
-                + storage.count_new(&mailbox_path);
-            parts.push(format!("UNSEEN {count}"));
-        }
-        if responses.contains(&"UIDNEXT") {
-            let current_uid = storage
-                .get_uid_for_folder(&format!("{username}/{folder_on_disk}"))
-                .await?;
-            parts.push(format!("UIDNEXT {}", current_uid + 1));
-        }
-        if responses.contains(&"UIDVALIDITY") {
-            let uidvalidity = get_or_create_uidvalidity(&mailbox_path).await?;
-            parts.push(format!("UIDVALIDITY {uidvalidity}"));
-        }
-        if responses.contains(&"DELETED") {
-            let mails = storage
-                .list_cur(format!("{username}/{folder_on_disk}"), &mailbox_path)
-                .await;
-            let count = mails.iter().filter(|m| m.is_trashed()).count();
-            parts.push(format!("DELETED {count}"));
-        }
-        if responses.contains(&"SIZE") {
-            let size: usize = storage
-                .list_all(format!("{username}/{folder_on_disk}"), &mailbox_path)
-                .await
-                .iter_mut()
-                .map(|mail| {
-                    if let Ok(parsed) = mail.parsed() {
-                        parsed.raw_bytes.len()
-                    } else {
-                        0
-                    }
-                })
-                .sum();
-            parts.push(format!("SIZE {size}"));
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &MailboxName::resolve(folder_on_disk, &username),
+            Rights::READ,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
         }
+        let parts = status_items(storage, &username, folder_on_disk, &responses).await?;
 
         let values = parts.join(" ");
         lines
@@ -101,13 +67,83 @@ impl Status<'_> {
     }
 }
 
+/// Computes the requested STATUS data items of a mailbox.
+#[instrument(skip(storage))]
+pub async fn status_items(
+    storage: &Storage,
+    username: &str,
+    folder_on_disk: &str,
+    responses: &[&str],
+) -> color_eyre::eyre::Result<Vec<String>> {
+    let mailbox_path = storage.to_ondisk_path(folder_on_disk.to_string(), username.to_string())?;
+    let mailbox = mailbox_id(folder_on_disk, username);
+
+    let mut parts: Vec<String> = Vec::new();
+
+    if responses.contains(&"MESSAGES") {
+        let count = storage.count_cur(&mailbox_path) + storage.count_new(&mailbox_path);
+        parts.push(format!("MESSAGES {count}"));
+    }
+    if responses.contains(&"UNSEEN") {
+        let count = storage
+            .list_cur(mailbox.clone(), &mailbox_path)
+            .await
+            .iter()
+            .filter(|mail| !mail.is_seen())
+            .count()
+            + storage.count_new(&mailbox_path);
+        parts.push(format!("UNSEEN {count}"));
+    }
+    if responses.contains(&"UIDNEXT") {
+        let current_uid = storage.get_uid_for_folder(&mailbox).await?;
+        parts.push(format!("UIDNEXT {}", current_uid + 1));
+    }
+    if responses.contains(&"UIDVALIDITY") {
+        let uidvalidity = get_or_create_uidvalidity(&mailbox_path).await?;
+        parts.push(format!("UIDVALIDITY {uidvalidity}"));
+    }
+    if responses.contains(&"HIGHESTMODSEQ") {
+        let highest_modseq = storage.get_highest_modseq(&mailbox).await?;
+        parts.push(format!("HIGHESTMODSEQ {highest_modseq}"));
+    }
+    if responses.contains(&"DELETED") {
+        let mails = storage.list_cur(mailbox.clone(), &mailbox_path).await;
+        let count = mails.iter().filter(|m| m.is_trashed()).count();
+        parts.push(format!("DELETED {count}"));
+    }
+    if responses.contains(&"SIZE") {
+        let size: usize = storage
+            .list_all(mailbox.clone(), &mailbox_path)
+            .await
+            .iter_mut()
+            .map(|mail| {
+                if let Ok(parsed) = mail.parsed() {
+                    parsed.raw_bytes.len()
+                } else {
+                    0
+                }
+            })
+            .sum();
+        parts.push(format!("SIZE {size}"));
+    }
+    if responses.contains(&"MAILBOXID") {
+        let object_id = storage
+            .mailbox_object_id(&MailboxName::resolve(folder_on_disk, username))
+            .await?;
+        parts.push(format!("MAILBOXID ({object_id})"));
+    }
+
+    Ok(parts)
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
-    use crate::commands::{CommandData, Commands};
+    use crate::commands::{rename::Rename, CommandData, Commands};
     use crate::servers::state::{Access, Connection, State};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[cfg_attr(coverage_nightly, coverage(off))]
@@ -119,6 +155,11 @@ mod tests {
                 secure: true,
                 username: Some(String::from("test_status_user")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -168,6 +209,11 @@ mod tests {
                 secure: true,
                 username: Some(String::from("test_status_uid_user")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -196,4 +242,120 @@ mod tests {
 
         assert_eq!(first, second, "UIDVALIDITY must be stable across calls");
     }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_status_highestmodseq() {
+        let data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test_status_modseq_user")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let cmd_data = CommandData {
+            tag: "c1",
+            command: Commands::Status,
+            arguments: &["INBOX", "(HIGHESTMODSEQ)"],
+        };
+        let (config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let mailbox_path = std::path::Path::new(&config.mail.maildir_folders)
+            .join("test_status_modseq_user")
+            .join("INBOX");
+        storage.create_dirs(&mailbox_path).unwrap();
+        storage
+            .store_new(
+                String::from("test_status_modseq_user/INBOX"),
+                &mailbox_path,
+                b"Subject: test\r\n\r\nHello\r\n",
+                None,
+            )
+            .await
+            .unwrap();
+
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let res = Status { data: &data }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await;
+        assert!(res.is_ok(), "{res:?}");
+        assert_eq!(
+            rx.next().await,
+            Some(String::from("* STATUS INBOX (HIGHESTMODSEQ 2)"))
+        );
+    }
+
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[tokio::test]
+    async fn test_status_mailboxid_survives_rename() {
+        let data = Data {
+            con_state: Connection {
+                state: State::Authenticated,
+                secure: true,
+                username: Some(String::from("test_status_mailboxid_user")),
+                active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
+            },
+        };
+        let (_config, storage) = erooster_core::test_helpers::setup_test_storage()
+            .await
+            .unwrap();
+        let mailbox_path = storage
+            .to_ondisk_path(
+                String::from("Projects"),
+                String::from("test_status_mailboxid_user"),
+            )
+            .unwrap();
+        storage.create_dirs(&mailbox_path).unwrap();
+
+        let (mut tx, mut rx) = mpsc::unbounded();
+        let cmd_data = CommandData {
+            tag: "c1",
+            command: Commands::Status,
+            arguments: &["Projects", "(MAILBOXID)"],
+        };
+        Status { data: &data }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        let before = rx.next().await.unwrap();
+        assert!(before.starts_with("* STATUS Projects (MAILBOXID (F"));
+        assert_eq!(rx.next().await.unwrap(), "c1 OK STATUS completed");
+
+        let cmd_data = CommandData {
+            tag: "c2",
+            command: Commands::Rename,
+            arguments: &["Projects", "Done"],
+        };
+        Rename { data: &data }
+            .exec(&mut tx, &cmd_data, &storage)
+            .await
+            .unwrap();
+        assert_eq!(rx.next().await.unwrap(), "c2 OK RENAME completed");
+
+        let cmd_data = CommandData {
+            tag: "c3",
+            command: Commands::Status,
+            arguments: &["Done", "(MAILBOXID)"],
+        };
+        Status { data: &data }
+            .exec(&mut tx, &storage, &cmd_data)
+            .await
+            .unwrap();
+        assert_eq!(
+            rx.next().await.unwrap(),
+            before.replace("STATUS Projects", "STATUS Done")
+        );
+    }
 }
@@ -3,16 +3,40 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{CommandData, Data},
+    commands::{
+        acl::{flag_rights, require_rights},
+        copy::uid_set_string,
+        parsers::store_modifiers,
+        CommandData, Data,
+    },
     servers::state::State,
 };
-use erooster_core::backend::storage::{MailEntry, MailEntryType, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName},
+    storage::{MailEntry, MailEntryType, MailStorage, Storage},
+};
+use std::path::Path;
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
+    nom::Finish,
     tracing::{debug, error, instrument},
 };
 
+/// Returns the ` MODSEQ (n)` FETCH item for a message whose flags just changed,
+/// or an empty string if CONDSTORE is not in use.
+async fn modseq_item(storage: &Storage, mailbox_path: &Path, id: &str, condstore: bool) -> String {
+    if condstore {
+        let modseq = storage
+            .find(mailbox_path, id)
+            .await
+            .map_or(0, |mail| mail.modseq());
+        format!(" MODSEQ ({modseq})")
+    } else {
+        String::new()
+    }
+}
+
 pub struct Store<'a> {
     pub data: &'a Data,
 }
@@ -35,7 +59,6 @@ impl Store<'_> {
         assert!(arguments.len() >= 2 + offset);
         if arguments.len() >= 2 + offset {
             if let State::Selected(folder, _) = &self.data.con_state.state {
-                let folder = folder.replace('/', ".");
                 let username = self
                     .data
                     .con_state
@@ -44,11 +67,17 @@ impl Store<'_> {
                     .context("Username missing in internal State")?;
                 let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
                 let mails: Vec<MailEntryType> = storage
-                    .list_all(format!("{username}/{folder}"), &mailbox_path)
+                    .list_all(mailbox_id(folder, &username), &mailbox_path)
                     .await;
 
                 let filtered_mails: Vec<MailEntryType> =
-                    if command_data.arguments[offset].contains(':') {
+                    if command_data.arguments[offset] == "$" {
+                        // RFC 5182: the saved search result holds UIDs
+                        mails
+                            .into_iter()
+                            .filter(|mail| self.data.con_state.saved_result.contains(&mail.uid()))
+                            .collect()
+                    } else if command_data.arguments[offset].contains(':') {
                         let range = command_data.arguments[offset]
                             .split(':')
                             .collect::<Vec<_>>();
@@ -73,9 +102,56 @@ impl Store<'_> {
                             .filter(|mail| mail.uid() == wanted_id)
                             .collect()
                     };
-                let action = arguments[1 + offset];
 
-                let flags = command_data.arguments[2 + offset..].to_vec();
+                // RFC 7162 §3.1.3: an optional (UNCHANGEDSINCE n) modifier precedes the action.
+                let mut action_index = 1 + offset;
+                let mut unchanged_since = None;
+                if arguments[action_index].starts_with('(') {
+                    let modifier_end = arguments[action_index..]
+                        .iter()
+                        .position(|arg| arg.ends_with(')'))
+                        .map(|end| action_index + end);
+                    let parsed = modifier_end.and_then(|end| {
+                        store_modifiers(&arguments[action_index..=end].join(" "))
+                            .finish()
+                            .ok()
+                            .map(|(_, modseq)| (end, modseq))
+                    });
+                    let Some((end, modseq)) = parsed.filter(|(end, _)| arguments.len() > end + 1)
+                    else {
+                        lines
+                            .send(format!(
+                                "{} BAD [CLIENTBUG] invalid STORE modifier",
+                                command_data.tag
+                            ))
+                            .await?;
+                        return Ok(());
+                    };
+                    unchanged_since = Some(modseq);
+                    action_index = end + 1;
+                }
+                let condstore =
+                    unchanged_since.is_some() || self.data.con_state.condstore_enabled();
+                let (modified, filtered_mails): (Vec<MailEntryType>, Vec<MailEntryType>) =
+                    filtered_mails.into_iter().partition(|mail| {
+                        unchanged_since.is_some_and(|modseq| mail.modseq() > modseq)
+                    });
+
+                let action = arguments[action_index];
+
+                let flags = command_data.arguments[action_index + 1..].to_vec();
+                if !require_rights(
+                    lines,
+                    storage,
+                    &username,
+                    &MailboxName::resolve(folder, &username),
+                    flag_rights(&flags),
+                    command_data.tag,
+                )
+                .await?
+                {
+                    return Ok(());
+                }
                 let flags_string = flags.join(" ");
                 if action.to_lowercase() == "flags" {
                     for mail in filtered_mails {
@@ -87,18 +163,20 @@ impl Store<'_> {
                             .expect("Failed to convert path. Your system may be incompatible");
                         if path.ends_with("new") {
                             if let Err(e) =
-                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags)
+                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags).await
                             {
                                 error!("Failed to store flags or move email {}: {}", mail.id(), e);
                             }
-                        } else if let Err(e) = storage.set_flags(&mailbox_path, mail.id(), &flags) {
+                        } else if let Err(e) = storage.set_flags(&mailbox_path, mail.id(), &flags).await {
                             error!("Failed to store flags or move email {}: {}", mail.id(), e);
                         }
 
+                        let modseq =
+                            modseq_item(storage, &mailbox_path, mail.id(), condstore).await;
                         if uid {
                             lines
                                 .feed(format!(
-                                    "* {} FETCH (UID {} FLAGS {})",
+                                    "* {} FETCH (UID {} FLAGS {}{modseq})",
                                     mail.uid(),
                                     mail.uid(),
                                     flags_string
@@ -106,7 +184,10 @@ impl Store<'_> {
                                 .await?;
                         } else {
                             lines
-                                .feed(format!("* {} FETCH (FLAGS {flags_string})", mail.uid()))
+                                .feed(format!(
+                                    "* {} FETCH (FLAGS {flags_string}{modseq})",
+                                    mail.uid()
+                                ))
                                 .await?;
                         }
                     }
@@ -120,13 +201,20 @@ impl Store<'_> {
                             .expect("Failed to convert path. Your system may be incompatible");
                         if path.ends_with("new") {
                             if let Err(e) =
-                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags)
+                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags).await
                             {
                                 error!("Failed to store flags or move email {}: {}", mail.id(), e);
                             }
-                        } else if let Err(e) = storage.set_flags(&mailbox_path, mail.id(), &flags) {
+                        } else if let Err(e) = storage.set_flags(&mailbox_path, mail.id(), &flags).await {
                             error!("Failed to store flags or move email {}: {}", mail.id(), e);
                         }
+                        if condstore {
+                            let modseq =
+                                modseq_item(storage, &mailbox_path, mail.id(), condstore).await;
+                            lines
+                                .feed(format!("* {} FETCH (UID {}{modseq})", mail.uid(), mail.uid()))
+                                .await?;
+                        }
                     }
                 } else if action.to_lowercase() == "+flags" {
                     for mail in filtered_mails {
@@ -139,18 +227,20 @@ impl Store<'_> {
                         debug!("Path: {}", path);
                         if path.ends_with("new") {
                             if let Err(e) =
-                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags)
+                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags).await
                             {
                                 error!("Failed to store flags or move email {}: {}", mail.id(), e);
                             }
-                        } else if let Err(e) = storage.add_flags(&mailbox_path, mail.id(), &flags) {
+                        } else if let Err(e) = storage.add_flags(&mailbox_path, mail.id(), &flags).await {
                             error!("Failed to store flags or move email {}: {}", mail.id(), e);
                         }
 
+                        let modseq =
+                            modseq_item(storage, &mailbox_path, mail.id(), condstore).await;
                         if uid {
                             lines
                                 .feed(format!(
-                                    "* {} FETCH (UID {} FLAGS {})",
+                                    "* {} FETCH (UID {} FLAGS {}{modseq})",
                                     mail.uid(),
                                     mail.uid(),
                                     flags_string
@@ -158,7 +248,10 @@ impl Store<'_> {
                                 .await?;
                         } else {
                             lines
-                                .feed(format!("* {} FETCH (FLAGS {flags_string})", mail.uid()))
+                                .feed(format!(
+                                    "* {} FETCH (FLAGS {flags_string}{modseq})",
+                                    mail.uid()
+                                ))
                                 .await?;
                         }
                     }
@@ -172,46 +265,50 @@ impl Store<'_> {
                             .expect("Failed to convert path. Your system may be incompatible");
                         if path.ends_with("new") {
                             if let Err(e) =
-                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags)
+                                storage.move_new_to_cur_with_flags(&mailbox_path, mail.id(), &flags).await
                             {
                                 error!("Failed to store flags or move email {}: {}", mail.id(), e);
                             }
-                        } else if let Err(e) = storage.add_flags(&mailbox_path, mail.id(), &flags) {
+                        } else if let Err(e) = storage.add_flags(&mailbox_path, mail.id(), &flags).await {
                             error!("Failed to store flags or move email {}: {}", mail.id(), e);
                         }
+                        if condstore {
+                            let modseq =
+                                modseq_item(storage, &mailbox_path, mail.id(), condstore).await;
+                            lines
+                                .feed(format!("* {} FETCH (UID {}{modseq})", mail.uid(), mail.uid()))
+                                .await?;
+                        }
                     }
                 } else if action.to_lowercase() == "-flags" {
                     for mail in filtered_mails {
-                        let mut current_flags = vec![];
-                        if mail.is_replied() {
-                            current_flags.push("\\Answered");
-                        } else if mail.is_trashed() {
-                            current_flags.push("\\Deleted");
-                        } else if mail.is_draft() {
-                            current_flags.push("\\Draft");
-                        } else if mail.is_seen() {
-                            current_flags.push("\\Seen");
-                        } else if mail.is_flagged() {
-                            current_flags.push("\\Flagged");
-                        }
-
-                        let new_flags = current_flags
+                        let removed: Vec<String> = flags
                             .iter()
-                            .copied()
-                            .filter_map(|x| {
-                                let x = x.replace(['(', ')'], "");
-                                (!flags.contains(&x.as_str())).then_some(x)
-                            })
-                            .collect::<Vec<_>>();
+                            .map(|flag| flag.replace(['(', ')'], ""))
+                            .collect();
+                        let new_flags = [
+                            (mail.is_replied(), "\\Answered"),
+                            (mail.is_trashed(), "\\Deleted"),
+                            (mail.is_draft(), "\\Draft"),
+                            (mail.is_seen(), "\\Seen"),
+                            (mail.is_flagged(), "\\Flagged"),
+                        ]
+                        .into_iter()
+                        .filter_map(|(set, flag)| set.then_some(flag))
+                        .chain(mail.keywords().iter().map(String::as_str))
+                        .filter(|flag| !removed.iter().any(|r| r.eq_ignore_ascii_case(flag)))
+                        .collect::<Vec<_>>();
 
-                        if let Err(e) = storage.remove_flags(&mailbox_path, mail.id(), &flags) {
+                        if let Err(e) = storage.remove_flags(&mailbox_path, mail.id(), &flags).await {
                             error!("Failed to store flags or move email {}: {}", mail.id(), e);
                         }
 
+                        let modseq =
+                            modseq_item(storage, &mailbox_path, mail.id(), condstore).await;
                         if uid {
                             lines
                                 .feed(format!(
-                                    "* {} FETCH (UID {} FLAGS ({}))",
+                                    "* {} FETCH (UID {} FLAGS ({}){modseq})",
                                     mail.uid(),
                                     mail.uid(),
                                     new_flags.join(" ")
@@ -220,7 +317,7 @@ impl Store<'_> {
                         } else {
                             lines
                                 .feed(format!(
-                                    "* {} FETCH (FLAGS ({}))",
+                                    "* {} FETCH (FLAGS ({}){modseq})",
                                     mail.uid(),
                                     new_flags.join(" ")
                                 ))
@@ -229,9 +326,16 @@ impl Store<'_> {
                     }
                 } else if action.to_lowercase() == "-flags.silent" {
                     for mail in filtered_mails {
-                        if let Err(e) = storage.remove_flags(&mailbox_path, mail.id(), &flags) {
+                        if let Err(e) = storage.remove_flags(&mailbox_path, mail.id(), &flags).await {
                             error!("Failed to store flags or move email {}: {}", mail.id(), e);
                         }
+                        if condstore {
+                            let modseq =
+                                modseq_item(storage, &mailbox_path, mail.id(), condstore).await;
+                            lines
+                                .feed(format!("* {} FETCH (UID {}{modseq})", mail.uid(), mail.uid()))
+                                .await?;
+                        }
                     }
                 } else {
                     lines
@@ -242,7 +346,17 @@ impl Store<'_> {
                         .await?;
                     return Ok(());
                 }
-                if uid {
+                if !modified.is_empty() {
+                    let mut modified_uids: Vec<u32> = modified.iter().map(MailEntry::uid).collect();
+                    modified_uids.sort_unstable();
+                    lines
+                        .feed(format!(
+                            "{} OK [MODIFIED {}] Conditional STORE failed",
+                            command_data.tag,
+                            uid_set_string(&modified_uids)
+                        ))
+                        .await?;
+                } else if uid {
                     lines
                         .feed(format!("{} Ok UID STORE completed", command_data.tag))
                         .await?;
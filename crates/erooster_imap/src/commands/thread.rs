@@ -0,0 +1,539 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! THREAD extension (RFC 5256) with the ORDEREDSUBJECT and REFERENCES
+//! algorithms.
+//!
+//! Both algorithms build a tree of [`Container`]s which is then turned into
+//! the nested list of the `THREAD` response. Containers without a message
+//! are placeholders for messages that are referenced but not part of the
+//! search result.
+
+use crate::{
+    commands::{
+        parsers::{thread_arguments, ThreadAlgorithm},
+        search::{check_search_condition, fill_saved_result, narrow_by_index, selected_mails},
+        sort::{base_subject, header_value, is_supported_charset, sent_date},
+        CommandData, Data,
+    },
+    servers::state::State,
+};
+use erooster_core::backend::{
+    acl::mailbox_id,
+    storage::{maildir::MaildirMailEntry, MailEntry, MailStorage, Storage},
+};
+use std::collections::HashMap;
+use {
+    color_eyre::{self, eyre::ContextCompat},
+    futures::{Sink, SinkExt},
+    nom::Finish,
+    nom_language::error::convert_error,
+    tracing::{debug, error, instrument},
+};
+
+pub struct Thread<'a> {
+    pub data: &'a Data,
+}
+
+impl Thread<'_> {
+    #[instrument(skip(self, lines, storage, command_data))]
+    pub async fn exec<S, E>(
+        &self,
+        lines: &mut S,
+        storage: &Storage,
+        command_data: &CommandData<'_>,
+        is_uid: bool,
+    ) -> color_eyre::eyre::Result<()>
+    where
+        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
+        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
+    {
+        let State::Selected(folder, _) = &self.data.con_state.state else {
+            lines
+                .send(format!(
+                    "{} NO [TRYCREATE] No mailbox selected",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        };
+        let username = self
+            .data
+            .con_state
+            .username
+            .clone()
+            .context("Username missing in internal State")?;
+        let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
+
+        let offset = usize::from(is_uid);
+        let arguments = command_data.arguments[offset..].join(" ");
+        let mut args = match thread_arguments(&arguments).finish() {
+            Ok((_, args)) => args,
+            Err(e) => {
+                error!(
+                    "Failed to parse thread arguments: {}",
+                    convert_error(arguments.as_str(), e)
+                );
+                lines
+                    .send(format!("{} BAD Unable to parse", command_data.tag))
+                    .await?;
+                return Ok(());
+            }
+        };
+        debug!("Parsed thread arguments: {:#?}", args);
+        if !is_supported_charset(&args.charset) {
+            lines
+                .send(format!(
+                    "{} NO [BADCHARSET (US-ASCII UTF-8)] Unsupported charset",
+                    command_data.tag
+                ))
+                .await?;
+            return Ok(());
+        }
+        fill_saved_result(&mut args.program, &self.data.con_state.saved_result);
+
+        let mailbox_id = mailbox_id(folder, &username);
+        let mut mails = selected_mails(storage, &mailbox_id, &mailbox_path).await;
+        narrow_by_index(storage, &mailbox_id, &args.program, &mut mails);
+        mails.retain_mut(|mail| check_search_condition(&args.program, mail, is_uid));
+        let messages: Vec<ThreadMessage> = mails
+            .iter_mut()
+            .map(|mail| ThreadMessage::from_mail(mail, is_uid))
+            .collect();
+
+        let threads = match args.algorithm {
+            ThreadAlgorithm::OrderedSubject => thread_ordered_subject(&messages),
+            ThreadAlgorithm::References => thread_references(&messages),
+        };
+        if threads.is_empty() {
+            lines.feed(String::from("* THREAD")).await?;
+        } else {
+            lines.feed(format!("* THREAD {threads}")).await?;
+        }
+        lines
+            .feed(format!("{} OK THREAD completed", command_data.tag))
+            .await?;
+        lines.flush().await?;
+        Ok(())
+    }
+}
+
+/// The parts of a message the threading algorithms look at
+#[derive(Debug, Clone, Default)]
+struct ThreadMessage {
+    /// The sequence number or UID reported to the client
+    id: u32,
+    sequence: u32,
+    date: i64,
+    subject: String,
+    /// Whether the subject marks the message as a reply or forward
+    reply: bool,
+    message_id: Option<String>,
+    /// The parent chain from `References`, or `In-Reply-To` if there is none
+    references: Vec<String>,
+}
+
+impl ThreadMessage {
+    fn from_mail(mail: &mut MaildirMailEntry, is_uid: bool) -> Self {
+        let sequence = mail.sequence_number().unwrap_or_default();
+        let (subject, reply) = header_value(mail, "subject")
+            .map(|subject| base_subject(&subject))
+            .unwrap_or_default();
+        let references = header_value(mail, "references")
+            .map(|references| message_ids(&references))
+            .filter(|references| !references.is_empty())
+            .or_else(|| {
+                header_value(mail, "in-reply-to")
+                    .map(|in_reply_to| message_ids(&in_reply_to).into_iter().take(1).collect())
+            })
+            .unwrap_or_default();
+        ThreadMessage {
+            id: if is_uid { mail.uid() } else { sequence },
+            sequence,
+            date: sent_date(mail),
+            subject,
+            reply,
+            message_id: header_value(mail, "message-id")
+                .and_then(|message_id| message_ids(&message_id).into_iter().next()),
+            references,
+        }
+    }
+}
+
+/// All `<...>` message ids in a header value
+fn message_ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|part| part.split_once('>'))
+        .map(|(id, _)| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+/// A node of the thread tree
+#[derive(Debug, Default)]
+struct Container {
+    /// Index into the messages, `None` for placeholders
+    message: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+/// The thread trees with all containers stored in one arena
+struct Forest<'a> {
+    messages: &'a [ThreadMessage],
+    containers: Vec<Container>,
+}
+
+impl<'a> Forest<'a> {
+    const fn new(messages: &'a [ThreadMessage]) -> Self {
+        Forest {
+            messages,
+            containers: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, message: Option<usize>) -> usize {
+        self.containers.push(Container {
+            message,
+            ..Container::default()
+        });
+        self.containers.len() - 1
+    }
+
+    /// Whether `ancestor` is `container` or one of its parents
+    fn is_ancestor(&self, ancestor: usize, container: usize) -> bool {
+        let mut current = Some(container);
+        while let Some(index) = current {
+            if index == ancestor {
+                return true;
+            }
+            current = self.containers[index].parent;
+        }
+        false
+    }
+
+    fn unlink(&mut self, child: usize) {
+        if let Some(parent) = self.containers[child].parent.take() {
+            self.containers[parent].children.retain(|c| *c != child);
+        }
+    }
+
+    fn link(&mut self, parent: usize, child: usize) {
+        self.unlink(child);
+        self.containers[child].parent = Some(parent);
+        self.containers[parent].children.push(child);
+    }
+
+    /// The message that represents a container, which is the first child
+    /// for placeholders
+    fn representative(&self, container: usize) -> Option<&ThreadMessage> {
+        let container = &self.containers[container];
+        match container.message {
+            Some(message) => Some(&self.messages[message]),
+            None => container
+                .children
+                .first()
+                .and_then(|child| self.representative(*child)),
+        }
+    }
+
+    /// Sorts siblings by date recursively (RFC 5256 §4, REFERENCES step 6)
+    fn sort(&mut self, siblings: &mut [usize]) {
+        for sibling in siblings.iter() {
+            let mut children = std::mem::take(&mut self.containers[*sibling].children);
+            self.sort(&mut children);
+            self.containers[*sibling].children = children;
+        }
+        siblings.sort_by_key(|container| {
+            self.representative(*container)
+                .map(|message| (message.date, message.sequence))
+        });
+    }
+
+    fn render(&self, container: usize, out: &mut String) {
+        let container = &self.containers[container];
+        if let Some(message) = container.message {
+            out.push_str(&self.messages[message].id.to_string());
+            match container.children.as_slice() {
+                [] => return,
+                [child] => {
+                    out.push(' ');
+                    self.render(*child, out);
+                    return;
+                }
+                _ => out.push(' '),
+            }
+        }
+        for child in &container.children {
+            out.push('(');
+            self.render(*child, out);
+            out.push(')');
+        }
+    }
+
+    fn render_threads(&self, roots: &[usize]) -> String {
+        let mut out = String::new();
+        for root in roots {
+            out.push('(');
+            self.render(*root, &mut out);
+            out.push(')');
+        }
+        out
+    }
+}
+
+/// ORDEREDSUBJECT: messages with the same base subject form a thread in
+/// which the first message is the parent of all others
+fn thread_ordered_subject(messages: &[ThreadMessage]) -> String {
+    let mut order: Vec<usize> = (0..messages.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&messages[*a], &messages[*b]);
+        (&a.subject, a.date, a.sequence).cmp(&(&b.subject, b.date, b.sequence))
+    });
+
+    let mut forest = Forest::new(messages);
+    let mut roots: Vec<usize> = Vec::new();
+    let mut current: Option<(usize, &str)> = None;
+    for index in order {
+        let container = forest.add(Some(index));
+        match current {
+            Some((root, subject)) if subject == messages[index].subject => {
+                forest.link(root, container);
+            }
+            _ => {
+                roots.push(container);
+                current = Some((container, &messages[index].subject));
+            }
+        }
+    }
+    forest.sort(&mut roots);
+    forest.render_threads(&roots)
+}
+
+/// REFERENCES: threads by the `References` and `In-Reply-To` headers and
+/// groups the remaining threads by their base subject
+#[allow(clippy::too_many_lines)]
+fn thread_references(messages: &[ThreadMessage]) -> String {
+    let mut forest = Forest::new(messages);
+    let mut id_table: HashMap<&str, usize> = HashMap::new();
+
+    // Step 1: link messages to the messages they reference
+    for (index, message) in messages.iter().enumerate() {
+        let container = match message.message_id.as_deref() {
+            Some(message_id) => match id_table.get(message_id) {
+                Some(existing) if forest.containers[*existing].message.is_none() => *existing,
+                // Duplicate message ids are treated as if the message had none
+                Some(_) => forest.add(None),
+                None => {
+                    let container = forest.add(None);
+                    id_table.insert(message_id, container);
+                    container
+                }
+            },
+            None => forest.add(None),
+        };
+        forest.containers[container].message = Some(index);
+
+        let mut previous: Option<usize> = None;
+        for reference in &message.references {
+            let current = *id_table
+                .entry(reference.as_str())
+                .or_insert_with(|| forest.add(None));
+            if let Some(parent) = previous {
+                if forest.containers[current].parent.is_none()
+                    && !forest.is_ancestor(current, parent)
+                {
+                    forest.link(parent, current);
+                }
+            }
+            previous = Some(current);
+        }
+        forest.unlink(container);
+        if let Some(parent) = previous {
+            if !forest.is_ancestor(container, parent) {
+                forest.link(parent, container);
+            }
+        }
+    }
+
+    // Steps 2 to 4: collect the roots and prune placeholders
+    let roots: Vec<usize> = (0..forest.containers.len())
+        .filter(|container| forest.containers[*container].parent.is_none())
+        .collect();
+    let mut roots: Vec<Option<usize>> = prune(&mut forest, roots, true)
+        .into_iter()
+        .map(Some)
+        .collect();
+
+    // Step 5: group the threads by base subject
+    let subject_of = |forest: &Forest<'_>, container: usize| {
+        forest
+            .representative(container)
+            .map(|message| message.subject.clone())
+            .filter(|subject| !subject.is_empty())
+    };
+    let is_reply = |forest: &Forest<'_>, container: usize| {
+        forest.containers[container]
+            .message
+            .is_some_and(|message| messages[message].reply)
+    };
+    let mut subject_table: HashMap<String, usize> = HashMap::new();
+    for root in roots.iter().flatten() {
+        let Some(subject) = subject_of(&forest, *root) else {
+            continue;
+        };
+        let is_dummy = forest.containers[*root].message.is_none();
+        // Placeholders win over messages and non-replies over replies
+        let replace = subject_table.get(&subject).is_none_or(|existing| {
+            (is_dummy && forest.containers[*existing].message.is_some())
+                || (is_reply(&forest, *existing) && !is_reply(&forest, *root))
+        });
+        if replace {
+            subject_table.insert(subject, *root);
+        }
+    }
+    for index in 0..roots.len() {
+        let Some(this) = roots[index] else {
+            continue;
+        };
+        let Some(subject) = subject_of(&forest, this) else {
+            continue;
+        };
+        let Some(&that) = subject_table.get(&subject) else {
+            continue;
+        };
+        if that == this {
+            continue;
+        }
+        let Some(that_index) = roots.iter().position(|root| *root == Some(that)) else {
+            continue;
+        };
+        let this_dummy = forest.containers[this].message.is_none();
+        let that_dummy = forest.containers[that].message.is_none();
+        if this_dummy && that_dummy {
+            for child in std::mem::take(&mut forest.containers[this].children) {
+                forest.containers[child].parent = None;
+                forest.link(that, child);
+            }
+            roots[index] = None;
+        } else if that_dummy || (is_reply(&forest, this) && !is_reply(&forest, that)) {
+            forest.link(that, this);
+            roots[index] = None;
+        } else if this_dummy || (is_reply(&forest, that) && !is_reply(&forest, this)) {
+            forest.link(this, that);
+            roots[that_index] = None;
+            subject_table.insert(subject, this);
+        } else {
+            let dummy = forest.add(None);
+            forest.link(dummy, that);
+            forest.link(dummy, this);
+            roots[that_index] = Some(dummy);
+            roots[index] = None;
+            subject_table.insert(subject, dummy);
+        }
+    }
+
+    // Step 6: sort and render
+    let mut roots: Vec<usize> = roots.into_iter().flatten().collect();
+    forest.sort(&mut roots);
+    forest.render_threads(&roots)
+}
+
+/// Removes empty placeholders and promotes the children of placeholders
+/// (RFC 5256 §4, REFERENCES step 4)
+///
+/// Placeholders in the root set are only replaced by their child if they
+/// have exactly one.
+fn prune(forest: &mut Forest<'_>, containers: Vec<usize>, is_root: bool) -> Vec<usize> {
+    let mut pruned = Vec::new();
+    for container in containers {
+        let children = std::mem::take(&mut forest.containers[container].children);
+        let children = prune(forest, children, false);
+        let is_dummy = forest.containers[container].message.is_none();
+        if is_dummy && (!is_root || children.len() <= 1) {
+            let parent = forest.containers[container].parent;
+            for child in &children {
+                forest.containers[*child].parent = parent;
+            }
+            pruned.extend(children);
+        } else {
+            for child in &children {
+                forest.containers[*child].parent = Some(container);
+            }
+            forest.containers[container].children = children;
+            pruned.push(container);
+        }
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(
+        id: u32,
+        subject: &str,
+        message_id: Option<&str>,
+        references: &[&str],
+    ) -> ThreadMessage {
+        let (subject, reply) = base_subject(subject);
+        ThreadMessage {
+            id,
+            sequence: id,
+            date: i64::from(id),
+            subject,
+            reply,
+            message_id: message_id.map(ToString::to_string),
+            references: references.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn test_message_ids() {
+        assert_eq!(
+            message_ids("<a@x> <b@y>\r\n <c@z>"),
+            vec!["a@x", "b@y", "c@z"]
+        );
+        assert!(message_ids("no ids").is_empty());
+    }
+
+    #[test]
+    fn test_thread_ordered_subject() {
+        let messages = vec![
+            message(1, "Hello", None, &[]),
+            message(2, "Other", None, &[]),
+            message(3, "Re: hello", None, &[]),
+            message(4, "Re: hello", None, &[]),
+            message(5, "Re: other", None, &[]),
+        ];
+        assert_eq!(thread_ordered_subject(&messages), "(1 (3)(4))(2 5)");
+    }
+
+    #[test]
+    fn test_thread_references() {
+        let messages = vec![
+            message(1, "Plans", Some("1@x"), &[]),
+            message(2, "Re: Plans", Some("2@x"), &["1@x"]),
+            message(3, "Re: Plans", Some("3@x"), &["1@x", "2@x"]),
+            message(4, "Re: Plans", Some("4@x"), &["1@x"]),
+            // Parent is missing, so it gets grouped by subject
+            message(5, "Re: Lunch", Some("5@x"), &["missing@x"]),
+            message(6, "Re: Lunch", Some("6@x"), &["other-missing@x"]),
+            message(7, "Unrelated", None, &[]),
+        ];
+        assert_eq!(thread_references(&messages), "(1 (2 3)(4))((5)(6))(7)");
+    }
+
+    #[test]
+    fn test_thread_references_ignores_loops() {
+        let messages = vec![
+            message(1, "a", Some("1@x"), &["2@x"]),
+            message(2, "b", Some("2@x"), &["1@x"]),
+        ];
+        assert_eq!(thread_references(&messages), "(2 1)");
+    }
+}
@@ -3,28 +3,32 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::commands::{
-    copy::Copy, fetch::Fetch, move_::Move, parsers::parse_selected_range, store::Store,
+    acl::require_rights,
+    copy::{uid_set_string, Copy},
+    fetch::Fetch, move_::Move, parsers::parse_selected_range, store::Store,
     CommandData, Data,
 };
-use erooster_core::backend::storage::{MailEntry, MailStorage, Storage};
+use erooster_core::backend::{
+    acl::{mailbox_id, MailboxName, Rights},
+    storage::{MailEntry, MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
-    tokio::fs,
     tracing::instrument,
 };
 
-use super::search::Search;
+use super::{search::Search, sort::Sort, thread::Thread};
 use crate::servers::state::{Access, State};
 
 pub struct Uid<'a> {
-    pub data: &'a Data,
+    pub data: &'a mut Data,
 }
 
 impl Uid<'_> {
     #[instrument(skip(self, lines, command_data, storage))]
     pub async fn exec<S, E>(
-        &self,
+        &mut self,
         lines: &mut S,
         command_data: &CommandData<'_>,
         storage: &Storage,
@@ -51,6 +55,14 @@ impl Uid<'_> {
             Search { data: self.data }
                 .exec(lines, storage, command_data, true)
                 .await?;
+        } else if command_data.arguments[0].to_lowercase() == "sort" {
+            Sort { data: self.data }
+                .exec(lines, storage, command_data, true)
+                .await?;
+        } else if command_data.arguments[0].to_lowercase() == "thread" {
+            Thread { data: self.data }
+                .exec(lines, storage, command_data, true)
+                .await?;
         } else if command_data.arguments[0].to_lowercase() == "store" {
             Store { data: self.data }
                 .exec(lines, storage, command_data, true)
@@ -122,29 +134,53 @@ impl Uid<'_> {
             .username
             .clone()
             .context("Username missing in internal State")?;
+        if !require_rights(
+            lines,
+            storage,
+            &username,
+            &MailboxName::resolve(folder, &username),
+            Rights::EXPUNGE,
+            command_data.tag,
+        )
+        .await?
+        {
+            return Ok(());
+        }
         let mailbox_path = storage.to_ondisk_path(folder.clone(), username.clone())?;
 
         let mails: Vec<_> = storage
-            .list_cur(format!("{username}/{folder}"), &mailbox_path)
+            .list_cur(mailbox_id(folder, &username), &mailbox_path)
             .await
             .into_iter()
             .chain(
                 storage
-                    .list_new(format!("{username}/{folder}"), &mailbox_path)
+                    .list_new(mailbox_id(folder, &username), &mailbox_path)
                     .await,
             )
             .collect();
 
+        let qresync = self.data.con_state.qresync_enabled();
+        let mut vanished = Vec::new();
         let mut seq = 1u32;
         for mail in mails {
             let uid_matches = uid_ranges.iter().any(|r| r.contains(&mail.uid()));
             if uid_matches && mail.is_trashed() {
-                fs::remove_file(mail.path()).await?;
-                lines.feed(format!("* {seq} EXPUNGE")).await?;
+                storage.expunge(&mailbox_path, mail.id()).await?;
+                if qresync {
+                    vanished.push(mail.uid());
+                } else {
+                    lines.feed(format!("* {seq} EXPUNGE")).await?;
+                }
             } else {
                 seq += 1;
             }
         }
+        if !vanished.is_empty() {
+            vanished.sort_unstable();
+            lines
+                .feed(format!("* VANISHED {}", uid_set_string(&vanished)))
+                .await?;
+        }
 
         lines.flush().await?;
         lines
@@ -161,13 +197,14 @@ mod tests {
     use crate::commands::{CommandData, Commands};
     use crate::servers::state::{Access, Connection, State};
     use futures::{channel::mpsc, StreamExt};
+    use std::collections::BTreeMap;
     use tokio;
 
     #[cfg_attr(coverage_nightly, coverage(off))]
     #[tokio::test]
     async fn test_uid_expunge_not_selected() {
-        let data = Data {
-            con_state: Connection::new(true),
+        let mut data = Data {
+            con_state: Connection::new(true, String::new()),
         };
         let cmd_data = CommandData {
             tag: "a1",
@@ -178,7 +215,7 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let uid = Uid { data: &data };
+        let uid = Uid { data: &mut data };
         let res = uid.uid_expunge(&mut tx, &cmd_data, &storage).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
@@ -192,12 +229,17 @@ mod tests {
     #[cfg_attr(coverage_nightly, coverage(off))]
     #[tokio::test]
     async fn test_uid_expunge_read_only() {
-        let data = Data {
+        let mut data = Data {
             con_state: Connection {
                 state: State::Selected("INBOX".to_string(), Access::ReadOnly),
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -209,7 +251,7 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let uid = Uid { data: &data };
+        let uid = Uid { data: &mut data };
         let res = uid.uid_expunge(&mut tx, &cmd_data, &storage).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
@@ -223,12 +265,17 @@ mod tests {
     #[cfg_attr(coverage_nightly, coverage(off))]
     #[tokio::test]
     async fn test_uid_expunge_no_uid_set() {
-        let data = Data {
+        let mut data = Data {
             con_state: Connection {
                 state: State::Selected("INBOX".to_string(), Access::ReadWrite),
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -240,7 +287,7 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let uid = Uid { data: &data };
+        let uid = Uid { data: &mut data };
         let res = uid.uid_expunge(&mut tx, &cmd_data, &storage).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
@@ -254,12 +301,17 @@ mod tests {
     #[cfg_attr(coverage_nightly, coverage(off))]
     #[tokio::test]
     async fn test_uid_expunge_invalid_uid_set() {
-        let data = Data {
+        let mut data = Data {
             con_state: Connection {
                 state: State::Selected("INBOX".to_string(), Access::ReadWrite),
                 secure: true,
                 username: Some(String::from("test")),
                 active_capabilities: vec![],
+                notify: None,
+                compressed: false,
+                saved_result: vec![],
+                client_id: BTreeMap::new(),
+                peer_addr: String::new(),
             },
         };
         let cmd_data = CommandData {
@@ -271,7 +323,7 @@ mod tests {
             .await
             .unwrap();
         let (mut tx, mut rx) = mpsc::unbounded();
-        let uid = Uid { data: &data };
+        let uid = Uid { data: &mut data };
         let res = uid.uid_expunge(&mut tx, &cmd_data, &storage).await;
         assert!(res.is_ok(), "{:?}", res);
         assert_eq!(
@@ -3,7 +3,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::commands::{CommandData, Data};
-use erooster_core::backend::storage::{MailStorage, Storage};
+use erooster_core::backend::{
+    acl::MailboxName,
+    storage::{MailStorage, Storage},
+};
 use {
     color_eyre::{self, eyre::ContextCompat},
     futures::{Sink, SinkExt},
@@ -29,15 +32,25 @@ impl Unsubscribe<'_> {
         let arguments = &command_data.arguments;
         assert!(arguments.len() == 1);
         if arguments.len() == 1 {
-            let folder = arguments[0].replace('/', ".");
-            let mailbox_path = storage.to_ondisk_path(
-                folder.clone(),
-                self.data
-                    .con_state
-                    .username
-                    .clone()
-                    .context("Username missing in internal State")?,
-            )?;
+            let folder = arguments[0].to_string();
+            let username = self
+                .data
+                .con_state
+                .username
+                .clone()
+                .context("Username missing in internal State")?;
+            // The subscription is kept with the mailbox itself and would be
+            // visible to everybody else who has access to it
+            if !MailboxName::resolve(&folder, &username).is_owned_by(&username) {
+                lines
+                    .send(format!(
+                        "{} NO [CANNOT] Subscriptions are only supported for personal mailboxes",
+                        command_data.tag
+                    ))
+                    .await?;
+                return Ok(());
+            }
+            let mailbox_path = storage.to_ondisk_path(folder.clone(), username)?;
             // Note we deviate from spec here and actually do this automatically. So we can just return OK here.
             if !mailbox_path.exists() {
                 lines
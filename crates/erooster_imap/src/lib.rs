@@ -19,9 +19,13 @@
     clippy::panic_in_result_fn
 )]
 
-use crate::commands::capability::{get_capabilities, get_unencrypted_capabilities};
+use crate::commands::capability::{capabilities, get_unencrypted_capabilities};
 use erooster_core::{
-    backend::{database::DB, storage::Storage},
+    backend::{
+        database::{Database, DB},
+        sessions,
+        storage::Storage,
+    },
     config::Config,
 };
 use {
@@ -34,11 +38,14 @@ use {
 pub(crate) mod commands;
 pub(crate) mod servers;
 
-/// A const variant of the Capabilities we welcome clients with
-pub const CAPABILITY_HELLO: &str = formatcp!(
-    "* OK [{}] IMAP4rev1/IMAP4rev2 Service Ready",
-    get_capabilities()
-);
+/// The Capabilities we welcome clients on encrypted connections with
+#[must_use]
+pub fn capability_hello(config: &Config) -> String {
+    format!(
+        "* OK [{}] IMAP4rev1/IMAP4rev2 Service Ready",
+        capabilities(config)
+    )
+}
 
 /// A const variant of the Capabilities we welcome clients with
 pub const CAPABILITY_UNENCRYPTED_HELLO: &str = formatcp!(
@@ -72,6 +79,10 @@ pub fn start(
     let config_clone = config.clone();
     let shutdown_clone = shutdown.clone();
     tokio::spawn(async move {
+        // Connections of a previous run are gone
+        if let Err(e) = sessions::clear(db_clone.get_pool(), servers::session::PROTOCOL).await {
+            error!("Unable to clear the IMAP sessions: {e:?}");
+        }
         if let Err(e) =
             servers::unencrypted::Unencrypted::run(config_clone, &db_clone, &storage_clone).await
         {
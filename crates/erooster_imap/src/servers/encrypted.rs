@@ -3,35 +3,38 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{Data, Response},
-    servers::state::{Connection, State},
-    Server, CAPABILITY_HELLO,
+    capability_hello,
+    commands::{
+        idle::Idle,
+        notify::{send_event, Notifications},
+        Data, Response,
+    },
+    servers::{session::Session, state::Connection},
+    Server,
 };
 use erooster_core::{
     backend::{
         database::DB,
-        storage::{MailStorage, Storage},
+        storage::Storage,
     },
+    compression::CompressibleStream,
     config::Config,
     line_codec::LinesCodec,
     LINE_LIMIT,
 };
-use notify;
 use std::{io, net::SocketAddr, path::Path, sync::Arc};
 use {
     color_eyre::{self, eyre::Context},
     futures::{SinkExt, StreamExt},
-    notify::{recommended_watcher, RecursiveMode, Watcher},
     rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
     tokio::{
         self,
         net::{TcpListener, TcpStream},
-        sync::mpsc,
     },
     tokio_rustls::{rustls, TlsAcceptor},
     tokio_stream::wrappers::TcpListenerStream,
     tokio_util::codec::Framed,
-    tracing::{debug, error, info, instrument},
+    tracing::{debug, error, info, instrument, Instrument},
 };
 
 /// An encrypted imap Server
@@ -143,7 +146,7 @@ pub fn listen_tls(
     database: &DB,
     storage: &Storage,
     acceptor: TlsAcceptor,
-    upper_data: Option<Data>,
+    upper: Option<(Data, Session)>,
     starttls: bool,
 ) -> color_eyre::eyre::Result<()> {
     let peer = tcp_stream
@@ -155,9 +158,14 @@ pub fn listen_tls(
     let database = database.clone();
     let storage = storage.clone();
     let config = config.clone();
+    // After STARTTLS the session of the plain connection continues
+    let (upper_data, mut session) = match upper {
+        Some((data, session)) => (Some(data), session),
+        None => (None, Session::new(peer, &database)),
+    };
+    let span = session.span();
 
-    // Start talking with new peer on new thread
-    tokio::spawn(async move {
+    let connection = async move {
         // Accept TCP connection
         let tls_stream = acceptor.accept(tcp_stream).await;
 
@@ -165,15 +173,21 @@ pub fn listen_tls(
         match tls_stream {
             Ok(stream) => {
                 debug!("[IMAP] TLS negotiation done");
+                if !starttls {
+                    session.open().await;
+                }
 
-                // Proceed as normal
-                let lines = Framed::new(stream, LinesCodec::new_with_max_length(LINE_LIMIT));
+                // Proceed as normal. The stream starts uncompressed until the client sends COMPRESS.
+                let lines = Framed::new(
+                    CompressibleStream::Plain(stream),
+                    LinesCodec::new_with_max_length(LINE_LIMIT).binary(),
+                );
                 // We split these as we handle the sink in a broadcast instead to be able to push non linear data over the socket
                 let (mut lines_sender, mut lines_reader) = lines.split();
 
                 // Greet the client with the capabilities we provide
                 if !starttls {
-                    if let Err(e) = lines_sender.send(CAPABILITY_HELLO.to_string()).await {
+                    if let Err(e) = lines_sender.send(capability_hello(&config)).await {
                         error!(
                             "Unable to send greeting to client. Closing connection. Error: {}",
                             e
@@ -182,7 +196,7 @@ pub fn listen_tls(
                     }
                 }
                 // Create our Connection
-                let connection = Connection::new(true);
+                let connection = Connection::new(true, peer.ip().to_string());
 
                 let mut data = if let Some(mut data) = upper_data.clone() {
                     {
@@ -194,103 +208,59 @@ pub fn listen_tls(
                         con_state: connection,
                     }
                 };
+                let mut notifications = Notifications::default();
+                notifications.update(&data, &storage);
                 // Read lines from the stream
-                while let Some(Ok(line)) = lines_reader.next().await {
+                loop {
+                    let line = tokio::select! {
+                        line = lines_reader.next() => line,
+                        event = notifications.next() => {
+                            if let Err(e) = send_event(&data, &mut lines_sender, &storage, event).await {
+                                error!("[IMAP] NOTIFY failed: {}", e);
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    let Some(Ok(line)) = line else {
+                        break;
+                    };
                     debug!("[IMAP] [{}] Got Command: {}", peer, line);
 
                     let response = data
                         .parse(&mut lines_sender, &config, &database, &storage, line)
                         .await;
+                    session.update(&data.con_state).await;
                     match response {
                         Ok(Response::Exit) => {
                             debug!("[IMAP] Closing TLS connection");
                             break;
                         }
                         Ok(Response::Idle { ref tag }) => {
-                            let tag = tag.clone();
-                            let idle_info = if let State::Selected(f, _) = &data.con_state.state {
-                                data.con_state
-                                    .username
-                                    .clone()
-                                    .map(|u| (f.replace('/', "."), u))
-                            } else {
-                                None
-                            };
-                            if let Some((folder, username)) = idle_info {
-                                match storage.to_ondisk_path(folder, username) {
-                                    Ok(mailbox_path) => {
-                                        let (tx, mut rx) =
-                                            mpsc::channel::<notify::Result<notify::Event>>(16);
-                                        match recommended_watcher(move |res| {
-                                            let _ = tx.blocking_send(res);
-                                        }) {
-                                            Ok(mut watcher) => {
-                                                let new_path = mailbox_path.join("new");
-                                                let watch_path = if new_path.exists() {
-                                                    &new_path
-                                                } else {
-                                                    &mailbox_path
-                                                };
-                                                if let Err(e) = watcher
-                                                    .watch(watch_path, RecursiveMode::NonRecursive)
-                                                {
-                                                    error!(
-                                                        "[IMAP] IDLE: failed to watch mailbox: {e}"
-                                                    );
-                                                } else {
-                                                    loop {
-                                                        tokio::select! {
-                                                            Some(_) = rx.recv() => {
-                                                                let count = storage.count_cur(&mailbox_path)
-                                                                    + storage.count_new(&mailbox_path);
-                                                                if let Err(e) = lines_sender.send(format!("* {count} EXISTS")).await {
-                                                                    error!("[IMAP] IDLE: send EXISTS failed: {e}");
-                                                                    break;
-                                                                }
-                                                            }
-                                                            line = lines_reader.next() => {
-                                                                match line {
-                                                                    Some(Ok(l)) if l.trim().eq_ignore_ascii_case("done") => {
-                                                                        if let Err(e) = lines_sender.send(format!("{tag} OK IDLE terminated")).await {
-                                                                            error!("[IMAP] IDLE: send termination failed: {e}");
-                                                                        }
-                                                                        break;
-                                                                    }
-                                                                    None => break,
-                                                                    _ => {}
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                                drop(watcher);
-                                            }
-                                            Err(e) => {
-                                                error!("[IMAP] IDLE: failed to create watcher: {e}")
-                                            }
-                                        }
-                                    }
-                                    Err(e) => error!("[IMAP] IDLE: bad mailbox path: {e}"),
-                                }
-                            } else {
-                                // No mailbox selected — just wait for DONE without sending updates.
-                                loop {
-                                    match lines_reader.next().await {
-                                        Some(Ok(l)) if l.trim().eq_ignore_ascii_case("done") => {
-                                            if let Err(e) = lines_sender
-                                                .send(format!("{tag} OK IDLE terminated"))
-                                                .await
-                                            {
-                                                error!("[IMAP] IDLE: send termination failed: {e}");
-                                            }
-                                            break;
-                                        }
-                                        None => break,
-                                        _ => {}
-                                    }
-                                }
+                            if let Err(e) = (Idle { data: &data })
+                                .exec(&mut lines_sender, &mut lines_reader, &storage, tag)
+                                .await
+                            {
+                                error!("[IMAP] IDLE failed: {}", e);
+                                break;
                             }
                         }
+                        Ok(Response::Compress) => {
+                            // Anything the client sent after the command is already compressed
+                            let lines = match lines_sender.reunite(lines_reader) {
+                                Ok(lines) => lines,
+                                Err(e) => {
+                                    error!("[IMAP] Unable to enable compression: {}", e);
+                                    break;
+                                }
+                            };
+                            let parts = lines.into_parts();
+                            let stream = parts.io.into_deflate(&parts.read_buf);
+                            (lines_sender, lines_reader) =
+                                Framed::new(stream, LinesCodec::new_with_max_length(LINE_LIMIT).binary())
+                                    .split();
+                            debug!("[IMAP] [{}] DEFLATE compression enabled", peer);
+                        }
                         Ok(_) => {}
                         // We try a last time to do a graceful shutdown before closing
                         Err(e) => {
@@ -305,10 +275,20 @@ pub fn listen_tls(
                             break;
                         }
                     }
+                    if let Err(e) = notifications
+                        .flush_pending(&data, &mut lines_sender, &storage)
+                        .await
+                    {
+                        error!("[IMAP] NOTIFY failed: {}", e);
+                        break;
+                    }
+                    notifications.update(&data, &storage);
                 }
             }
             Err(e) => error!("[IMAP] Got error while accepting TLS: {}", e),
         }
-    });
+    };
+    // Start talking with new peer on new thread
+    tokio::spawn(connection.instrument(span));
     Ok(())
 }
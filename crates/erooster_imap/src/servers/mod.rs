@@ -3,5 +3,6 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod encrypted;
+pub mod session;
 pub mod state;
 pub mod unencrypted;
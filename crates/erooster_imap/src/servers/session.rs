@@ -0,0 +1,108 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::servers::state::Connection;
+use erooster_core::backend::{
+    database::{Database, DB},
+    sessions::{self, client_description},
+};
+use std::{collections::BTreeMap, net::SocketAddr};
+use {
+    tokio::runtime::Handle,
+    tracing::{error, field, info_span, Span},
+    uuid::Uuid,
+};
+
+/// The protocol name of the IMAP connections in the sessions table
+pub const PROTOCOL: &str = "imap";
+
+/// A client connection as shown by `eroosterctl session list`
+///
+/// The tracing span of the connection carries the same id, the user and the
+/// client the connection belongs to. The connection is removed from the
+/// sessions table again when the session is dropped.
+#[derive(Debug)]
+pub struct Session {
+    id: String,
+    peer: SocketAddr,
+    span: Span,
+    database: DB,
+    username: Option<String>,
+    client_id: BTreeMap<String, String>,
+}
+
+impl Session {
+    pub fn new(peer: SocketAddr, database: &DB) -> Self {
+        let id = Uuid::new_v4().to_string();
+        let span = info_span!(
+            "imap_session",
+            session = %id,
+            %peer,
+            user = field::Empty,
+            client = field::Empty
+        );
+        Session {
+            id,
+            peer,
+            span,
+            database: database.clone(),
+            username: None,
+            client_id: BTreeMap::new(),
+        }
+    }
+
+    /// The span all work of the connection runs in
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    /// Registers the connection in the sessions table
+    pub async fn open(&self) {
+        let peer = self.peer.to_string();
+        if let Err(e) = sessions::open(self.database.get_pool(), &self.id, PROTOCOL, &peer).await {
+            error!("[IMAP] Unable to register the session: {e}");
+        }
+    }
+
+    /// Records a new login or ID of the client
+    pub async fn update(&mut self, con_state: &Connection) {
+        if self.username == con_state.username && self.client_id == con_state.client_id {
+            return;
+        }
+        self.username.clone_from(&con_state.username);
+        self.client_id.clone_from(&con_state.client_id);
+        if let Some(username) = &self.username {
+            self.span.record("user", username.as_str());
+        }
+        if !self.client_id.is_empty() {
+            self.span
+                .record("client", client_description(&self.client_id));
+        }
+        if let Err(e) = sessions::update(
+            self.database.get_pool(),
+            &self.id,
+            self.username.as_deref(),
+            &self.client_id,
+        )
+        .await
+        {
+            error!("[IMAP] Unable to update the session: {e}");
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let Ok(runtime) = Handle::try_current() else {
+            return;
+        };
+        let database = self.database.clone();
+        let id = std::mem::take(&mut self.id);
+        runtime.spawn(async move {
+            if let Err(e) = sessions::close(database.get_pool(), &id).await {
+                error!("[IMAP] Unable to remove the session: {e}");
+            }
+        });
+    }
+}
@@ -2,7 +2,9 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::commands::{auth::AuthenticationMethod, parsers::DateTime};
+use crate::commands::parsers::{DateTime, NotifyEventGroup};
+use erooster_core::sasl::Exchange;
+use std::collections::BTreeMap;
 
 /// State of the connection session between us and the Client
 #[derive(Debug, Clone)]
@@ -11,15 +13,48 @@ pub struct Connection {
     pub secure: bool,
     pub username: Option<String>,
     pub active_capabilities: Vec<Capabilities>,
+    /// The event groups requested with NOTIFY SET, if any (RFC 5465)
+    pub notify: Option<Vec<NotifyEventGroup>>,
+    /// Whether COMPRESS=DEFLATE is active on the transport (RFC 4978)
+    pub compressed: bool,
+    /// The UIDs saved with `RETURN (SAVE)` for the `$` reference (RFC 5182)
+    pub saved_result: Vec<u32>,
+    /// The fields the client sent with the ID command (RFC 2971)
+    pub client_id: BTreeMap<String, String>,
+    /// The IP address of the client
+    pub peer_addr: String,
 }
 
 impl Connection {
-    pub const fn new(secure: bool) -> Self {
+    pub const fn new(secure: bool, peer_addr: String) -> Self {
         Connection {
             state: State::NotAuthenticated,
             secure,
             username: None,
             active_capabilities: vec![],
+            notify: None,
+            compressed: false,
+            saved_result: vec![],
+            client_id: BTreeMap::new(),
+            peer_addr,
+        }
+    }
+
+    /// Whether the client enabled CONDSTORE, either explicitly or through
+    /// a CONDSTORE-enabling command (RFC 7162 §3.1).
+    pub fn condstore_enabled(&self) -> bool {
+        self.active_capabilities.contains(&Capabilities::Condstore)
+    }
+
+    /// Whether the client enabled QRESYNC via ENABLE (RFC 7162 §3.2.3).
+    pub fn qresync_enabled(&self) -> bool {
+        self.active_capabilities.contains(&Capabilities::QResync)
+    }
+
+    /// Marks CONDSTORE as enabled for the rest of the session.
+    pub fn enable_condstore(&mut self) {
+        if !self.condstore_enabled() {
+            self.active_capabilities.push(Capabilities::Condstore);
         }
     }
 }
@@ -27,6 +62,8 @@ impl Connection {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Capabilities {
     UTF8,
+    Condstore,
+    QResync,
     Other(String),
 }
 
@@ -34,8 +71,8 @@ pub enum Capabilities {
 pub enum State {
     /// Initial State
     NotAuthenticated,
-    /// Auth in progress
-    Authenticating(AuthenticationMethod, String),
+    /// Auth in progress, waiting for the next client response
+    Authenticating(Exchange, String),
     /// Auth successful
     Authenticated,
     /// Folder selected
@@ -46,15 +83,44 @@ pub enum State {
     /// - flags
     /// - datetime
     Appending(AppendingState),
+    /// A command is interrupted by a literal (RFC 9051 §4.3)
+    ReceivingLiteral(LiteralState),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LiteralState {
+    /// The command received so far, earlier literals already turned into
+    /// quoted strings
+    pub line: String,
+    /// The octets of the current literal received so far
+    pub literal: Vec<u8>,
+    /// The octets of the current literal which are still to be received,
+    /// `None` while the rest of the command line is expected
+    pub remaining: Option<usize>,
+    /// Whether a literal exceeded the size limit. Its octets are skipped and
+    /// the command is refused once complete.
+    pub too_big: bool,
+    /// Whether a literal or the rest of the command isn't UTF-8 text. The
+    /// command is refused once complete.
+    pub not_text: bool,
+    /// State to restore once the command is complete
+    pub previous_state: Box<State>,
 }
 
 #[derive(PartialEq, Eq, Clone)]
 pub struct AppendingState {
     pub folder: String,
-    pub flags: Option<Vec<String>>,
-    pub datetime: Option<DateTime>,
-    pub data: Option<Vec<u8>>,
-    pub datalen: usize,
+    /// Messages which are complete. They are only stored once the command
+    /// ends so that MULTIAPPEND (RFC 3502) is atomic.
+    pub messages: Vec<AppendMessage>,
+    /// The message which is currently received
+    pub current: Option<AppendMessage>,
+    /// The octets of the current literal which are still to be received,
+    /// `None` while the rest of the command line is expected
+    pub literal: Option<usize>,
+    /// The response for a command which already failed but whose remaining
+    /// non-synchronizing literals still have to be read
+    pub error: Option<String>,
     pub tag: String,
     /// State to restore when APPEND completes (RFC 9051: APPEND does not change
     /// the selected mailbox).
@@ -66,14 +132,25 @@ impl std::fmt::Debug for AppendingState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AppendingState")
             .field("folder", &self.folder)
-            .field("flags", &self.flags)
-            .field("datetime", &self.datetime)
-            .field("datalen", &self.datalen)
+            .field("messages", &self.messages.len())
+            .field("literal", &self.literal)
+            .field("error", &self.error)
             .field("tag", &self.tag)
             .finish()
     }
 }
 
+/// A single message of an APPEND command
+#[derive(PartialEq, Eq, Clone)]
+pub struct AppendMessage {
+    pub flags: Option<Vec<String>>,
+    pub datetime: Option<DateTime>,
+    pub data: Vec<u8>,
+    /// Whether the message is built from a CATENATE list (RFC 4469) which
+    /// isn't closed yet
+    pub catenate: bool,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Access {
     ReadOnly,
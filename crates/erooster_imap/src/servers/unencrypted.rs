@@ -3,32 +3,35 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    commands::{Data, Response},
+    commands::{
+        idle::Idle,
+        notify::{send_event, Notifications},
+        Data, Response,
+    },
     servers::{
         encrypted::{get_tls_acceptor, listen_tls},
-        state::{Connection, State},
+        session::Session,
+        state::Connection,
     },
     Server, CAPABILITY_UNENCRYPTED_HELLO,
 };
 use erooster_core::{
     backend::{
         database::DB,
-        storage::{MailStorage, Storage},
+        storage::Storage,
     },
     config::Config,
     line_codec::LinesCodec,
     LINE_LIMIT,
 };
-use notify;
 use std::net::SocketAddr;
 use {
     color_eyre::{self, Result},
     futures::{SinkExt, StreamExt},
-    notify::{recommended_watcher, RecursiveMode, Watcher},
-    tokio::{self, net::TcpListener, sync::mpsc, task::JoinHandle},
+    tokio::{self, net::TcpListener, task::JoinHandle},
     tokio_stream::wrappers::TcpListenerStream,
     tokio_util::codec::Framed,
-    tracing::{debug, error, info, instrument},
+    tracing::{debug, error, info, instrument, Instrument},
 };
 
 /// An unencrypted imap Server
@@ -73,8 +76,11 @@ async fn listen(mut stream: TcpListenerStream, config: &Config, database: &DB, s
         let database = database.clone();
         let storage = storage.clone();
         let config = config.clone();
-        let connection: JoinHandle<Result<()>> = tokio::spawn(async move {
-            let lines = Framed::new(tcp_stream, LinesCodec::new_with_max_length(LINE_LIMIT));
+        let mut session = Session::new(peer, &database);
+        let span = session.span();
+        let connection = async move {
+            session.open().await;
+            let lines = Framed::new(tcp_stream, LinesCodec::new_with_max_length(LINE_LIMIT).binary());
             let (mut lines_sender, mut lines_reader) = lines.split();
             if let Err(e) = lines_sender
                 .send(CAPABILITY_UNENCRYPTED_HELLO.to_string())
@@ -86,16 +92,28 @@ async fn listen(mut stream: TcpListenerStream, config: &Config, database: &DB, s
                 );
                 return Ok(());
             }
-            let state = Connection::new(false);
+            let state = Connection::new(false, peer.ip().to_string());
 
             let mut data = Data { con_state: state };
             let mut do_starttls = false;
-            while let Some(Ok(line)) = lines_reader.next().await {
+            let mut notifications = Notifications::default();
+            loop {
+                let line = tokio::select! {
+                    line = lines_reader.next() => line,
+                    event = notifications.next() => {
+                        send_event(&data, &mut lines_sender, &storage, event).await?;
+                        continue;
+                    }
+                };
+                let Some(Ok(line)) = line else {
+                    break;
+                };
                 debug!("[IMAP] [{}] Got Command: {}", peer, line);
 
                 let response = data
                     .parse(&mut lines_sender, &config, &database, &storage, line)
                     .await;
+                session.update(&data.con_state).await;
 
                 match response {
                     Ok(Response::Exit) => {
@@ -111,89 +129,12 @@ async fn listen(mut stream: TcpListenerStream, config: &Config, database: &DB, s
                         break;
                     }
                     Ok(Response::Idle { ref tag }) => {
-                        let tag = tag.clone();
-                        let idle_info = if let State::Selected(f, _) = &data.con_state.state {
-                            data.con_state
-                                .username
-                                .clone()
-                                .map(|u| (f.replace('/', "."), u))
-                        } else {
-                            None
-                        };
-                        if let Some((folder, username)) = idle_info {
-                            match storage.to_ondisk_path(folder, username) {
-                                Ok(mailbox_path) => {
-                                    let (tx, mut rx) =
-                                        mpsc::channel::<notify::Result<notify::Event>>(16);
-                                    match recommended_watcher(move |res| {
-                                        let _ = tx.blocking_send(res);
-                                    }) {
-                                        Ok(mut watcher) => {
-                                            let new_path = mailbox_path.join("new");
-                                            let watch_path = if new_path.exists() {
-                                                &new_path
-                                            } else {
-                                                &mailbox_path
-                                            };
-                                            if let Err(e) = watcher
-                                                .watch(watch_path, RecursiveMode::NonRecursive)
-                                            {
-                                                error!("[IMAP] IDLE: failed to watch mailbox: {e}");
-                                            } else {
-                                                loop {
-                                                    tokio::select! {
-                                                        Some(_) = rx.recv() => {
-                                                            let count = storage.count_cur(&mailbox_path)
-                                                                + storage.count_new(&mailbox_path);
-                                                            if let Err(e) = lines_sender.send(format!("* {count} EXISTS")).await {
-                                                                error!("[IMAP] IDLE: send EXISTS failed: {e}");
-                                                                break;
-                                                            }
-                                                        }
-                                                        line = lines_reader.next() => {
-                                                            match line {
-                                                                Some(Ok(l)) if l.trim().eq_ignore_ascii_case("done") => {
-                                                                    if let Err(e) = lines_sender.send(format!("{tag} OK IDLE terminated")).await {
-                                                                        error!("[IMAP] IDLE: send termination failed: {e}");
-                                                                    }
-                                                                    break;
-                                                                }
-                                                                None => break,
-                                                                _ => {}
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            drop(watcher);
-                                        }
-                                        Err(e) => {
-                                            error!("[IMAP] IDLE: failed to create watcher: {e}")
-                                        }
-                                    }
-                                }
-                                Err(e) => error!("[IMAP] IDLE: bad mailbox path: {e}"),
-                            }
-                        } else {
-                            // No mailbox selected — just wait for DONE without sending updates.
-                            loop {
-                                match lines_reader.next().await {
-                                    Some(Ok(l)) if l.trim().eq_ignore_ascii_case("done") => {
-                                        if let Err(e) = lines_sender
-                                            .send(format!("{tag} OK IDLE terminated"))
-                                            .await
-                                        {
-                                            error!("[IMAP] IDLE: send termination failed: {e}");
-                                        }
-                                        break;
-                                    }
-                                    None => break,
-                                    _ => {}
-                                }
-                            }
-                        }
+                        Idle { data: &data }
+                            .exec(&mut lines_sender, &mut lines_reader, &storage, tag)
+                            .await?;
                     }
-                    Ok(Response::Continue) => {}
+                    // COMPRESS is refused before the connection is secure
+                    Ok(Response::Continue | Response::Compress) => {}
                     // We try a last time to do a graceful shutdown before closing
                     Err(e) => {
                         if let Err(e) = lines_sender
@@ -207,6 +148,10 @@ async fn listen(mut stream: TcpListenerStream, config: &Config, database: &DB, s
                         break;
                     }
                 }
+                notifications
+                    .flush_pending(&data, &mut lines_sender, &storage)
+                    .await?;
+                notifications.update(&data, &storage);
             }
             if do_starttls {
                 debug!("[IMAP] Starting to reunite");
@@ -221,14 +166,15 @@ async fn listen(mut stream: TcpListenerStream, config: &Config, database: &DB, s
                     &database,
                     &storage,
                     acceptor,
-                    Some(data),
+                    Some((data, session)),
                     true,
                 ) {
                     error!("[SMTP] Error while upgrading to tls: {}", e);
                 }
             }
             Ok(())
-        });
+        };
+        let connection: JoinHandle<Result<()>> = tokio::spawn(connection.instrument(span));
         let resp = connection.await;
         if let Ok(Err(e)) = resp {
             error!("[IMAP] Error: {:?}", e);
@@ -4,19 +4,17 @@
 
 use crate::{
     commands::{CommandData, Data},
-    servers::state::{AuthState, State},
+    servers::state::State,
 };
 use erooster_core::{
-    backend::database::{Database, DB},
-    BASE64_DECODER,
+    backend::database::DB,
+    config::Config,
+    sasl::{AuthError, Exchange, Protocol, Step},
 };
 use {
-    base64::Engine,
     color_eyre,
     futures::{Sink, SinkExt},
-    secrecy::{ExposeSecret, SecretBox, SecretString},
-    simdutf8::compat::from_utf8,
-    tracing::{debug, error, instrument},
+    tracing::{debug, instrument},
 };
 
 pub struct Auth<'a> {
@@ -24,10 +22,11 @@ pub struct Auth<'a> {
 }
 
 impl Auth<'_> {
-    #[instrument(skip(self, lines, database, command_data))]
+    #[instrument(skip(self, lines, config, database, command_data))]
     pub async fn exec<S, E>(
         &mut self,
         lines: &mut S,
+        config: &Config,
         database: &DB,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
@@ -36,230 +35,90 @@ impl Auth<'_> {
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
         let secure = self.data.con_state.secure;
-        if secure {
-            if command_data.arguments.is_empty() {
-                lines
-                    .send(String::from("454 4.7.0 Temporary authentication failure"))
-                    .await?;
-                return Ok(());
-            }
-            if command_data.arguments[0] == "LOGIN" {
-                {
-                    self.data.con_state.state = State::Authenticating(AuthState::Username);
-                };
-                lines.send(String::from("334 VXNlcm5hbWU6")).await?;
-            } else if command_data.arguments[0] == "PLAIN" {
-                if command_data.arguments.len() == 2 {
-                    self.plain(lines, database, command_data.arguments[1])
-                        .await?;
-                } else {
-                    {
-                        self.data.con_state.state = State::Authenticating(AuthState::Plain);
-                    };
-                    lines.send(String::from("+ \"\"")).await?;
-                }
-            } else {
-                lines
-                    .send(String::from("535 4.7.8 Authentication credentials invalid"))
-                    .await?;
-            }
-        } else {
+        if !secure {
             lines
-                .send(String::from(
-                    "538 5.7.11 Encryption required for requested authentication mechanism",
-                ))
+                .send(response(AuthError::EncryptionRequired).to_string())
                 .await?;
+            return Ok(());
         }
-        Ok(())
-    }
+        let Some(mechanism) = command_data.arguments.first() else {
+            lines
+                .send(String::from("501 5.5.4 Syntax: AUTH mechanism"))
+                .await?;
+            return Ok(());
+        };
 
-    #[instrument(skip(self, lines, line))]
-    pub async fn username<S, E>(
-        &mut self,
-        lines: &mut S,
-        line: &str,
-    ) -> color_eyre::eyre::Result<()>
-    where
-        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
-        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
-    {
-        let bytes = BASE64_DECODER.decode(line.as_bytes());
-        match bytes {
-            Ok(bytes) => {
-                let username = from_utf8(&bytes)?;
-                {
-                    self.data.con_state.state =
-                        State::Authenticating(AuthState::Password(username.to_string()));
-                };
-                lines.send(String::from("334 UGFzc3dvcmQ6")).await?;
+        match Exchange::start(
+            config,
+            Protocol::Smtp,
+            mechanism,
+            secure,
+            &self.data.con_state.peer_addr,
+        ) {
+            Ok(exchange) => {
+                if let Some(initial_response) = command_data.arguments.get(1) {
+                    self.respond(lines, config, database, initial_response, exchange)
+                        .await?;
+                } else {
+                    let challenge = exchange.initial_challenge();
+                    self.data.con_state.state = State::Authenticating(exchange);
+                    lines.send(format!("334 {challenge}")).await?;
+                }
             }
-            Err(_) => {
-                lines
-                    .send(String::from("535 5.7.8 Authentication credentials invalid"))
-                    .await?;
+            Err(error) => {
+                lines.send(response(error).to_string()).await?;
             }
         }
-
         Ok(())
     }
 
-    #[instrument(skip(self, lines, database, line))]
-    pub async fn plain<S, E>(
+    /// Relays a client response to the SASL exchange
+    #[instrument(skip(self, lines, config, database, line, exchange))]
+    pub async fn respond<S, E>(
         &mut self,
         lines: &mut S,
+        config: &Config,
         database: &DB,
         line: &str,
+        mut exchange: Exchange,
     ) -> color_eyre::eyre::Result<()>
     where
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
-        let bytes = BASE64_DECODER.decode(line.as_bytes());
-        match bytes {
-            Ok(bytes) => {
-                let auth_data_vec: Vec<&str> = bytes
-                    .split(|x| *x == b'\0')
-                    .filter_map(|string| {
-                        let new_string = from_utf8(string);
-                        if let Ok(new_string) = new_string {
-                            if new_string.is_empty() {
-                                return None;
-                            }
-                            return Some(new_string);
-                        }
-                        None
-                    })
-                    .collect();
-
-                if auth_data_vec.len() == 2 {
-                    let username = auth_data_vec[0];
-                    let password = SecretString::new(Box::from(auth_data_vec[1]));
-
-                    debug!("[SMTP] Making sure user exists");
-                    if database.user_exists(username).await {
-                        debug!("[SMTP] Verify credentials");
-                        if !database.verify_user(username, password).await {
-                            {
-                                self.data.con_state.state = State::NotAuthenticated;
-                            };
-                            lines
-                                .send(String::from("535 5.7.8 Authentication credentials invalid"))
-                                .await?;
-                            debug!("[SMTP] Invalid user or password");
-                            return Ok(());
-                        }
-                        let secure = self.data.con_state.secure;
-                        if secure {
-                            {
-                                self.data.con_state.state =
-                                    State::Authenticated(username.to_string());
-                                debug!("[SMTP] User authenticated");
-                            };
-
-                            lines
-                                .send(String::from("235 2.7.0 Authentication Succeeded"))
-                                .await?;
-                        } else {
-                            lines
-                                .send(String::from(
-                                    "538 5.7.11 Encryption required for requested authentication mechanism",
-                                ))
-                                .await?;
-                        }
-                    } else {
-                        {
-                            self.data.con_state.state = State::NotAuthenticated;
-                        };
-                        lines
-                            .send(String::from("535 5.7.8 Authentication credentials invalid"))
-                            .await?;
-                    }
-                } else {
-                    {
-                        self.data.con_state.state = State::NotAuthenticated;
-                    };
-                    lines
-                        .send(String::from("432 4.7.12 A password transition is needed"))
-                        .await?;
-                }
+        self.data.con_state.state = State::NotAuthenticated;
+        match exchange.respond(config, database, line).await {
+            Step::Challenge(challenge) => {
+                self.data.con_state.state = State::Authenticating(exchange);
+                lines.send(format!("334 {challenge}")).await?;
             }
-            Err(e) => {
-                {
-                    self.data.con_state.state = State::NotAuthenticated;
-                };
-                error!("Error logging in: {}", e);
+            Step::Success(username) => {
+                debug!("[SMTP] Authenticated with {}", exchange.mechanism().name());
+                self.data.con_state.state = State::Authenticated(username);
                 lines
-                    .send(String::from("432 4.7.12 A password transition is needed"))
+                    .send(String::from("235 2.7.0 Authentication Succeeded"))
                     .await?;
             }
+            Step::Failure(error) => {
+                lines.send(response(error).to_string()).await?;
+            }
         }
         Ok(())
     }
+}
 
-    #[instrument(skip(self, lines, database, line))]
-    pub async fn password<S, E>(
-        &mut self,
-        lines: &mut S,
-        database: &DB,
-        line: &str,
-    ) -> color_eyre::eyre::Result<()>
-    where
-        E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
-        S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
-    {
-        let bytes = BASE64_DECODER.decode(line.as_bytes());
-        match bytes {
-            Ok(bytes) => {
-                let password = SecretString::new(Box::from(from_utf8(
-                    SecretBox::<[u8]>::new(bytes.into_boxed_slice()).expose_secret(),
-                )?));
-
-                {
-                    if let State::Authenticating(AuthState::Password(username)) =
-                        &self.data.con_state.state
-                    {
-                        if database.user_exists(username).await {
-                            let valid = database.verify_user(username, password).await;
-                            if !valid {
-                                self.data.con_state.state = State::NotAuthenticated;
-                                lines
-                                    .send(String::from(
-                                        "535 5.7.8 Authentication credentials invalid",
-                                    ))
-                                    .await?;
-                                return Ok(());
-                            }
-                        } else {
-                            self.data.con_state.state = State::NotAuthenticated;
-                            lines
-                                .send(String::from("535 5.7.8 Authentication credentials invalid"))
-                                .await?;
-                            return Ok(());
-                        }
-                    } else {
-                        self.data.con_state.state = State::NotAuthenticated;
-                        lines
-                            .send(String::from("432 4.7.12 A password transition is needed"))
-                            .await?;
-                        return Ok(());
-                    }
-                    if let State::Authenticating(AuthState::Password(username)) =
-                        &self.data.con_state.state
-                    {
-                        self.data.con_state.state = State::Authenticated(username.clone());
-                    }
-                };
-                lines
-                    .send(String::from("235 2.7.0  Authentication Succeeded"))
-                    .await?;
-            }
-            Err(_) => {
-                lines
-                    .send(String::from("454 4.7.0 Temporary authentication failure"))
-                    .await?;
-            }
+/// The reply for a failed authentication (RFC 4954)
+const fn response(error: AuthError) -> &'static str {
+    match error {
+        AuthError::UnsupportedMechanism => "504 5.5.4 Unrecognized authentication type",
+        AuthError::EncryptionRequired => {
+            "538 5.7.11 Encryption required for requested authentication mechanism"
         }
-
-        Ok(())
+        AuthError::Malformed => "501 5.5.2 Cannot decode response",
+        AuthError::Canceled => "501 5.0.0 Authentication canceled",
+        AuthError::InvalidCredentials => "535 5.7.8 Authentication credentials invalid",
+        AuthError::NotAuthorized => "535 5.7.8 Not authorized to act as the requested user",
+        AuthError::TooManyAttempts => "454 4.7.0 Too many failed attempts, try again later",
+        AuthError::Unavailable => "454 4.7.0 Temporary authentication failure",
     }
 }
@@ -4,12 +4,11 @@
 
 use crate::{
     commands::Data,
-    servers::{
-        sending::{dkim_sign, EmailPayload},
-        state::Data as StateData,
-        state::State,
+    servers::{dsn, sending::dkim_sign, state::Data as StateData, state::State},
+    utils::{
+        delivery::{queue_outbound, store_in_inbox},
+        rspamd::{Action, Response},
     },
-    utils::rspamd::{Action, Response},
 };
 
 enum RspamdDecision {
@@ -21,11 +20,14 @@ use cfg_if::cfg_if;
 use color_eyre::{self, eyre::ContextCompat};
 use erooster_core::{
     backend::{
+        aliases::{self, Target},
         database::{Database, DB},
-        queue,
+        domains, queue,
+        quota::QuotaUsage,
         storage::{MailStorage, Storage},
     },
     config::{Config, Rspamd},
+    srs,
 };
 use futures::{Sink, SinkExt};
 use mail_auth::DkimResult;
@@ -34,7 +36,7 @@ use mail_auth::{dmarc::verify::DmarcParameters, DmarcResult};
 use mail_auth::{AuthenticatedMessage, MessageAuthenticator};
 use reqwest;
 use simdutf8::compat::from_utf8;
-use std::{collections::BTreeMap, io::Write, path::Path, time::Duration};
+use std::{io::Write, time::Duration};
 use time::{macros::format_description, OffsetDateTime};
 use tracing::{debug, instrument, warn};
 use uuid;
@@ -91,6 +93,39 @@ impl DataCommand<'_> {
             let Some(receipts) = &self.data.con_state.receipts else {
                 color_eyre::eyre::bail!("No receipts")
             };
+            // Check all local mailboxes first so the message is either
+            // delivered to every recipient or rejected as a whole.
+            if let State::ReceivingData((username, data)) = &self.data.con_state.state {
+                let size = data.0.len() as u64;
+                let mut mailboxes = Vec::new();
+                for receipt in receipts {
+                    if username.is_none()
+                        || domains::is_local_address(database.get_pool(), config, receipt).await?
+                    {
+                        for target in aliases::resolve(database, config, receipt).await? {
+                            if let Target::Mailbox(mailbox) = target {
+                                mailboxes.push((receipt, mailbox));
+                            }
+                        }
+                    }
+                }
+                for (receipt, mailbox) in mailboxes {
+                    if !storage
+                        .quota_allows(&mailbox, &QuotaUsage::message(size))
+                        .await?
+                    {
+                        lines
+                            .send(format!("552 5.2.2 Mailbox \"{receipt}\" is full"))
+                            .await?;
+                        let state = username
+                            .clone()
+                            .map_or(State::NotAuthenticated, State::Authenticated);
+                        self.data.con_state.reset_envelope();
+                        self.data.con_state.state = state;
+                        return Ok(());
+                    }
+                }
+            }
             self.data.con_state.state = if let State::ReceivingData((Some(username), data)) =
                 &self.data.con_state.state
             {
@@ -98,12 +133,18 @@ impl DataCommand<'_> {
 
                 let mut inner_data = data.clone();
                 inner_data.0.truncate(inner_data.0.len() - 2);
+                // Sign with the key of the sender's domain if it has its own
+                let sender_domain = self
+                    .data
+                    .con_state
+                    .sender
+                    .as_ref()
+                    .and_then(|sender| sender.rsplit_once('@'))
+                    .map_or(config.mail.hostname.as_str(), |(_, domain)| domain);
+                let dkim = domains::dkim_key(database.get_pool(), config, sender_domain).await?;
+                let mut delivered = Vec::new();
                 for address in receipts {
-                    let mut to: BTreeMap<String, Vec<String>> = BTreeMap::new();
                     let domain = address.split('@').collect::<Vec<&str>>()[1];
-                    to.entry(domain.to_string())
-                        .or_default()
-                        .push(address.clone());
                     let received_header = format!(
                         "Received: from {} ({} [{}])\r\n	by {} (Erooster) with ESMTPS\r\n	id 00000001\r\n	(envelope-from <{}>)\r\n	for <{}>; {}\r\n",
                         self.data.con_state.ehlo.as_ref().context("Missing ehlo")?,
@@ -143,9 +184,8 @@ impl DataCommand<'_> {
                                 lines
                                     .send(String::from("550 5.7.1 Message cannot be accepted."))
                                     .await?;
-                                self.data.con_state.receipts = None;
-                                self.data.con_state.sender = None;
                                 self.data.con_state.state = State::Authenticated(username.clone());
+                                self.data.con_state.reset_envelope();
                                 return Ok(());
                             }
                             RspamdDecision::TempReject => {
@@ -154,9 +194,8 @@ impl DataCommand<'_> {
                                         "451 4.7.1 Service temporarily unavailable, please try again later.",
                                     ))
                                     .await?;
-                                self.data.con_state.receipts = None;
-                                self.data.con_state.sender = None;
                                 self.data.con_state.state = State::Authenticated(username.clone());
+                                self.data.con_state.reset_envelope();
                                 return Ok(());
                             }
                         }
@@ -164,119 +203,103 @@ impl DataCommand<'_> {
                         data
                     };
 
-                    if domain == config.mail.hostname {
-                        // Local recipient — write directly to their maildir.
-                        let folder = "INBOX".to_string();
-                        let mailbox_path = Path::new(&config.mail.maildir_folders)
-                            .join(address.clone())
-                            .join(folder.clone());
-                        let db_foldername = format!("{address}/{folder}");
-                        let is_new = !mailbox_path.exists();
-                        storage.create_dirs(&mailbox_path)?;
-                        if is_new {
-                            storage.add_flag(&mailbox_path, "\\Subscribed").await?;
-                            storage.add_flag(&mailbox_path, "\\NoInferiors").await?;
-                        }
-                        let signed_data = match dkim_sign(
-                            &config.mail.hostname,
-                            data,
-                            &config.mail.dkim_key_path,
-                            &config.mail.dkim_key_selector,
-                        ) {
-                            Ok(s) => s,
-                            Err(e) => {
-                                tracing::warn!("DKIM signing failed for local delivery: {e}");
-                                data.to_string()
+                    let from = self
+                        .data
+                        .con_state
+                        .sender
+                        .clone()
+                        .context("Missing sender in internal state")?;
+                    if domains::is_hosted(database.get_pool(), config, domain).await? {
+                        // Local recipient — write directly to the maildirs of
+                        // the users it resolves to and forward the rest.
+                        let signed_data =
+                            match dkim_sign(&dkim.domain, data, &dkim.key_path, &dkim.selector) {
+                                Ok(s) => s,
+                                Err(e) => {
+                                    tracing::warn!("DKIM signing failed for local delivery: {e}");
+                                    data.to_string()
+                                }
+                            };
+                        let mut stored = false;
+                        for target in aliases::resolve(database, config, address).await? {
+                            match target {
+                                Target::Mailbox(mailbox) => {
+                                    let message_id = store_in_inbox(
+                                        config,
+                                        storage,
+                                        &mailbox,
+                                        signed_data.as_bytes(),
+                                        None,
+                                    )
+                                    .await?;
+                                    stored = true;
+                                    debug!("Stored local message: {}", message_id);
+                                    // Record in audit queue so postmasters can inspect local deliveries.
+                                    let audit_id = uuid::Uuid::new_v4();
+                                    let audit_payload = serde_json::json!({
+                                        "id": audit_id,
+                                        "to": { domain: [address] },
+                                        "from": from,
+                                        "local_delivery": true,
+                                    });
+                                    queue::push_local(
+                                        database.get_pool(),
+                                        audit_id,
+                                        audit_payload.to_string(),
+                                        &from,
+                                        &mailbox,
+                                    )
+                                    .await?;
+                                }
+                                Target::Forward(forward) => {
+                                    queue_outbound(
+                                        database,
+                                        config,
+                                        &from,
+                                        &forward,
+                                        data,
+                                        &dkim,
+                                        self.data.con_state.require_tls,
+                                        self.data.con_state.dsn.forwarded(address, &forward),
+                                    )
+                                    .await?;
+                                }
                             }
-                        };
-                        let message_id = storage
-                            .store_new(db_foldername, &mailbox_path, signed_data.as_bytes(), None)
-                            .await?;
-                        debug!("Stored local message: {}", message_id);
-                        // Record in audit queue so postmasters can inspect local deliveries.
-                        let audit_id = uuid::Uuid::new_v4();
-                        let from = self
-                            .data
-                            .con_state
-                            .sender
-                            .clone()
-                            .context("Missing sender in internal state")?;
-                        let audit_payload = serde_json::json!({
-                            "id": audit_id,
-                            "to": { domain: [address] },
-                            "from": from,
-                            "local_delivery": true,
-                        });
-                        queue::push_local(
-                            database.get_pool(),
-                            audit_id,
-                            audit_payload.to_string(),
-                            &from,
-                            &address,
-                        )
-                        .await?;
+                        }
+                        if stored {
+                            delivered.push(dsn::Status::new(address, dsn::Action::Delivered, None));
+                        }
                     } else {
                         // Remote recipient — queue for outbound SMTP delivery.
-                        let email_id = uuid::Uuid::new_v4();
-                        let from = self
-                            .data
-                            .con_state
-                            .sender
-                            .clone()
-                            .context("Missing sender in internal state")?;
-                        let to_addrs_str = to
-                            .values()
-                            .flatten()
-                            .cloned()
-                            .collect::<Vec<_>>()
-                            .join(", ");
-                        let email_payload = EmailPayload {
-                            id: email_id,
-                            to,
-                            from: from.clone(),
-                            body: data.to_string(),
-                            sender_domain: config.mail.hostname.clone(),
-                            dkim_key_path: config.mail.dkim_key_path.clone(),
-                            dkim_key_selector: config.mail.dkim_key_selector.clone(),
-                            require_tls: self.data.con_state.require_tls,
-                        };
-                        let payload_json = serde_json::to_string(&email_payload)?;
-                        queue::push(
-                            database.get_pool(),
-                            email_id,
-                            payload_json,
+                        queue_outbound(
+                            database,
+                            config,
                             &from,
-                            &to_addrs_str,
+                            address,
+                            data,
+                            &dkim,
+                            self.data.con_state.require_tls,
+                            self.data.con_state.dsn.only(address),
                         )
                         .await?;
-                        debug!("Email queued for outbound sending");
                     }
                 }
 
                 lines
                     .send(String::from("250 2.6.0 Message accepted"))
                     .await?;
+                self.notify_delivered(config, database, storage, &inner_data.0, delivered)
+                    .await;
 
                 // RFC 5321: reset envelope state after DATA so the client
                 // can send another message in this session without re-AUTH.
-                self.data.con_state.receipts = None;
-                self.data.con_state.sender = None;
-
-                State::Authenticated(username.clone())
+                let state = State::Authenticated(username.clone());
+                self.data.con_state.reset_envelope();
+                state
             } else if let State::ReceivingData((None, data)) = &self.data.con_state.state {
                 debug!("No authenticated user");
+                let mut delivered = Vec::new();
                 for receipt in receipts {
-                    let folder = "INBOX".to_string();
-                    let mailbox_path = Path::new(&config.mail.maildir_folders)
-                        .join(receipt.clone())
-                        .join(folder.clone());
-                    let db_foldername = format!("{receipt}/{folder}");
-                    let is_new = !mailbox_path.exists();
-                    storage.create_dirs(&mailbox_path)?;
-                    if is_new {
-                        storage.add_flag(&mailbox_path, "\\Subscribed").await?;
-                        storage.add_flag(&mailbox_path, "\\NoInferiors").await?;
-                    }
                     let received_header = format!(
                         "Received: from {} ({} [{}])\r\n	by {} (Erooster) with ESMTPS\r\n	id 00000001\r\n	for <{}>; {}\r\n",
                         self.data.con_state.ehlo.as_ref().context("Missing ehlo")?,
@@ -315,9 +338,8 @@ impl DataCommand<'_> {
                                 lines
                                     .send(String::from("550 5.7.1 Message rejected by spam filter"))
                                     .await?;
-                                self.data.con_state.receipts = None;
-                                self.data.con_state.sender = None;
                                 self.data.con_state.state = State::NotAuthenticated;
+                                self.data.con_state.reset_envelope();
                                 return Ok(());
                             }
                             RspamdDecision::TempReject => {
@@ -326,9 +348,8 @@ impl DataCommand<'_> {
                                         "451 4.7.1 Spam check inconclusive, please try again later",
                                     ))
                                     .await?;
-                                self.data.con_state.receipts = None;
-                                self.data.con_state.sender = None;
                                 self.data.con_state.state = State::NotAuthenticated;
+                                self.data.con_state.reset_envelope();
                                 return Ok(());
                             }
                         }
@@ -450,21 +471,37 @@ impl DataCommand<'_> {
                         }
                     }
 
-                    let message_id = storage
-                        .store_new(
-                            db_foldername,
-                            &mailbox_path,
-                            data.as_bytes(),
-                            Some(dkim_status.to_string()),
-                        )
-                        .await?;
-                    debug!("Stored message: {}", message_id);
+                    let mut stored = false;
+                    for target in aliases::resolve(database, config, receipt).await? {
+                        match target {
+                            Target::Mailbox(mailbox) => {
+                                let message_id = store_in_inbox(
+                                    config,
+                                    storage,
+                                    &mailbox,
+                                    data.as_bytes(),
+                                    Some(dkim_status.to_string()),
+                                )
+                                .await?;
+                                stored = true;
+                                debug!("Stored message: {}", message_id);
+                            }
+                            Target::Forward(forward) => {
+                                self.forward(config, database, receipt, &forward, data)
+                                    .await?;
+                            }
+                        }
+                    }
+                    if stored {
+                        delivered.push(dsn::Status::new(receipt, dsn::Action::Delivered, None));
+                    }
                 }
                 lines
                     .send(String::from("250 2.6.0 Message accepted"))
                     .await?;
-                self.data.con_state.receipts = None;
-                self.data.con_state.sender = None;
+                self.notify_delivered(config, database, storage, &data.0, delivered)
+                    .await;
+                self.data.con_state.reset_envelope();
                 State::NotAuthenticated
             } else {
                 self.data.con_state.state = State::NotAuthenticated;
@@ -479,6 +516,75 @@ impl DataCommand<'_> {
         Ok(())
     }
 
+    /// Forwards an incoming message to the external target of an alias of
+    /// `receipt`, with the sender rewritten so SPF keeps passing.
+    async fn forward(
+        &self,
+        config: &Config,
+        database: &DB,
+        receipt: &str,
+        target: &str,
+        data: &str,
+    ) -> color_eyre::Result<()> {
+        let sender = self
+            .data
+            .con_state
+            .sender
+            .as_ref()
+            .context("Missing sender in internal state")?;
+        let domain = receipt
+            .rsplit_once('@')
+            .map_or(config.mail.hostname.as_str(), |(_, domain)| domain);
+        let from = if let Some(secret) = &config.mail.srs_secret {
+            srs::forward(secret, sender, domain)
+        } else {
+            warn!("Forwarding to {target} without srs_secret, SPF of {sender} is going to fail");
+            sender.clone()
+        };
+        let dkim = domains::dkim_key(database.get_pool(), config, domain).await?;
+        queue_outbound(
+            database,
+            config,
+            &from,
+            target,
+            data,
+            &dkim,
+            self.data.con_state.require_tls,
+            self.data.con_state.dsn.forwarded(receipt, target),
+        )
+        .await
+    }
+
+    /// Tells the sender about the local deliveries it asked to be notified
+    /// about. The message is accepted at this point, so failing to do so is
+    /// only logged.
+    async fn notify_delivered(
+        &self,
+        config: &Config,
+        database: &DB,
+        storage: &Storage,
+        message: &[u8],
+        delivered: Vec<dsn::Status>,
+    ) {
+        let Some(sender) = &self.data.con_state.sender else {
+            return;
+        };
+        let message = String::from_utf8_lossy(message);
+        if let Err(e) = dsn::notify(
+            config,
+            database,
+            storage,
+            sender,
+            &self.data.con_state.dsn,
+            &message,
+            delivered,
+        )
+        .await
+        {
+            warn!("Failed to send delivery status notification: {e:?}");
+        }
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn call_rspamd(
         &self,
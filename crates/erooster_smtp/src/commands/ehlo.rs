@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::commands::{CommandData, Data};
+use erooster_core::{config::Config, sasl::Mechanism};
 use {
     color_eyre::{self, eyre::bail},
     futures::{Sink, SinkExt},
@@ -15,11 +16,10 @@ pub struct Ehlo<'a> {
 }
 
 impl Ehlo<'_> {
-    #[instrument(skip(self, hostname, max_message_bytes, lines, command_data))]
+    #[instrument(skip(self, config, lines, command_data))]
     pub async fn exec<S, E>(
         &mut self,
-        hostname: &str,
-        max_message_bytes: u64,
+        config: &Config,
         lines: &mut S,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
@@ -27,6 +27,7 @@ impl Ehlo<'_> {
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
+        let hostname = &config.mail.hostname;
         if command_data.arguments.is_empty() {
             bail!("Invalid EHLO arguments: {:?}", command_data.arguments);
         }
@@ -63,14 +64,26 @@ impl Ehlo<'_> {
         lines.feed(format!("250-{hostname}")).await?;
         lines.feed(String::from("250-ENHANCEDSTATUSCODES")).await?;
         lines.feed(String::from("250-PIPELINING")).await?;
-        lines.feed(format!("250-SIZE {max_message_bytes}")).await?;
+        lines
+            .feed(format!(
+                "250-SIZE {}",
+                config.mail.max_message_size.as_bytes()
+            ))
+            .await?;
         lines.feed(String::from("250-8BITMIME")).await?;
         lines.feed(String::from("250-SMTPUTF8")).await?;
+        lines.feed(String::from("250-DSN")).await?;
         if !self.data.con_state.secure {
             lines.feed(String::from("250-STARTTLS")).await?;
         }
         if self.data.con_state.secure {
-            lines.feed(String::from("250-AUTH LOGIN PLAIN")).await?;
+            let mechanisms: Vec<&str> = Mechanism::available(config)
+                .into_iter()
+                .map(Mechanism::name)
+                .collect();
+            lines
+                .feed(format!("250-AUTH {}", mechanisms.join(" ")))
+                .await?;
             lines.feed(String::from("250-REQUIRETLS")).await?;
         }
         lines.feed(String::from("250 VRFY")).await?;
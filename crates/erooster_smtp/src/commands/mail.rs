@@ -3,7 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::commands::{parsers::localpart_arguments, CommandData, Data};
-use crate::servers::state::State;
+use crate::servers::{dsn, state::State};
 use erooster_core::config::Config;
 use {
     color_eyre::{
@@ -20,6 +20,7 @@ pub struct Mail<'a> {
 }
 
 impl Mail<'_> {
+    #[allow(clippy::too_many_lines)]
     #[instrument(skip(self, lines, command_data, config))]
     pub async fn exec<S, E>(
         &mut self,
@@ -50,9 +51,12 @@ impl Mail<'_> {
 
         match localpart_arguments(command_data.arguments[0]).map(|(_, senders)| senders) {
             Ok(args) => {
-                // Parse extra MAIL FROM parameters (SIZE=, BODY=, REQUIRETLS).
+                // Parse extra MAIL FROM parameters (SIZE=, BODY=, REQUIRETLS,
+                // RET=, ENVID=).
                 let mut declared_size: Option<u64> = None;
                 let mut require_tls = false;
+                let mut ret = None;
+                let mut envid = None;
 
                 for param in &command_data.arguments[1..] {
                     let upper = param.to_uppercase();
@@ -65,6 +69,24 @@ impl Mail<'_> {
                         }
                     } else if upper == "REQUIRETLS" {
                         require_tls = true;
+                    } else if let Some(value) = upper.strip_prefix("RET=") {
+                        let Some(value) = dsn::Ret::parse(value) else {
+                            lines
+                                .send(format!("501 5.5.4 Invalid RET parameter: {param}"))
+                                .await?;
+                            return Ok(());
+                        };
+                        ret = Some(value);
+                    } else if upper.starts_with("ENVID=") {
+                        // The identifier is returned as it was given
+                        let value = &param["ENVID=".len()..];
+                        if value.len() > 100 || dsn::xtext_decode(value).is_none() {
+                            lines
+                                .send(format!("501 5.5.4 Invalid ENVID parameter: {param}"))
+                                .await?;
+                            return Ok(());
+                        }
+                        envid = Some(value.to_string());
                     }
                     // BODY= (8BITMIME, 7BIT, BINARYMIME) — accepted, not enforced
                 }
@@ -109,12 +131,14 @@ impl Mail<'_> {
                     }
                 }
 
-                let senders: Vec<_> = args.iter().map(ToString::to_string).collect();
-                self.data.con_state.sender = Some(senders[0].clone());
+                // `MAIL FROM:<>` is the null sender of bounces
+                let sender = args.first().map(ToString::to_string).unwrap_or_default();
+                self.data.con_state.reset_envelope();
+                self.data.con_state.sender = Some(sender);
                 self.data.con_state.declared_size = declared_size;
-                if require_tls {
-                    self.data.con_state.require_tls = true;
-                }
+                self.data.con_state.require_tls = require_tls;
+                self.data.con_state.dsn.ret = ret;
+                self.data.con_state.dsn.envid = envid;
 
                 lines
                     .send(format!(
@@ -7,7 +7,7 @@ use crate::{
         auth::Auth, data::DataCommand, ehlo::Ehlo, mail::Mail, noop::Noop, quit::Quit, rcpt::Rcpt,
         rset::Rset, vrfy::Vrfy,
     },
-    servers::state::{AuthState, Connection, State},
+    servers::state::{Connection, State},
 };
 use erooster_core::{
     backend::{database::DB, storage::Storage},
@@ -174,18 +174,10 @@ impl Data {
                 .await?;
             // We are done here
             return Ok(Response::Continue);
-        } else if let State::Authenticating(auth_state) = state {
-            match auth_state {
-                AuthState::Username => {
-                    Auth { data: self }.username(lines, &line).await?;
-                }
-                AuthState::Plain => {
-                    Auth { data: self }.plain(lines, database, &line).await?;
-                }
-                AuthState::Password(_) => {
-                    Auth { data: self }.password(lines, database, &line).await?;
-                }
-            }
+        } else if let State::Authenticating(exchange) = state {
+            Auth { data: self }
+                .respond(lines, config, database, &line, exchange)
+                .await?;
             // We are done here
             return Ok(Response::Continue);
         }
@@ -213,16 +205,11 @@ impl Data {
                         return Ok(Response::STARTTLS);
                     }
                     Commands::RSET => {
-                        Rset.exec(lines).await?;
+                        Rset { data: self }.exec(lines).await?;
                     }
                     Commands::EHLO => {
                         Ehlo { data: self }
-                            .exec(
-                                &config.mail.hostname,
-                                config.mail.max_message_size.as_bytes(),
-                                lines,
-                                &command_data,
-                            )
+                            .exec(config, lines, &command_data)
                             .await?;
                     }
                     Commands::QUIT => {
@@ -237,7 +224,7 @@ impl Data {
                     }
                     Commands::RCPTTO => {
                         Rcpt { data: self }
-                            .exec(lines, database, &config.mail.hostname, &command_data)
+                            .exec(lines, config, database, storage, &command_data)
                             .await?;
                     }
                     Commands::DATA => {
@@ -245,7 +232,7 @@ impl Data {
                     }
                     Commands::AUTH => {
                         Auth { data: self }
-                            .exec(lines, database, &command_data)
+                            .exec(lines, config, database, &command_data)
                             .await?;
                     }
                     Commands::NOOP => {
@@ -4,9 +4,18 @@
 
 use crate::{
     commands::{parsers::localpart_arguments, CommandData, Data},
-    servers::state::State,
+    servers::{dsn, state::State},
+};
+use erooster_core::{
+    backend::{
+        aliases::{self, Target},
+        database::{Database, DB},
+        domains,
+        quota::QuotaUsage,
+        storage::{MailStorage, Storage},
+    },
+    config::Config,
 };
-use erooster_core::backend::database::{Database, DB};
 use {
     color_eyre::{self, eyre::bail},
     futures::{Sink, SinkExt},
@@ -18,12 +27,13 @@ pub struct Rcpt<'a> {
 }
 
 impl Rcpt<'_> {
-    #[instrument(skip(self, lines, database, command_data))]
+    #[instrument(skip(self, lines, config, database, storage, command_data))]
     pub async fn exec<S, E>(
         &mut self,
         lines: &mut S,
+        config: &Config,
         database: &DB,
-        hostname: &str,
+        storage: &Storage,
         command_data: &CommandData<'_>,
     ) -> color_eyre::eyre::Result<()>
     where
@@ -41,10 +51,23 @@ impl Rcpt<'_> {
             .map(ToString::to_string)
             .collect();
 
+        // DSN parameters (RFC 3461 §4)
+        let authenticated = matches!(self.data.con_state.state, State::Authenticated(_));
+        let params = match dsn::Recipient::parse(&command_data.arguments[1..], authenticated) {
+            Ok(params) => params,
+            Err(reply) => {
+                lines.send(reply).await?;
+                return Ok(());
+            }
+        };
+
         {
-            if matches!(&self.data.con_state.state, State::NotAuthenticated) {
-                for receipt in &receipts {
-                    if !receipt.contains(hostname) {
+            // Reject recipients who don't exist or whose mailbox can't take
+            // the message anymore before the client sends the data.
+            let size = self.data.con_state.declared_size.unwrap_or(0);
+            for receipt in &receipts {
+                if !domains::is_local_address(database.get_pool(), config, receipt).await? {
+                    if matches!(&self.data.con_state.state, State::NotAuthenticated) {
                         lines
                             .feed(String::from(
                                 "551-5.7.1 Forwarding to remote hosts disabled",
@@ -58,16 +81,42 @@ impl Rcpt<'_> {
                         lines.flush().await?;
                         return Ok(());
                     }
-                    if !database.user_exists(&receipt.to_lowercase()).await {
-                        lines
-                            .send(format!("550 5.1.1 Mailbox \"{receipt}\" does not exist"))
-                            .await?;
-                        return Ok(());
+                    continue;
+                }
+                let targets = aliases::resolve(database, config, receipt).await?;
+                if targets.is_empty() {
+                    lines
+                        .send(format!("550 5.1.1 Mailbox \"{receipt}\" does not exist"))
+                        .await?;
+                    return Ok(());
+                }
+                for target in targets {
+                    if let Target::Mailbox(mailbox) = target {
+                        if !storage
+                            .quota_allows(&mailbox, &QuotaUsage::message(size))
+                            .await?
+                        {
+                            lines
+                                .send(format!("552 5.2.2 Mailbox \"{receipt}\" is full"))
+                                .await?;
+                            return Ok(());
+                        }
                     }
                 }
             }
 
-            self.data.con_state.receipts = Some(receipts);
+            // Each RCPT TO adds to the recipients of the message
+            let con_state = &mut self.data.con_state;
+            for receipt in receipts {
+                con_state
+                    .dsn
+                    .recipients
+                    .insert(receipt.to_lowercase(), params.clone());
+                let all_receipts = con_state.receipts.get_or_insert_with(Vec::new);
+                if !all_receipts.contains(&receipt) {
+                    all_receipts.push(receipt);
+                }
+            }
         };
 
         lines
@@ -2,21 +2,26 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::commands::Data;
 use {
     color_eyre,
     futures::{Sink, SinkExt},
     tracing::instrument,
 };
 
-pub struct Rset;
+pub struct Rset<'a> {
+    pub data: &'a mut Data,
+}
 
-impl Rset {
+impl Rset<'_> {
     #[instrument(skip(self, lines))]
-    pub async fn exec<S, E>(&self, lines: &mut S) -> color_eyre::eyre::Result<()>
+    pub async fn exec<S, E>(&mut self, lines: &mut S) -> color_eyre::eyre::Result<()>
     where
         E: std::error::Error + std::marker::Sync + std::marker::Send + 'static,
         S: Sink<String, Error = E> + std::marker::Unpin + std::marker::Send,
     {
+        // RFC 5321 §4.1.1.5: abort the current mail transaction
+        self.data.con_state.reset_envelope();
         lines.feed(String::from("250 OK")).await?;
         lines.flush().await?;
         Ok(())
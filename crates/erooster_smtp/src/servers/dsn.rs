@@ -0,0 +1,750 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Delivery Status Notifications (RFC 3461, RFC 3464).
+//!
+//! Senders choose which notifications they get with the `NOTIFY` and `ORCPT`
+//! parameters of `RCPT TO` and the `RET` and `ENVID` parameters of
+//! `MAIL FROM`. Without them they are told when a message is delayed or
+//! can't be delivered. Unauthenticated clients can't ask for success or
+//! delay notifications. Notifications are `multipart/report` messages sent
+//! with the null sender, so one which can't be delivered is dropped instead
+//! of causing another one.
+
+use crate::utils::delivery::{queue_outbound, store_in_inbox};
+use color_eyre::Result;
+use erooster_core::{
+    backend::{
+        aliases::{self, Target},
+        database::{Database, DB},
+        domains,
+        storage::Storage,
+    },
+    config::Config,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write};
+use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+use tracing::debug;
+use uuid::Uuid;
+
+/// How much of a message a failure notification returns (`RET=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ret {
+    /// The whole message
+    Full,
+    /// Only its headers
+    Headers,
+}
+
+impl Ret {
+    pub const fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("FULL") {
+            Some(Self::Full)
+        } else if value.eq_ignore_ascii_case("HDRS") {
+            Some(Self::Headers)
+        } else {
+            None
+        }
+    }
+
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "FULL",
+            Self::Headers => "HDRS",
+        }
+    }
+}
+
+/// The events the sender is notified about for a recipient (`NOTIFY=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Notify {
+    pub success: bool,
+    pub failure: bool,
+    pub delay: bool,
+}
+
+impl Default for Notify {
+    /// Failures and delays, as most servers do when the client doesn't ask
+    fn default() -> Self {
+        Self {
+            success: false,
+            failure: true,
+            delay: true,
+        }
+    }
+}
+
+impl Notify {
+    const NEVER: Self = Self {
+        success: false,
+        failure: false,
+        delay: false,
+    };
+
+    /// Parses `NEVER` or a comma separated list of `SUCCESS`, `FAILURE` and
+    /// `DELAY`.
+    pub fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("NEVER") {
+            return Some(Self::NEVER);
+        }
+        let mut notify = Self::NEVER;
+        for keyword in value.split(',') {
+            if keyword.eq_ignore_ascii_case("SUCCESS") {
+                notify.success = true;
+            } else if keyword.eq_ignore_ascii_case("FAILURE") {
+                notify.failure = true;
+            } else if keyword.eq_ignore_ascii_case("DELAY") {
+                notify.delay = true;
+            } else {
+                return None;
+            }
+        }
+        Some(notify)
+    }
+
+    fn to_param(self) -> String {
+        let keywords: Vec<&str> = [
+            (self.success, "SUCCESS"),
+            (self.failure, "FAILURE"),
+            (self.delay, "DELAY"),
+        ]
+        .into_iter()
+        .filter_map(|(set, keyword)| set.then_some(keyword))
+        .collect();
+        if keywords.is_empty() {
+            "NEVER".to_string()
+        } else {
+            keywords.join(",")
+        }
+    }
+}
+
+/// The DSN parameters of one recipient
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recipient {
+    /// `None` when the client left it to the server
+    pub notify: Option<Notify>,
+    /// The address the client was originally given, as `type;xtext`
+    pub orcpt: Option<String>,
+}
+
+impl Recipient {
+    /// Parses the parameters of `RCPT TO`, which may only be `NOTIFY` and
+    /// `ORCPT`.
+    ///
+    /// Unauthenticated clients only get failure notifications, otherwise
+    /// anyone could have success and delay reports sent to a forged sender.
+    /// Returns the reply for an invalid or unknown parameter.
+    pub fn parse(params: &[&str], authenticated: bool) -> Result<Self, String> {
+        let mut recipient = Self::default();
+        for param in params {
+            let (keyword, value) = param.split_once('=').unwrap_or((param, ""));
+            if keyword.eq_ignore_ascii_case("NOTIFY") {
+                let Some(mut notify) = Notify::parse(value) else {
+                    return Err(format!("501 5.5.4 Invalid NOTIFY parameter: {param}"));
+                };
+                if !authenticated {
+                    notify.success = false;
+                    notify.delay = false;
+                }
+                recipient.notify = Some(notify);
+            } else if keyword.eq_ignore_ascii_case("ORCPT") {
+                let valid = value
+                    .split_once(';')
+                    .is_some_and(|(_, address)| xtext_decode(address).is_some());
+                if !valid {
+                    return Err(format!("501 5.5.4 Invalid ORCPT parameter: {param}"));
+                }
+                recipient.orcpt = Some(value.to_string());
+            } else {
+                return Err(format!("555 5.5.4 Unsupported parameter: {param}"));
+            }
+        }
+        Ok(recipient)
+    }
+}
+
+/// The DSN parameters of a message
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub ret: Option<Ret>,
+    /// The client's identifier of the message, xtext encoded
+    #[serde(default)]
+    pub envid: Option<String>,
+    /// Keyed by the lowercase recipient address
+    #[serde(default)]
+    pub recipients: BTreeMap<String, Recipient>,
+}
+
+impl Request {
+    /// The parameters of a recipient, the defaults if it has none.
+    pub fn recipient(&self, address: &str) -> Recipient {
+        self.recipients
+            .get(&address.to_lowercase())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The request for the copy of the message sent on to `address`.
+    pub fn only(&self, address: &str) -> Self {
+        self.forwarded(address, address)
+    }
+
+    /// The request for the copy of the message for `recipient` forwarded to
+    /// `target`, which keeps `recipient` as the original recipient.
+    pub fn forwarded(&self, recipient: &str, target: &str) -> Self {
+        let mut params = self.recipient(recipient);
+        if params.orcpt.is_none() && recipient != target {
+            params.orcpt = Some(format!("rfc822;{}", xtext_encode(recipient)));
+        }
+        Self {
+            ret: self.ret,
+            envid: self.envid.clone(),
+            recipients: BTreeMap::from([(target.to_lowercase(), params)]),
+        }
+    }
+
+    /// Whether the sender wants to know about `action` for `address`.
+    pub fn notifies(&self, address: &str, action: Action) -> bool {
+        let notify = self.recipient(address).notify.unwrap_or_default();
+        match action {
+            Action::Failed => notify.failure,
+            Action::Delayed => notify.delay,
+            Action::Delivered | Action::Relayed => notify.success,
+        }
+    }
+
+    /// The parameters passed on with `MAIL FROM` to a server supporting DSN.
+    pub fn mail_params(&self) -> String {
+        let mut params = String::new();
+        if let Some(ret) = self.ret {
+            let _ = write!(params, " RET={}", ret.as_str());
+        }
+        if let Some(envid) = &self.envid {
+            let _ = write!(params, " ENVID={envid}");
+        }
+        params
+    }
+
+    /// The parameters passed on with `RCPT TO` to a server supporting DSN.
+    pub fn rcpt_params(&self, address: &str) -> String {
+        let recipient = self.recipient(address);
+        let mut params = String::new();
+        if let Some(notify) = recipient.notify {
+            let _ = write!(params, " NOTIFY={}", notify.to_param());
+        }
+        if let Some(orcpt) = &recipient.orcpt {
+            let _ = write!(params, " ORCPT={orcpt}");
+        }
+        params
+    }
+}
+
+/// Decodes xtext (RFC 3461 §4), `None` if it is malformed.
+pub fn xtext_decode(value: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'+' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'!'..=b'~' if byte != b'=' => decoded.push(byte),
+            _ => return None,
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Encodes `value` as xtext (RFC 3461 §4).
+pub fn xtext_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut encoded, byte| {
+        if (b'!'..=b'~').contains(&byte) && byte != b'+' && byte != b'=' {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "+{byte:02X}");
+        }
+        encoded
+    })
+}
+
+/// What happened to a message for one recipient
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Delivery was given up
+    Failed,
+    /// Delivery is still being retried
+    Delayed,
+    /// Stored in a local mailbox
+    Delivered,
+    /// Handed to a server which doesn't send notifications
+    Relayed,
+}
+
+impl Action {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Failed => "failed",
+            Self::Delayed => "delayed",
+            Self::Delivered => "delivered",
+            Self::Relayed => "relayed",
+        }
+    }
+}
+
+/// The outcome for one recipient of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    pub recipient: String,
+    pub action: Action,
+    /// The reply of the remote server, if the delivery got that far
+    pub diagnostic: Option<String>,
+}
+
+impl Status {
+    /// The outcome for `recipient`, with the reply quoted in the delivery
+    /// `error` as diagnostic.
+    pub fn new(recipient: &str, action: Action, error: Option<&str>) -> Self {
+        Self {
+            recipient: recipient.to_string(),
+            action,
+            diagnostic: error.and_then(smtp_reply).map(ToString::to_string),
+        }
+    }
+
+    /// The enhanced status code (RFC 3463) of the outcome
+    fn code(&self) -> &str {
+        let reply = self.diagnostic.as_deref();
+        let enhanced = reply
+            .and_then(|reply| reply.get(4..))
+            .and_then(|text| text.split_whitespace().next())
+            .filter(|code| is_status_code(code));
+        match (self.action, enhanced) {
+            (Action::Failed | Action::Delayed, Some(code)) => code,
+            (Action::Failed, None) if reply.is_some_and(|reply| reply.starts_with('5')) => "5.0.0",
+            // Retries ran out
+            (Action::Failed, None) => "4.4.7",
+            (Action::Delayed, None) => "4.0.0",
+            (Action::Delivered | Action::Relayed, _) => "2.0.0",
+        }
+    }
+}
+
+/// Finds the first negative SMTP reply quoted in a delivery error.
+fn smtp_reply(error: &str) -> Option<&str> {
+    let bytes = error.as_bytes();
+    (0..bytes.len().saturating_sub(3))
+        .find(|&i| {
+            (i == 0 || bytes[i - 1] == b' ')
+                && matches!(bytes[i], b'4' | b'5')
+                && bytes[i + 1].is_ascii_digit()
+                && bytes[i + 2].is_ascii_digit()
+                && matches!(bytes[i + 3], b' ' | b'-')
+        })
+        .map(|i| error[i..].trim_end())
+}
+
+fn is_status_code(code: &str) -> bool {
+    let parts: Vec<&str> = code.split('.').collect();
+    parts.len() == 3
+        && matches!(parts[0], "2" | "4" | "5")
+        && parts[1..]
+            .iter()
+            .all(|part| (1..=3).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Drops line breaks and other control characters from client supplied
+/// values before they end up in a header.
+fn header_safe(value: &str) -> String {
+    value.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Builds the `multipart/report` notification about `message` for `sender`.
+#[allow(clippy::too_many_lines)]
+pub fn report(
+    hostname: &str,
+    sender: &str,
+    request: &Request,
+    message: &str,
+    statuses: &[Status],
+) -> Result<String> {
+    let boundary = format!("{}/{hostname}", Uuid::new_v4().simple());
+    let date = OffsetDateTime::now_utc().format(&Rfc2822)?;
+    let failed = statuses.iter().any(|s| s.action == Action::Failed);
+    let delayed = statuses.iter().any(|s| s.action == Action::Delayed);
+    let subject = if failed {
+        "Undelivered Mail Returned to Sender"
+    } else if delayed {
+        "Delayed Mail (still being retried)"
+    } else {
+        "Successful Mail Delivery Report"
+    };
+
+    let mut report = String::new();
+    write!(
+        report,
+        "From: Mail Delivery System <MAILER-DAEMON@{hostname}>\r\n\
+         To: <{sender}>\r\n\
+         Subject: {subject}\r\n\
+         Date: {date}\r\n\
+         Message-ID: <{}@{hostname}>\r\n\
+         Auto-Submitted: auto-replied\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/report; report-type=delivery-status;\r\n\
+         \tboundary=\"{boundary}\"\r\n\
+         \r\n\
+         This is a MIME-encapsulated message.\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Description: Notification\r\n\
+         \r\n\
+         This is the mail system at {hostname}.\r\n",
+        Uuid::new_v4()
+    )?;
+    for (action, text) in [
+        (
+            Action::Failed,
+            "Your message could not be delivered to these recipients:",
+        ),
+        (
+            Action::Delayed,
+            "Your message could not be delivered to these recipients yet. Delivery is \
+             going to be retried, there is no need to send it again:",
+        ),
+        (
+            Action::Delivered,
+            "Your message was delivered to these recipients:",
+        ),
+        (
+            Action::Relayed,
+            "Your message was passed on to the servers of these recipients, which \
+             don't send any further notifications:",
+        ),
+    ] {
+        let recipients: Vec<&Status> = statuses.iter().filter(|s| s.action == action).collect();
+        if recipients.is_empty() {
+            continue;
+        }
+        write!(report, "\r\n{text}\r\n\r\n")?;
+        for status in recipients {
+            match &status.diagnostic {
+                Some(reply) => write!(report, "  <{}>: {reply}\r\n", status.recipient)?,
+                None => write!(report, "  <{}>\r\n", status.recipient)?,
+            }
+        }
+    }
+
+    write!(
+        report,
+        "\r\n--{boundary}\r\n\
+         Content-Type: message/delivery-status\r\n\
+         Content-Description: Delivery report\r\n\
+         \r\n\
+         Reporting-MTA: dns; {hostname}\r\n"
+    )?;
+    if let Some(envid) = request.envid.as_deref().and_then(xtext_decode) {
+        write!(report, "Original-Envelope-Id: {}\r\n", header_safe(&envid))?;
+    }
+    for status in statuses {
+        write!(
+            report,
+            "\r\nFinal-Recipient: rfc822; {}\r\n",
+            status.recipient
+        )?;
+        let orcpt = request.recipient(&status.recipient).orcpt;
+        if let Some((kind, address)) = orcpt.as_deref().and_then(|orcpt| orcpt.split_once(';')) {
+            if let Some(address) = xtext_decode(address) {
+                write!(
+                    report,
+                    "Original-Recipient: {}; {}\r\n",
+                    header_safe(kind),
+                    header_safe(&address)
+                )?;
+            }
+        }
+        write!(
+            report,
+            "Action: {}\r\nStatus: {}\r\n",
+            status.action.as_str(),
+            status.code()
+        )?;
+        if let Some(reply) = &status.diagnostic {
+            write!(report, "Diagnostic-Code: smtp; {}\r\n", header_safe(reply))?;
+        }
+        if matches!(status.action, Action::Failed | Action::Delayed) {
+            write!(report, "Last-Attempt-Date: {date}\r\n")?;
+        }
+    }
+
+    // Only failures return the whole message, and only if the sender wants it
+    if failed && request.ret != Some(Ret::Headers) {
+        write!(
+            report,
+            "\r\n--{boundary}\r\n\
+             Content-Type: message/rfc822\r\n\
+             Content-Description: Undelivered message\r\n\
+             \r\n\
+             {message}\r\n"
+        )?;
+    } else {
+        let headers = message
+            .split_once("\r\n\r\n")
+            .map_or(message, |(headers, _)| headers);
+        write!(
+            report,
+            "\r\n--{boundary}\r\n\
+             Content-Type: text/rfc822-headers\r\n\
+             Content-Description: Message headers\r\n\
+             \r\n\
+             {headers}\r\n"
+        )?;
+    }
+    write!(report, "\r\n--{boundary}--")?;
+    Ok(report)
+}
+
+/// Notifies `sender` about the outcomes it asked for. Local senders get the
+/// notification in their mailboxes, others through the outbound queue.
+/// Messages from the null sender are never reported on.
+pub async fn notify(
+    config: &Config,
+    database: &DB,
+    storage: &Storage,
+    sender: &str,
+    request: &Request,
+    message: &str,
+    statuses: Vec<Status>,
+) -> Result<()> {
+    let statuses: Vec<Status> = statuses
+        .into_iter()
+        .filter(|status| request.notifies(&status.recipient, status.action))
+        .collect();
+    if sender.is_empty() || statuses.is_empty() {
+        return Ok(());
+    }
+    let report = report(&config.mail.hostname, sender, request, message, &statuses)?;
+    let dkim = domains::dkim_key(database.get_pool(), config, &config.mail.hostname).await?;
+    let targets = aliases::resolve(database, config, sender).await?;
+    if targets.is_empty() {
+        debug!("Dropping delivery status notification for unknown sender {sender}");
+    }
+    for target in targets {
+        match target {
+            Target::Mailbox(mailbox) => {
+                store_in_inbox(config, storage, &mailbox, report.as_bytes(), None).await?;
+            }
+            Target::Forward(address) => {
+                queue_outbound(
+                    database,
+                    config,
+                    "",
+                    &address,
+                    &report,
+                    &dkim,
+                    false,
+                    Request::default(),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_are_parsed_and_passed_on() {
+        assert_eq!(Notify::parse("never"), Some(Notify::NEVER));
+        assert_eq!(
+            Notify::parse("SUCCESS,Delay"),
+            Some(Notify {
+                success: true,
+                failure: false,
+                delay: true,
+            })
+        );
+        assert_eq!(Notify::parse("NEVER,SUCCESS"), None);
+        assert_eq!(Notify::parse(""), None);
+        assert_eq!(Ret::parse("hdrs"), Some(Ret::Headers));
+        assert_eq!(Ret::parse("BODY"), None);
+
+        assert_eq!(
+            xtext_decode("a+2Bb+3Dc@example.com").unwrap(),
+            "a+b=c@example.com"
+        );
+        assert_eq!(xtext_decode("a+2"), None);
+        assert_eq!(xtext_decode("a b"), None);
+        assert_eq!(xtext_encode("a+b=c d"), "a+2Bb+3Dc+20d");
+
+        let mut request = Request {
+            ret: Some(Ret::Headers),
+            envid: Some("QQ314159".to_string()),
+            recipients: BTreeMap::new(),
+        };
+        request.recipients.insert(
+            "bob@example.com".to_string(),
+            Recipient {
+                notify: Notify::parse("SUCCESS,FAILURE"),
+                orcpt: None,
+            },
+        );
+        assert_eq!(request.mail_params(), " RET=HDRS ENVID=QQ314159");
+        assert_eq!(
+            request.rcpt_params("Bob@example.com"),
+            " NOTIFY=SUCCESS,FAILURE"
+        );
+        assert_eq!(request.rcpt_params("carol@example.com"), "");
+        assert!(request.notifies("bob@example.com", Action::Relayed));
+        assert!(!request.notifies("bob@example.com", Action::Delayed));
+        assert!(request.notifies("carol@example.com", Action::Delayed));
+        assert!(!request.notifies("carol@example.com", Action::Delivered));
+
+        let forwarded = request.forwarded("bob@example.com", "bob@example.net");
+        assert_eq!(
+            forwarded.rcpt_params("bob@example.net"),
+            " NOTIFY=SUCCESS,FAILURE ORCPT=rfc822;bob@example.com"
+        );
+    }
+
+    #[test]
+    fn recipient_parameters_are_checked() {
+        let recipient = Recipient::parse(
+            &["notify=success,delay", "ORCPT=rfc822;bob+40example.com"],
+            true,
+        )
+        .unwrap();
+        assert_eq!(recipient.notify, Notify::parse("SUCCESS,DELAY"));
+        assert_eq!(recipient.orcpt.as_deref(), Some("rfc822;bob+40example.com"));
+
+        // Only failures are reported to unauthenticated clients
+        let recipient = Recipient::parse(&["NOTIFY=SUCCESS,FAILURE,DELAY"], false).unwrap();
+        assert_eq!(recipient.notify, Notify::parse("FAILURE"));
+        let recipient = Recipient::parse(&["NOTIFY=SUCCESS"], false).unwrap();
+        assert_eq!(recipient.notify, Some(Notify::NEVER));
+
+        assert_eq!(
+            Recipient::parse(&["NOTIFY=SOMETIMES"], true),
+            Err(String::from(
+                "501 5.5.4 Invalid NOTIFY parameter: NOTIFY=SOMETIMES"
+            ))
+        );
+        assert_eq!(
+            Recipient::parse(&["ORCPT=bob@example.com"], true),
+            Err(String::from(
+                "501 5.5.4 Invalid ORCPT parameter: ORCPT=bob@example.com"
+            ))
+        );
+        assert_eq!(
+            Recipient::parse(&["XFOO=1"], true),
+            Err(String::from("555 5.5.4 Unsupported parameter: XFOO=1"))
+        );
+        assert_eq!(
+            Recipient::parse(&["NOTIFYX"], true),
+            Err(String::from("555 5.5.4 Unsupported parameter: NOTIFYX"))
+        );
+    }
+
+    #[test]
+    fn statuses_use_the_remote_reply() {
+        let error = "Failed to deliver message to example.com: all MX hosts exhausted, \
+                     last error: RCPT TO rejected for bob@example.com: 550 5.1.1 No such user";
+        let status = Status::new("bob@example.com", Action::Failed, Some(error));
+        assert_eq!(status.diagnostic.as_deref(), Some("550 5.1.1 No such user"));
+        assert_eq!(status.code(), "5.1.1");
+
+        let status = Status::new("bob@example.com", Action::Failed, Some("550 Go away"));
+        assert_eq!(status.code(), "5.0.0");
+        let status = Status::new(
+            "bob@example.com",
+            Action::Failed,
+            Some("Connection to port 25 timed out"),
+        );
+        assert_eq!(status.diagnostic, None);
+        assert_eq!(status.code(), "4.4.7");
+        let status = Status::new("bob@example.com", Action::Delayed, Some("451-4.3.0 Later"));
+        assert_eq!(status.code(), "4.3.0");
+        assert_eq!(
+            Status::new("bob@example.com", Action::Delivered, None).code(),
+            "2.0.0"
+        );
+    }
+
+    #[test]
+    fn reports_describe_each_recipient() {
+        let mut request = Request {
+            ret: None,
+            envid: Some("id+0D+0Ainjected".to_string()),
+            recipients: BTreeMap::new(),
+        };
+        request.recipients.insert(
+            "bob@example.com".to_string(),
+            Recipient {
+                notify: None,
+                orcpt: Some("rfc822;team@example.org".to_string()),
+            },
+        );
+        let message = "Subject: Hello\r\nFrom: alice@example.org\r\n\r\nSecret body";
+        let statuses = [
+            Status::new(
+                "bob@example.com",
+                Action::Failed,
+                Some("550 5.1.1 No such user"),
+            ),
+            Status::new("carol@example.com", Action::Delayed, None),
+        ];
+        let full = report(
+            "mail.example.org",
+            "alice@example.org",
+            &request,
+            message,
+            &statuses,
+        )
+        .unwrap();
+
+        assert!(full.contains("Subject: Undelivered Mail Returned to Sender\r\n"));
+        assert!(full.contains("Auto-Submitted: auto-replied\r\n"));
+        assert!(full.contains("report-type=delivery-status"));
+        assert!(full.contains("Reporting-MTA: dns; mail.example.org\r\n"));
+        assert!(full.contains("Original-Envelope-Id: idinjected\r\n"));
+        assert!(full.contains(
+            "Final-Recipient: rfc822; bob@example.com\r\n\
+             Original-Recipient: rfc822; team@example.org\r\n\
+             Action: failed\r\n\
+             Status: 5.1.1\r\n\
+             Diagnostic-Code: smtp; 550 5.1.1 No such user\r\n"
+        ));
+        assert!(full.contains(
+            "Final-Recipient: rfc822; carol@example.com\r\n\
+             Action: delayed\r\n\
+             Status: 4.0.0\r\n"
+        ));
+        assert!(full.contains("Content-Type: message/rfc822\r\n"));
+        assert!(full.contains("Secret body"));
+
+        request.ret = Some(Ret::Headers);
+        let headers_only = report(
+            "mail.example.org",
+            "alice@example.org",
+            &request,
+            message,
+            &statuses,
+        )
+        .unwrap();
+        assert!(headers_only.contains("Content-Type: text/rfc822-headers\r\n"));
+        assert!(!headers_only.contains("Secret body"));
+        assert!(headers_only.ends_with("--"));
+    }
+}
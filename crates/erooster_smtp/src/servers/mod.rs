@@ -15,6 +15,7 @@ use {
 };
 
 pub(crate) mod dane;
+pub(crate) mod dsn;
 pub(crate) mod encrypted;
 pub(crate) mod mta_sts;
 pub(crate) mod sending;
@@ -86,7 +87,12 @@ pub async fn start(
 
     let db_clone = database.clone();
     let shutdown_flag_clone = shutdown_flag.clone();
-    tokio::spawn(worker::run(db_clone, shutdown_flag_clone));
+    tokio::spawn(worker::run(
+        config,
+        db_clone,
+        storage.clone(),
+        shutdown_flag_clone,
+    ));
 
     Ok(())
 }
@@ -3,12 +3,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::dane::{fetch_tlsa_records, validate_cert_against_tlsa};
+use super::dsn;
 use super::mta_sts::{fetch_mta_sts_policy, mx_allowed_by_policy, MtaStsMode};
 use erooster_core::line_codec::LinesCodec;
 use std::{collections::BTreeMap, error::Error, io, net::IpAddr, path::Path, time::Duration};
 use {
     color_eyre::{self, Result},
-    futures::{SinkExt, StreamExt},
+    futures::{stream::SplitStream, SinkExt, StreamExt},
     hickory_resolver::{proto::rr::RData, TokioResolver},
     mail_auth::{
         common::{
@@ -45,13 +46,21 @@ pub struct EmailPayload {
     pub to: BTreeMap<String, Vec<String>>,
     pub from: String,
     pub body: String,
+    /// The server's hostname, sent with EHLO.
     pub sender_domain: String,
+    /// The signing domain of `dkim_key_path`. Payloads queued before hosted
+    /// domains existed sign as `sender_domain`.
+    #[serde(default)]
+    pub dkim_domain: Option<String>,
     pub dkim_key_path: String,
     pub dkim_key_selector: String,
     /// When true, delivery MUST use TLS (RFC 8689). Defaults to false for
     /// backwards-compatibility with serialized payloads that predate this field.
     #[serde(default)]
     pub require_tls: bool,
+    /// What the sender wants to be notified about (RFC 3461).
+    #[serde(default)]
+    pub dsn: dsn::Request,
 }
 
 trait AsyncReadWrite: AsyncRead + AsyncWrite + Send {}
@@ -110,6 +119,8 @@ struct Port25Connection {
     /// DER-encoded leaf certificate presented by the server during TLS handshake.
     /// None when `is_tls` is false.
     peer_cert_der: Option<Vec<u8>>,
+    /// True when the server advertised DSN (RFC 3461) in its last EHLO.
+    dsn_advertised: bool,
 }
 
 /// Connects to the remote host on port 25, negotiates STARTTLS when available
@@ -145,6 +156,7 @@ async fn connect_with_starttls(
     // Send EHLO and collect capability lines
     sender.send(format!("EHLO {}", email.sender_domain)).await?;
     let mut starttls_available = false;
+    let mut dsn_advertised = false;
     loop {
         let line = reader
             .next()
@@ -158,6 +170,9 @@ async fn connect_with_starttls(
         if line.len() > 4 && line[4..].eq_ignore_ascii_case("STARTTLS") {
             starttls_available = true;
         }
+        if line.len() > 4 && line[4..].eq_ignore_ascii_case("DSN") {
+            dsn_advertised = true;
+        }
         // The final EHLO line uses a space instead of a hyphen after the code
         if line.len() > 3 && line.as_bytes()[3] == b' ' {
             break;
@@ -209,6 +224,7 @@ async fn connect_with_starttls(
                 .send(format!("EHLO {}", email.sender_domain))
                 .await?;
             let mut requiretls_advertised = false;
+            let mut dsn_advertised = false;
             loop {
                 let line = tls_reader
                     .next()
@@ -221,6 +237,9 @@ async fn connect_with_starttls(
                 if line.len() > 4 && line[4..].eq_ignore_ascii_case("REQUIRETLS") {
                     requiretls_advertised = true;
                 }
+                if line.len() > 4 && line[4..].eq_ignore_ascii_case("DSN") {
+                    dsn_advertised = true;
+                }
                 if line.len() > 3 && line.as_bytes()[3] == b' ' {
                     break;
                 }
@@ -234,6 +253,7 @@ async fn connect_with_starttls(
                 is_tls: true,
                 requiretls_advertised,
                 peer_cert_der,
+                dsn_advertised,
             });
         }
     }
@@ -250,48 +270,127 @@ async fn connect_with_starttls(
         is_tls: false,
         requiretls_advertised: false,
         peer_cert_der: None,
+        dsn_advertised,
     })
 }
 
+/// What became of one recipient in a delivery attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Accepted by its server. `dsn` is true if the server supports DSN and
+    /// notifies the sender from there on.
+    Delivered { dsn: bool },
+    /// Not accepted for now (4xx reply or no usable server); retried later.
+    Deferred(String),
+    /// Rejected for good (5xx reply); never retried.
+    Rejected(String),
+}
+
+impl Outcome {
+    /// The outcome of a negative reply.
+    fn of_reply(reply: &str) -> Self {
+        if reply.starts_with('5') {
+            Self::Rejected(reply.to_string())
+        } else {
+            Self::Deferred(reply.to_string())
+        }
+    }
+}
+
+/// Reads a (possibly multiline) reply and returns its last line.
+async fn read_reply(
+    reader: &mut SplitStream<DynFramed>,
+    command: &str,
+) -> Result<String, Box<dyn Error + Send + Sync + 'static>> {
+    loop {
+        let line = reader
+            .next()
+            .await
+            .ok_or_else(|| format!("No response to {command}"))??;
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(line);
+        }
+    }
+}
+
 /// Delivers a message to all recipients using a stream that is positioned
 /// immediately after the EHLO exchange (i.e. ready to accept MAIL FROM).
+/// The DSN parameters are passed on when the server supports them.
+///
+/// Returns the outcome for each recipient. Recipients rejected by `RCPT TO`
+/// don't stop the others from getting the message. Errors mean the whole
+/// transaction failed temporarily and may be tried with another server.
 #[instrument(skip(framed, email, to))]
 async fn smtp_deliver(
     framed: DynFramed,
     email: &EmailPayload,
     to: &[String],
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    dsn: bool,
+) -> Result<BTreeMap<String, Outcome>, Box<dyn Error + Send + Sync + 'static>> {
     let (mut sender, mut reader) = framed.split();
+    let mut outcomes = BTreeMap::new();
 
-    sender.send(format!("MAIL FROM:<{}>", email.from)).await?;
-    let line = reader.next().await.ok_or("No response to MAIL FROM")??;
+    let mail_params = if dsn {
+        email.dsn.mail_params()
+    } else {
+        String::new()
+    };
+    sender
+        .send(format!("MAIL FROM:<{}>{mail_params}", email.from))
+        .await?;
+    let line = read_reply(&mut reader, "MAIL FROM").await?;
     debug!("[{}] MAIL FROM: {}", email.id, line);
     if !line.starts_with("250") {
         sender.send(String::from("QUIT")).await?;
+        if line.starts_with('5') {
+            for addr in to {
+                outcomes.insert(addr.clone(), Outcome::Rejected(line.clone()));
+            }
+            return Ok(outcomes);
+        }
         return Err(format!("MAIL FROM rejected: {line}").into());
     }
 
+    let mut accepted = Vec::new();
     for addr in to {
-        sender.send(format!("RCPT TO:<{addr}>")).await?;
-        let line = reader.next().await.ok_or("No response to RCPT TO")??;
+        let rcpt_params = if dsn {
+            email.dsn.rcpt_params(addr)
+        } else {
+            String::new()
+        };
+        sender
+            .send(format!("RCPT TO:<{addr}>{rcpt_params}"))
+            .await?;
+        let line = read_reply(&mut reader, "RCPT TO").await?;
         debug!("[{}] RCPT TO <{}>: {}", email.id, addr, line);
         // 251 = user not local, but will forward — still acceptable
-        if !line.starts_with("250") && !line.starts_with("251") {
-            sender.send(String::from("QUIT")).await?;
-            return Err(format!("RCPT TO rejected for {addr}: {line}").into());
+        if line.starts_with("250") || line.starts_with("251") {
+            accepted.push(addr);
+        } else {
+            outcomes.insert(addr.clone(), Outcome::of_reply(&line));
         }
     }
+    if accepted.is_empty() {
+        sender.send(String::from("QUIT")).await?;
+        return Ok(outcomes);
+    }
 
     sender.send(String::from("DATA")).await?;
-    let line = reader.next().await.ok_or("No response to DATA")??;
+    let line = read_reply(&mut reader, "DATA").await?;
     debug!("[{}] DATA: {}", email.id, line);
     if !line.starts_with("354") {
         sender.send(String::from("QUIT")).await?;
+        if line.starts_with('5') {
+            for addr in accepted {
+                outcomes.insert(addr.clone(), Outcome::Rejected(line.clone()));
+            }
+            return Ok(outcomes);
+        }
         return Err(format!("DATA rejected: {line}").into());
     }
 
     let signed = dkim_sign(
-        &email.sender_domain,
+        email.dkim_domain.as_ref().unwrap_or(&email.sender_domain),
         &email.body,
         &email.dkim_key_path,
         &email.dkim_key_selector,
@@ -299,30 +398,36 @@ async fn smtp_deliver(
     sender.send(signed).await?;
     sender.send(String::from(".")).await?;
 
-    let line = reader
-        .next()
-        .await
-        .ok_or("No response after message body")??;
+    let line = read_reply(&mut reader, "the message body").await?;
     debug!("[{}] Message accepted: {}", email.id, line);
-    if !line.starts_with("250") {
-        sender.send(String::from("QUIT")).await?;
+    sender.send(String::from("QUIT")).await?;
+    let outcome = if line.starts_with("250") {
+        Outcome::Delivered { dsn }
+    } else if line.starts_with('5') {
+        Outcome::Rejected(line)
+    } else {
         return Err(format!("Message rejected by remote server: {line}").into());
+    };
+    for addr in accepted {
+        outcomes.insert(addr.clone(), outcome.clone());
     }
-
-    sender.send(String::from("QUIT")).await?;
-    Ok(())
+    Ok(outcomes)
 }
 
 /// Looks up MX records for each destination domain (sorted by priority),
 /// negotiates STARTTLS, and delivers the message.  Tries each MX host in
 /// priority order before giving up on a domain.
+///
+/// Returns the outcome for each recipient, a failing domain doesn't keep the
+/// message from the other domains.
 #[allow(clippy::too_many_lines)]
 #[instrument(skip(email))]
 pub async fn send_email_job(
     email: &EmailPayload,
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+) -> Result<BTreeMap<String, Outcome>, Box<dyn Error + Send + Sync + 'static>> {
     debug!("[{}] Starting email delivery", email.id);
     let resolver = TokioResolver::builder_tokio()?.build()?;
+    let mut outcomes = BTreeMap::new();
 
     for (target, to) in &email.to {
         debug!("[{}] Resolving delivery path for {}", email.id, target);
@@ -366,6 +471,7 @@ pub async fn send_email_job(
         }
 
         let mut delivered = false;
+        let mut last_error = None;
         'mx: for (_, host) in &mx_hosts {
             debug!("[{}] Trying MX host {}", email.id, host);
             let ip = match resolver.lookup_ip(host.as_str()).await {
@@ -409,6 +515,7 @@ pub async fn send_email_job(
                         "[{}] Port 25 connection to {} ({}) failed: {}",
                         email.id, host, ip, e
                     );
+                    last_error = Some(e.to_string());
                     continue 'mx;
                 }
             };
@@ -471,12 +578,13 @@ pub async fn send_email_job(
             }
 
             let tls_label = if conn.is_tls { "STARTTLS" } else { "plain" };
-            match smtp_deliver(conn.framed, email, to).await {
-                Ok(()) => {
+            match smtp_deliver(conn.framed, email, to, conn.dsn_advertised).await {
+                Ok(host_outcomes) => {
                     debug!(
-                        "[{}] Delivered to {} via {} ({}) on port 25",
-                        email.id, target, host, tls_label
+                        "[{}] Delivered to {} via {} ({}) on port 25: {:?}",
+                        email.id, target, host, tls_label, host_outcomes
                     );
+                    outcomes.extend(host_outcomes);
                     delivered = true;
                     break 'mx;
                 }
@@ -485,17 +593,139 @@ pub async fn send_email_job(
                         "[{}] Delivery to {} via {} ({}) on port 25 failed: {}",
                         email.id, target, host, tls_label, e
                     );
+                    last_error = Some(e.to_string());
                 }
             }
         }
 
         if !delivered {
-            return Err(
-                format!("Failed to deliver message to {target}: all MX hosts exhausted").into(),
-            );
+            // Keep the last reply so the sender learns why in the bounce
+            let reason = last_error.map_or_else(String::new, |e| format!(", last error: {e}"));
+            let error =
+                format!("Failed to deliver message to {target}: all MX hosts exhausted{reason}");
+            warn!("[{}] {}", email.id, error);
+            for addr in to {
+                outcomes.insert(addr.clone(), Outcome::Deferred(error.clone()));
+            }
         }
     }
 
     debug!("[{}] Email delivery complete", email.id);
-    Ok(())
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, DuplexStream};
+
+    fn payload(to: &[&str]) -> EmailPayload {
+        EmailPayload {
+            id: Uuid::new_v4(),
+            to: BTreeMap::from([(
+                "example.com".to_string(),
+                to.iter().map(ToString::to_string).collect(),
+            )]),
+            from: "alice@example.org".to_string(),
+            body: "Subject: Hello\r\n\r\nHi".to_string(),
+            sender_domain: "mail.example.org".to_string(),
+            dkim_domain: None,
+            dkim_key_path: String::new(),
+            dkim_key_selector: String::new(),
+            require_tls: false,
+            dsn: dsn::Request::default(),
+        }
+    }
+
+    /// Answers each command with the next of `replies` and returns the
+    /// commands including the final QUIT.
+    async fn fake_server(stream: DuplexStream, replies: Vec<&'static str>) -> Vec<String> {
+        let mut framed = Framed::new(stream, LinesCodec::new());
+        let mut commands = Vec::new();
+        for reply in replies {
+            commands.push(framed.next().await.unwrap().unwrap());
+            for line in reply.split('\n') {
+                framed.send(line.to_string()).await.unwrap();
+            }
+        }
+        commands.push(framed.next().await.unwrap().unwrap());
+        commands
+    }
+
+    fn client(stream: DuplexStream) -> DynFramed {
+        Framed::new(Box::new(stream) as DynStream, LinesCodec::new())
+    }
+
+    #[tokio::test]
+    async fn rejected_recipients_dont_abort_the_transaction() {
+        let (client_stream, server_stream) = duplex(4096);
+        let server = tokio::spawn(fake_server(
+            server_stream,
+            vec![
+                "250 OK",
+                "550-5.1.1 No such\n550 5.1.1 user",
+                "451 4.3.0 Try again later",
+            ],
+        ));
+        let mut email = payload(&["bob@example.com", "carol@example.com"]);
+        email.dsn.ret = Some(dsn::Ret::Headers);
+        email.dsn.recipients.insert(
+            "bob@example.com".to_string(),
+            dsn::Recipient {
+                notify: dsn::Notify::parse("FAILURE"),
+                orcpt: None,
+            },
+        );
+        let to: Vec<String> = email.to["example.com"].clone();
+
+        let outcomes = smtp_deliver(client(client_stream), &email, &to, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            outcomes["bob@example.com"],
+            Outcome::Rejected("550 5.1.1 user".to_string())
+        );
+        assert_eq!(
+            outcomes["carol@example.com"],
+            Outcome::Deferred("451 4.3.0 Try again later".to_string())
+        );
+        assert_eq!(
+            server.await.unwrap(),
+            [
+                "MAIL FROM:<alice@example.org> RET=HDRS",
+                "RCPT TO:<bob@example.com> NOTIFY=FAILURE",
+                "RCPT TO:<carol@example.com>",
+                "QUIT",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_sender_rejection_fails_every_recipient() {
+        let (client_stream, server_stream) = duplex(4096);
+        let server = tokio::spawn(fake_server(server_stream, vec!["550 5.7.1 Go away"]));
+        let email = payload(&["bob@example.com", "carol@example.com"]);
+        let to: Vec<String> = email.to["example.com"].clone();
+
+        let outcomes = smtp_deliver(client(client_stream), &email, &to, false)
+            .await
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes
+            .values()
+            .all(|outcome| *outcome == Outcome::Rejected("550 5.7.1 Go away".to_string())));
+        assert_eq!(
+            server.await.unwrap(),
+            ["MAIL FROM:<alice@example.org>", "QUIT"]
+        );
+
+        // A temporary rejection lets the next MX host try
+        let (client_stream, server_stream) = duplex(4096);
+        let server = tokio::spawn(fake_server(server_stream, vec!["421 4.3.2 Busy"]));
+        assert!(smtp_deliver(client(client_stream), &email, &to, false)
+            .await
+            .is_err());
+        server.await.unwrap();
+    }
 }
@@ -2,7 +2,10 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::servers::dsn;
+use erooster_core::sasl::Exchange;
 use mail_auth::SpfOutput;
+use std::collections::BTreeMap;
 
 /// State of the connection session between us and the Client
 #[derive(Debug, Clone)]
@@ -21,6 +24,8 @@ pub struct Connection {
     pub spf_result: Option<SpfOutput>,
     /// Client-declared message size from `MAIL FROM` SIZE= parameter (bytes).
     pub declared_size: Option<u64>,
+    /// DSN parameters of `MAIL FROM` and each `RCPT TO` (RFC 3461).
+    pub dsn: dsn::Request,
 }
 
 impl Connection {
@@ -36,8 +41,22 @@ impl Connection {
             peer_addr,
             spf_result: None,
             declared_size: None,
+            dsn: dsn::Request {
+                ret: None,
+                envid: None,
+                recipients: BTreeMap::new(),
+            },
         }
     }
+
+    /// Forgets the sender, recipients and parameters of the current message.
+    pub fn reset_envelope(&mut self) {
+        self.receipts = None;
+        self.sender = None;
+        self.declared_size = None;
+        self.require_tls = false;
+        self.dsn = dsn::Request::default();
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -46,8 +65,8 @@ pub enum State {
     NotAuthenticated,
     /// DATA command issued, if not None this means we were authenticated
     ReceivingData((Option<String>, Data)),
-    /// Authentication in progress
-    Authenticating(AuthState),
+    /// Authentication in progress, waiting for the next client response
+    Authenticating(Exchange),
     /// Authentication done
     Authenticated(String),
 }
@@ -61,11 +80,3 @@ impl std::fmt::Debug for Data {
         f.debug_struct("Data").finish()
     }
 }
-
-#[allow(clippy::module_name_repetitions)]
-#[derive(Debug, Clone)]
-pub enum AuthState {
-    Plain,
-    Username,
-    Password(String),
-}
@@ -3,11 +3,25 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! Background queue worker: pops outbound emails from the persistent queue and
-//! attempts delivery via `send_email_job`. On success the entry is acked; on
-//! failure it is nacked so the scheduler can retry with exponential backoff.
+//! attempts delivery via `send_email_job`. Once every recipient got the message
+//! or rejected it for good the entry is acked; otherwise it is nacked so the
+//! scheduler can retry the remaining recipients with exponential backoff.
+//! Senders are notified when delivery takes long or is given up (RFC 3464).
 
-use crate::servers::sending::{send_email_job, EmailPayload};
-use erooster_core::backend::{database::Database, database::DB, queue};
+use crate::servers::{
+    dsn::{self, Action, Status},
+    sending::{send_email_job, EmailPayload, Outcome},
+};
+use erooster_core::{
+    backend::{
+        database::Database,
+        database::DB,
+        queue::{self, RecipientStatus},
+        storage::Storage,
+    },
+    config::Config,
+};
+use std::collections::BTreeSet;
 use {
     serde_json,
     tokio::{self, time::Duration},
@@ -15,12 +29,15 @@ use {
     tracing::{error, info, instrument, warn},
 };
 
+/// How long a message waits in the queue before its sender is warned.
+const DELAY_WARNING_SECS: i64 = 4 * 60 * 60;
+
 /// Starts the outbound queue worker loop.
 ///
 /// The worker polls the queue every `poll_interval` and delivers any ready
 /// messages. It exits cleanly when `shutdown` is cancelled.
-#[instrument(skip(database, shutdown))]
-pub async fn run(database: DB, shutdown: CancellationToken) {
+#[instrument(skip(config, database, storage, shutdown))]
+pub async fn run(config: Config, database: DB, storage: Storage, shutdown: CancellationToken) {
     let poll_interval = Duration::from_secs(30);
     info!("Outbound queue worker started");
 
@@ -31,13 +48,13 @@ pub async fn run(database: DB, shutdown: CancellationToken) {
                 return;
             }
             () = tokio::time::sleep(poll_interval) => {
-                process_queue(&database).await;
+                process_queue(&config, &database, &storage).await;
             }
         }
     }
 }
 
-async fn process_queue(database: &DB) {
+async fn process_queue(config: &Config, database: &DB, storage: &Storage) {
     loop {
         match queue::pop(database.get_pool()).await {
             Err(e) => {
@@ -46,54 +63,162 @@ async fn process_queue(database: &DB) {
             }
             Ok(None) => return, // queue empty
             Ok(Some(entry)) => {
-                deliver(database, entry).await;
+                deliver(config, database, storage, entry).await;
             }
         }
     }
 }
 
-#[allow(clippy::cognitive_complexity)]
-async fn deliver(database: &DB, entry: queue::QueueEntry) {
-    let payload: EmailPayload = match serde_json::from_str(&entry.payload) {
+#[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
+async fn deliver(config: &Config, database: &DB, storage: &Storage, entry: queue::QueueEntry) {
+    let pool = database.get_pool();
+    let mut payload: EmailPayload = match serde_json::from_str(&entry.payload) {
         Ok(p) => p,
         Err(e) => {
             error!("Malformed queue entry {}: {e:?}", entry.id);
             // Poison pill — remove it so it doesn't block the queue forever.
-            if let Err(e) = queue::ack(database.get_pool(), &entry.id).await {
+            if let Err(e) = queue::ack(pool, &entry.id).await {
                 error!("Failed to ack malformed entry {}: {e:?}", entry.id);
             }
             return;
         }
     };
 
-    match send_email_job(&payload).await {
-        Ok(()) => {
-            info!("Successfully delivered outbound email {}", entry.id);
-            if let Err(e) = queue::ack(database.get_pool(), &entry.id).await {
-                error!("Failed to ack delivered entry {}: {e:?}", entry.id);
+    // Recipients which got the message or rejected it in an earlier attempt
+    // are done with.
+    let done: BTreeSet<String> = match queue::recipients(pool, &entry.id).await {
+        Ok(recipients) => recipients
+            .into_iter()
+            .filter(|recipient| recipient.status != RecipientStatus::Pending)
+            .map(|recipient| recipient.address)
+            .collect(),
+        Err(e) => {
+            error!("Failed to load recipients of entry {}: {e:?}", entry.id);
+            if let Err(nack_err) = queue::nack(pool, &entry.id, &format!("{e:?}")).await {
+                error!("Failed to nack entry {}: {nack_err:?}", entry.id);
             }
+            return;
         }
+    };
+    for addresses in payload.to.values_mut() {
+        addresses.retain(|address| !done.contains(address));
+    }
+    payload.to.retain(|_, addresses| !addresses.is_empty());
+
+    let outcomes = match send_email_job(&payload).await {
+        Ok(outcomes) => outcomes,
         Err(e) => {
-            let next_attempt = entry.attempts + 1;
-            if next_attempt >= queue::MAX_ATTEMPTS {
-                warn!(
-                    "Outbound email {} exceeded max attempts, giving up: {e:?}",
-                    entry.id
-                );
-                if let Err(ack_err) = queue::ack(database.get_pool(), &entry.id).await {
-                    error!("Failed to remove abandoned entry {}: {ack_err:?}", entry.id);
+            let error = e.to_string();
+            payload
+                .to
+                .values()
+                .flatten()
+                .map(|address| (address.clone(), Outcome::Deferred(error.clone())))
+                .collect()
+        }
+    };
+
+    let mut statuses = Vec::new();
+    let mut deferred = Vec::new();
+    for (address, outcome) in &outcomes {
+        let (status, reply) = match outcome {
+            Outcome::Delivered { dsn } => {
+                if !dsn {
+                    statuses.push(Status::new(address, Action::Relayed, None));
                 }
-            } else {
-                warn!(
-                    "Outbound email {} delivery attempt {} failed, will retry: {e:?}",
-                    entry.id, entry.attempts
+                (RecipientStatus::Delivered, None)
+            }
+            Outcome::Rejected(reply) => {
+                statuses.push(Status::new(address, Action::Failed, Some(reply)));
+                (RecipientStatus::Failed, Some(reply.as_str()))
+            }
+            Outcome::Deferred(reply) => {
+                deferred.push((address, reply));
+                (RecipientStatus::Pending, Some(reply.as_str()))
+            }
+        };
+        if let Err(e) = queue::set_recipient(pool, &entry.id, address, &status, reply).await {
+            error!(
+                "Failed to record the delivery state of {address} for entry {}: {e:?}",
+                entry.id
+            );
+        }
+    }
+
+    if deferred.is_empty() {
+        info!("Outbound email {} is done with all recipients", entry.id);
+        if let Err(e) = queue::ack(pool, &entry.id).await {
+            error!("Failed to ack delivered entry {}: {e:?}", entry.id);
+        }
+    } else {
+        let error = deferred
+            .iter()
+            .map(|(address, reply)| format!("{address}: {reply}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let next_attempt = entry.attempts + 1;
+        if next_attempt >= queue::MAX_ATTEMPTS {
+            warn!(
+                "Outbound email {} exceeded max attempts, giving up: {error}",
+                entry.id
+            );
+            if let Err(ack_err) = queue::ack(pool, &entry.id).await {
+                error!("Failed to remove abandoned entry {}: {ack_err:?}", entry.id);
+            }
+            statuses.extend(
+                deferred
+                    .iter()
+                    .map(|(address, reply)| Status::new(address, Action::Failed, Some(reply))),
+            );
+        } else {
+            warn!(
+                "Outbound email {} delivery attempt {} failed for some recipients, will retry: {error}",
+                entry.id, entry.attempts
+            );
+            if let Err(nack_err) = queue::nack(pool, &entry.id, &error).await {
+                error!("Failed to nack entry {}: {nack_err:?}", entry.id);
+            }
+            // Warn once, on the first failure after the threshold
+            if queued_secs(entry.attempts) >= DELAY_WARNING_SECS
+                && queued_secs(entry.attempts - 1) < DELAY_WARNING_SECS
+            {
+                statuses.extend(
+                    deferred
+                        .iter()
+                        .map(|(address, reply)| Status::new(address, Action::Delayed, Some(reply))),
                 );
-                if let Err(nack_err) =
-                    queue::nack(database.get_pool(), &entry.id, &format!("{e:?}")).await
-                {
-                    error!("Failed to nack entry {}: {nack_err:?}", entry.id);
-                }
             }
         }
     }
+    notify(config, database, storage, &payload, statuses).await;
+}
+
+/// Seconds a message has been waiting in the queue at delivery `attempt`.
+fn queued_secs(attempt: i32) -> i64 {
+    (0..attempt).map(queue::retry_delay_secs).sum()
+}
+
+async fn notify(
+    config: &Config,
+    database: &DB,
+    storage: &Storage,
+    payload: &EmailPayload,
+    statuses: Vec<Status>,
+) {
+    if let Err(e) = dsn::notify(
+        config,
+        database,
+        storage,
+        &payload.from,
+        &payload.dsn,
+        &payload.body,
+        statuses,
+    )
+    .await
+    {
+        error!(
+            "Failed to send delivery status notification for {}: {e:?}",
+            payload.id
+        );
+    }
 }
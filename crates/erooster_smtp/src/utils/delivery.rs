@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hands accepted messages to local mailboxes or the outbound queue.
+
+use crate::servers::{dsn, sending::EmailPayload};
+use erooster_core::{
+    backend::{
+        database::{Database, DB},
+        domains::DkimKey,
+        queue,
+        storage::{MailStorage, Storage},
+    },
+    config::Config,
+};
+use std::{collections::BTreeMap, path::Path};
+use tracing::debug;
+
+/// Stores a message in the INBOX of a local user, creating it on the first
+/// delivery.
+pub async fn store_in_inbox(
+    config: &Config,
+    storage: &Storage,
+    mailbox: &str,
+    data: &[u8],
+    dkim_status: Option<String>,
+) -> color_eyre::Result<String> {
+    let folder = "INBOX".to_string();
+    let mailbox_path = Path::new(&config.mail.maildir_folders)
+        .join(mailbox)
+        .join(folder.clone());
+    let db_foldername = format!("{mailbox}/{folder}");
+    let is_new = !mailbox_path.exists();
+    storage.create_dirs(&mailbox_path)?;
+    if is_new {
+        storage.add_flag(&mailbox_path, "\\Subscribed").await?;
+        storage.add_flag(&mailbox_path, "\\NoInferiors").await?;
+    }
+    storage
+        .store_new(db_foldername, &mailbox_path, data, dkim_status)
+        .await
+}
+
+/// Queues a message for outbound SMTP delivery to `address`.
+#[allow(clippy::too_many_arguments)]
+pub async fn queue_outbound(
+    database: &DB,
+    config: &Config,
+    from: &str,
+    address: &str,
+    data: &str,
+    dkim: &DkimKey,
+    require_tls: bool,
+    dsn: dsn::Request,
+) -> color_eyre::Result<()> {
+    let mut to: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let domain = address
+        .rsplit_once('@')
+        .map_or(address, |(_, domain)| domain);
+    to.entry(domain.to_string())
+        .or_default()
+        .push(address.to_string());
+    let email_id = uuid::Uuid::new_v4();
+    let email_payload = EmailPayload {
+        id: email_id,
+        to,
+        from: from.to_string(),
+        body: data.to_string(),
+        sender_domain: config.mail.hostname.clone(),
+        dkim_domain: Some(dkim.domain.clone()),
+        dkim_key_path: dkim.key_path.clone(),
+        dkim_key_selector: dkim.selector.clone(),
+        require_tls,
+        dsn,
+    };
+    let payload_json = serde_json::to_string(&email_payload)?;
+    queue::push(database.get_pool(), email_id, payload_json, from, address).await?;
+    debug!("Email queued for outbound sending");
+    Ok(())
+}
@@ -11,12 +11,18 @@
 )]
 
 use askama::Template;
-use erooster_core::config::Config;
-use std::net::SocketAddr;
+use erooster_core::{
+    backend::{
+        database::{Database, DB},
+        domains,
+    },
+    config::Config,
+};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 use {
     axum::{
-        extract::Extension,
-        http::{header, HeaderValue, StatusCode},
+        extract::{Extension, Query},
+        http::{header, HeaderMap, HeaderValue, StatusCode},
         response::{Html, IntoResponse, Response},
         routing::get,
         Router,
@@ -28,8 +34,8 @@ use {
 };
 
 /// Starts the webserver used for the admin page and metrics
-#[tracing::instrument(skip(config))]
-pub async fn start(config: &Config) -> color_eyre::eyre::Result<()> {
+#[tracing::instrument(skip(config, database))]
+pub async fn start(config: &Config, database: &DB) -> color_eyre::eyre::Result<()> {
     let addrs: Vec<SocketAddr> = if let Some(listen_ips) = &config.listen_ips {
         listen_ips
             .iter()
@@ -41,6 +47,7 @@ pub async fn start(config: &Config) -> color_eyre::eyre::Result<()> {
     };
     for addr in addrs {
         let config = config.clone();
+        let database = database.clone();
         tokio::spawn(async move {
             let app = Router::new()
                 .route("/", get(handler))
@@ -48,7 +55,10 @@ pub async fn start(config: &Config) -> color_eyre::eyre::Result<()> {
                     "/.well-known/autoconfig/mail/config-v1.1.xml",
                     get(autoconfig),
                 )
-                .layer(Extension(std::sync::Arc::new(config.clone())))
+                // Requested from autoconfig.<domain> by Thunderbird
+                .route("/mail/config-v1.1.xml", get(autoconfig))
+                .layer(Extension(Arc::new(config.clone())))
+                .layer(Extension(Arc::new(database)))
                 .layer(TraceLayer::new_for_http());
 
             if config.webserver.tls {
@@ -89,13 +99,44 @@ async fn handler() -> Html<&'static str> {
     Html("<h1>Hello, World!</h1>")
 }
 
-#[allow(clippy::unused_async)]
-async fn autoconfig(Extension(config): Extension<std::sync::Arc<Config>>) -> impl IntoResponse {
+/// Describes the servers for the domain of the `emailaddress` the client
+/// asks for, or of the `autoconfig.<domain>` host it asked. Domains which are
+/// not hosted are not found.
+async fn autoconfig(
+    Extension(config): Extension<Arc<Config>>,
+    Extension(database): Extension<Arc<DB>>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let requested = query
+        .get("emailaddress")
+        .and_then(|address| address.rsplit_once('@'))
+        .map(|(_, domain)| domain.to_string())
+        .or_else(|| {
+            headers
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .and_then(|host| host.split(':').next())
+                .and_then(|host| host.strip_prefix("autoconfig."))
+                .map(ToString::to_string)
+        });
+    let domain = match requested {
+        Some(domain) => match domains::is_hosted(database.get_pool(), &config, &domain).await {
+            Ok(true) => domain.to_lowercase(),
+            Ok(false) => return StatusCode::NOT_FOUND.into_response(),
+            Err(e) => {
+                error!(?e, "Failed to look up domain {}", domain);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        },
+        None => config.mail.hostname.clone(),
+    };
     let template = AutoconfigTemplate {
-        domain: config.mail.hostname.clone(),
+        domain,
+        hostname: config.mail.hostname.clone(),
         displayname: config.mail.displayname.clone(),
     };
-    XmlTemplate(template)
+    XmlTemplate(template).into_response()
 }
 
 #[derive(Template)]
@@ -103,6 +144,7 @@ async fn autoconfig(Extension(config): Extension<std::sync::Arc<Config>>) -> imp
 #[template(path = "autoconfig.xml")]
 struct AutoconfigTemplate {
     domain: String,
+    hostname: String,
     displayname: String,
 }
 
@@ -0,0 +1,134 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `eroosterctl alias` — address aliases, catch-alls and forwarding.
+
+use crate::output::{
+    color_disabled, print_error, print_json, print_success, print_table, print_warning,
+    OutputFormat,
+};
+use clap::Subcommand;
+use color_eyre::eyre::Result;
+use erooster_core::{
+    backend::{
+        aliases,
+        database::{get_database, Database},
+        domains,
+    },
+    config::Config,
+};
+use serde::Serialize;
+
+#[derive(Subcommand, Debug)]
+pub enum AliasCommands {
+    /// Deliver mail for an address to local users or external addresses
+    Add {
+        /// Address of a hosted domain, `*@domain` for its catch-all
+        address: String,
+        /// Users, aliases or external addresses to deliver to
+        #[arg(required = true)]
+        targets: Vec<String>,
+    },
+
+    /// Remove a target of an alias, or the whole alias
+    #[command(alias = "rm")]
+    Remove {
+        /// The alias address
+        address: String,
+        /// The target to remove (defaults to all targets)
+        target: Option<String>,
+    },
+
+    /// List all aliases
+    #[command(alias = "ls")]
+    List,
+}
+
+#[derive(Serialize)]
+struct AliasRow {
+    address: String,
+    target: String,
+    created_at: String,
+}
+
+pub async fn run(
+    cmd: AliasCommands,
+    config: &Config,
+    format: OutputFormat,
+    no_color: bool,
+) -> Result<()> {
+    let no_color = color_disabled(no_color);
+    let db = get_database(config).await?;
+    match cmd {
+        AliasCommands::Add { address, targets } => {
+            if !domains::is_local_address(db.get_pool(), config, &address).await? {
+                print_error(
+                    no_color,
+                    &format!("'{address}' is not an address of a hosted domain."),
+                );
+                std::process::exit(1);
+            }
+            for target in &targets {
+                if !target.contains('@') {
+                    print_error(no_color, &format!("'{target}' is not an address."));
+                    std::process::exit(1);
+                }
+            }
+            for target in &targets {
+                aliases::add(db.get_pool(), &address, target).await?;
+            }
+            let resolved = aliases::resolve(&db, config, &address).await?;
+            if resolved.is_empty() {
+                print_warning(
+                    no_color,
+                    &format!("'{address}' does not reach any user or external address."),
+                );
+            }
+            print_success(
+                no_color,
+                &format!(
+                    "Alias '{}' delivers to {}.",
+                    address.to_lowercase(),
+                    targets.join(", ")
+                ),
+            );
+        }
+        AliasCommands::Remove { address, target } => {
+            let removed = aliases::remove(db.get_pool(), &address, target.as_deref()).await?;
+            if removed == 0 {
+                let msg = match &target {
+                    Some(target) => format!("Alias '{address}' has no target '{target}'."),
+                    None => format!("Alias '{address}' does not exist."),
+                };
+                print_error(no_color, &msg);
+                std::process::exit(1);
+            }
+            print_success(
+                no_color,
+                &format!("{removed} target(s) of alias '{address}' removed."),
+            );
+        }
+        AliasCommands::List => {
+            let aliases = aliases::list(db.get_pool()).await?;
+            if format == OutputFormat::Json {
+                let rows: Vec<AliasRow> = aliases
+                    .into_iter()
+                    .map(|a| AliasRow {
+                        address: a.address,
+                        target: a.target,
+                        created_at: a.created_at,
+                    })
+                    .collect();
+                print_json(&rows)?;
+            } else {
+                let rows = aliases
+                    .into_iter()
+                    .map(|a| vec![a.address, a.target, a.created_at])
+                    .collect();
+                print_table(&["ADDRESS", "TARGET", "CREATED"], rows);
+            }
+        }
+    }
+    Ok(())
+}
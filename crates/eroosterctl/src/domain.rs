@@ -2,18 +2,29 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-//! `eroosterctl domain check` — DNS health check (MX, SPF, DKIM, DMARC).
+//! `eroosterctl domain` — hosted domains and their DNS health check (MX, SPF,
+//! DKIM, DMARC).
 
-use crate::output::{color_disabled, print_json, OutputFormat};
+use crate::output::{
+    color_disabled, print_error, print_json, print_success, print_table, print_warning,
+    OutputFormat,
+};
 use clap::Subcommand;
 use color_eyre::eyre::Result;
-use erooster_core::config::Config;
+use erooster_core::{
+    backend::{
+        database::{get_database, Database},
+        domains,
+    },
+    config::Config,
+};
 use hickory_resolver::{
     proto::rr::rdata::{MX, TXT},
     TokioResolver,
 };
 use owo_colors::OwoColorize;
 use serde::Serialize;
+use std::io::IsTerminal;
 
 #[derive(Subcommand, Debug)]
 pub enum DomainCommands {
@@ -22,6 +33,40 @@ pub enum DomainCommands {
         /// Hostname to check (defaults to mail.hostname from config)
         hostname: Option<String>,
     },
+
+    /// Host an additional mail domain
+    Add {
+        /// The domain to deliver mail for locally
+        domain: String,
+        /// Private key to DKIM sign the domain's mail with (defaults to `mail.dkim_key_path`)
+        #[arg(long, requires = "dkim_selector")]
+        dkim_key: Option<String>,
+        /// DNS selector of the domain's DKIM key
+        #[arg(long, requires = "dkim_key")]
+        dkim_selector: Option<String>,
+    },
+
+    /// Stop hosting a mail domain
+    #[command(alias = "rm")]
+    Remove {
+        /// The domain to remove
+        domain: String,
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// List the hosted mail domains
+    #[command(alias = "ls")]
+    List,
+}
+
+#[derive(Debug, Serialize)]
+struct DomainRow {
+    domain: String,
+    dkim_key_path: String,
+    dkim_key_selector: String,
+    created_at: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -35,6 +80,7 @@ pub async fn run(
     cmd: DomainCommands,
     config: &Config,
     format: OutputFormat,
+    yes: bool,
     no_color: bool,
 ) -> Result<()> {
     let no_color = color_disabled(no_color);
@@ -44,9 +90,124 @@ pub async fn run(
                 .as_deref()
                 .unwrap_or(&config.mail.hostname)
                 .to_string();
-            check_dns(&host, &config.mail.dkim_key_selector, format, no_color).await
+            let db = get_database(config).await?;
+            let selector = domains::dkim_key(db.get_pool(), config, &host)
+                .await?
+                .selector;
+            check_dns(&host, &selector, format, no_color).await
+        }
+        DomainCommands::Add {
+            domain,
+            dkim_key,
+            dkim_selector,
+        } => add(&domain, dkim_key, dkim_selector, config, no_color).await,
+        DomainCommands::Remove {
+            domain,
+            yes: local_yes,
+        } => remove(&domain, yes || local_yes, config, no_color).await,
+        DomainCommands::List => list(config, format).await,
+    }
+}
+
+async fn add(
+    domain: &str,
+    dkim_key: Option<String>,
+    dkim_selector: Option<String>,
+    config: &Config,
+    no_color: bool,
+) -> Result<()> {
+    if domain.eq_ignore_ascii_case(&config.mail.hostname) {
+        print_error(
+            no_color,
+            &format!("'{domain}' is mail.hostname and always hosted."),
+        );
+        std::process::exit(1);
+    }
+    if let Some(key) = &dkim_key {
+        if !std::path::Path::new(key).exists() {
+            print_warning(
+                no_color,
+                &format!("DKIM key '{key}' does not exist, mail from {domain} fails to sign until it does."),
+            );
         }
     }
+
+    let db = get_database(config).await?;
+    let dkim = dkim_key.as_deref().zip(dkim_selector.as_deref());
+    domains::add(db.get_pool(), domain, dkim).await?;
+    print_success(
+        no_color,
+        &format!("Domain '{}' added.", domain.to_lowercase()),
+    );
+    Ok(())
+}
+
+async fn remove(domain: &str, yes: bool, config: &Config, no_color: bool) -> Result<()> {
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            print_error(
+                no_color,
+                "Confirmation required. Pass --yes to remove non-interactively.",
+            );
+            std::process::exit(2);
+        }
+        let confirmed = dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Remove '{domain}'? Mail to it is no longer accepted."
+            ))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            println!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    let db = get_database(config).await?;
+    if !domains::remove(db.get_pool(), domain).await? {
+        print_error(no_color, &format!("Domain '{domain}' is not hosted."));
+        std::process::exit(1);
+    }
+    print_success(no_color, &format!("Domain '{domain}' removed."));
+    Ok(())
+}
+
+async fn list(config: &Config, format: OutputFormat) -> Result<()> {
+    let db = get_database(config).await?;
+    // mail.hostname is hosted without being in the table
+    let mut rows = vec![DomainRow {
+        domain: config.mail.hostname.clone(),
+        dkim_key_path: config.mail.dkim_key_path.clone(),
+        dkim_key_selector: config.mail.dkim_key_selector.clone(),
+        created_at: None,
+    }];
+    for domain in domains::list(db.get_pool()).await? {
+        let dkim = domains::dkim_key(db.get_pool(), config, &domain.name).await?;
+        rows.push(DomainRow {
+            domain: domain.name,
+            dkim_key_path: dkim.key_path,
+            dkim_key_selector: dkim.selector,
+            created_at: Some(domain.created_at),
+        });
+    }
+
+    if format == OutputFormat::Json {
+        print_json(&rows)?;
+    } else {
+        let rows = rows
+            .into_iter()
+            .map(|r| {
+                vec![
+                    r.domain,
+                    r.dkim_key_selector,
+                    r.dkim_key_path,
+                    r.created_at.unwrap_or_else(|| String::from("config")),
+                ]
+            })
+            .collect();
+        print_table(&["DOMAIN", "DKIM SELECTOR", "DKIM KEY", "ADDED"], rows);
+    }
+    Ok(())
 }
 
 async fn check_dns(
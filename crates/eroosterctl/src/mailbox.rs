@@ -2,13 +2,17 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-//! `eroosterctl mailbox` — mailbox listing subcommand.
+//! `eroosterctl mailbox` — mailbox listing and search index subcommands.
 
-use crate::output::{print_json, print_table, OutputFormat};
+use crate::output::{print_json, print_success, print_table, OutputFormat};
 use clap::Subcommand;
 use color_eyre::eyre::Result;
 use erooster_core::{
-    backend::{admin::list_mailboxes_for_user, database::{get_database, Database}},
+    backend::{
+        admin::list_mailboxes_for_user,
+        database::{get_database, Database},
+        storage::{get_storage, MailStorage},
+    },
     config::Config,
 };
 use serde::Serialize;
@@ -21,6 +25,11 @@ pub enum MailboxCommands {
         /// Email address of the user
         email: String,
     },
+    /// Rebuild the full-text search index of all mailboxes of a user
+    Reindex {
+        /// Email address of the user
+        email: String,
+    },
 }
 
 #[derive(Serialize)]
@@ -29,9 +38,21 @@ struct MailboxRow {
     messages: i64,
 }
 
-pub async fn run(cmd: MailboxCommands, config: &Config, format: OutputFormat) -> Result<()> {
+#[derive(Serialize)]
+struct ReindexResult {
+    email: String,
+    messages: usize,
+}
+
+pub async fn run(
+    cmd: MailboxCommands,
+    config: &Config,
+    format: OutputFormat,
+    no_color: bool,
+) -> Result<()> {
     match cmd {
         MailboxCommands::List { email } => list(&email, config, format).await,
+        MailboxCommands::Reindex { email } => reindex(&email, config, format, no_color).await,
     }
 }
 
@@ -57,3 +78,19 @@ async fn list(email: &str, config: &Config, format: OutputFormat) -> Result<()>
     }
     Ok(())
 }
+
+async fn reindex(email: &str, config: &Config, format: OutputFormat, no_color: bool) -> Result<()> {
+    let db = get_database(config).await?;
+    let storage = get_storage(db, config.clone());
+    let messages = storage.reindex(email).await?;
+
+    if format == OutputFormat::Json {
+        print_json(&ReindexResult {
+            email: email.to_string(),
+            messages,
+        })?;
+    } else {
+        print_success(no_color, &format!("Indexed {messages} messages of '{email}'."));
+    }
+    Ok(())
+}
@@ -10,11 +10,13 @@ use clap::{Parser, Subcommand};
 use color_eyre::Result;
 use erooster_core::panic_handler::EroosterPanicMessage;
 
+mod alias;
 mod config_cmd;
 mod domain;
 mod mailbox;
 mod output;
 mod queue;
+mod session;
 mod status;
 mod user;
 
@@ -73,9 +75,17 @@ enum Commands {
     #[command(subcommand, alias = "q")]
     Queue(queue::QueueCommands),
 
-    /// Domain DNS record checks
+    /// Connected clients
+    #[command(subcommand)]
+    Session(session::SessionCommands),
+
+    /// Hosted domains and their DNS record checks
     #[command(subcommand, alias = "dns")]
     Domain(domain::DomainCommands),
+
+    /// Address aliases, catch-alls and forwarding
+    #[command(subcommand)]
+    Alias(alias::AliasCommands),
 }
 
 #[tokio::main]
@@ -117,13 +127,19 @@ async fn main() -> Result<()> {
             user::run(cmd, &config, cli.output, cli.yes, cli.no_color).await?;
         }
         Commands::Mailbox(cmd) => {
-            mailbox::run(cmd, &config, cli.output).await?;
+            mailbox::run(cmd, &config, cli.output, cli.no_color).await?;
         }
         Commands::Queue(cmd) => {
             queue::run(cmd, &config, cli.output, cli.yes, cli.no_color).await?;
         }
+        Commands::Session(cmd) => {
+            session::run(cmd, &config, cli.output).await?;
+        }
         Commands::Domain(cmd) => {
-            domain::run(cmd, &config, cli.output, cli.no_color).await?;
+            domain::run(cmd, &config, cli.output, cli.yes, cli.no_color).await?;
+        }
+        Commands::Alias(cmd) => {
+            alias::run(cmd, &config, cli.output, cli.no_color).await?;
         }
         Commands::Version => unreachable!(),
     }
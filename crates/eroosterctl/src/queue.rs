@@ -10,7 +10,7 @@ use color_eyre::eyre::Result;
 use erooster_core::{
     backend::{
         database::{get_database, Database},
-        queue::{self, QueueEntry},
+        queue::{self, QueueEntry, QueueRecipient},
     },
     config::Config,
 };
@@ -118,6 +118,23 @@ async fn queue_list(status: Option<&str>, config: &Config, format: OutputFormat)
     Ok(())
 }
 
+#[derive(Serialize)]
+struct RecipientRow {
+    address: String,
+    status: String,
+    last_reply: Option<String>,
+}
+
+impl From<QueueRecipient> for RecipientRow {
+    fn from(r: QueueRecipient) -> Self {
+        RecipientRow {
+            address: r.address,
+            status: r.status.to_string(),
+            last_reply: r.last_reply,
+        }
+    }
+}
+
 async fn queue_show(id: &str, config: &Config, format: OutputFormat) -> Result<()> {
     let db = get_database(config).await?;
     let entries = queue::list_all(db.get_pool(), None).await?;
@@ -127,6 +144,7 @@ async fn queue_show(id: &str, config: &Config, format: OutputFormat) -> Result<(
         eprintln!("No queue entry with id '{id}'.");
         std::process::exit(1);
     };
+    let recipients = queue::recipients(db.get_pool(), &entry.id).await?;
 
     if format == OutputFormat::Json {
         #[derive(Serialize)]
@@ -137,6 +155,7 @@ async fn queue_show(id: &str, config: &Config, format: OutputFormat) -> Result<(
             from: String,
             to: String,
             last_error: Option<String>,
+            recipients: Vec<RecipientRow>,
             delivery_log: Vec<erooster_core::backend::queue::DeliveryAttempt>,
         }
         print_json(&FullEntry {
@@ -146,6 +165,7 @@ async fn queue_show(id: &str, config: &Config, format: OutputFormat) -> Result<(
             from: entry.from_addr,
             to: entry.to_addrs,
             last_error: entry.last_error,
+            recipients: recipients.into_iter().map(RecipientRow::from).collect(),
             delivery_log: entry.delivery_log,
         })?;
     } else {
@@ -157,6 +177,17 @@ async fn queue_show(id: &str, config: &Config, format: OutputFormat) -> Result<(
         if let Some(err) = &entry.last_error {
             println!("Last error:   {err}");
         }
+        if !recipients.is_empty() {
+            println!("\nRecipients:");
+            for recipient in &recipients {
+                match &recipient.last_reply {
+                    Some(reply) => {
+                        println!("  {} ({}): {reply}", recipient.address, recipient.status);
+                    }
+                    None => println!("  {} ({})", recipient.address, recipient.status),
+                }
+            }
+        }
         if !entry.delivery_log.is_empty() {
             println!("\nDelivery log:");
             for attempt in &entry.delivery_log {
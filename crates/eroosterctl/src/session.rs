@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: 2026 MTRNord
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! `eroosterctl session` — connected client subcommands.
+
+use crate::output::{print_json, print_table, OutputFormat};
+use clap::Subcommand;
+use color_eyre::eyre::Result;
+use erooster_core::{
+    backend::{
+        database::{get_database, Database},
+        sessions::{self, client_description},
+    },
+    config::Config,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Subcommand, Debug)]
+pub enum SessionCommands {
+    /// List the connected clients
+    #[command(alias = "ls")]
+    List,
+}
+
+#[derive(Serialize)]
+struct SessionRow {
+    id: String,
+    protocol: String,
+    peer: String,
+    username: Option<String>,
+    client_id: BTreeMap<String, String>,
+    started_at: String,
+}
+
+pub async fn run(cmd: SessionCommands, config: &Config, format: OutputFormat) -> Result<()> {
+    match cmd {
+        SessionCommands::List => list(config, format).await,
+    }
+}
+
+async fn list(config: &Config, format: OutputFormat) -> Result<()> {
+    let db = get_database(config).await?;
+    let sessions = sessions::list(db.get_pool()).await?;
+
+    if format == OutputFormat::Json {
+        let rows: Vec<SessionRow> = sessions
+            .into_iter()
+            .map(|s| SessionRow {
+                id: s.id,
+                protocol: s.protocol,
+                peer: s.peer,
+                username: s.username,
+                client_id: s.client_id,
+                started_at: s.started_at,
+            })
+            .collect();
+        print_json(&rows)?;
+    } else {
+        let rows = sessions
+            .into_iter()
+            .map(|s| {
+                vec![
+                    s.id,
+                    s.protocol,
+                    s.peer,
+                    s.username.unwrap_or_default(),
+                    client_description(&s.client_id),
+                    s.started_at,
+                ]
+            })
+            .collect();
+        print_table(
+            &["ID", "PROTOCOL", "PEER", "USER", "CLIENT", "STARTED"],
+            rows,
+        );
+    }
+    Ok(())
+}
@@ -8,10 +8,13 @@ use crate::output::{color_disabled, print_error, print_json, print_success, prin
 use clap::Subcommand;
 use color_eyre::eyre::Result;
 use erooster_core::{
-    backend::database::{get_database, Database},
+    backend::{
+        app_passwords::{self, Scope},
+        database::{get_database, Database},
+    },
     config::Config,
 };
-use secrecy::SecretString;
+use secrecy::{ExposeSecret, SecretString};
 use serde::Serialize;
 use std::io::IsTerminal;
 
@@ -22,7 +25,6 @@ pub enum UserCommands {
     List,
 
     /// Add a new user
-    #[command(alias = "add")]
     Add {
         /// Email address of the new user
         #[arg(short, long)]
@@ -66,6 +68,41 @@ pub enum UserCommands {
         #[arg(short, long)]
         file: Option<String>,
     },
+
+    /// Manage a user's application-specific passwords
+    #[command(subcommand)]
+    AppPassword(AppPasswordCommands),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AppPasswordCommands {
+    /// Generate a new app password and print it once
+    Add {
+        /// Email address of the user
+        email: String,
+        /// Name to recognize the password by, e.g. the client using it
+        #[arg(short, long)]
+        name: String,
+        /// Protocols the password is valid for: imap, smtp or all
+        #[arg(short, long, default_value = "all")]
+        scope: Scope,
+    },
+
+    /// List a user's app passwords
+    #[command(alias = "ls")]
+    List {
+        /// Email address of the user
+        email: String,
+    },
+
+    /// Revoke an app password
+    #[command(alias = "rm")]
+    Revoke {
+        /// Email address of the user
+        email: String,
+        /// Name of the password to revoke
+        name: String,
+    },
 }
 
 #[derive(Serialize)]
@@ -73,6 +110,21 @@ struct UserRow {
     username: String,
 }
 
+#[derive(Serialize)]
+struct AppPasswordRow {
+    name: String,
+    scope: String,
+    created_at: String,
+    last_used: Option<String>,
+}
+
+#[derive(Serialize)]
+struct NewAppPassword {
+    name: String,
+    scope: String,
+    password: String,
+}
+
 pub async fn run(
     cmd: UserCommands,
     config: &Config,
@@ -94,6 +146,7 @@ pub async fn run(
         } => passwd(&email, current_password, new_password, config, no_color).await,
         UserCommands::Exists { email } => exists(&email, config).await,
         UserCommands::Import { file } => import(file, config, no_color).await,
+        UserCommands::AppPassword(cmd) => app_password(cmd, config, format, no_color).await,
     }
 }
 
@@ -289,3 +342,77 @@ async fn import(file: Option<String>, config: &Config, no_color: bool) -> Result
     }
     Ok(())
 }
+
+async fn app_password(
+    cmd: AppPasswordCommands,
+    config: &Config,
+    format: OutputFormat,
+    no_color: bool,
+) -> Result<()> {
+    let db = get_database(config).await?;
+    match cmd {
+        AppPasswordCommands::Add { email, name, scope } => {
+            if !db.user_exists(&email).await {
+                print_error(no_color, &format!("User '{email}' does not exist."));
+                std::process::exit(1);
+            }
+            let password = app_passwords::create(db.get_pool(), &email, &name, scope).await?;
+            if format == OutputFormat::Json {
+                print_json(&NewAppPassword {
+                    name,
+                    scope: scope.to_string(),
+                    password: password.expose_secret().to_string(),
+                })?;
+            } else {
+                print_success(
+                    no_color,
+                    &format!("App password '{name}' added for '{email}' ({scope}):"),
+                );
+                println!("{}", password.expose_secret());
+                println!("It is not shown again.");
+            }
+        }
+        AppPasswordCommands::List { email } => {
+            let passwords = app_passwords::list(db.get_pool(), &email).await?;
+            if format == OutputFormat::Json {
+                let rows: Vec<AppPasswordRow> = passwords
+                    .into_iter()
+                    .map(|p| AppPasswordRow {
+                        name: p.name,
+                        scope: p.scope.to_string(),
+                        created_at: p.created_at,
+                        last_used: p.last_used,
+                    })
+                    .collect();
+                print_json(&rows)?;
+            } else {
+                let rows = passwords
+                    .into_iter()
+                    .map(|p| {
+                        vec![
+                            p.name,
+                            p.scope.to_string(),
+                            p.created_at,
+                            p.last_used.unwrap_or_else(|| String::from("never")),
+                        ]
+                    })
+                    .collect();
+                print_table(&["NAME", "SCOPE", "CREATED", "LAST USED"], rows);
+            }
+        }
+        AppPasswordCommands::Revoke { email, name } => {
+            if !app_passwords::revoke(db.get_pool(), &email, &name).await? {
+                print_error(
+                    no_color,
+                    &format!("'{email}' has no app password named '{name}'."),
+                );
+                std::process::exit(1);
+            }
+            print_success(
+                no_color,
+                &format!("App password '{name}' of '{email}' revoked."),
+            );
+        }
+    }
+    Ok(())
+}